@@ -0,0 +1,76 @@
+//! 再描画のループで、所有権を持つ木とアリーナに確保した木の差分計算を比べるベンチマーク
+//!
+//! `cargo bench --features arena --bench arena`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minimal_virtual_dom_library::arena::{update_dom_arena, ArenaNode, RenderArena};
+use minimal_virtual_dom_library::builder::element;
+use minimal_virtual_dom_library::self_virtual_dom::{update_dom, ElementType, VNode};
+
+const ROWS: usize = 200;
+const ITERATIONS: u32 = 500;
+
+fn owned_tree(tick: usize) -> VNode {
+    VNode {
+        element_type: element(
+            "table",
+            &[],
+            (0..ROWS)
+                .map(|row| {
+                    let value = if row == tick % ROWS { tick } else { row };
+                    element(
+                        "tr",
+                        &[("class", "row")],
+                        (
+                            element("td", &[], row.to_string()),
+                            element("td", &[], value.to_string()),
+                        ),
+                    )
+                })
+                .collect::<Vec<ElementType>>(),
+        ),
+    }
+}
+
+fn arena_tree(arena: &RenderArena, tick: usize) -> ArenaNode<'_> {
+    let rows: Vec<ArenaNode> = (0..ROWS)
+        .map(|row| {
+            let value = if row == tick % ROWS { tick } else { row };
+            arena.element(
+                "tr",
+                &[("class", "row")],
+                [
+                    arena.element("td", &[], [arena.text(&row.to_string())]),
+                    arena.element("td", &[], [arena.text(&value.to_string())]),
+                ],
+            )
+        })
+        .collect();
+    arena.element("table", &[], rows)
+}
+
+fn measure(name: &str, mut f: impl FnMut(usize)) {
+    let start = Instant::now();
+    for tick in 0..ITERATIONS as usize {
+        f(tick);
+    }
+    let elapsed: Duration = start.elapsed();
+    println!("{:<8} {:>10.1?} / iteration", name, elapsed / ITERATIONS);
+}
+
+fn main() {
+    let old = owned_tree(0);
+
+    measure("owned", |tick| {
+        black_box(update_dom(&old, &owned_tree(tick)));
+    });
+
+    let mut arena = RenderArena::new();
+    measure("arena", |tick| {
+        let new = arena_tree(&arena, tick);
+        black_box(update_dom_arena(&old, &new));
+        arena.reset();
+    });
+}
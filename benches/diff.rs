@@ -0,0 +1,119 @@
+//! 典型的な文書の構築・差分計算・HTML への変換にかかる時間と、
+//! コストモデルの有無による差分のJSONの大きさを測るベンチマーク
+//!
+//! `cargo bench --bench diff`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minimal_virtual_dom_library::builder::element;
+use minimal_virtual_dom_library::self_virtual_dom::{
+    update_dom, update_dom_with, virtual_dom_to_html, CostModel, DiffOptions, ElementType, VNode,
+};
+
+const ROWS: usize = 200;
+const ITERATIONS: u32 = 500;
+
+fn document(tick: usize) -> VNode {
+    VNode {
+        element_type: element(
+            "main",
+            &[("id", "app"), ("class", "page")],
+            (
+                element("h1", &[("class", "title")], "Orders"),
+                element(
+                    "table",
+                    &[("class", "orders")],
+                    (0..ROWS)
+                        .map(|row| {
+                            let status = if row == tick % ROWS {
+                                "shipped"
+                            } else {
+                                "open"
+                            };
+                            element(
+                                "tr",
+                                &[("class", "row"), ("data-status", status)],
+                                (
+                                    element("td", &[], row.to_string()),
+                                    element("td", &[("class", "status")], status),
+                                    element("td", &[], (row * 100).to_string()),
+                                ),
+                            )
+                        })
+                        .collect::<Vec<ElementType>>(),
+                ),
+            ),
+        ),
+    }
+}
+
+/**
+ * key を持たない行を並べ替えた表。すべてのセルのテキストが変わる
+ */
+fn sorted(descending: bool) -> VNode {
+    let mut rows: Vec<usize> = (0..ROWS).collect();
+    if descending {
+        rows.reverse();
+    }
+    VNode {
+        element_type: element(
+            "table",
+            &[],
+            rows.into_iter()
+                .map(|row| {
+                    element(
+                        "tr",
+                        &[],
+                        (
+                            element("td", &[], row.to_string()),
+                            element("td", &[], format!("item {}", row)),
+                        ),
+                    )
+                })
+                .collect::<Vec<ElementType>>(),
+        ),
+    }
+}
+
+fn payload(name: &str, old: &VNode, new: &VNode) {
+    let bytes = |options: &DiffOptions| {
+        let diff = update_dom_with(old, new, options).diff;
+        (diff.len(), serde_json::to_vec(&diff).unwrap().len())
+    };
+    let (patches, plain) = bytes(&DiffOptions::default());
+    let (replaced, costed) = bytes(&DiffOptions::default().with_cost_model(CostModel::default()));
+    println!(
+        "{:<8} {:>5} patches {:>8} bytes -> {:>5} patches {:>8} bytes",
+        name, patches, plain, replaced, costed
+    );
+}
+
+fn measure(name: &str, mut f: impl FnMut(usize)) {
+    let start = Instant::now();
+    for tick in 0..ITERATIONS as usize {
+        f(tick);
+    }
+    let elapsed: Duration = start.elapsed();
+    println!("{:<8} {:>10.1?} / iteration", name, elapsed / ITERATIONS);
+}
+
+fn main() {
+    let old = document(0);
+
+    measure("build", |tick| {
+        black_box(document(tick));
+    });
+
+    let trees: Vec<VNode> = (0..ITERATIONS as usize).map(document).collect();
+    measure("diff", |tick| {
+        black_box(update_dom(&old, &trees[tick]));
+    });
+
+    measure("html", |tick| {
+        black_box(virtual_dom_to_html(&trees[tick].element_type));
+    });
+
+    payload("one row", &old, &trees[1]);
+    payload("sorted", &sorted(false), &sorted(true));
+}
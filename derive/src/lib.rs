@@ -0,0 +1,148 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitStr};
+
+/**
+ * `#[render(...)]` で指定できる設定
+ */
+#[derive(Default)]
+struct RenderAttrs {
+    tag: Option<String>,
+    class: Option<String>,
+    attr: Option<String>,
+    skip: bool,
+    nested: bool,
+}
+
+impl RenderAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut render = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("render")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    render.tag = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("class") {
+                    render.class = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("attr") {
+                    render.attr = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("skip") {
+                    render.skip = true;
+                } else if meta.path.is_ident("nested") {
+                    render.nested = true;
+                } else {
+                    return Err(meta.error("expected tag, class, attr, skip or nested"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(render)
+    }
+}
+
+/**
+ * 構造体を仮想DOMの要素に変換する Render trait の実装を生成する derive マクロ
+ *
+ * 構造体には `#[render(tag = "li", class = "item")]` で外側の要素を指定する（既定は div）
+ * 各フィールドは既定でフィールド名を class に持つ span 要素になり、値は ToString で文字列にする
+ * フィールドには次の指定ができる
+ * - `#[render(tag = "strong", class = "title")]` 要素のタグと class を変える
+ * - `#[render(attr = "data-id")]` 子要素ではなく外側の要素の属性にする
+ * - `#[render(nested)]` Render を実装した値として描画する
+ * - `#[render(skip)]` 描画しない
+ */
+#[proc_macro_derive(Render, attributes(render))]
+pub fn derive_render(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "Render can only be derived for structs",
+        ));
+    };
+
+    let container = RenderAttrs::parse(&input.attrs)?;
+    let tag = container.tag.unwrap_or_else(|| "div".to_string());
+    let class = container.class.map(|class| {
+        quote! { attrs.insert("class".into(), #class.into()); }
+    });
+
+    let mut statements = Vec::new();
+    let fields: Vec<_> = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (index, field) in fields.into_iter().enumerate() {
+        let render = RenderAttrs::parse(&field.attrs)?;
+        if render.skip {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let index = syn::Index::from(index);
+                quote! { #index }
+            }
+        };
+
+        if let Some(attr) = render.attr {
+            statements.push(quote! {
+                attrs.insert(#attr.into(), ::std::string::ToString::to_string(&self.#member).into());
+            });
+            continue;
+        }
+
+        let content = if render.nested {
+            quote! { ::minimal_virtual_dom_library::render::Render::render(&self.#member) }
+        } else {
+            quote! {
+                ::minimal_virtual_dom_library::self_virtual_dom::ElementType::Text(
+                    ::std::string::ToString::to_string(&self.#member).into(),
+                )
+            }
+        };
+        let field_tag = render.tag.unwrap_or_else(|| "span".to_string());
+        let field_class = render
+            .class
+            .or_else(|| field.ident.as_ref().map(|ident| ident.to_string()));
+        let field_class = field_class.map(|class| {
+            quote! { field_attrs.insert("class".into(), #class.into()); }
+        });
+        statements.push(quote! {
+            let mut field_attrs = ::minimal_virtual_dom_library::self_virtual_dom::Attributes::new();
+            #field_class
+            children.push(::minimal_virtual_dom_library::self_virtual_dom::ElementType::Element(
+                #field_tag.to_string(),
+                field_attrs,
+                vec![#content],
+            ));
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::minimal_virtual_dom_library::render::Render for #ident #ty_generics #where_clause {
+            fn render(&self) -> ::minimal_virtual_dom_library::self_virtual_dom::ElementType {
+                #[allow(unused_mut)]
+                let mut attrs = ::minimal_virtual_dom_library::self_virtual_dom::Attributes::new();
+                #[allow(unused_mut)]
+                let mut children = ::std::vec::Vec::new();
+                #class
+                #(#statements)*
+                ::minimal_virtual_dom_library::self_virtual_dom::ElementType::Element(
+                    #tag.to_string(),
+                    attrs,
+                    children,
+                )
+            }
+        }
+    })
+}
@@ -0,0 +1,201 @@
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use warp::http::HeaderValue;
+use warp::hyper::body::HttpBody;
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::self_virtual_dom::AppResponse;
+
+/**
+ * レスポンスに含まれる差分の数を伝えるヘッダー名
+ */
+pub const PATCH_COUNT_HEADER: &str = "x-patch-count";
+
+/**
+ * 1リクエスト分のアクセスログを表す構造体
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub patch_count: Option<usize>,
+    pub payload_bytes: Option<u64>,
+    pub request_id: Option<String>,
+}
+
+/**
+ * アクセスログの出力先を表す trait
+ */
+pub trait AccessLogSink: Send + Sync {
+    fn record(&self, entry: &AccessLogEntry);
+}
+
+/**
+ * アクセスログを1行のJSONとして標準出力に書き出す出力先
+ */
+pub struct StdoutSink;
+
+impl AccessLogSink for StdoutSink {
+    fn record(&self, entry: &AccessLogEntry) {
+        if let Ok(line) = serde_json::to_string(entry) {
+            println!("{}", line);
+        }
+    }
+}
+
+/**
+ * 差分の数をヘッダーに付与したJSONレスポンスを生成する関数
+ */
+pub fn patch_reply(app_response: &AppResponse) -> Response {
+    let mut response = warp::reply::json(app_response).into_response();
+    response.headers_mut().insert(
+        PATCH_COUNT_HEADER,
+        HeaderValue::from(app_response.diff.len()),
+    );
+    if let Some(id) = app_response
+        .request_id
+        .as_deref()
+        .and_then(|id| HeaderValue::from_str(id).ok())
+    {
+        response.headers_mut().insert(REQUEST_ID_HEADER, id);
+    }
+    response
+}
+
+/**
+ * ルートを包み、リクエストごとのアクセスログを出力先に記録するフィルタを返す関数
+ */
+pub fn access_log<F, T>(
+    sink: Arc<dyn AccessLogSink>,
+    filter: F,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(filter)
+        .map(
+            move |start: Instant, method: warp::http::Method, path: FullPath, reply: T| {
+                let response = reply.into_response();
+                let entry = AccessLogEntry {
+                    method: method.to_string(),
+                    path: path.as_str().to_string(),
+                    status: response.status().as_u16(),
+                    latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+                    patch_count: response
+                        .headers()
+                        .get(PATCH_COUNT_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok()),
+                    payload_bytes: response.body().size_hint().exact(),
+                    request_id: response
+                        .headers()
+                        .get(REQUEST_ID_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string),
+                };
+                sink.record(&entry);
+                response
+            },
+        )
+}
+
+/**
+ * アクセスログを OpenTelemetry のメトリクスとして記録する出力先
+ */
+#[cfg(feature = "otel")]
+pub struct OtelSink {
+    latency: opentelemetry::metrics::Histogram<f64>,
+    patches: opentelemetry::metrics::Counter<u64>,
+    payload: opentelemetry::metrics::Counter<u64>,
+}
+
+#[cfg(feature = "otel")]
+impl OtelSink {
+    pub fn new() -> Self {
+        let meter = opentelemetry::global::meter("minimal-virtual-dom-library");
+        Self {
+            latency: meter.f64_histogram("vdom.http.latency_ms").init(),
+            patches: meter.u64_counter("vdom.http.patch_count").init(),
+            payload: meter.u64_counter("vdom.http.payload_bytes").init(),
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Default for OtelSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "otel")]
+impl AccessLogSink for OtelSink {
+    fn record(&self, entry: &AccessLogEntry) {
+        use opentelemetry::KeyValue;
+
+        let attributes = [
+            KeyValue::new("http.method", entry.method.clone()),
+            KeyValue::new("http.route", entry.path.clone()),
+            KeyValue::new("http.status_code", entry.status as i64),
+        ];
+        self.latency.record(entry.latency_ms, &attributes);
+        if let Some(patch_count) = entry.patch_count {
+            self.patches.add(patch_count as u64, &attributes);
+        }
+        if let Some(payload_bytes) = entry.payload_bytes {
+            self.payload.add(payload_bytes, &attributes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemorySink {
+        entries: Mutex<Vec<AccessLogEntry>>,
+    }
+
+    impl AccessLogSink for MemorySink {
+        fn record(&self, entry: &AccessLogEntry) {
+            self.entries.lock().unwrap().push(entry.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let sink = Arc::new(MemorySink::default());
+        let route = warp::path("run_app").map(|| {
+            patch_reply(&AppResponse {
+                diff: vec![],
+                html: "<div></div>".to_string(),
+                request_id: Some("req-1".to_string()),
+            })
+        });
+        let filter = access_log(sink.clone(), route);
+
+        let response = warp::test::request().path("/run_app").reply(&filter).await;
+
+        assert_eq!(response.status(), 200);
+        let entries = sink.entries.lock().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[0].path, "/run_app");
+        assert_eq!(entries[0].patch_count, Some(0));
+        assert_eq!(entries[0].request_id.as_deref(), Some("req-1"));
+        assert_eq!(entries[0].payload_bytes, Some(response.body().len() as u64));
+    }
+}
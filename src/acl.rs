@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+use crate::auth::Identity;
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{Diff, ElementType};
+
+/**
+ * 部分木に設定する保護の種類
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Access {
+    /** 誰も変更できない */
+    ReadOnly,
+    /** 指定した送信者だけが変更できる */
+    Owner(Identity),
+}
+
+/**
+ * 保護された部分木への変更が拒否されたことを表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AclViolation {
+    /** 拒否された差分の適用先のパス */
+    pub path: NodePath,
+    /** 違反した保護が設定されている部分木のパス */
+    pub protected_path: NodePath,
+    pub access: Access,
+}
+
+/**
+ * 部分木ごとの保護を管理し、差分が保護に違反していないかを判定する構造体
+ */
+#[derive(Debug, Clone, Default)]
+pub struct AccessControl {
+    rules: Vec<(NodePath, Access)>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * パス配下の部分木に保護を設定する関数
+     */
+    pub fn protect(mut self, path: NodePath, access: Access) -> Self {
+        self.rules.push((path, access));
+        self
+    }
+
+    /**
+     * 送信者が old から new への変更を行えるかを差分から判定する関数
+     * 保護された部分木の中への差分と、保護された部分木の内容を変える祖先の置き換えを拒否する
+     * 移動の差分は移動元と移動先の両方を判定する
+     */
+    pub fn check(
+        &self,
+        identity: &Identity,
+        old: &ElementType,
+        new: &ElementType,
+        patches: &[Diff],
+    ) -> Result<(), AclViolation> {
+        for (protected_path, access) in &self.rules {
+            if matches!(access, Access::Owner(owner) if owner == identity) {
+                continue;
+            }
+            for patch in patches {
+                let paths = match patch {
+                    Diff::MoveNode(from, to, _) => vec![from, to],
+                    patch => vec![patch.path()],
+                };
+                for path in paths {
+                    let touches = path.starts_with(protected_path)
+                        || (protected_path.starts_with(path)
+                            && old.get(protected_path) != new.get(protected_path));
+                    if touches {
+                        return Err(AclViolation {
+                            path: path.clone(),
+                            protected_path: protected_path.clone(),
+                            access: access.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::Attributes;
+    use crate::self_virtual_dom::{update_dom, VNode};
+
+    fn tree(header: &str, body: &str) -> VNode {
+        VNode {
+            element_type: ElementType::Element(
+                "div".to_string(),
+                Attributes::new(),
+                vec![
+                    ElementType::Text(header.to_string().into()),
+                    ElementType::Text(body.to_string().into()),
+                ],
+            ),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let acl = AccessControl::new()
+            .protect(vec![0], Access::ReadOnly)
+            .protect(vec![1], Access::Owner(Identity::new("alice")));
+        let check = |identity: &Identity, old: &VNode, new: &VNode| {
+            let patches = update_dom(old, new).diff;
+            acl.check(identity, &old.element_type, &new.element_type, &patches)
+        };
+
+        let old = tree("header", "body");
+        let alice = Identity::new("alice");
+        let bob = Identity::new("bob");
+
+        assert!(check(&alice, &old, &tree("header", "edited")).is_ok());
+
+        let violation = check(&bob, &old, &tree("header", "edited")).unwrap_err();
+        assert_eq!(violation.protected_path, vec![1]);
+        assert_eq!(violation.access, Access::Owner(alice.clone()));
+
+        let violation = check(&alice, &old, &tree("edited", "body")).unwrap_err();
+        assert_eq!(violation.access, Access::ReadOnly);
+    }
+
+    #[test]
+    fn test_check_move_into_protected() {
+        let list = |items: &[&str]| {
+            ElementType::Element(
+                "ul".to_string(),
+                Attributes::new(),
+                items
+                    .iter()
+                    .map(|item| ElementType::Text(item.to_string().into()))
+                    .collect(),
+            )
+        };
+        let board = |protected: &[&str], open: &[&str]| {
+            ElementType::Element(
+                "div".to_string(),
+                Attributes::new(),
+                vec![list(protected), list(open)],
+            )
+        };
+        let acl = AccessControl::new().protect(vec![0], Access::ReadOnly);
+        let old = board(&["a"], &["b"]);
+        let new = board(&["a", "b"], &[]);
+        let moved = VNode {
+            element_type: ElementType::Text("b".into()),
+        };
+
+        let violation = acl
+            .check(
+                &Identity::anonymous(),
+                &old,
+                &new,
+                &[Diff::MoveNode(vec![1, 0], vec![0, 1], moved.clone())],
+            )
+            .unwrap_err();
+        assert_eq!(violation.path, vec![0, 1]);
+        assert_eq!(violation.protected_path, vec![0]);
+
+        let new = board(&["a"], &["b"]);
+        assert!(acl
+            .check(
+                &Identity::anonymous(),
+                &old,
+                &new,
+                &[Diff::MoveNode(vec![1, 0], vec![1, 0], moved)],
+            )
+            .is_ok());
+    }
+}
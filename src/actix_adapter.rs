@@ -0,0 +1,103 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+use crate::error::PROBLEM_CONTENT_TYPE;
+use crate::handler::{HttpHandler, UpdateInputRequest};
+
+/**
+ * 仮想DOMのエンドポイントを actix-web の App に登録する関数を返す関数
+ * `App::new().configure(configure(handler))` のように利用する
+ */
+pub fn configure(handler: Arc<HttpHandler>) -> impl Fn(&mut web::ServiceConfig) + Clone {
+    move |config: &mut web::ServiceConfig| {
+        config
+            .app_data(web::Data::from(handler.clone()))
+            .route("/", web::get().to(index))
+            .route("/run_app", web::get().to(run_app))
+            .route("/update_input", web::post().to(update_input));
+    }
+}
+
+async fn index(handler: web::Data<HttpHandler>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(handler.index())
+}
+
+async fn run_app(handler: web::Data<HttpHandler>) -> HttpResponse {
+    HttpResponse::Ok().json(handler.run_app())
+}
+
+async fn update_input(
+    handler: web::Data<HttpHandler>,
+    http_request: HttpRequest,
+    request: web::Json<UpdateInputRequest>,
+) -> HttpResponse {
+    let header_value = |name| {
+        http_request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let result = handler
+        .authenticate(
+            header_value(header::AUTHORIZATION),
+            header_value(header::COOKIE),
+        )
+        .and_then(|identity| {
+            let remote = http_request.peer_addr().map(|addr| addr.ip());
+            handler.update_input(&identity, remote, request.into_inner())
+        });
+    match result {
+        Ok(app_response) => HttpResponse::Ok().json(app_response),
+        Err(err) => {
+            let status = StatusCode::from_u16(err.status_code())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            HttpResponse::build(status)
+                .content_type(PROBLEM_CONTENT_TYPE)
+                .json(err.to_problem(Some(handler.store().version())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+    use crate::auth::{Identity, StaticTokens};
+    use crate::middleware::MiddlewareChain;
+    use crate::store::Store;
+    use crate::template::TemplateSource;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_update_input_authenticates() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_auth(Arc::new(
+            StaticTokens::new().with_token("secret", Identity::new("alice")),
+        ));
+        let app = test::init_service(App::new().configure(configure(Arc::new(handler)))).await;
+        let request = |cookie: &str| {
+            test::TestRequest::post()
+                .uri("/update_input")
+                .insert_header((header::COOKIE, cookie))
+                .set_json(serde_json::json!({"input": "Hi"}))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, request("vdom_token=wrong")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+
+        let response = test::call_service(&app, request("vdom_token=secret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::binding::BoundView;
+use crate::builder::element;
+use crate::router::{link, Params, Router};
+use crate::self_virtual_dom::{update_dom, AppResponse, Attributes, ElementType, VNode};
+
+/**
+ * デモアプリの初期状態の仮想DOMを返す関数
+ */
+pub fn initial_tree() -> VNode {
+    VNode {
+        element_type: ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![ElementType::Text("".into())],
+        ),
+    }
+}
+
+/**
+ * 入力値をプレビューする仮想DOMを返す関数
+ */
+pub fn render_input(input: &str) -> VNode {
+    VNode {
+        element_type: ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            if input.is_empty() {
+                vec![]
+            } else {
+                vec![ElementType::Text(input.to_string().into())]
+            },
+        ),
+    }
+}
+
+pub fn run_app(dynamic_input: &str) -> AppResponse {
+    let old_dom = VNode {
+        element_type: ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![
+                ElementType::Text(dynamic_input.to_string().into()),
+                ElementType::Element(
+                    "input".to_string(),
+                    [("id".into(), "myInput".into())].into_iter().collect(),
+                    vec![],
+                ),
+            ],
+        ),
+    };
+
+    let new_dom = VNode {
+        element_type: ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![ElementType::Text(dynamic_input.to_string().into())],
+        ),
+    };
+
+    // 仮想DOMの更新の差分を取得
+    update_dom(&old_dom, &new_dom)
+}
+
+/**
+ * デモアプリのテンプレートと状態を返す関数
+ * クライアントが `state.input` に送った入力値をプレビューに表示する
+ * 入力欄は index.html に置かれているため、仮想DOMの外の入力欄として登録する
+ */
+pub fn input_view() -> BoundView {
+    BoundView::new(
+        element("div", &[("bind:text", "state.input")], ()),
+        serde_json::json!({ "input": "" }),
+    )
+    .with_external_input("state.input")
+}
+
+/**
+ * デモアプリのページの一覧（静的サイトとして書き出すルート）
+ */
+pub const DEMO_ROUTES: &[&str] = &["/", "/about"];
+
+/**
+ * デモアプリのページのルーター
+ * ページの骨組みを揃えておくことで、遷移時には変わった部分だけが差分になる
+ */
+pub fn demo_router() -> Router {
+    let page = |title: &'static str, body: &'static str, href: &str, label: &'static str| VNode {
+        element_type: element(
+            "main",
+            &[],
+            (
+                element("h1", &[], title),
+                element("p", &[], body),
+                link(href, label),
+            ),
+        ),
+    };
+    Router::new()
+        .route("/", move |_: &Params| {
+            page("Home", "仮想DOMのデモです", "/about", "About")
+        })
+        .route("/about", move |_: &Params| {
+            page("About", "差分だけを送ってページを切り替えます", "/", "Home")
+        })
+}
@@ -0,0 +1,321 @@
+use bumpalo::Bump;
+
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{
+    escape_comment, escape_text, explained_diff, sort_patches, AppResponse, Attributes, Diff,
+    DiffOptions, ElementType, VNode, KEY_ATTR, RAW_TEXT_ELEMENTS, VOID_ELEMENTS,
+};
+
+/**
+ * アリーナに確保された仮想DOMのノード
+ * 文字列・属性・子要素はすべてアリーナから借用するため、描画のたびにヒープへ確保しない
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArenaNode<'a> {
+    Text(&'a str),
+    Element(&'a str, &'a [(&'a str, &'a str)], &'a [ArenaNode<'a>]),
+    Comment(&'a str),
+}
+
+impl ArenaNode<'_> {
+    /**
+     * 所有権を持つ通常のノードに変換する関数
+     */
+    pub fn to_element(&self) -> ElementType {
+        match self {
+            ArenaNode::Text(text) => ElementType::Text(text.to_string().into()),
+            ArenaNode::Comment(text) => ElementType::Comment(text.to_string()),
+            ArenaNode::Element(tag, attrs, children) => ElementType::Element(
+                tag.to_string(),
+                attrs
+                    .iter()
+                    .map(|(name, value)| (name.to_string().into(), value.to_string().into()))
+                    .collect(),
+                children.iter().map(ArenaNode::to_element).collect(),
+            ),
+        }
+    }
+
+    /**
+     * 通常のノードと同じ内容かを、新たに確保せずに判定する関数
+     */
+    pub fn eq_element(&self, other: &ElementType) -> bool {
+        match (self, other) {
+            (ArenaNode::Text(a), ElementType::Text(b)) => *a == b,
+            (ArenaNode::Comment(a), ElementType::Comment(b)) => *a == b,
+            (
+                ArenaNode::Element(tag, attrs, children),
+                ElementType::Element(b_tag, b_attrs, b_children),
+            ) => {
+                *tag == b_tag
+                    && attrs_eq(attrs, b_attrs)
+                    && children.len() == b_children.len()
+                    && children
+                        .iter()
+                        .zip(b_children)
+                        .all(|(a, b)| a.eq_element(b))
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * 通常のノードに変換せずに virtual_dom_to_html と同じ形式のHTMLにする関数
+     * テキストと属性値は virtual_dom_to_html と同じ規則でエスケープする
+     */
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        self.write_html(&mut html, false);
+        html
+    }
+
+    fn write_html(&self, html: &mut String, raw_text: bool) {
+        match self {
+            ArenaNode::Text(text) if raw_text => html.push_str(text),
+            ArenaNode::Text(text) => html.push_str(&escape_text(text)),
+            ArenaNode::Comment(text) => {
+                html.push_str("<!--");
+                html.push_str(&escape_comment(text));
+                html.push_str("-->");
+            }
+            ArenaNode::Element(tag, attrs, children) => {
+                html.push('<');
+                html.push_str(tag);
+                html.push(' ');
+                for (index, (name, value)) in attrs.iter().enumerate() {
+                    if index > 0 {
+                        html.push(' ');
+                    }
+                    html.push_str(name);
+                    html.push_str("=\"");
+                    html.push_str(&value.replace('&', "&amp;").replace('"', "&quot;"));
+                    html.push('"');
+                }
+                html.push('>');
+                if VOID_ELEMENTS.contains(tag) && children.is_empty() {
+                    return;
+                }
+                let raw_text = RAW_TEXT_ELEMENTS.contains(tag);
+                for child in children.iter() {
+                    child.write_html(html, raw_text);
+                }
+                html.push_str("</");
+                html.push_str(tag);
+                html.push('>');
+            }
+        }
+    }
+}
+
+fn attrs_eq(attrs: &[(&str, &str)], other: &Attributes) -> bool {
+    attrs.len() == other.len()
+        && attrs
+            .iter()
+            .all(|(name, value)| other.get(*name).is_some_and(|other| other == value))
+}
+
+/**
+ * 再描画で作る一時的な木を確保するアリーナ
+ * 描画ごとに reset して使い回すことで、確保と解放のコストをまとめて省く
+ */
+#[derive(Default)]
+pub struct RenderArena {
+    bump: Bump,
+}
+
+impl RenderArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self {
+            bump: Bump::with_capacity(bytes),
+        }
+    }
+
+    pub fn text<'a>(&'a self, text: &str) -> ArenaNode<'a> {
+        ArenaNode::Text(self.bump.alloc_str(text))
+    }
+
+    pub fn comment<'a>(&'a self, text: &str) -> ArenaNode<'a> {
+        ArenaNode::Comment(self.bump.alloc_str(text))
+    }
+
+    /**
+     * タグ名、属性、子要素から要素を組み立てる関数
+     * 同じ名前の属性は後に指定したものを使う
+     * 属性は Attributes と同じく名前の順に並べて持つ
+     */
+    pub fn element<'a>(
+        &'a self,
+        tag: &str,
+        attrs: &[(&str, &str)],
+        children: impl IntoIterator<Item = ArenaNode<'a>, IntoIter: ExactSizeIterator>,
+    ) -> ArenaNode<'a> {
+        let mut unique: Vec<(&str, &str)> = Vec::with_capacity(attrs.len());
+        for (name, value) in attrs {
+            match unique.iter_mut().find(|(existing, _)| existing == name) {
+                Some(attr) => attr.1 = value,
+                None => unique.push((name, value)),
+            }
+        }
+        unique.sort_by_key(|(name, _)| *name);
+        let attrs = self.bump.alloc_slice_fill_iter(
+            unique
+                .into_iter()
+                .map(|(name, value)| (&*self.bump.alloc_str(name), &*self.bump.alloc_str(value))),
+        );
+        ArenaNode::Element(
+            self.bump.alloc_str(tag),
+            attrs,
+            self.bump.alloc_slice_fill_iter(children),
+        )
+    }
+
+    /**
+     * アリーナに確保したすべてのノードを解放し、確保した領域を次の描画で再利用する関数
+     */
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+
+    /**
+     * アリーナが確保している領域の大きさを返す関数
+     */
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+}
+
+/**
+ * 所有権を持つ古い木とアリーナに確保した新しい木の差分を取る関数
+ * 同じ内容の部分木は確保せずに読み飛ばし、異なる部分木だけを通常のノードに変換して比較する
+ * 結果は新しい木を通常のノードにして update_dom した場合と同じになる
+ */
+pub fn update_dom_arena(old: &VNode, new: &ArenaNode) -> AppResponse {
+    let options = DiffOptions::default();
+    let mut diff = Vec::new();
+    diff_arena(&old.element_type, new, &mut Vec::new(), &options, &mut diff);
+    sort_patches(&mut diff);
+
+    AppResponse {
+        diff,
+        html: new.to_html(),
+        request_id: None,
+    }
+}
+
+fn diff_arena(
+    old: &ElementType,
+    new: &ArenaNode,
+    path: &mut NodePath,
+    options: &DiffOptions,
+    diff: &mut Vec<Diff>,
+) {
+    if new.eq_element(old) {
+        return;
+    }
+    // 同じタグ・属性で key を持たない同じ数の子要素だけは、通常の差分と同じく位置で対応を取って読み進める
+    if let (
+        ElementType::Element(old_tag, old_attrs, old_children),
+        ArenaNode::Element(tag, attrs, children),
+    ) = (old, new)
+    {
+        let keyed = old_children
+            .iter()
+            .any(|child| matches!(child, ElementType::Element(_, attrs, _) if attrs.contains_key(KEY_ATTR)))
+            || children.iter().any(|child| {
+                matches!(child, ArenaNode::Element(_, attrs, _) if attrs.iter().any(|(name, _)| *name == KEY_ATTR))
+            });
+        if old_tag == tag
+            && attrs_eq(attrs, old_attrs)
+            && old_children.len() == children.len()
+            && !keyed
+        {
+            for (index, (old_child, child)) in old_children.iter().zip(children.iter()).enumerate()
+            {
+                path.push(index);
+                diff_arena(old_child, child, path, options, diff);
+                path.pop();
+            }
+            return;
+        }
+    }
+
+    // 置き換えや key による対応付けが必要な部分木は通常の差分に任せる
+    let new = new.to_element();
+    for explanation in explained_diff(old, &new, options) {
+        diff.push(match explanation.diff {
+            Diff::AddNode(sub_path, node) => {
+                Diff::AddNode([path.as_slice(), &sub_path].concat(), node)
+            }
+            Diff::RemoveNode(sub_path, node) => {
+                Diff::RemoveNode([path.as_slice(), &sub_path].concat(), node)
+            }
+            Diff::MoveNode(from, to, node) => Diff::MoveNode(
+                [path.as_slice(), &from].concat(),
+                [path.as_slice(), &to].concat(),
+                node,
+            ),
+            Diff::SetAttribute(sub_path, name, value) => {
+                Diff::SetAttribute([path.as_slice(), &sub_path].concat(), name, value)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::update_dom;
+
+    #[test]
+    fn test_update_dom_arena_matches_update_dom() {
+        let old = VNode {
+            element_type: element(
+                "div",
+                &[],
+                (
+                    element("h1", &[], "Title"),
+                    element("ul", &[], [element("li", &[("key", "a")], "A")]),
+                    element("p", &[("class", "x")], "old"),
+                ),
+            ),
+        };
+
+        let mut arena = RenderArena::new();
+        let items = [
+            arena.element("li", &[("key", "b")], [arena.text("B")]),
+            arena.element("li", &[("key", "a")], [arena.text("A")]),
+        ];
+        let new = arena.element(
+            "div",
+            &[],
+            [
+                arena.element("h1", &[], [arena.text("Title")]),
+                arena.element("ul", &[], items),
+                arena.element(
+                    "p",
+                    &[("title", "a & \"b\""), ("class", "y")],
+                    [arena.text("<new> & old")],
+                ),
+                arena.element("script", &[], [arena.text("if (a < b) {}")]),
+            ],
+        );
+
+        let expected = update_dom(
+            &old,
+            &VNode {
+                element_type: new.to_element(),
+            },
+        );
+        let actual = update_dom_arena(&old, &new);
+        assert_eq!(actual.diff, expected.diff);
+        assert_eq!(actual.html, expected.html);
+
+        assert!(arena.allocated_bytes() > 0);
+        arena.reset();
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use warp::reject::Reject;
+use warp::{Filter, Rejection};
+
+/**
+ * 認証トークンを送る Cookie 名
+ */
+pub const AUTH_COOKIE: &str = "vdom_token";
+
+/**
+ * 認証されたリクエストの送信者を表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Identity {
+    pub id: String,
+}
+
+impl Identity {
+    pub fn new(id: &str) -> Self {
+        Self { id: id.to_string() }
+    }
+
+    /**
+     * 認証を行わない場合に使う匿名の送信者を返す関数
+     */
+    pub fn anonymous() -> Self {
+        Self::new("anonymous")
+    }
+}
+
+/**
+ * 認証に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "authentication token is missing"),
+            AuthError::InvalidToken => write!(f, "authentication token is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl Reject for AuthError {}
+
+/**
+ * リクエストのトークンから送信者を特定する trait
+ */
+pub trait AuthProvider: Send + Sync {
+    fn authenticate(&self, token: Option<&str>) -> Result<Identity, AuthError>;
+}
+
+/**
+ * すべてのリクエストを匿名の送信者として許可する既定の認証
+ */
+pub struct AllowAll;
+
+impl AuthProvider for AllowAll {
+    fn authenticate(&self, _token: Option<&str>) -> Result<Identity, AuthError> {
+        Ok(Identity::anonymous())
+    }
+}
+
+/**
+ * 事前に登録したトークンだけを許可する認証
+ */
+#[derive(Default)]
+pub struct StaticTokens {
+    tokens: HashMap<String, Identity>,
+}
+
+impl StaticTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * トークンと送信者の組を登録する関数
+     */
+    pub fn with_token(mut self, token: &str, identity: Identity) -> Self {
+        self.tokens.insert(token.to_string(), identity);
+        self
+    }
+}
+
+impl AuthProvider for StaticTokens {
+    fn authenticate(&self, token: Option<&str>) -> Result<Identity, AuthError> {
+        let token = token.ok_or(AuthError::MissingToken)?;
+        self.tokens
+            .get(token)
+            .cloned()
+            .ok_or(AuthError::InvalidToken)
+    }
+}
+
+/**
+ * Authorization ヘッダーの Bearer トークン、なければ Cookie のトークンを返す関数
+ */
+pub fn request_token<'a>(
+    authorization: Option<&'a str>,
+    cookie: Option<&'a str>,
+) -> Option<&'a str> {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(cookie)
+}
+
+/**
+ * Cookie ヘッダーから指定した名前の値を取り出す関数
+ */
+pub fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
+/**
+ * Authorization ヘッダーの Bearer トークン、なければ Cookie のトークンで送信者を認証するフィルタ
+ */
+pub fn authenticate(
+    provider: Arc<dyn AuthProvider>,
+) -> impl Filter<Extract = (Identity,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::cookie::optional::<String>(AUTH_COOKIE))
+        .and_then(move |header: Option<String>, cookie: Option<String>| {
+            let provider = provider.clone();
+            async move {
+                provider
+                    .authenticate(request_token(header.as_deref(), cookie.as_deref()))
+                    .map_err(warp::reject::custom)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::recover_problem;
+    use warp::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let provider = StaticTokens::new().with_token("secret", Identity::new("alice"));
+        let route = warp::path("update_input")
+            .and(authenticate(Arc::new(provider)))
+            .map(|identity: Identity| identity.id)
+            .recover(|err| recover_problem(err, None));
+
+        let response = warp::test::request()
+            .path("/update_input")
+            .header("authorization", "Bearer secret")
+            .reply(&route)
+            .await;
+        assert_eq!(response.body(), "alice");
+
+        let response = warp::test::request()
+            .path("/update_input")
+            .header("cookie", format!("{}=secret", AUTH_COOKIE))
+            .reply(&route)
+            .await;
+        assert_eq!(response.body(), "alice");
+
+        let response = warp::test::request()
+            .path("/update_input")
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()["content-type"],
+            crate::error::PROBLEM_CONTENT_TYPE
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::error::{VdomError, PROBLEM_CONTENT_TYPE};
+use crate::handler::{HttpHandler, UpdateInputRequest};
+use crate::self_virtual_dom::AppResponse;
+
+/**
+ * 仮想DOMのエンドポイントを axum の Router として返す関数
+ * 匿名の送信者を接続元で区別するため、`into_make_service_with_connect_info::<SocketAddr>()` で起動する
+ */
+pub fn router(handler: Arc<HttpHandler>) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/run_app", get(run_app))
+        .route("/update_input", post(update_input))
+        .with_state(handler)
+}
+
+async fn index(State(handler): State<Arc<HttpHandler>>) -> Html<String> {
+    Html(handler.index())
+}
+
+async fn run_app(State(handler): State<Arc<HttpHandler>>) -> Json<AppResponse> {
+    Json(handler.run_app())
+}
+
+async fn update_input(
+    State(handler): State<Arc<HttpHandler>>,
+    remote: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<UpdateInputRequest>,
+) -> Response {
+    let header_value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let result = handler
+        .authenticate(
+            header_value(header::AUTHORIZATION),
+            header_value(header::COOKIE),
+        )
+        .and_then(|identity| {
+            let remote = remote.map(|ConnectInfo(addr)| addr.ip());
+            handler.update_input(&identity, remote, request)
+        });
+    match result {
+        Ok(app_response) => Json(app_response).into_response(),
+        Err(err) => problem_response(&handler, &err),
+    }
+}
+
+fn problem_response(handler: &HttpHandler, err: &VdomError) -> Response {
+    let status =
+        StatusCode::from_u16(err.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let problem = err.to_problem(Some(handler.store().version()));
+    (
+        status,
+        [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+        Json(problem),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+    use crate::auth::{Identity, StaticTokens};
+    use crate::middleware::MiddlewareChain;
+    use crate::store::Store;
+    use crate::template::TemplateSource;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower_service::Service;
+
+    #[tokio::test]
+    async fn test_update_input_authenticates() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_auth(Arc::new(
+            StaticTokens::new().with_token("secret", Identity::new("alice")),
+        ));
+        let mut app = router(Arc::new(handler));
+        let request = |authorization: &str| {
+            Request::post("/update_input")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::AUTHORIZATION, authorization)
+                .body(Body::from(r#"{"input": "Hi"}"#))
+                .unwrap()
+        };
+
+        let response = app.call(request("Bearer wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            PROBLEM_CONTENT_TYPE
+        );
+
+        let response = app.call(request("Bearer secret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
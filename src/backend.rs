@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::patch::apply_patches;
+use crate::store::{Snapshot, Store, Update};
+
+/**
+ * 状態の保存先の操作に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendError {
+    pub message: String,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "state backend error: {}", self.message)
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<std::io::Error> for BackendError {
+    fn from(err: std::io::Error) -> Self {
+        Self {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for BackendError {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            message: err.to_string(),
+        }
+    }
+}
+
+/**
+ * セッションごとの仮想DOMのスナップショットと差分の記録（ジャーナル）を保存する trait
+ */
+pub trait StateBackend: Send + Sync {
+    fn load_snapshot(&self, session: &str) -> Result<Option<Snapshot>, BackendError>;
+    fn save_snapshot(&self, session: &str, snapshot: &Snapshot) -> Result<(), BackendError>;
+    fn append_journal(&self, session: &str, update: &Update) -> Result<(), BackendError>;
+    fn load_journal(&self, session: &str) -> Result<Vec<Update>, BackendError>;
+    /** バージョンが up_to 以下の更新をジャーナルから取り除く */
+    fn truncate_journal(&self, session: &str, up_to: u64) -> Result<(), BackendError>;
+}
+
+/**
+ * 保存先からスナップショットを読み込み、それ以降のジャーナルを適用して最新の状態を復元する関数
+ * スナップショットが保存されていない場合は None を返す
+ */
+pub fn restore(
+    backend: &dyn StateBackend,
+    session: &str,
+) -> Result<Option<Snapshot>, BackendError> {
+    let Some(mut snapshot) = backend.load_snapshot(session)? else {
+        return Ok(None);
+    };
+    for update in backend.load_journal(session)? {
+        if update.version <= snapshot.version {
+            continue;
+        }
+        if update.version != snapshot.version + 1 {
+            return Err(BackendError {
+                message: format!(
+                    "journal skips from version {} to {}",
+                    snapshot.version, update.version
+                ),
+            });
+        }
+        apply_patches(&mut snapshot.tree.element_type, update.replayable_diff()).map_err(
+            |err| BackendError {
+                message: err.to_string(),
+            },
+        )?;
+        snapshot.version = update.version;
+    }
+    Ok(Some(snapshot))
+}
+
+/**
+ * ジャーナルを圧縮する間隔と、圧縮後も残す件数の設定
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    /** この件数の更新を追記するたびにスナップショットを保存する */
+    pub every: u64,
+    /** スナップショットより前の更新のうちジャーナルに残す件数 */
+    pub retain: u64,
+}
+
+impl Default for Compaction {
+    fn default() -> Self {
+        Self {
+            every: 100,
+            retain: 0,
+        }
+    }
+}
+
+/**
+ * ストアの現在の状態を保存し、以降の更新をジャーナルに追記し続けるタスクを起動する関数
+ * 更新を取りこぼした場合はスナップショットを保存し直す
+ */
+pub fn persist(
+    store: Arc<Store>,
+    backend: Arc<dyn StateBackend>,
+    session: String,
+) -> JoinHandle<()> {
+    persist_with_compaction(store, backend, session, Compaction::default())
+}
+
+/**
+ * persist と同じく状態を保存し続け、compaction.every 件の更新ごとにスナップショットを保存してジャーナルを切り詰める関数
+ * 長く続くセッションでも復元時に適用する差分と保存先の容量が一定に収まる
+ */
+pub fn persist_with_compaction(
+    store: Arc<Store>,
+    backend: Arc<dyn StateBackend>,
+    session: String,
+    compaction: Compaction,
+) -> JoinHandle<()> {
+    let mut receiver = store.subscribe();
+    // タスクがストアを保持し続けると更新のチャネルが閉じないため、弱い参照で持つ
+    let weak_store = Arc::downgrade(&store);
+    let initial = store.snapshot();
+
+    tokio::spawn(async move {
+        let mut appended = 0;
+        // 次に保存するスナップショット。Some(None) の場合は保存済みのスナップショットとジャーナルから作る
+        let mut pending = Some(Some(initial));
+        loop {
+            // 保存先への書き込みはブロックするため、非同期のワーカーを止めないよう別のスレッドで行う
+            if let Some(snapshot) = pending.take() {
+                let (backend, session) = (backend.clone(), session.clone());
+                let result = tokio::task::spawn_blocking(move || {
+                    let snapshot = match snapshot {
+                        Some(snapshot) => Ok(Some(snapshot)),
+                        None => restore(backend.as_ref(), &session),
+                    };
+                    match snapshot {
+                        Ok(Some(snapshot)) => {
+                            save_snapshot(backend.as_ref(), &session, &snapshot, compaction)
+                        }
+                        Ok(None) => {}
+                        Err(err) => eprintln!("Failed to compact {:?}: {}", session, err),
+                    }
+                })
+                .await;
+                if result.is_err() {
+                    break;
+                }
+                appended = 0;
+            }
+
+            pending = match receiver.recv().await {
+                Ok(update) => {
+                    let (backend, session) = (backend.clone(), session.clone());
+                    let result = tokio::task::spawn_blocking(move || {
+                        if let Err(err) = backend.append_journal(&session, &update) {
+                            eprintln!("Failed to append journal of {:?}: {}", session, err);
+                        }
+                    })
+                    .await;
+                    if result.is_err() {
+                        break;
+                    }
+                    appended += 1;
+                    // ストアの状態は追記済みの更新より進んでいることがあるため、ジャーナルから作る
+                    (appended >= compaction.every).then_some(None)
+                }
+                // 取りこぼした更新はジャーナルから復元できないため、ストアの現在の状態を保存する
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    weak_store.upgrade().map(|store| Some(store.snapshot()))
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+        }
+    })
+}
+
+/**
+ * スナップショットを保存し、compaction.retain 件を残してそれより前のジャーナルを切り詰める関数
+ */
+fn save_snapshot(
+    backend: &dyn StateBackend,
+    session: &str,
+    snapshot: &Snapshot,
+    compaction: Compaction,
+) {
+    let result = backend.save_snapshot(session, snapshot).and_then(|()| {
+        backend.truncate_journal(session, snapshot.version.saturating_sub(compaction.retain))
+    });
+    if let Err(err) = result {
+        eprintln!("Failed to save snapshot of {:?}: {}", session, err);
+    }
+}
+
+#[derive(Default)]
+struct SessionState {
+    snapshot: Option<Snapshot>,
+    journal: Vec<Update>,
+}
+
+/**
+ * プロセス内のメモリに状態を保存する保存先
+ */
+#[derive(Default)]
+pub struct MemoryBackend {
+    sessions: Mutex<HashMap<String, SessionState>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateBackend for MemoryBackend {
+    fn load_snapshot(&self, session: &str) -> Result<Option<Snapshot>, BackendError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .get(session)
+            .and_then(|state| state.snapshot.clone()))
+    }
+
+    fn save_snapshot(&self, session: &str, snapshot: &Snapshot) -> Result<(), BackendError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.entry(session.to_string()).or_default().snapshot = Some(snapshot.clone());
+        Ok(())
+    }
+
+    fn append_journal(&self, session: &str, update: &Update) -> Result<(), BackendError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions
+            .entry(session.to_string())
+            .or_default()
+            .journal
+            .push(update.clone());
+        Ok(())
+    }
+
+    fn load_journal(&self, session: &str) -> Result<Vec<Update>, BackendError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .get(session)
+            .map(|state| state.journal.clone())
+            .unwrap_or_default())
+    }
+
+    fn truncate_journal(&self, session: &str, up_to: u64) -> Result<(), BackendError> {
+        if let Some(state) = self.sessions.lock().unwrap().get_mut(session) {
+            state.journal.retain(|update| update.version > up_to);
+        }
+        Ok(())
+    }
+}
+
+/**
+ * ディレクトリ配下のファイルに状態を保存する保存先
+ * スナップショットは `<session>.snapshot.json`、ジャーナルは1行1更新の `<session>.journal.jsonl` に書き込む
+ */
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /**
+     * セッションのファイルのパスを返す関数
+     * セッション名はクライアントから渡されることがあるため、dir の外を指せないよう英数字と `-` `_` だけを受け付ける
+     */
+    fn path(&self, session: &str, suffix: &str) -> Result<PathBuf, BackendError> {
+        let valid = !session.is_empty()
+            && session
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(BackendError {
+                message: format!("invalid session name {:?}", session),
+            });
+        }
+        Ok(self.dir.join(format!("{}.{}", session, suffix)))
+    }
+}
+
+impl StateBackend for FileBackend {
+    fn load_snapshot(&self, session: &str) -> Result<Option<Snapshot>, BackendError> {
+        match std::fs::read_to_string(self.path(session, "snapshot.json")?) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save_snapshot(&self, session: &str, snapshot: &Snapshot) -> Result<(), BackendError> {
+        std::fs::create_dir_all(&self.dir)?;
+        // 書き込み途中で停止しても前のスナップショットが壊れないよう、一時ファイルから置き換える
+        let temporary = self.path(session, "snapshot.json.tmp")?;
+        std::fs::write(&temporary, serde_json::to_vec(snapshot)?)?;
+        std::fs::rename(temporary, self.path(session, "snapshot.json")?)?;
+        Ok(())
+    }
+
+    fn append_journal(&self, session: &str, update: &Update) -> Result<(), BackendError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(session, "journal.jsonl")?)?;
+        writeln!(file, "{}", serde_json::to_string(update)?)?;
+        Ok(())
+    }
+
+    fn load_journal(&self, session: &str) -> Result<Vec<Update>, BackendError> {
+        match std::fs::read_to_string(self.path(session, "journal.jsonl")?) {
+            Ok(lines) => lines
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| Ok(serde_json::from_str(line)?))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn truncate_journal(&self, session: &str, up_to: u64) -> Result<(), BackendError> {
+        let mut lines = String::new();
+        for update in self.load_journal(session)? {
+            if update.version > up_to {
+                lines.push_str(&serde_json::to_string(&update)?);
+                lines.push('\n');
+            }
+        }
+        let temporary = self.path(session, "journal.jsonl.tmp")?;
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(&temporary, lines)?;
+        std::fs::rename(temporary, self.path(session, "journal.jsonl")?)?;
+        Ok(())
+    }
+}
+
+/**
+ * Redis に状態を保存する保存先
+ * スナップショットは `vdom:<session>:snapshot` の文字列、ジャーナルは `vdom:<session>:journal` のリストに書き込む
+ */
+#[cfg(feature = "redis")]
+pub struct RedisBackend {
+    client: redis::Client,
+}
+
+#[cfg(feature = "redis")]
+impl RedisBackend {
+    pub fn new(url: &str) -> Result<Self, BackendError> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for BackendError {
+    fn from(err: redis::RedisError) -> Self {
+        Self {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+impl StateBackend for RedisBackend {
+    fn load_snapshot(&self, session: &str) -> Result<Option<Snapshot>, BackendError> {
+        use redis::Commands;
+
+        let json: Option<String> = self
+            .client
+            .get_connection()?
+            .get(format!("vdom:{}:snapshot", session))?;
+        json.map(|json| Ok(serde_json::from_str(&json)?))
+            .transpose()
+    }
+
+    fn save_snapshot(&self, session: &str, snapshot: &Snapshot) -> Result<(), BackendError> {
+        use redis::Commands;
+
+        self.client.get_connection()?.set::<_, _, ()>(
+            format!("vdom:{}:snapshot", session),
+            serde_json::to_string(snapshot)?,
+        )?;
+        Ok(())
+    }
+
+    fn append_journal(&self, session: &str, update: &Update) -> Result<(), BackendError> {
+        use redis::Commands;
+
+        self.client.get_connection()?.rpush::<_, _, ()>(
+            format!("vdom:{}:journal", session),
+            serde_json::to_string(update)?,
+        )?;
+        Ok(())
+    }
+
+    fn load_journal(&self, session: &str) -> Result<Vec<Update>, BackendError> {
+        use redis::Commands;
+
+        let lines: Vec<String> =
+            self.client
+                .get_connection()?
+                .lrange(format!("vdom:{}:journal", session), 0, -1)?;
+        lines
+            .iter()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    fn truncate_journal(&self, session: &str, up_to: u64) -> Result<(), BackendError> {
+        use redis::Commands;
+
+        // ジャーナルはバージョン順に並んでいるため、先頭から取り除く件数を数えて LTRIM する
+        let removed = self
+            .load_journal(session)?
+            .iter()
+            .take_while(|update| update.version <= up_to)
+            .count();
+        self.client.get_connection()?.ltrim::<_, ()>(
+            format!("vdom:{}:journal", session),
+            removed as isize,
+            -1,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+    use crate::self_virtual_dom::{Attributes, ElementType, VNode};
+
+    #[tokio::test]
+    async fn test_persist_and_restore() {
+        let dir = std::env::temp_dir().join(format!("vdom-backend-{}", std::process::id()));
+        let backend: Arc<dyn StateBackend> = Arc::new(FileBackend::new(dir.clone()));
+        let store = Arc::new(Store::new(initial_tree()));
+        let handle = persist(store.clone(), backend.clone(), "session-1".to_string());
+
+        for input in ["a", "ab", "abc"] {
+            store.update(render_input(input));
+        }
+        drop(store);
+        handle.await.unwrap();
+
+        let restored = restore(backend.as_ref(), "session-1").unwrap().unwrap();
+        assert_eq!(restored.version, 3);
+        assert_eq!(restored.tree.to_string(), "<div >abc</div>");
+        assert!(restore(backend.as_ref(), "unknown").unwrap().is_none());
+        assert!(backend.load_snapshot("../session-1").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_empty_text() {
+        let backend = Arc::new(MemoryBackend::new());
+        let div = |children: Vec<ElementType>| VNode {
+            element_type: ElementType::Element("div".to_string(), Attributes::new(), children),
+        };
+        let text = |text: &'static str| ElementType::Text(text.into());
+        let store = Arc::new(Store::new(div(vec![ElementType::Element(
+            "p".to_string(),
+            Attributes::new(),
+            vec![text("x")],
+        )])));
+        let handle = persist(store.clone(), backend.clone(), "session-1".to_string());
+
+        // 空のテキストノードは描画されないため配信する差分には含まれないが、ジャーナルからは復元できる
+        store.update(div(vec![text("")]));
+        store.update(div(vec![text(""), text("y")]));
+        let expected = store.snapshot().tree;
+        drop(store);
+        handle.await.unwrap();
+
+        let restored = restore(backend.as_ref(), "session-1").unwrap().unwrap();
+        assert_eq!(restored.version, 2);
+        assert_eq!(restored.tree, expected);
+    }
+
+    #[tokio::test]
+    async fn test_journal_compaction() {
+        let backend = Arc::new(MemoryBackend::new());
+        let store = Arc::new(Store::new(initial_tree()));
+        let handle = persist_with_compaction(
+            store.clone(),
+            backend.clone(),
+            "session-1".to_string(),
+            Compaction {
+                every: 2,
+                retain: 1,
+            },
+        );
+
+        for input in ["a", "b", "c", "d", "e"] {
+            store.update(render_input(input));
+            tokio::task::yield_now().await;
+        }
+        drop(store);
+        handle.await.unwrap();
+
+        let snapshot = backend.load_snapshot("session-1").unwrap().unwrap();
+        let journal = backend.load_journal("session-1").unwrap();
+        assert!(journal.len() <= 3);
+        assert!(journal
+            .iter()
+            .all(|update| update.version + 1 >= snapshot.version));
+        let restored = restore(backend.as_ref(), "session-1").unwrap().unwrap();
+        assert_eq!(restored.version, 5);
+        assert_eq!(restored.tree.to_string(), "<div >e</div>");
+    }
+}
@@ -0,0 +1,500 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{AppResponse, Diff, ElementType, Text, VNode};
+use crate::store::Store;
+
+/**
+ * バインディングを表す属性名の接頭辞。`bind:value="state.query"` のように書く
+ */
+pub const BIND_PREFIX: &str = "bind:";
+
+/**
+ * バインディングの式の先頭に置く状態の名前
+ */
+pub const STATE_ROOT: &str = "state";
+
+/**
+ * 要素の内容をテキストとしてバインドする特別なプロパティ名
+ */
+pub const TEXT_PROPERTY: &str = "text";
+
+/**
+ * チェックボックスの状態をバインドするプロパティ名。状態が true のときだけ checked 属性を付ける
+ */
+pub const CHECKED_PROPERTY: &str = "checked";
+
+/**
+ * バインディングの式を解釈・評価できなかったことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingError {
+    pub expression: String,
+    pub message: String,
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid binding {:?}: {}", self.expression, self.message)
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+/**
+ * 状態の中の位置を表すパスの1要素
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+/**
+ * `state.items[0].name` のような式を解釈した状態の中の位置
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatePath {
+    pub segments: Vec<Segment>,
+}
+
+impl StatePath {
+    /**
+     * 式を解釈する関数
+     * 式は state から始まり、`.名前` でフィールドを、`[数値]` で配列の要素を辿る
+     */
+    pub fn parse(expression: &str) -> Result<Self, BindingError> {
+        let error = |message: &str| BindingError {
+            expression: expression.to_string(),
+            message: message.to_string(),
+        };
+        let rest = expression
+            .trim()
+            .strip_prefix(STATE_ROOT)
+            .ok_or_else(|| error("expression must start with `state`"))?;
+
+        let mut segments = Vec::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '.' => {
+                    let mut name = String::new();
+                    while let Some(&ch) = chars.peek() {
+                        if !(ch.is_alphanumeric() || ch == '_' || ch == '-') {
+                            break;
+                        }
+                        name.push(ch);
+                        chars.next();
+                    }
+                    if name.is_empty() {
+                        return Err(error("expected a field name after `.`"));
+                    }
+                    segments.push(Segment::Field(name));
+                }
+                '[' => {
+                    let mut digits = String::new();
+                    let mut closed = false;
+                    for ch in chars.by_ref() {
+                        if ch == ']' {
+                            closed = true;
+                            break;
+                        }
+                        digits.push(ch);
+                    }
+                    if !closed {
+                        return Err(error("missing `]`"));
+                    }
+                    let index = digits
+                        .trim()
+                        .parse()
+                        .map_err(|_| error("expected an index inside `[]`"))?;
+                    segments.push(Segment::Index(index));
+                }
+                _ => return Err(error("expected `.` or `[`")),
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    /**
+     * 状態からパスの位置の値を取得する関数
+     */
+    pub fn get<'a>(&self, state: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(state, |value, segment| match segment {
+                Segment::Field(name) => value.get(name),
+                Segment::Index(index) => value.get(index),
+            })
+    }
+
+    /**
+     * 状態のパスの位置に値を書き込む関数
+     * 途中のフィールドがなければオブジェクトを作るが、配列の範囲外には書き込めない
+     */
+    pub fn set(&self, state: &mut Value, value: Value) -> Result<(), BindingError> {
+        let mut current = state;
+        for segment in &self.segments {
+            current = match segment {
+                Segment::Field(name) => {
+                    if current.is_null() {
+                        *current = Value::Object(Default::default());
+                    }
+                    let Value::Object(map) = current else {
+                        return Err(self.error("cannot set a field on a non-object"));
+                    };
+                    map.entry(name.clone()).or_insert(Value::Null)
+                }
+                Segment::Index(index) => current
+                    .get_mut(index)
+                    .ok_or_else(|| self.error("index is out of range"))?,
+            };
+        }
+        *current = value;
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> BindingError {
+        BindingError {
+            expression: self.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StatePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", STATE_ROOT)?;
+        for segment in &self.segments {
+            match segment {
+                Segment::Field(name) => write!(f, ".{}", name)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * ノードの1つのプロパティと状態の位置の結び付き
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub property: String,
+    pub path: StatePath,
+}
+
+/**
+ * 木の中のバインディングをパス付きで集める関数
+ */
+pub fn bindings(tree: &ElementType) -> Result<Vec<(NodePath, Binding)>, BindingError> {
+    let mut found = Vec::new();
+    for (path, node) in tree.iter_with_paths() {
+        let ElementType::Element(_, attrs, _) = node else {
+            continue;
+        };
+        let mut bound: Vec<(&Text, &Text)> = attrs
+            .iter()
+            .filter(|(name, _)| name.starts_with(BIND_PREFIX))
+            .collect();
+        bound.sort();
+        for (name, expression) in bound {
+            found.push((
+                path.clone(),
+                Binding {
+                    property: name[BIND_PREFIX.len()..].to_string(),
+                    path: StatePath::parse(expression)?,
+                },
+            ));
+        }
+    }
+    Ok(found)
+}
+
+/**
+ * バインディングを状態の値で解決した木を返す関数
+ * `bind:<プロパティ>` の値を同名の属性に、`bind:text` の値を要素の内容に書き込む
+ * `bind:checked` は値が true のときだけ checked 属性を付け、select の `bind:value` は値の一致する option を選択する
+ * 入力のプロパティの bind: 属性はクライアントが変更を送り返せるように残し、
+ * クライアントから変更されることのない bind:text は取り除く
+ */
+pub fn resolve_bindings(tree: &ElementType, state: &Value) -> Result<ElementType, BindingError> {
+    let mut resolved = tree.clone();
+    resolve_node(&mut resolved, state)?;
+    Ok(resolved)
+}
+
+fn resolve_node(node: &mut ElementType, state: &Value) -> Result<(), BindingError> {
+    let ElementType::Element(tag, attrs, children) = node else {
+        return Ok(());
+    };
+    let bound: Vec<(String, Text)> = attrs
+        .iter()
+        .filter_map(|(name, expression)| {
+            let property = name.strip_prefix(BIND_PREFIX)?;
+            Some((property.to_string(), expression.clone()))
+        })
+        .collect();
+    for (property, expression) in bound {
+        let path = StatePath::parse(&expression)?;
+        if property == CHECKED_PROPERTY {
+            // checked は値に関係なく有効になる真偽値の属性のため、true のときだけ付ける
+            if matches!(path.get(state), Some(Value::Bool(true))) {
+                attrs.insert(CHECKED_PROPERTY.into(), "".into());
+            } else {
+                attrs.remove(CHECKED_PROPERTY);
+            }
+            continue;
+        }
+        let value = path.get(state).map(display_value).unwrap_or_default();
+        if property == TEXT_PROPERTY {
+            attrs.remove(format!("{}{}", BIND_PREFIX, TEXT_PROPERTY).as_str());
+            *children = vec![ElementType::Text(value.into())];
+        } else if property == "value" && tag == "select" {
+            // select の value 属性は無視されるため、値の一致する option を選択する
+            select_option(children, &value);
+        } else {
+            attrs.insert(property.into(), value.into());
+        }
+    }
+    for child in children {
+        resolve_node(child, state)?;
+    }
+    Ok(())
+}
+
+fn select_option(options: &mut [ElementType], value: &str) {
+    for option in options {
+        if let ElementType::Element(_, attrs, _) = option {
+            if attrs.get("value").is_some_and(|option| option == value) {
+                attrs.insert("selected".into(), "".into());
+            } else {
+                attrs.remove("selected");
+            }
+        }
+    }
+}
+
+/**
+ * 状態の値を属性やテキストに書き込む文字列にする関数
+ * 文字列はそのまま、null は空文字列、それ以外は JSON にする
+ */
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/**
+ * クライアントが値を変更できる入力欄の要素
+ */
+pub const INPUT_ELEMENTS: [&str; 3] = ["input", "textarea", "select"];
+
+/**
+ * クライアントが変更を送り返せる入力欄のプロパティ
+ */
+pub const INPUT_PROPERTIES: [&str; 2] = ["value", "checked"];
+
+/**
+ * クライアントから送られる、バインドされた入力の変更
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindingChange {
+    pub path: String,
+    pub value: Value,
+}
+
+/**
+ * クライアントからの変更を、木のいずれかの入力欄の値にバインドされている状態の位置に書き込む関数
+ * バインドされていない位置や、bind:text などクライアントが変更しないプロパティにだけバインドされた位置への変更は拒否する
+ */
+pub fn route_change(
+    tree: &ElementType,
+    state: &mut Value,
+    change: &BindingChange,
+) -> Result<StatePath, BindingError> {
+    let path = StatePath::parse(&change.path)?;
+    let writable = bindings(tree)?.iter().any(|(node_path, binding)| {
+        binding.path == path
+            && INPUT_PROPERTIES.contains(&binding.property.as_str())
+            && matches!(
+                tree.get(node_path),
+                Some(ElementType::Element(tag, _, _)) if INPUT_ELEMENTS.contains(&tag.as_str())
+            )
+    });
+    if !writable {
+        return Err(path.error("path is not bound to any input value"));
+    }
+    path.set(state, change.value.clone())?;
+    Ok(path)
+}
+
+/**
+ * バインディングを含むテンプレートと、それにバインドされた状態の組
+ * クライアントからの変更を状態に書き込み、テンプレートを描画し直してストアに反映する
+ */
+pub struct BoundView {
+    template: ElementType,
+    state: Mutex<Value>,
+    /** 仮想DOMの外に置かれた入力欄がバインドする状態の位置 */
+    external_inputs: Vec<String>,
+}
+
+impl BoundView {
+    pub fn new(template: ElementType, state: Value) -> Self {
+        Self {
+            template,
+            state: Mutex::new(state),
+            external_inputs: Vec::new(),
+        }
+    }
+
+    /**
+     * ページのテンプレートなど、仮想DOMの外に置かれた入力欄がバインドする状態の位置を登録する関数
+     * 登録した位置は、テンプレートの入力欄にバインドされた位置と同じくクライアントから書き込める
+     */
+    pub fn with_external_input(mut self, path: &str) -> Self {
+        self.external_inputs.push(path.to_string());
+        self
+    }
+
+    /**
+     * バインディングを含むテンプレートを返す関数
+     */
+    pub fn template(&self) -> &ElementType {
+        &self.template
+    }
+
+    /**
+     * 現在の状態を返す関数
+     */
+    pub fn state(&self) -> Value {
+        self.state.lock().unwrap().clone()
+    }
+
+    /**
+     * 現在の状態でテンプレートを描画する関数
+     */
+    pub fn render(&self) -> Result<VNode, BindingError> {
+        Ok(VNode {
+            element_type: resolve_bindings(&self.template, &self.state.lock().unwrap())?,
+        })
+    }
+
+    /**
+     * クライアントからの変更を状態に書き込み、描画し直した木をストアに反映して差分を返す関数
+     * check が差分を拒否した場合は状態もストアも更新しない
+     */
+    pub fn apply<E: From<BindingError>>(
+        &self,
+        store: &Store,
+        change: &BindingChange,
+        check: impl FnOnce(&VNode, &VNode, &[Diff]) -> Result<(), E>,
+    ) -> Result<AppResponse, E> {
+        // 状態の更新とストアへの反映の間に他の変更が割り込まないようにロックを持ち続ける
+        let mut state = self.state.lock().unwrap();
+        let mut next = state.clone();
+        let path = StatePath::parse(&change.path)?;
+        let external = self
+            .external_inputs
+            .iter()
+            .any(|input| StatePath::parse(input).is_ok_and(|input| input == path));
+        if external {
+            path.set(&mut next, change.value.clone())?;
+        } else {
+            route_change(&self.template, &mut next, change)?;
+        }
+        let tree = VNode {
+            element_type: resolve_bindings(&self.template, &next)?,
+        };
+        let app_response = store.try_update(tree, check)?;
+        *state = next;
+        Ok(app_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use serde_json::json;
+
+    #[test]
+    fn test_state_path() {
+        let path = StatePath::parse("state.items[1].name").unwrap();
+        assert_eq!(path.to_string(), "state.items[1].name");
+
+        let mut state = json!({ "items": [{ "name": "a" }, { "name": "b" }] });
+        assert_eq!(path.get(&state), Some(&json!("b")));
+        path.set(&mut state, json!("c")).unwrap();
+        assert_eq!(state["items"][1]["name"], "c");
+
+        assert!(StatePath::parse("items.name").is_err());
+        assert!(StatePath::parse("state..name").is_err());
+        assert!(StatePath::parse("state.items[1").is_err());
+        assert!(StatePath::parse("state.items[9]")
+            .unwrap()
+            .set(&mut state, json!(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_and_route_change() {
+        let tree = element(
+            "form",
+            &[],
+            [
+                element("input", &[("bind:value", "state.query")], ()),
+                element("p", &[("bind:text", "state.query")], ()),
+                element("h1", &[("bind:text", "state.title")], ()),
+            ],
+        );
+        let mut state = json!({ "query": "rust" });
+        assert_eq!(
+            resolve_bindings(&tree, &state).unwrap(),
+            element(
+                "form",
+                &[],
+                [
+                    element(
+                        "input",
+                        &[("bind:value", "state.query"), ("value", "rust")],
+                        ()
+                    ),
+                    element("p", &[], "rust"),
+                    element("h1", &[], ""),
+                ],
+            )
+        );
+
+        let change = BindingChange {
+            path: "state.query".to_string(),
+            value: json!("wasm"),
+        };
+        route_change(&tree, &mut state, &change).unwrap();
+        assert_eq!(state, json!({ "query": "wasm" }));
+
+        let unbound = BindingChange {
+            path: "state.admin".to_string(),
+            value: json!(true),
+        };
+        assert!(route_change(&tree, &mut state, &unbound).is_err());
+
+        // テキストにだけバインドされた位置はクライアントから書き込めない
+        let text_only = BindingChange {
+            path: "state.title".to_string(),
+            value: json!("<img src=x onerror=alert(1)>"),
+        };
+        assert!(route_change(&tree, &mut state, &text_only).is_err());
+        assert_eq!(
+            element("p", &[], "<b>&</b>").to_string(),
+            "<p >&lt;b&gt;&amp;&lt;/b&gt;</p>"
+        );
+    }
+}
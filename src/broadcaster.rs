@@ -0,0 +1,511 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::iter::NodePath;
+use crate::middleware::MiddlewareChain;
+use crate::selector::Selector;
+use crate::self_virtual_dom::{filter_diff, virtual_dom_to_html, Diff, ElementType};
+use crate::squash::squash;
+use crate::store::Store;
+use crate::ws::ServerMessage;
+
+/**
+ * 購読者ごとのキューに溜められるメッセージの既定の件数
+ */
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 32;
+
+/**
+ * キューが溢れたときにまとめた差分として保持できる既定の件数
+ * これを超える場合は全体の再同期に切り替える
+ */
+pub const DEFAULT_MAX_COALESCED_PATCHES: usize = 256;
+
+/**
+ * 購読者を識別するID
+ */
+pub type SubscriberId = u64;
+
+/**
+ * 購読者が受け取りたい差分の範囲を表す列挙型
+ */
+#[derive(Debug, Clone)]
+pub enum Topic {
+    /** すべての差分 */
+    All,
+    /** 指定したパス配下の差分（パスは指定したパスからの相対パスに変換される） */
+    Root(NodePath),
+    /** セレクタに一致するノード配下の差分 */
+    Selector(Selector),
+}
+
+impl Topic {
+    /**
+     * 差分が購読範囲に関係するかを判定する関数
+     * 購読範囲の中の変更と、購読範囲を含む祖先の置き換えを関係ありとみなす
+     */
+    pub fn is_relevant(&self, tree: &ElementType, patch: &Diff) -> bool {
+        let overlaps = |root: &NodePath| {
+            let path = patch.path();
+            path.starts_with(root) || root.starts_with(path)
+        };
+        match self {
+            Topic::All => true,
+            Topic::Root(root) => overlaps(root),
+            Topic::Selector(selector) => {
+                selector.select(tree).iter().any(|(root, _)| overlaps(root))
+            }
+        }
+    }
+
+    /**
+     * 再同期で送り直す部分木を返す関数
+     * Root の差分は購読したパスからの相対パスで届くため、そのパスの部分木だけを送る
+     * All と Selector の差分は木全体のパスのままなので、木全体を送る
+     */
+    pub fn resync_root<'a>(&self, tree: &'a ElementType) -> Option<&'a ElementType> {
+        match self {
+            Topic::Root(root) => tree.get(root),
+            Topic::All | Topic::Selector(_) => Some(tree),
+        }
+    }
+
+    /**
+     * 差分から購読範囲に関係するものだけを取り出す関数
+     */
+    pub fn patches_for(&self, tree: &ElementType, patches: &[Diff]) -> Vec<Diff> {
+        match self {
+            Topic::Root(root) => filter_diff(patches, root),
+            _ => patches
+                .iter()
+                .filter(|patch| self.is_relevant(tree, patch))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/**
+ * 購読者ごとの有限のメッセージキュー
+ * 溢れた場合は溜まっている差分を squash でまとめ、それでも大きすぎる場合は全体の再同期に置き換える
+ */
+pub struct ClientQueue {
+    capacity: usize,
+    max_coalesced_patches: usize,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<ServerMessage>,
+    closed: bool,
+}
+
+impl ClientQueue {
+    pub fn new(capacity: usize, max_coalesced_patches: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            max_coalesced_patches,
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /**
+     * メッセージをキューに追加する関数
+     * 溢れた場合は差分をまとめ、まとめきれなければ resync が返すメッセージに置き換える
+     */
+    pub fn push(&self, message: ServerMessage, resync: impl FnOnce() -> ServerMessage) {
+        let mut state = self.state.lock().unwrap();
+        state.messages.push_back(message);
+
+        if state.messages.len() > self.capacity {
+            let mut others = VecDeque::new();
+            let mut patches = Vec::new();
+            let mut latest = None;
+            // まとめた差分のリクエストIDは、すべて同じリクエストによるものの場合だけ残す
+            let mut request_ids: Option<Option<String>> = None;
+            for message in state.messages.drain(..) {
+                match message {
+                    ServerMessage::Patch {
+                        version,
+                        diff,
+                        request_id,
+                    } => {
+                        patches.extend(diff);
+                        latest = Some(version);
+                        request_ids = match request_ids {
+                            Some(previous) if previous != request_id => Some(None),
+                            _ => Some(request_id),
+                        };
+                    }
+                    ServerMessage::Resync { .. } => {
+                        patches.clear();
+                        others.retain(|message| !matches!(message, ServerMessage::Resync { .. }));
+                        others.push_back(message);
+                    }
+                    message => others.push_back(message),
+                }
+            }
+
+            let patches = squash(patches);
+            if let Some(version) = latest {
+                if patches.len() > self.max_coalesced_patches || others.len() >= self.capacity {
+                    others.retain(|message| !matches!(message, ServerMessage::Resync { .. }));
+                    others.push_back(resync());
+                } else {
+                    others.push_back(ServerMessage::Patch {
+                        version,
+                        diff: patches,
+                        request_id: request_ids.flatten(),
+                    });
+                }
+            }
+            state.messages = others;
+        }
+
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /**
+     * 先頭のメッセージを待たずに取り出す関数
+     */
+    pub fn try_recv(&self) -> Option<ServerMessage> {
+        self.state.lock().unwrap().messages.pop_front()
+    }
+
+    /**
+     * メッセージが届くまで待機して取り出す関数
+     */
+    pub async fn recv(&self) -> ServerMessage {
+        loop {
+            let notified = self.notify.notified();
+            if let Some(message) = self.try_recv() {
+                return message;
+            }
+            notified.await;
+        }
+    }
+
+    /**
+     * キューに溜まっているメッセージの件数を取得する関数
+     */
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+}
+
+/**
+ * 購読者の受信側
+ * 破棄されると購読は次回の配信時に解除される
+ */
+pub struct Subscription {
+    pub id: SubscriberId,
+    pub queue: Arc<ClientQueue>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().closed = true;
+    }
+}
+
+struct Subscriber {
+    topic: Topic,
+    queue: Arc<ClientQueue>,
+}
+
+/**
+ * 購読範囲ごとに関係する差分だけを各購読者のキューへ配信する構造体
+ */
+pub struct Broadcaster {
+    capacity: usize,
+    max_coalesced_patches: usize,
+    next_id: AtomicU64,
+    subscribers: Mutex<HashMap<SubscriberId, Subscriber>>,
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBSCRIBER_CAPACITY)
+    }
+}
+
+impl Broadcaster {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_max_coalesced_patches(capacity, DEFAULT_MAX_COALESCED_PATCHES)
+    }
+
+    /**
+     * キューが溢れたときにまとめて保持できる差分の件数を指定して生成する関数
+     */
+    pub fn with_max_coalesced_patches(capacity: usize, max_coalesced_patches: usize) -> Self {
+        Self {
+            capacity,
+            max_coalesced_patches,
+            next_id: AtomicU64::new(0),
+            subscribers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /**
+     * 購読範囲を指定して購読を開始する関数
+     */
+    pub fn subscribe(&self, topic: Topic) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let queue = Arc::new(ClientQueue::new(self.capacity, self.max_coalesced_patches));
+        self.subscribers.lock().unwrap().insert(
+            id,
+            Subscriber {
+                topic,
+                queue: queue.clone(),
+            },
+        );
+        Subscription { id, queue }
+    }
+
+    /**
+     * 購読を解除する関数
+     */
+    pub fn unsubscribe(&self, id: SubscriberId) {
+        self.subscribers.lock().unwrap().remove(&id);
+    }
+
+    /**
+     * 現在の購読者数を取得する関数
+     */
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /**
+     * 各購読者へ購読範囲に関係する差分だけを配信し、配信した購読者数を返す関数
+     * request_id は差分を発生させたリクエストのIDで、メッセージにそのまま付与する
+     */
+    pub fn publish(
+        &self,
+        tree: &ElementType,
+        version: u64,
+        patches: &[Diff],
+        request_id: Option<&str>,
+    ) -> usize {
+        self.publish_with(tree, version, patches, request_id, virtual_dom_to_html)
+    }
+
+    /**
+     * publish と同じく配信し、キューが溢れた購読者へ送り直すHTMLを render で描画する関数
+     */
+    pub fn publish_with(
+        &self,
+        tree: &ElementType,
+        version: u64,
+        patches: &[Diff],
+        request_id: Option<&str>,
+        render: impl Fn(&ElementType) -> String,
+    ) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|_, subscriber| !subscriber.queue.is_closed());
+
+        let mut delivered = 0;
+        for subscriber in subscribers.values() {
+            let relevant = subscriber.topic.patches_for(tree, patches);
+            if relevant.is_empty() {
+                continue;
+            }
+
+            subscriber.queue.push(
+                ServerMessage::Patch {
+                    version,
+                    diff: relevant,
+                    request_id: request_id.map(str::to_string),
+                },
+                || ServerMessage::Resync {
+                    version,
+                    html: subscriber
+                        .topic
+                        .resync_root(tree)
+                        .map(&render)
+                        .unwrap_or_default(),
+                },
+            );
+            delivered += 1;
+        }
+
+        delivered
+    }
+
+    /**
+     * ストアの更新をミドルウェアに通してから購読者へ配信し続けるタスクを起動する関数
+     */
+    pub fn forward_from(
+        self: &Arc<Self>,
+        store: Arc<Store>,
+        middleware: Arc<MiddlewareChain>,
+    ) -> JoinHandle<()> {
+        let broadcaster = self.clone();
+        let mut updates = store.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => {
+                        let tree = store.snapshot().tree;
+                        let patches = middleware.process(update.diff);
+                        broadcaster.publish_with(
+                            &tree.element_type,
+                            update.version,
+                            &patches,
+                            update.request_id.as_deref(),
+                            |node| middleware.process_html(virtual_dom_to_html(node)),
+                        );
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::Attributes;
+    use crate::self_virtual_dom::VNode;
+
+    #[test]
+    fn test_publish_filters_by_topic() {
+        let tree = ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![
+                ElementType::Element(
+                    "section".to_string(),
+                    [("id".into(), "chat".into())].into_iter().collect(),
+                    vec![ElementType::Text("hi".into())],
+                ),
+                ElementType::Text("footer".into()),
+            ],
+        );
+        let patch = Diff::AddNode(
+            vec![0, 0],
+            VNode {
+                element_type: ElementType::Text("hi".into()),
+            },
+        );
+
+        let broadcaster = Broadcaster::new(1);
+        let all = broadcaster.subscribe(Topic::All);
+        let chat = broadcaster.subscribe(Topic::Selector(Selector::parse("#chat").unwrap()));
+        let footer = broadcaster.subscribe(Topic::Root(vec![1]));
+        let closed = broadcaster.subscribe(Topic::All);
+        drop(closed);
+
+        assert_eq!(broadcaster.publish(&tree, 1, &[patch], None), 2);
+        assert!(all.queue.try_recv().is_some());
+        assert!(chat.queue.try_recv().is_some());
+        assert!(footer.queue.try_recv().is_none());
+        assert_eq!(broadcaster.subscriber_count(), 3);
+    }
+
+    #[test]
+    fn test_root_resync_contains_only_subtree() {
+        let tree = |value: &str| {
+            ElementType::Element(
+                "div".to_string(),
+                Attributes::new(),
+                vec![
+                    ElementType::Element(
+                        "section".to_string(),
+                        Attributes::new(),
+                        vec![ElementType::Text(value.to_string().into())],
+                    ),
+                    ElementType::Text("footer".into()),
+                ],
+            )
+        };
+        let patch = |value: &str| {
+            Diff::AddNode(
+                vec![0, 0],
+                VNode {
+                    element_type: ElementType::Text(value.to_string().into()),
+                },
+            )
+        };
+
+        let broadcaster = Broadcaster::with_max_coalesced_patches(1, 0);
+        let section = broadcaster.subscribe(Topic::Root(vec![0]));
+        broadcaster.publish(&tree("a"), 1, &[patch("a")], None);
+        broadcaster.publish(&tree("b"), 2, &[patch("b")], None);
+
+        assert_eq!(
+            section.queue.try_recv(),
+            Some(ServerMessage::Resync {
+                version: 2,
+                html: "<section >b</section>".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_queue_coalesces_then_resyncs() {
+        let text = |value: &str| VNode {
+            element_type: ElementType::Text(value.to_string().into()),
+        };
+        let resync = || ServerMessage::Resync {
+            version: 0,
+            html: "resync".to_string(),
+        };
+
+        let queue = ClientQueue::new(2, 2);
+        for (version, [old, new]) in [["", "a"], ["a", "b"], ["b", "c"]].into_iter().enumerate() {
+            queue.push(
+                ServerMessage::Patch {
+                    version: version as u64,
+                    diff: vec![
+                        Diff::RemoveNode(vec![0], text(old)),
+                        Diff::AddNode(vec![0], text(new)),
+                    ],
+                    request_id: Some("req-1".to_string()),
+                },
+                resync,
+            );
+        }
+        assert_eq!(queue.len(), 1);
+        assert_eq!(
+            queue.try_recv(),
+            Some(ServerMessage::Patch {
+                version: 2,
+                diff: vec![
+                    Diff::RemoveNode(vec![0], text("")),
+                    Diff::AddNode(vec![0], text("c")),
+                ],
+                request_id: Some("req-1".to_string()),
+            })
+        );
+
+        for index in 0..3 {
+            queue.push(
+                ServerMessage::Patch {
+                    version: index,
+                    diff: vec![Diff::AddNode(vec![index as usize], text("x"))],
+                    request_id: None,
+                },
+                resync,
+            );
+        }
+        assert_eq!(queue.try_recv(), Some(resync()));
+        assert!(queue.is_empty());
+    }
+}
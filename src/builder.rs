@@ -0,0 +1,317 @@
+use crate::self_virtual_dom::{Attributes, ElementType, Text, VNode, KEY_ATTR};
+
+/**
+ * 1つの仮想DOMのノードに変換できる値を表す trait
+ */
+pub trait IntoVNode {
+    fn into_element(self) -> ElementType;
+}
+
+/**
+ * 0個以上の子ノードに変換できる値を表す trait
+ * 単一のノード、Option、Vec、イテレータ、タプルを組み合わせて子要素を渡せる
+ */
+pub trait IntoChildren {
+    fn push_children(self, children: &mut Vec<ElementType>);
+}
+
+impl IntoVNode for ElementType {
+    fn into_element(self) -> ElementType {
+        self
+    }
+}
+
+impl IntoVNode for VNode {
+    fn into_element(self) -> ElementType {
+        self.element_type
+    }
+}
+
+impl IntoVNode for String {
+    fn into_element(self) -> ElementType {
+        ElementType::Text(self.into())
+    }
+}
+
+/**
+ * 文字列リテラルは確保せずに借用したテキストノードになる
+ * 一時的な文字列から作る場合は String に変換して渡す
+ */
+impl IntoVNode for &'static str {
+    fn into_element(self) -> ElementType {
+        ElementType::Text(Text::Borrowed(self))
+    }
+}
+
+impl IntoVNode for Text {
+    fn into_element(self) -> ElementType {
+        ElementType::Text(self)
+    }
+}
+
+impl IntoVNode for &String {
+    fn into_element(self) -> ElementType {
+        ElementType::Text(self.clone().into())
+    }
+}
+
+macro_rules! impl_into_vnode_for_numbers {
+    ($($number:ty),*) => {
+        $(
+            impl IntoVNode for $number {
+                fn into_element(self) -> ElementType {
+                    ElementType::Text(self.to_string().into())
+                }
+            }
+        )*
+    };
+}
+
+impl_into_vnode_for_numbers!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64
+);
+
+macro_rules! impl_into_children_for_nodes {
+    ($($node:ty),*) => {
+        $(
+            impl IntoChildren for $node {
+                fn push_children(self, children: &mut Vec<ElementType>) {
+                    children.push(self.into_element());
+                }
+            }
+        )*
+    };
+}
+
+impl_into_children_for_nodes!(
+    ElementType,
+    VNode,
+    String,
+    &'static str,
+    Text,
+    &String,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64
+);
+
+impl IntoChildren for () {
+    fn push_children(self, _children: &mut Vec<ElementType>) {}
+}
+
+impl<T: IntoChildren> IntoChildren for Option<T> {
+    fn push_children(self, children: &mut Vec<ElementType>) {
+        if let Some(value) = self {
+            value.push_children(children);
+        }
+    }
+}
+
+impl<T: IntoChildren> IntoChildren for Vec<T> {
+    fn push_children(self, children: &mut Vec<ElementType>) {
+        for value in self {
+            value.push_children(children);
+        }
+    }
+}
+
+impl<T: IntoChildren, const N: usize> IntoChildren for [T; N] {
+    fn push_children(self, children: &mut Vec<ElementType>) {
+        for value in self {
+            value.push_children(children);
+        }
+    }
+}
+
+macro_rules! impl_into_children_for_iterators {
+    ($($iter:ident < $($param:ident),* >),*) => {
+        $(
+            impl<$($param),*> IntoChildren for std::iter::$iter<$($param),*>
+            where
+                std::iter::$iter<$($param),*>: Iterator,
+                <std::iter::$iter<$($param),*> as Iterator>::Item: IntoChildren,
+            {
+                fn push_children(self, children: &mut Vec<ElementType>) {
+                    for value in self {
+                        value.push_children(children);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_into_children_for_iterators!(
+    Map<I, F>,
+    Filter<I, P>,
+    FilterMap<I, F>,
+    Chain<A, B>,
+    Take<I>,
+    Skip<I>,
+    Rev<I>,
+    Cloned<I>
+);
+
+impl<T: IntoChildren> IntoChildren for std::vec::IntoIter<T> {
+    fn push_children(self, children: &mut Vec<ElementType>) {
+        for value in self {
+            value.push_children(children);
+        }
+    }
+}
+
+macro_rules! impl_into_children_for_tuples {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: IntoChildren),+> IntoChildren for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn push_children(self, children: &mut Vec<ElementType>) {
+                    let ($($name,)+) = self;
+                    $($name.push_children(children);)+
+                }
+            }
+        )*
+    };
+}
+
+impl_into_children_for_tuples!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H)
+);
+
+/**
+ * 子要素になる値をノードの一覧に変換する関数
+ */
+pub fn children(values: impl IntoChildren) -> Vec<ElementType> {
+    let mut children = Vec::new();
+    values.push_children(&mut children);
+    children
+}
+
+/**
+ * タグ名、属性、子要素から要素を組み立てる関数
+ */
+pub fn element(tag: &str, attrs: &[(&str, &str)], values: impl IntoChildren) -> ElementType {
+    let attrs: Attributes = attrs
+        .iter()
+        .map(|(name, value)| (name.to_string().into(), value.to_string().into()))
+        .collect();
+    ElementType::Element(tag.to_string(), attrs, children(values))
+}
+
+/**
+ * 項目の一覧から key 付きの子要素を組み立てる関数
+ * key は描画した要素の key 属性に設定され、差分の計算で項目の追加・削除を位置のずれなく検出するために使われる
+ * 要素以外のノードには key を付けられないため、描画結果は要素にする
+ */
+pub fn keyed_list<T, K, N>(
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> K,
+    render: impl Fn(&T) -> N,
+) -> Vec<ElementType>
+where
+    K: ToString,
+    N: IntoVNode,
+{
+    items
+        .into_iter()
+        .map(|item| {
+            let mut node = render(&item).into_element();
+            if let ElementType::Element(_, attrs, _) = &mut node {
+                attrs.insert(KEY_ATTR.into(), key(&item).to_string().into());
+            }
+            node
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::{update_dom, virtual_dom_to_html, Diff};
+
+    #[test]
+    fn test_heterogeneous_children() {
+        let items = ["a", "b"];
+        let render_item = |item: &&'static str| element("li", &[], *item);
+        let note: Option<String> = None;
+
+        let list = element(
+            "ul",
+            &[],
+            (
+                items.iter().map(render_item),
+                note,
+                element("li", &[("class", "footer")], ("total: ", items.len())),
+            ),
+        );
+
+        assert_eq!(
+            virtual_dom_to_html(&list),
+            r#"<ul ><li >a</li><li >b</li><li class="footer">total: 2</li></ul>"#
+        );
+    }
+
+    #[test]
+    fn test_keyed_list_diff() {
+        let view = |todos: &[(u32, &'static str)]| VNode {
+            element_type: element(
+                "ul",
+                &[],
+                keyed_list(
+                    todos.iter(),
+                    |todo| todo.0,
+                    |todo| element("li", &[], todo.1),
+                ),
+            ),
+        };
+
+        let old = view(&[(1, "a"), (2, "b"), (3, "c")]);
+        let new = view(&[(0, "z"), (1, "a"), (3, "C")]);
+        let paths: Vec<(bool, Vec<usize>)> = update_dom(&old, &new)
+            .diff
+            .iter()
+            .map(|patch| (matches!(patch, Diff::AddNode(..)), patch.path().clone()))
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                (false, vec![2, 0]),
+                (false, vec![1]),
+                (true, vec![0]),
+                (true, vec![2, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_text_is_borrowed() {
+        let title = element("h1", &[], ("Title", String::from("!")));
+        let ElementType::Element(_, _, children) = &title else {
+            unreachable!()
+        };
+        assert!(matches!(
+            children[0],
+            ElementType::Text(Text::Borrowed("Title"))
+        ));
+        assert!(matches!(children[1], ElementType::Text(Text::Owned(_))));
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use crate::app::render_input;
+use crate::request_id;
+use crate::self_virtual_dom::AppResponse;
+use crate::store::Store;
+
+/**
+ * キャッシュの利用状況
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /** 差分の基準が変わったために捨てたエントリーの数 */
+    pub invalidated: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /** 保持しているエントリーが基準にしている木のバージョン */
+    base: Option<u64>,
+    entries: HashMap<u64, AppResponse>,
+    /** 古いものから順に並べた入力のハッシュ */
+    order: VecDeque<u64>,
+    stats: CacheStats,
+}
+
+/**
+ * 入力のハッシュをキーに、計算済みの AppResponse を返すキャッシュ
+ * 差分は基準にした木によって変わるため、基準のバージョンが変わった時点ですべてのエントリーを捨てる
+ * 描画に時間がかかる場合に、同じ入力に対する差分の計算を省く
+ */
+#[derive(Debug)]
+pub struct ResponseCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    /**
+     * capacity 個までの入力の結果を保持するキャッシュを作る関数
+     * 上限を超えた場合は最も古く保存したものから捨てる
+     */
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /**
+     * base の木に対する input の結果を返し、なければ render で計算して保存する関数
+     * 保存した結果のリクエストIDは、取り出した時点のリクエストのものに置き換える
+     */
+    pub fn get_or_render(
+        &self,
+        base: u64,
+        input: &str,
+        render: impl FnOnce() -> AppResponse,
+    ) -> AppResponse {
+        let key = input_hash(input);
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.base != Some(base) {
+                state.stats.invalidated += state.entries.len() as u64;
+                state.entries.clear();
+                state.order.clear();
+                state.base = Some(base);
+            }
+            if let Some(cached) = state.entries.get(&key).cloned() {
+                state.stats.hits += 1;
+                return AppResponse {
+                    request_id: request_id::current(),
+                    ..cached
+                };
+            }
+            state.stats.misses += 1;
+        }
+
+        // 描画の間はロックを持たない
+        let app_response = render();
+        let mut state = self.state.lock().unwrap();
+        if state.base == Some(base) && self.capacity > 0 && !state.entries.contains_key(&key) {
+            if state.entries.len() >= self.capacity {
+                if let Some(oldest) = state.order.pop_front() {
+                    state.entries.remove(&oldest);
+                }
+            }
+            state.order.push_back(key);
+            state.entries.insert(key, app_response.clone());
+        }
+        app_response
+    }
+
+    /**
+     * すべてのエントリーを捨てる関数
+     */
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.invalidated += state.entries.len() as u64;
+        state.entries.clear();
+        state.order.clear();
+        state.base = None;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+fn input_hash(input: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    input.hash(&mut hasher);
+    hasher.finish()
+}
+
+/**
+ * デモアプリの入力のプレビューを、ストアの現在のバージョンを基準にキャッシュして返す関数
+ * 入力の更新でストアのバージョンが進むと、以前の結果は使われなくなる
+ */
+pub fn preview_input(store: &Store, cache: &ResponseCache, input: &str) -> AppResponse {
+    // バージョンを読んだ後に更新が割り込んだ場合の結果も、新しいバージョンで呼び出した時点で捨てられる
+    cache.get_or_render(store.version(), input, || {
+        store.update_dom_readonly(&render_input(input))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+
+    #[test]
+    fn test_response_cache() {
+        let store = Store::new(initial_tree());
+        let cache = ResponseCache::new(8);
+
+        let first = preview_input(&store, &cache, "Hi");
+        let second = preview_input(&store, &cache, "Hi");
+        assert_eq!(first.diff, second.diff);
+        assert_eq!(cache.stats().hits, 1);
+
+        // 基準の木が変わると、同じ入力でも差分を計算し直す
+        store.update(render_input("Hi"));
+        let after = preview_input(&store, &cache, "Hi");
+        assert!(after.diff.is_empty());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                invalidated: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::self_virtual_dom::VNode;
+
+/**
+ * 書き込み元ごとの更新回数を数えるベクタークロック
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorClock(BTreeMap<String, u64>);
+
+impl VectorClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 書き込み元の更新回数を返す関数
+     */
+    pub fn get(&self, writer: &str) -> u64 {
+        self.0.get(writer).copied().unwrap_or_default()
+    }
+
+    /**
+     * 書き込み元の更新回数を1つ進める関数
+     */
+    pub fn tick(&mut self, writer: &str) {
+        *self.0.entry(writer.to_string()).or_default() += 1;
+    }
+
+    /**
+     * 書き込み元ごとに大きい方の回数を取る関数
+     */
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (writer, count) in &other.0 {
+            let entry = self.0.entry(writer.clone()).or_default();
+            *entry = (*entry).max(*count);
+        }
+    }
+
+    /**
+     * 2つのクロックの前後関係を返す関数
+     * どちらも相手の知らない更新を含む（並行している）場合は None を返す
+     */
+    pub fn compare(&self, other: &VectorClock) -> Option<Ordering> {
+        let mut writers = self.0.keys().chain(other.0.keys());
+        writers.try_fold(Ordering::Equal, |ordering, writer| {
+            match (ordering, self.get(writer).cmp(&other.get(writer))) {
+                (Ordering::Equal, next) => Some(next),
+                (current, Ordering::Equal) => Some(current),
+                (current, next) if current == next => Some(current),
+                _ => None,
+            }
+        })
+    }
+
+    /**
+     * other の更新をすべて含んでいるかを判定する関数
+     */
+    pub fn dominates(&self, other: &VectorClock) -> bool {
+        matches!(
+            self.compare(other),
+            Some(Ordering::Greater | Ordering::Equal)
+        )
+    }
+}
+
+/**
+ * 書き込み元が知らない更新がストアにあったことを表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub writer: String,
+    /** 書き込み元が最後に受け取ったクロック */
+    pub seen: VectorClock,
+    /** ストアの現在のクロック */
+    pub current: VectorClock,
+}
+
+/**
+ * 競合した書き込みの扱い
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /** 指定した木で書き込みを続ける */
+    Apply(VNode),
+    /** 書き込みを取り消す */
+    Reject,
+}
+
+/**
+ * 競合した書き込みをどう扱うかを決める trait
+ */
+pub trait ConflictPolicy: Send + Sync {
+    fn resolve(&self, conflict: &Conflict, current: &VNode, proposed: VNode) -> Resolution;
+}
+
+impl<F> ConflictPolicy for F
+where
+    F: Fn(&Conflict, &VNode, VNode) -> Resolution + Send + Sync,
+{
+    fn resolve(&self, conflict: &Conflict, current: &VNode, proposed: VNode) -> Resolution {
+        self(conflict, current, proposed)
+    }
+}
+
+/**
+ * 後から届いた書き込みで上書きする方針
+ */
+pub struct LastWriterWins;
+
+impl ConflictPolicy for LastWriterWins {
+    fn resolve(&self, _conflict: &Conflict, _current: &VNode, proposed: VNode) -> Resolution {
+        Resolution::Apply(proposed)
+    }
+}
+
+/**
+ * 競合した書き込みをすべて取り消す方針
+ */
+pub struct RejectConflicts;
+
+impl ConflictPolicy for RejectConflicts {
+    fn resolve(&self, _conflict: &Conflict, _current: &VNode, _proposed: VNode) -> Resolution {
+        Resolution::Reject
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+    use crate::store::Store;
+
+    #[test]
+    fn test_vector_clock_order() {
+        let mut a = VectorClock::new();
+        a.tick("alice");
+        let mut b = a.clone();
+        b.tick("bob");
+        assert_eq!(a.compare(&b), Some(Ordering::Less));
+
+        a.tick("alice");
+        assert_eq!(a.compare(&b), None);
+
+        a.merge(&b);
+        assert!(a.dominates(&b));
+        assert_eq!(a.get("alice"), 2);
+        assert_eq!(a.get("bob"), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let store = Store::new(initial_tree());
+        let seen = VectorClock::new();
+
+        let (_, clock) = store
+            .write("alice", &seen, render_input("a"), &RejectConflicts)
+            .unwrap();
+        assert_eq!(clock.get("alice"), 1);
+
+        let conflict = store
+            .write("bob", &seen, render_input("b"), &RejectConflicts)
+            .unwrap_err();
+        assert_eq!(conflict.current, clock);
+        assert_eq!(store.version(), 1);
+
+        let (_, clock) = store
+            .write("bob", &seen, render_input("b"), &LastWriterWins)
+            .unwrap();
+        assert_eq!(store.updates_since(1).unwrap()[0].clock, clock);
+    }
+}
@@ -0,0 +1,333 @@
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::patch::apply_patches;
+use crate::self_virtual_dom::{replace_root, Diff, VNode};
+use crate::store::{Snapshot, Store};
+
+/**
+ * サーバーインスタンス間で共有する更新
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterMessage {
+    /** 更新を発生させたインスタンスのID */
+    pub origin: String,
+    /** 発生元のインスタンスでのバージョン。インスタンスごとに独立して数えるため、受け取った側では使わない */
+    pub version: u64,
+    pub diff: Vec<Diff>,
+    /** 発生元での更新直後の木。受け取った側はこの木を自身のストアに適用する */
+    pub tree: VNode,
+    /** 更新を発生させたリクエストのID */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/**
+ * インスタンス間の通信に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterError {
+    pub message: String,
+}
+
+impl fmt::Display for ClusterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cluster bus error: {}", self.message)
+    }
+}
+
+impl std::error::Error for ClusterError {}
+
+impl From<serde_json::Error> for ClusterError {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            message: err.to_string(),
+        }
+    }
+}
+
+/**
+ * 更新をすべてのサーバーインスタンスへ届ける経路を表す trait
+ */
+pub trait ClusterBus: Send + Sync {
+    fn publish(&self, message: &ClusterMessage) -> Result<(), ClusterError>;
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage>;
+}
+
+/**
+ * 同じプロセス内のインスタンス間で更新を共有する経路
+ * テストや、1プロセスに複数のサーバーを立てる場合に使う
+ */
+pub struct InProcessBus {
+    sender: broadcast::Sender<ClusterMessage>,
+}
+
+impl InProcessBus {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity.max(1)).0,
+        }
+    }
+}
+
+impl Default for InProcessBus {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl ClusterBus for InProcessBus {
+    fn publish(&self, message: &ClusterMessage) -> Result<(), ClusterError> {
+        // 購読しているインスタンスがない場合の送信エラーは無視する
+        let _ = self.sender.send(message.clone());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage> {
+        BroadcastStream::new(self.sender.subscribe())
+            .filter_map(|message| async move { message.ok() })
+            .boxed()
+    }
+}
+
+/**
+ * Redis の Pub/Sub で更新を共有する経路
+ */
+#[cfg(feature = "redis")]
+pub struct RedisBus {
+    client: redis::Client,
+    channel: String,
+    /** 送信に使う接続。失敗した場合は次の送信でつなぎ直す */
+    connection: Mutex<Option<redis::Connection>>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisBus {
+    pub fn new(url: &str, channel: &str) -> Result<Self, ClusterError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(|err| ClusterError {
+                message: err.to_string(),
+            })?,
+            channel: channel.to_string(),
+            connection: Mutex::new(None),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl ClusterBus for RedisBus {
+    fn publish(&self, message: &ClusterMessage) -> Result<(), ClusterError> {
+        use redis::Commands;
+
+        let payload = serde_json::to_string(message)?;
+        let mut connection = self.connection.lock().unwrap();
+        let result = match connection.as_mut() {
+            Some(connection) => Ok(connection),
+            None => self
+                .client
+                .get_connection()
+                .map(|opened| connection.insert(opened)),
+        }
+        .and_then(|connection| connection.publish::<_, _, ()>(self.channel.as_str(), payload));
+        if result.is_err() {
+            *connection = None;
+        }
+        result.map_err(|err| ClusterError {
+            message: err.to_string(),
+        })
+    }
+
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(64);
+        let (client, channel) = (self.client.clone(), self.channel.clone());
+        // 同期版の Pub/Sub は受信でブロックするため、専用のスレッドで待ち受ける
+        tokio::task::spawn_blocking(move || -> redis::RedisResult<()> {
+            let mut connection = client.get_connection()?;
+            let mut pubsub = connection.as_pubsub();
+            pubsub.subscribe(&channel)?;
+            loop {
+                let payload: String = pubsub.get_message()?.get_payload()?;
+                let Ok(message) = serde_json::from_str(&payload) else {
+                    continue;
+                };
+                if sender.blocking_send(message).is_err() {
+                    return Ok(());
+                }
+            }
+        });
+        tokio_stream::wrappers::ReceiverStream::new(receiver).boxed()
+    }
+}
+
+/**
+ * インスタンスのストアの更新を経路へ流し、他のインスタンスの更新を自身のストアへ適用するタスクを起動する関数
+ * 他のインスタンスの更新はストアの更新として配信されるため、購読者へは自身のバージョンで届く
+ * 自身が流した更新は経路から戻ってきても適用せず、他のインスタンスから適用した更新は経路へ流し直さない
+ * タスクはストアを弱い参照で持ち、ストアが破棄されると終了する
+ */
+pub fn join_cluster(
+    bus: Arc<dyn ClusterBus>,
+    instance: String,
+    store: Arc<Store>,
+) -> JoinHandle<()> {
+    let mut updates = store.subscribe();
+    let mut remote = bus.subscribe();
+    let mut published = store.snapshot();
+    let weak_store = Arc::downgrade(&store);
+    drop(store);
+    // 他のインスタンスから適用した更新のバージョン。経路へ流し直さないように覚えておく
+    let applied = Arc::new(Mutex::new(HashSet::new()));
+
+    let outgoing = {
+        let (bus, origin, weak_store, applied) = (
+            bus.clone(),
+            instance.clone(),
+            weak_store.clone(),
+            applied.clone(),
+        );
+        async move {
+            loop {
+                let message = match updates.recv().await {
+                    Ok(update) if update.version <= published.version => continue,
+                    Ok(update) => {
+                        // 流す木は更新直後のものにするため、ストアの最新の木ではなく差分を適用し続けた木を使う
+                        let replayed = update.version == published.version + 1
+                            && apply_patches(
+                                &mut published.tree.element_type,
+                                update.replayable_diff(),
+                            )
+                            .is_ok();
+                        if replayed {
+                            published.version = update.version;
+                            if applied.lock().unwrap().remove(&update.version) {
+                                continue;
+                            }
+                            ClusterMessage {
+                                origin: origin.clone(),
+                                version: update.version,
+                                diff: update.diff,
+                                tree: published.tree.clone(),
+                                request_id: update.request_id,
+                            }
+                        } else {
+                            let Some(message) =
+                                resync(&weak_store, &origin, &mut published, &applied)
+                            else {
+                                break;
+                            };
+                            message
+                        }
+                    }
+                    // 取りこぼした更新は差分を適用できないため、ストアの現在の木を流し直す
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let Some(message) = resync(&weak_store, &origin, &mut published, &applied)
+                        else {
+                            break;
+                        };
+                        message
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if let Err(err) = bus.publish(&message) {
+                    eprintln!("Failed to publish update {}: {}", message.version, err);
+                }
+            }
+        }
+    };
+    // メッセージは更新後の木を持つため、経路で取りこぼしがあっても次のメッセージで追いつく
+    let incoming = async move {
+        while let Some(message) = remote.next().await {
+            if message.origin == instance {
+                continue;
+            }
+            let Some(store) = weak_store.upgrade() else {
+                break;
+            };
+            let (before, after) = store.modify_snapshots(|tree| *tree = message.tree);
+            if after.version != before.version {
+                applied.lock().unwrap().insert(after.version);
+            }
+        }
+    };
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = outgoing => {}
+            _ = incoming => {}
+        }
+    })
+}
+
+/**
+ * ストアの現在の木を、差分を適用し続ける木として取り直し、それを流すメッセージを返す関数
+ * ストアが破棄されている場合は None を返す
+ */
+fn resync(
+    store: &Weak<Store>,
+    origin: &str,
+    published: &mut Snapshot,
+    applied: &Mutex<HashSet<u64>>,
+) -> Option<ClusterMessage> {
+    *published = store.upgrade()?.snapshot();
+    applied
+        .lock()
+        .unwrap()
+        .retain(|version| *version > published.version);
+    Some(ClusterMessage {
+        origin: origin.to_string(),
+        version: published.version,
+        diff: replace_root(&published.tree),
+        tree: published.tree.clone(),
+        request_id: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_updates_reach_other_instances() {
+        let bus: Arc<dyn ClusterBus> = Arc::new(InProcessBus::default());
+        let stores: Vec<Arc<Store>> = (0..2)
+            .map(|id| {
+                let store = Arc::new(Store::new(initial_tree()));
+                join_cluster(bus.clone(), format!("instance-{}", id), store.clone());
+                store
+            })
+            .collect();
+        let mut remote = stores[1].subscribe();
+
+        stores[0].update(render_input("Hello"));
+
+        let update = tokio::time::timeout(Duration::from_secs(2), remote.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.version, 1);
+        assert_eq!(stores[1].snapshot().tree, stores[0].snapshot().tree);
+
+        // 他のインスタンスから適用した更新は流し直されないため、どちらのストアもそれ以上更新されない
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(stores[0].version(), 1);
+        assert_eq!(stores[1].version(), 1);
+
+        // ストアを破棄するとタスクも終了する
+        let store = Arc::new(Store::new(initial_tree()));
+        let handle = join_cluster(bus.clone(), "instance-2".to_string(), store.clone());
+        drop(store);
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+
+use crate::binding::{BIND_PREFIX, CHECKED_PROPERTY};
+use crate::builder::{element, keyed_list, IntoChildren, IntoVNode};
+use crate::conditional::{placeholder, when};
+use crate::self_virtual_dom::ElementType;
+use crate::visit::{walk_mut, Transformer};
+
+/**
+ * クリックされたときにクライアントが送るアクションの名前を持つ属性名
+ */
+pub const ACTION_ATTR: &str = "data-action";
+
+/**
+ * ポータルの内容を描画する先の要素の id を持つ属性名
+ */
+pub const PORTAL_ATTR: &str = "data-portal";
+
+/**
+ * モーダルの描画先になる要素の id
+ */
+pub const MODAL_ROOT: &str = "modal-root";
+
+/**
+ * クリックでアクションを送るボタンを返す関数
+ * クライアントはクリックを `POST /event` に action のイベントとして送り、on_action に登録した処理が受け取る
+ */
+pub fn button(label: impl IntoChildren, action: &str) -> ElementType {
+    element(
+        "button",
+        &[("type", "button"), (ACTION_ATTR, action)],
+        label,
+    )
+}
+
+/**
+ * 値を状態にバインドしたテキスト入力欄を返す関数
+ * 値は BoundView の描画時に path の状態で解決され、入力はクライアントから path への変更として送られる
+ */
+pub fn text_input(name: &str, path: &str) -> ElementType {
+    let bind = format!("{}value", BIND_PREFIX);
+    element(
+        "input",
+        &[("type", "text"), ("name", name), (bind.as_str(), path)],
+        (),
+    )
+}
+
+/**
+ * チェックの有無を状態にバインドしたラベル付きのチェックボックスを返す関数
+ * path の状態が true のときだけ checked が付き、クライアントはチェックの変更を真偽値として path に送る
+ */
+pub fn checkbox(name: &str, label: impl IntoChildren, path: &str) -> ElementType {
+    let bind = format!("{}{}", BIND_PREFIX, CHECKED_PROPERTY);
+    let input = element(
+        "input",
+        &[("type", "checkbox"), ("name", name), (bind.as_str(), path)],
+        (),
+    );
+    element("label", &[], (input, label))
+}
+
+/**
+ * (値, 表示名) の一覧から選択肢を作り、選択した値を状態にバインドしたセレクトボックスを返す関数
+ * path の状態と値の一致する選択肢が選択され、クライアントは選択の変更を path に送る
+ * 選択肢は値を key にするため、一覧が変わっても選択肢の位置がずれない
+ */
+pub fn select(name: &str, options: &[(&str, &str)], path: &str) -> ElementType {
+    let bind = format!("{}value", BIND_PREFIX);
+    element(
+        "select",
+        &[("name", name), (bind.as_str(), path)],
+        keyed_list(
+            options.iter(),
+            |(value, _)| *value,
+            |(value, label)| element("option", &[("value", value)], label.to_string()),
+        ),
+    )
+}
+
+/**
+ * 内容を target の id を持つ要素の中に描画させる関数
+ * mount_portals を適用するまでは、内容は呼び出した位置に置かれる
+ */
+pub fn portal(target: &str, content: impl IntoVNode) -> ElementType {
+    element("template", &[(PORTAL_ATTR, target)], content.into_element())
+}
+
+/**
+ * 開いている間だけ MODAL_ROOT に描画されるモーダルを返す関数
+ * 閉じている間はプレースホルダーを置くため、開閉しても兄弟要素の位置が変わらない
+ */
+pub fn modal(
+    id: &str,
+    open: bool,
+    title: impl IntoChildren,
+    body: impl IntoChildren,
+) -> ElementType {
+    when(
+        open,
+        portal(
+            MODAL_ROOT,
+            element(
+                "div",
+                &[("id", id), ("role", "dialog"), ("aria-modal", "true")],
+                (
+                    element("h2", &[], title),
+                    element("div", &[], body),
+                    button("Close", &format!("{}:close", id)),
+                ),
+            ),
+        ),
+    )
+}
+
+/**
+ * key、見出し、内容を持つタブの一覧を返す関数
+ * 見出しのボタンは `<name>:<key>` のアクションを送り、パネルは key 付きで active 以外を hidden にする
+ */
+pub fn tabs<N: IntoVNode + Clone>(
+    name: &str,
+    active: &str,
+    panels: &[(&str, &str, N)],
+) -> ElementType {
+    let tablist = element(
+        "div",
+        &[("role", "tablist")],
+        keyed_list(
+            panels.iter(),
+            |(key, _, _)| *key,
+            |(key, label, _)| {
+                let mut tab = button(label.to_string(), &format!("{}:{}", name, key));
+                set_attr(&mut tab, "role", "tab");
+                let selected = if *key == active { "true" } else { "false" };
+                set_attr(&mut tab, "aria-selected", selected);
+                tab
+            },
+        ),
+    );
+    let panels = keyed_list(
+        panels.iter(),
+        |(key, _, _)| *key,
+        |(key, _, content)| {
+            let mut panel = element(
+                "div",
+                &[("role", "tabpanel")],
+                content.clone().into_element(),
+            );
+            if *key != active {
+                set_attr(&mut panel, "hidden", "");
+            }
+            panel
+        },
+    );
+    element("div", &[("class", "tabs")], (tablist, panels))
+}
+
+fn set_attr(node: &mut ElementType, name: &'static str, value: &str) {
+    if let ElementType::Element(_, attrs, _) = node {
+        attrs.insert(name.into(), value.to_string().into());
+    }
+}
+
+/**
+ * ポータルの内容を描画先の要素の末尾に移すパス
+ * 元の位置にはプレースホルダーを置き、描画先が木にないポータルはそのまま残す
+ */
+struct MountPortals {
+    targets: HashSet<String>,
+    pending: Vec<(String, Vec<ElementType>)>,
+}
+
+impl Transformer for MountPortals {
+    fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+        let ElementType::Element(tag, attrs, children) = node else {
+            return;
+        };
+        if tag != "template" {
+            return;
+        }
+        let Some(target) = attrs.get(PORTAL_ATTR) else {
+            return;
+        };
+        if self.targets.contains(target.as_ref()) {
+            self.pending
+                .push((target.to_string(), std::mem::take(children)));
+            *node = placeholder();
+        }
+    }
+}
+
+/**
+ * 木の中のポータルを描画先の要素に移す関数
+ * 描画先の要素の中にあるポータルも含めて、木に現れる順に描画先へ追加する
+ */
+pub fn mount_portals(tree: &mut ElementType) {
+    let targets = tree
+        .iter_with_paths()
+        .filter_map(|(_, node)| match node {
+            ElementType::Element(_, attrs, _) => attrs.get("id").map(|id| id.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut portals = MountPortals {
+        targets,
+        pending: Vec::new(),
+    };
+    walk_mut(tree, &mut portals);
+    for (target, content) in portals.pending {
+        if let Some(ElementType::Element(_, _, children)) = find_by_id(tree, &target) {
+            children.extend(content);
+        }
+    }
+}
+
+fn find_by_id<'a>(node: &'a mut ElementType, id: &str) -> Option<&'a mut ElementType> {
+    let ElementType::Element(_, attrs, _) = node else {
+        return None;
+    };
+    if attrs.get("id").is_some_and(|value| value == id) {
+        return Some(node);
+    }
+    let ElementType::Element(_, _, children) = node else {
+        return None;
+    };
+    children.iter_mut().find_map(|child| find_by_id(child, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binding::{BindingChange, BindingError, BoundView};
+    use crate::self_virtual_dom::{update_dom, virtual_dom_to_html, VNode};
+    use crate::store::Store;
+    use serde_json::json;
+
+    #[test]
+    fn test_text_input_binding() {
+        let view = BoundView::new(
+            element(
+                "form",
+                &[],
+                (
+                    text_input("query", "state.query"),
+                    checkbox("all", "All", "state.all"),
+                    select("size", &[("s", "Small"), ("m", "Medium")], "state.size"),
+                ),
+            ),
+            json!({ "query": "rust", "all": true, "size": "m" }),
+        );
+        assert_eq!(
+            view.render().unwrap().to_string(),
+            r#"<form ><input bind:value="state.query" name="query" type="text" value="rust"><label ><input bind:checked="state.all" checked="" name="all" type="checkbox">All</label><select bind:value="state.size" name="size"><option key="s" value="s">Small</option><option key="m" selected="" value="m">Medium</option></select></form>"#
+        );
+
+        // チェックボックスと選択の変更も入力欄と同じく状態に書き込める
+        let store = Store::new(view.render().unwrap());
+        for (path, value) in [("state.all", json!(false)), ("state.size", json!("s"))] {
+            let change = BindingChange {
+                path: path.to_string(),
+                value,
+            };
+            view.apply(&store, &change, |_, _, _| Ok::<(), BindingError>(()))
+                .unwrap();
+        }
+        let html = view.render().unwrap().to_string();
+        assert!(html.contains(r#"<input bind:checked="state.all" name="all" type="checkbox">"#));
+        assert!(html.contains(r#"<option key="s" selected="" value="s">Small</option>"#));
+    }
+
+    #[test]
+    fn test_modal_portal() {
+        let page = |open: bool| {
+            let mut tree = element(
+                "body",
+                &[],
+                (
+                    element(
+                        "main",
+                        &[],
+                        (
+                            button("Open", "confirm:open"),
+                            modal("confirm", open, "Delete?", "Really?"),
+                        ),
+                    ),
+                    element("div", &[("id", MODAL_ROOT)], ()),
+                ),
+            );
+            mount_portals(&mut tree);
+            VNode { element_type: tree }
+        };
+
+        let opened = page(true);
+        assert_eq!(
+            virtual_dom_to_html(&opened.element_type.get(&[0]).unwrap().clone()),
+            r#"<main ><button data-action="confirm:open" type="button">Open</button><!----></main>"#
+        );
+        let dialog = opened.element_type.get(&[1, 0]).unwrap();
+        assert!(virtual_dom_to_html(dialog)
+            .starts_with(r#"<div aria-modal="true" id="confirm" role="dialog">"#));
+
+        // 開閉で変わるのは描画先の要素だけで、呼び出した位置の main は変わらない
+        let paths: Vec<_> = update_dom(&page(false), &opened)
+            .diff
+            .iter()
+            .map(|patch| patch.path().clone())
+            .collect();
+        assert_eq!(paths, vec![vec![1], vec![1]]);
+    }
+
+    #[test]
+    fn test_tabs() {
+        let panels = [("a", "A", "first"), ("b", "B", "second")];
+        let html = virtual_dom_to_html(&tabs("nav", "b", &panels));
+        assert!(html.contains(r#"<button aria-selected="false" data-action="nav:a" key="a" role="tab" type="button">A</button>"#));
+        assert!(html.contains(r#"<div hidden="" key="a" role="tabpanel">first</div><div key="b" role="tabpanel">second</div>"#));
+    }
+}
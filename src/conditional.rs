@@ -0,0 +1,93 @@
+use crate::builder::IntoVNode;
+use crate::self_virtual_dom::ElementType;
+
+/**
+ * 非表示の要素の代わりに置くコメントノードを返す関数
+ * 要素の表示を切り替えても兄弟要素の位置が変わらないため、差分が切り替えた位置だけに収まる
+ */
+pub fn placeholder() -> ElementType {
+    ElementType::Comment(String::new())
+}
+
+/**
+ * 条件が真のときだけノードを表示し、偽のときはプレースホルダーを置く関数
+ */
+pub fn when(condition: bool, node: impl IntoVNode) -> ElementType {
+    if condition {
+        node.into_element()
+    } else {
+        placeholder()
+    }
+}
+
+/**
+ * 条件に応じて2つのノードのどちらかを返す関数
+ */
+pub fn either(condition: bool, then: impl IntoVNode, otherwise: impl IntoVNode) -> ElementType {
+    if condition {
+        then.into_element()
+    } else {
+        otherwise.into_element()
+    }
+}
+
+/**
+ * 値のパターンに応じてノードを選ぶマクロ
+ * どのパターンにも一致しない場合はプレースホルダーを置く
+ *
+ * ```
+ * use minimal_virtual_dom_library::match_node;
+ *
+ * let count = 2;
+ * let node = match_node!(count, {
+ *     0 => "empty",
+ *     1 => "one item",
+ *     n if n < 10 => format!("{} items", n),
+ * });
+ * assert_eq!(node.to_string(), "2 items");
+ * ```
+ */
+#[macro_export]
+macro_rules! match_node {
+    ($value:expr, { $($pattern:pat $(if $guard:expr)? => $node:expr),+ $(,)? }) => {
+        match $value {
+            $($pattern $(if $guard)? => $crate::builder::IntoVNode::into_element($node),)+
+            #[allow(unreachable_patterns)]
+            _ => $crate::conditional::placeholder(),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::{update_dom, VNode};
+
+    fn view(show_banner: bool) -> VNode {
+        VNode {
+            element_type: element(
+                "div",
+                &[],
+                (
+                    when(show_banner, element("p", &[], "banner")),
+                    element("p", &[], "content"),
+                    match_node!(show_banner, { true => "on" }),
+                ),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_conditional_rendering() {
+        assert_eq!(
+            view(false).to_string(),
+            "<div ><!----><p >content</p><!----></div>"
+        );
+
+        let patches = update_dom(&view(false), &view(true)).diff;
+        let paths: Vec<_> = patches.iter().map(|patch| patch.path().clone()).collect();
+        assert_eq!(paths, vec![vec![2], vec![0], vec![0], vec![2]]);
+        assert_eq!(either(false, "a", "b"), ElementType::Text("b".into()));
+    }
+}
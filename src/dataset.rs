@@ -0,0 +1,137 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::self_virtual_dom::{ElementType, Text};
+
+/**
+ * data-* 属性の接頭辞
+ */
+pub const DATA_PREFIX: &str = "data-";
+
+/**
+ * 名前から data-* 属性名を組み立てる関数
+ * 既に data- で始まる名前はそのまま使う
+ */
+pub fn data_attr_name(name: &str) -> String {
+    if name.starts_with(DATA_PREFIX) {
+        name.to_string()
+    } else {
+        format!("{}{}", DATA_PREFIX, name)
+    }
+}
+
+/**
+ * 値を属性に埋め込める JSON 文字列にする関数
+ * 文字列の中の &, <, >, ' を \u エスケープにするため、描画時に引用符を置き換えるだけで安全に属性値にできる
+ */
+pub fn to_data_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let json = serde_json::to_string(value)?;
+    let mut escaped = String::with_capacity(json.len());
+    for ch in json.chars() {
+        match ch {
+            '&' => escaped.push_str("\\u0026"),
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '\'' => escaped.push_str("\\u0027"),
+            _ => escaped.push(ch),
+        }
+    }
+    Ok(escaped)
+}
+
+impl ElementType {
+    /**
+     * data-* 属性の値を取得する関数
+     */
+    pub fn data(&self, name: &str) -> Option<&str> {
+        let ElementType::Element(_, attrs, _) = self else {
+            return None;
+        };
+        attrs
+            .get(data_attr_name(name).as_str())
+            .map(|value| value.as_ref())
+    }
+
+    /**
+     * data-* 属性に値を設定する関数
+     * 要素以外のノードは属性を持てないため変更しない
+     */
+    pub fn set_data(&mut self, name: &str, value: &str) {
+        if let ElementType::Element(_, attrs, _) = self {
+            attrs.insert(data_attr_name(name).into(), value.to_string().into());
+        }
+    }
+
+    /**
+     * data-* 属性を取り除き、設定されていた値を返す関数
+     */
+    pub fn remove_data(&mut self, name: &str) -> Option<String> {
+        let ElementType::Element(_, attrs, _) = self else {
+            return None;
+        };
+        attrs
+            .remove(data_attr_name(name).as_str())
+            .map(Text::into_owned)
+    }
+
+    /**
+     * 構造化された値を JSON にして data-* 属性に設定する関数
+     * クライアント側のウィジェットに設定を渡すときに使う
+     */
+    pub fn set_data_json<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> serde_json::Result<()> {
+        let json = to_data_json(value)?;
+        self.set_data(name, &json);
+        Ok(())
+    }
+
+    /**
+     * data-* 属性の JSON を型付きの値として読み出す関数
+     * 属性がない場合は Ok(None) を返す
+     */
+    pub fn data_json<T: DeserializeOwned>(&self, name: &str) -> serde_json::Result<Option<T>> {
+        self.data(name).map(serde_json::from_str).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+    use crate::self_virtual_dom::Attributes;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        title: String,
+        limit: u32,
+    }
+
+    #[test]
+    fn test_data_json_round_trip() {
+        let mut node = ElementType::Element("div".to_string(), Attributes::new(), vec![]);
+        let config = Config {
+            title: "<b>\"Tom & Jerry's\"</b>".to_string(),
+            limit: 10,
+        };
+        node.set_data_json("config", &config).unwrap();
+
+        let attr = node.data("config").unwrap();
+        assert!(!attr.contains(['&', '<', '>', '\'']));
+        assert_eq!(
+            node.data_json::<Config>("data-config").unwrap(),
+            Some(config)
+        );
+        assert_eq!(node.data_json::<Config>("missing").unwrap(), None);
+
+        let html = virtual_dom_to_html(&node);
+        assert!(html.starts_with("<div data-config=\"{&quot;title&quot;:"));
+        assert!(html.contains("\\u003cb\\u003e\\&quot;Tom \\u0026 Jerry\\u0027s"));
+
+        assert!(node.remove_data("config").is_some());
+        assert_eq!(node.data("config"), None);
+    }
+}
@@ -0,0 +1,270 @@
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use std::collections::VecDeque;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::builder::element;
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{ElementType, KEY_ATTR};
+use crate::store::Store;
+
+/**
+ * 外部のデータソースから届く、key 付きの一覧に対する変更
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /** key の項目を追加、または既にあれば置き換える */
+    Upsert(String, ElementType),
+    /** key の項目を取り除く */
+    Remove(String),
+}
+
+/**
+ * 変更のストリームを提供する外部データソースを表す trait
+ */
+pub trait DataSource: Send + Sync {
+    fn subscribe(&self) -> BoxStream<'static, Change>;
+}
+
+/**
+ * key 付きの一覧に変更を反映する関数
+ * max_items を超えた場合は先頭の古い項目から取り除く
+ */
+pub fn apply_change(list: &mut ElementType, change: Change, max_items: Option<usize>) {
+    let ElementType::Element(_, _, children) = list else {
+        return;
+    };
+    let position = |children: &[ElementType], key: &str| {
+        children.iter().position(|child| {
+            matches!(child, ElementType::Element(_, attrs, _)
+                if attrs.get(KEY_ATTR).map(|value| value.as_ref()) == Some(key))
+        })
+    };
+
+    match change {
+        Change::Upsert(key, mut node) => {
+            if let ElementType::Element(_, attrs, _) = &mut node {
+                attrs.insert(KEY_ATTR.into(), key.clone().into());
+            }
+            match position(children, &key) {
+                Some(index) => children[index] = node,
+                None => children.push(node),
+            }
+        }
+        Change::Remove(key) => {
+            if let Some(index) = position(children, &key) {
+                children.remove(index);
+            }
+        }
+    }
+
+    if let Some(max_items) = max_items {
+        let overflow = children.len().saturating_sub(max_items);
+        children.drain(..overflow);
+    }
+}
+
+/**
+ * データソースの変更をストアの list_path にある一覧へ反映し続けるタスクを起動する関数
+ * 一覧は key で差分を取るため、変更のあった項目だけが配信される
+ */
+pub fn connect(
+    source: &dyn DataSource,
+    store: Arc<Store>,
+    list_path: NodePath,
+    max_items: Option<usize>,
+) -> JoinHandle<()> {
+    let mut changes = source.subscribe();
+    tokio::spawn(async move {
+        while let Some(change) = changes.next().await {
+            store.modify(|tree| {
+                if let Some(list) = tree.element_type.get_mut(&list_path) {
+                    apply_change(list, change, max_items);
+                }
+            });
+        }
+    })
+}
+
+/**
+ * チャネルに送られた変更をそのまま流すデータソース
+ * ストリームを購読できるのは最初の1回だけ
+ */
+pub struct ChannelSource {
+    receiver: Mutex<Option<mpsc::Receiver<Change>>>,
+}
+
+impl ChannelSource {
+    pub fn new(capacity: usize) -> (mpsc::Sender<Change>, Self) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (
+            sender,
+            Self {
+                receiver: Mutex::new(Some(receiver)),
+            },
+        )
+    }
+}
+
+impl DataSource for ChannelSource {
+    fn subscribe(&self) -> BoxStream<'static, Change> {
+        match self.receiver.lock().unwrap().take() {
+            Some(receiver) => ReceiverStream::new(receiver).boxed(),
+            None => stream::empty().boxed(),
+        }
+    }
+}
+
+/**
+ * ファイルに追記された行を一定間隔で読み取り、行番号を key とする項目として流すデータソース
+ */
+pub struct FileTail {
+    path: PathBuf,
+    interval: Duration,
+}
+
+impl FileTail {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self { path, interval }
+    }
+}
+
+struct TailState {
+    path: PathBuf,
+    interval: Duration,
+    offset: u64,
+    line: usize,
+    partial: String,
+    ready: VecDeque<Change>,
+}
+
+impl TailState {
+    /**
+     * 前回読んだ位置以降を読み取り、改行まで揃った行を変更として積む関数
+     * ファイルが切り詰められた場合は先頭から読み直す
+     * ランタイムのスレッドを止めないよう tokio::fs で読み取る
+     */
+    async fn read_new_lines(&mut self) {
+        let Ok(mut file) = tokio::fs::File::open(&self.path).await else {
+            return;
+        };
+        let length = file
+            .metadata()
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if length < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        let mut appended = String::new();
+        if file.seek(SeekFrom::Start(self.offset)).await.is_err()
+            || file.read_to_string(&mut appended).await.is_err()
+        {
+            return;
+        }
+        self.offset += appended.len() as u64;
+        self.partial.push_str(&appended);
+
+        while let Some(end) = self.partial.find('\n') {
+            let text: String = self.partial.drain(..=end).collect();
+            self.ready.push_back(Change::Upsert(
+                self.line.to_string(),
+                element("li", &[], text.trim_end_matches(['\r', '\n']).to_string()),
+            ));
+            self.line += 1;
+        }
+    }
+}
+
+impl DataSource for FileTail {
+    fn subscribe(&self) -> BoxStream<'static, Change> {
+        let state = TailState {
+            path: self.path.clone(),
+            interval: self.interval,
+            offset: 0,
+            line: 0,
+            partial: String::new(),
+            ready: VecDeque::new(),
+        };
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(change) = state.ready.pop_front() {
+                    return Some((change, state));
+                }
+                state.read_new_lines().await;
+                if state.ready.is_empty() {
+                    tokio::time::sleep(state.interval).await;
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::VNode;
+
+    fn dashboard() -> Arc<Store> {
+        Arc::new(Store::new(VNode {
+            element_type: element("div", &[], element("ul", &[], ())),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_channel_source_updates_keyed_list() {
+        let store = dashboard();
+        let mut updates = store.subscribe();
+        let (sender, source) = ChannelSource::new(8);
+        let handle = connect(&source, store.clone(), vec![0], Some(2));
+
+        for (key, value) in [("a", "1"), ("b", "2"), ("a", "3"), ("c", "4")] {
+            sender
+                .send(Change::Upsert(key.to_string(), element("li", &[], value)))
+                .await
+                .unwrap();
+        }
+        drop(sender);
+        handle.await.unwrap();
+
+        for _ in 0..3 {
+            updates.recv().await.unwrap();
+        }
+        let last = updates.recv().await.unwrap();
+        assert!(last
+            .diff
+            .iter()
+            .all(|patch| patch.path().len() == 2 && patch.path()[0] == 0));
+        assert_eq!(
+            store.snapshot().tree.to_string(),
+            r#"<div ><ul ><li key="b">2</li><li key="c">4</li></ul></div>"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_tail() {
+        let path = std::env::temp_dir().join(format!("vdom-tail-{}.log", std::process::id()));
+        std::fs::write(&path, "first\nsecond\npart").unwrap();
+
+        let source = FileTail::new(path.clone(), Duration::from_millis(5));
+        let changes: Vec<Change> = source.subscribe().take(2).collect().await;
+
+        assert_eq!(
+            changes,
+            vec![
+                Change::Upsert("0".to_string(), element("li", &[], "first")),
+                Change::Upsert("1".to_string(), element("li", &[], "second")),
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::ws::ServerMessage;
+
+/**
+ * 開発モードで変更を監視するディレクトリ
+ */
+pub const WATCHED_DIRS: [&str; 2] = ["src", "static"];
+
+/**
+ * 監視対象のファイルと最終更新時刻の一覧
+ */
+pub type Snapshot = HashMap<PathBuf, SystemTime>;
+
+/**
+ * 監視対象のディレクトリ配下にあるファイルの最終更新時刻を取得する関数
+ * 存在しないディレクトリは無視する
+ */
+pub fn snapshot(roots: &[PathBuf]) -> Snapshot {
+    let mut files = HashMap::new();
+    for root in roots {
+        collect_files(root, &mut files);
+    }
+    files
+}
+
+fn collect_files(path: &Path, files: &mut Snapshot) {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            collect_files(&entry.path(), files);
+        }
+    } else if let Ok(modified) = metadata.modified() {
+        files.insert(path.to_path_buf(), modified);
+    }
+}
+
+/**
+ * 一定間隔でファイルの変更を検出し、変更があれば Reload を配信するタスクを起動する関数
+ */
+pub fn watch(
+    roots: Vec<PathBuf>,
+    interval: Duration,
+    sender: broadcast::Sender<ServerMessage>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut previous = snapshot(&roots);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let current = snapshot(&roots);
+            if current != previous {
+                println!("Detected changes, reloading connected clients");
+                // 接続中のクライアントがいない場合の送信エラーは無視する
+                let _ = sender.send(ServerMessage::Reload);
+                previous = current;
+            }
+        }
+    })
+}
+
+/**
+ * クレートのルートを基準にした監視対象ディレクトリのうち、存在するものの一覧を返す関数
+ */
+pub fn default_roots() -> Vec<PathBuf> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    WATCHED_DIRS
+        .iter()
+        .map(|dir| manifest_dir.join(dir))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watch_sends_reload() {
+        let root = std::env::temp_dir().join(format!("vdom-dev-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("index.html"), "v1").unwrap();
+
+        let (sender, mut receiver) = broadcast::channel(4);
+        let handle = watch(vec![root.clone()], Duration::from_millis(10), sender);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        std::fs::write(root.join("added.html"), "v2").unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message, ServerMessage::Reload);
+
+        handle.abort();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use crate::builder::element;
+use crate::self_virtual_dom::{virtual_dom_to_html, ElementType};
+
+/**
+ * HTML5 の DOCTYPE 宣言
+ */
+pub const DOCTYPE: &str = "<!DOCTYPE html>";
+
+/**
+ * DOCTYPE と html/head/body を持つ1ページ分のHTML文書を表す構造体
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /** html 要素の lang 属性 */
+    pub lang: String,
+    /** title 要素の内容 */
+    pub title: Option<String>,
+    /** title 以外に head に置く要素 */
+    pub head: Vec<ElementType>,
+    /** body の子要素 */
+    pub body: Vec<ElementType>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            lang: "en".to_string(),
+            title: None,
+            head: vec![
+                element("meta", &[("charset", "UTF-8")], ()),
+                element(
+                    "meta",
+                    &[
+                        ("name", "viewport"),
+                        ("content", "width=device-width, initial-scale=1.0"),
+                    ],
+                    (),
+                ),
+            ],
+            body: Vec::new(),
+        }
+    }
+}
+
+impl Document {
+    /**
+     * 文字コードと viewport の meta を持つ空の文書を返す関数
+     */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_lang(mut self, lang: &str) -> Self {
+        self.lang = lang.to_string();
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /**
+     * name と content を持つ meta 要素を head に追加する関数
+     */
+    pub fn with_meta(mut self, name: &str, content: &str) -> Self {
+        self.head
+            .push(element("meta", &[("name", name), ("content", content)], ()));
+        self
+    }
+
+    /**
+     * link 要素を head に追加する関数
+     */
+    pub fn with_link(mut self, rel: &str, href: &str) -> Self {
+        self.head
+            .push(element("link", &[("rel", rel), ("href", href)], ()));
+        self
+    }
+
+    /**
+     * 外部スクリプトを読み込む script 要素を head に追加する関数
+     */
+    pub fn with_script(mut self, src: &str) -> Self {
+        self.head
+            .push(element("script", &[("src", src), ("defer", "")], ()));
+        self
+    }
+
+    /**
+     * 要素を body の末尾に追加する関数
+     */
+    pub fn with_body(mut self, node: ElementType) -> Self {
+        self.body.push(node);
+        self
+    }
+
+    /**
+     * 文書を html 要素を根とする仮想DOMに変換する関数
+     */
+    pub fn to_tree(&self) -> ElementType {
+        let title = self
+            .title
+            .as_ref()
+            .map(|title| element("title", &[], title));
+        element(
+            "html",
+            &[("lang", &self.lang)],
+            (
+                element("head", &[], (self.head.clone(), title)),
+                element("body", &[], self.body.clone()),
+            ),
+        )
+    }
+}
+
+/**
+ * 文書を DOCTYPE から始まる完全なHTMLに変換する関数
+ */
+pub fn render_document(document: &Document) -> String {
+    format!("{}{}", DOCTYPE, virtual_dom_to_html(&document.to_tree()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_document() {
+        let document = Document::new()
+            .with_lang("ja")
+            .with_title("Demo")
+            .with_link("stylesheet", "/app.css")
+            .with_body(element("div", &[("id", "app")], "Hello"));
+
+        let html = render_document(&document);
+        assert!(
+            html.starts_with("<!DOCTYPE html><html lang=\"ja\"><head ><meta charset=\"UTF-8\">")
+        );
+        assert!(html.contains("<link "));
+        assert!(!html.contains("</link>"));
+        assert!(html.ends_with(
+            "<title >Demo</title></head><body ><div id=\"app\">Hello</div></body></html>"
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+
+use crate::acl::AccessControl;
+use crate::auth::Identity;
+use crate::error::VdomError;
+use crate::event::DropEvent;
+use crate::iter::NodePath;
+use crate::patch::apply_patches;
+use crate::request_id;
+use crate::self_virtual_dom::{
+    update_dom, virtual_dom_to_html, AppResponse, Diff, ElementType, VNode, KEY_ATTR,
+};
+use crate::store::Store;
+
+/**
+ * ドロップを key 付きのリストの並べ替えとして受け付けられないことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropError {
+    pub source: NodePath,
+    pub target: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for DropError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot drop {:?} at {:?}: {}",
+            self.source, self.target, self.message
+        )
+    }
+}
+
+impl std::error::Error for DropError {}
+
+impl From<DropError> for VdomError {
+    fn from(err: DropError) -> Self {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    }
+}
+
+/**
+ * 子要素がすべて key を持つ要素かを判定する関数
+ * key の重複するリストは項目を区別できないため、key 付きのリストとして扱わない
+ */
+fn is_keyed_list(node: &ElementType) -> bool {
+    let ElementType::Element(_, _, children) = node else {
+        return false;
+    };
+    let mut keys = Vec::with_capacity(children.len());
+    for child in children {
+        let ElementType::Element(_, attrs, _) = child else {
+            return false;
+        };
+        let Some(key) = attrs.get(KEY_ATTR) else {
+            return false;
+        };
+        if keys.contains(&key) {
+            return false;
+        }
+        keys.push(key);
+    }
+    true
+}
+
+/**
+ * ドロップを検証し、ドラッグした要素を移動する MoveNode の差分に変換する関数
+ * source は古い木での位置、target は移動後の木での位置で、どちらも key 付きのリストの項目でなければならない
+ */
+pub fn move_patch(tree: &ElementType, drop: &DropEvent) -> Result<Diff, DropError> {
+    let error = |message: &str| DropError {
+        source: drop.source.clone(),
+        target: drop.target.clone(),
+        message: message.to_string(),
+    };
+    let (Some((_, source_parent)), Some(_)) = (drop.source.split_last(), drop.target.split_last())
+    else {
+        return Err(error("the root cannot be moved"));
+    };
+    let node = tree
+        .get(&drop.source)
+        .ok_or_else(|| error("source does not exist"))?;
+    if !matches!(node, ElementType::Element(_, attrs, _) if attrs.get(KEY_ATTR).map(|key| key.as_ref()) == Some(drop.key.as_str()))
+    {
+        return Err(error("source does not have the dragged key"));
+    }
+    if !tree.get(source_parent).is_some_and(is_keyed_list) {
+        return Err(error("source is not an item of a keyed list"));
+    }
+    if drop.target.starts_with(&drop.source) {
+        return Err(error("an element cannot be dropped into itself"));
+    }
+
+    let patch = Diff::MoveNode(
+        drop.source.clone(),
+        drop.target.clone(),
+        VNode {
+            element_type: node.clone(),
+        },
+    );
+    let mut moved = tree.clone();
+    apply_patches(&mut moved, std::slice::from_ref(&patch)).map_err(|err| error(&err.message))?;
+    let target_parent = &drop.target[..drop.target.len() - 1];
+    if !moved.get(target_parent).is_some_and(is_keyed_list) {
+        return Err(error("target is not a position in a keyed list"));
+    }
+    Ok(patch)
+}
+
+/**
+ * 送信者 identity のドロップをストアの木に適用し、MoveNode の差分を返す関数
+ * ドロップのパスを求めた後に木が更新されていた場合や、検証に失敗した場合、移動元か移動先が保護された部分木の場合はストアを変更しない
+ * ストアに登録されたパスが移動以外の部分も書き換えた場合は、MoveNode の代わりに通常の差分の計算結果を返す
+ */
+pub fn apply_drop(
+    store: &Store,
+    drop: &DropEvent,
+    identity: &Identity,
+    access_control: &AccessControl,
+) -> Result<AppResponse, VdomError> {
+    let mut moved = None;
+    let (before, after) = store.try_modify_snapshots(|tree, version| {
+        if version != drop.base_version {
+            return Err(VdomError::from(DropError {
+                source: drop.source.clone(),
+                target: drop.target.clone(),
+                message: format!(
+                    "the tree was updated from version {} to {}",
+                    drop.base_version, version
+                ),
+            }));
+        }
+        let patch = move_patch(&tree.element_type, drop)?;
+        let mut moved_tree = tree.clone();
+        // move_patch で適用できることを確かめているため失敗しない
+        let _ = apply_patches(&mut moved_tree.element_type, std::slice::from_ref(&patch));
+        access_control.check(
+            identity,
+            &tree.element_type,
+            &moved_tree.element_type,
+            std::slice::from_ref(&patch),
+        )?;
+        *tree = moved_tree.clone();
+        moved = Some((patch, moved_tree));
+        Ok(())
+    })?;
+    let (patch, moved) = moved.expect("the closure succeeded");
+    let diff = if after.version == before.version {
+        Vec::new()
+    } else if after.tree == moved {
+        vec![patch]
+    } else {
+        update_dom(&before.tree, &after.tree).diff
+    };
+    Ok(AppResponse {
+        diff,
+        html: virtual_dom_to_html(&after.tree.element_type),
+        request_id: request_id::current(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Access;
+    use crate::builder::{element, keyed_list};
+
+    fn lists() -> ElementType {
+        let list = |items: &[&'static str]| {
+            element(
+                "ul",
+                &[],
+                keyed_list(
+                    items.iter().copied(),
+                    |item| *item,
+                    |item| element("li", &[], *item),
+                ),
+            )
+        };
+        element("div", &[], (list(&["a", "b", "c"]), list(&["d"])))
+    }
+
+    #[test]
+    fn test_move_patch() {
+        let drop = |source: NodePath, target: NodePath, key: &str| DropEvent {
+            source,
+            target,
+            key: key.to_string(),
+            base_version: 0,
+        };
+        let patch = move_patch(&lists(), &drop(vec![0, 0], vec![0, 2], "a")).unwrap();
+        assert!(
+            matches!(&patch, Diff::MoveNode(from, to, _) if from == &vec![0, 0] && to == &vec![0, 2])
+        );
+
+        let mut tree = lists();
+        let patch = move_patch(&tree, &drop(vec![0, 1], vec![1, 0], "b")).unwrap();
+        apply_patches(&mut tree, &[patch]).unwrap();
+        assert_eq!(
+            tree.to_string(),
+            r#"<div ><ul ><li key="a">a</li><li key="c">c</li></ul><ul ><li key="b">b</li><li key="d">d</li></ul></div>"#
+        );
+
+        assert!(move_patch(&lists(), &drop(vec![0], vec![1], "")).is_err());
+        assert!(move_patch(&lists(), &drop(vec![0, 0], vec![0, 0, 0], "a")).is_err());
+        assert!(move_patch(&lists(), &drop(vec![0, 0], vec![0, 5], "a")).is_err());
+        // key が一致しない場合は別の要素をドラッグしていたため受け付けない
+        assert!(move_patch(&lists(), &drop(vec![0, 0], vec![0, 2], "b")).is_err());
+    }
+
+    #[test]
+    fn test_apply_drop() {
+        let store = Store::new(VNode {
+            element_type: lists(),
+        });
+        let drop = |source: NodePath, key: &str, base_version: u64| DropEvent {
+            source,
+            target: vec![0, 0],
+            key: key.to_string(),
+            base_version,
+        };
+        let anonymous = Identity::anonymous();
+        let open = AccessControl::new();
+        let app_response =
+            apply_drop(&store, &drop(vec![0, 2], "c", 0), &anonymous, &open).unwrap();
+        assert_eq!(app_response.diff.len(), 1);
+        assert!(matches!(app_response.diff[0], Diff::MoveNode(..)));
+        assert_eq!(store.version(), 1);
+
+        let result = apply_drop(&store, &drop(vec![9, 0], "c", 1), &anonymous, &open);
+        assert!(matches!(result, Err(VdomError::BadRequest { .. })));
+        // 古いバージョンで求めたパスは受け付けない
+        let result = apply_drop(&store, &drop(vec![0, 2], "b", 0), &anonymous, &open);
+        assert!(matches!(result, Err(VdomError::BadRequest { .. })));
+        assert_eq!(store.version(), 1);
+
+        // 保護されたリストへは移動できない
+        let protected = AccessControl::new().protect(vec![0], Access::ReadOnly);
+        let result = apply_drop(&store, &drop(vec![1, 0], "d", 1), &anonymous, &protected);
+        assert!(matches!(result, Err(VdomError::AccessDenied(_))));
+        assert_eq!(store.version(), 1);
+
+        // 登録されたパスが移動以外の部分も書き換えた場合は、通常の差分を返す
+        struct MarkFirst;
+        impl crate::visit::Transformer for MarkFirst {
+            fn pre(&mut self, path: &[usize], node: &mut ElementType) {
+                if let (Some(0), ElementType::Element(tag, attrs, _)) = (path.last(), node) {
+                    if tag == "li" {
+                        attrs.insert("class".into(), "first".into());
+                    }
+                }
+            }
+        }
+        store.register_transform(MarkFirst);
+        let mut tree = store.snapshot().tree.element_type;
+        let app_response =
+            apply_drop(&store, &drop(vec![0, 2], "b", 1), &anonymous, &open).unwrap();
+        apply_patches(&mut tree, &app_response.diff).unwrap();
+        assert!(app_response.diff.len() > 1);
+        assert_eq!(virtual_dom_to_html(&tree), app_response.html);
+        assert_eq!(store.version(), 2);
+    }
+}
@@ -0,0 +1,239 @@
+use serde::Serialize;
+use std::fmt;
+use warp::reject::Reject;
+
+use crate::acl::AclViolation;
+use crate::auth::AuthError;
+use crate::binding::BindingError;
+use crate::sanitize::InputError;
+
+/**
+ * エラーレスポンスの Content-Type（RFC 7807）
+ */
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/**
+ * 仮想DOMのエンドポイントで発生するエラーを表す列挙型
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind")]
+pub enum VdomError {
+    /** 保護された部分木を変更しようとした */
+    AccessDenied(AclViolation),
+    /** 登録されていないフォームが送信された */
+    UnknownForm { name: String },
+    /** 処理が登録されていない種類のイベントが送られた */
+    UnhandledEvent { event: String },
+    /** 送信者を認証できなかった */
+    Unauthorized { message: String },
+    /** 流量制限を超えた */
+    RateLimited,
+    /** 送られた仮想DOMの形式が正しくない */
+    InvalidTree { path: String, message: String },
+    /** リクエストの形式が正しくない */
+    BadRequest { message: String },
+    /** 入力が規則に合わない */
+    InvalidInput { path: String, message: String },
+    /** ルートが存在しない */
+    NotFound,
+    /** ルートが対応していないメソッドで呼び出された */
+    MethodNotAllowed,
+    /** リクエストボディが大きすぎる */
+    PayloadTooLarge,
+    /** 想定していないエラー */
+    Internal { message: String },
+}
+
+/**
+ * 想定していないエラーでクライアントに返す説明
+ */
+pub const INTERNAL_ERROR_DETAIL: &str = "an internal error occurred";
+
+/**
+ * RFC 7807 の problem details を表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /** エラーが発生した時点の仮想DOMのバージョン */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+impl VdomError {
+    /**
+     * エラーに対応するHTTPステータスコードを返す関数
+     */
+    pub fn status_code(&self) -> u16 {
+        match self {
+            VdomError::AccessDenied(_) => 403,
+            VdomError::UnknownForm { .. } | VdomError::UnhandledEvent { .. } => 404,
+            VdomError::Unauthorized { .. } => 401,
+            VdomError::RateLimited => 429,
+            VdomError::InvalidTree { .. } | VdomError::BadRequest { .. } => 400,
+            VdomError::InvalidInput { .. } => 422,
+            VdomError::NotFound => 404,
+            VdomError::MethodNotAllowed => 405,
+            VdomError::PayloadTooLarge => 413,
+            VdomError::Internal { .. } => 500,
+        }
+    }
+
+    /**
+     * エラーの種類を表す識別子（problem の type に使う）を返す関数
+     */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VdomError::AccessDenied(_) => "access-denied",
+            VdomError::UnknownForm { .. } => "unknown-form",
+            VdomError::UnhandledEvent { .. } => "unhandled-event",
+            VdomError::Unauthorized { .. } => "unauthorized",
+            VdomError::RateLimited => "rate-limited",
+            VdomError::InvalidTree { .. } => "invalid-tree",
+            VdomError::BadRequest { .. } => "bad-request",
+            VdomError::InvalidInput { .. } => "invalid-input",
+            VdomError::NotFound => "not-found",
+            VdomError::MethodNotAllowed => "method-not-allowed",
+            VdomError::PayloadTooLarge => "payload-too-large",
+            VdomError::Internal { .. } => "internal",
+        }
+    }
+
+    /**
+     * エラーの要約を返す関数
+     */
+    pub fn title(&self) -> &'static str {
+        match self {
+            VdomError::AccessDenied(_) => "Protected subtree",
+            VdomError::UnknownForm { .. } => "Unknown form",
+            VdomError::UnhandledEvent { .. } => "Unhandled event",
+            VdomError::Unauthorized { .. } => "Unauthorized",
+            VdomError::RateLimited => "Too Many Requests",
+            VdomError::InvalidTree { .. } => "Invalid virtual DOM",
+            VdomError::BadRequest { .. } => "Bad Request",
+            VdomError::InvalidInput { .. } => "Unprocessable Content",
+            VdomError::NotFound => "Not Found",
+            VdomError::MethodNotAllowed => "Method Not Allowed",
+            VdomError::PayloadTooLarge => "Payload Too Large",
+            VdomError::Internal { .. } => "Internal Server Error",
+        }
+    }
+
+    /**
+     * エラーを problem details に変換する関数
+     * 想定していないエラーの内容はサーバーの内部の情報を含むため、ログに出力してクライアントには一般的なメッセージを返す
+     */
+    pub fn to_problem(&self, version: Option<u64>) -> Problem {
+        let detail = match self {
+            VdomError::Internal { message } => {
+                eprintln!("Internal error: {}", message);
+                INTERNAL_ERROR_DETAIL.to_string()
+            }
+            _ => self.to_string(),
+        };
+        Problem {
+            type_: format!("/problems/{}", self.kind()),
+            title: self.title().to_string(),
+            status: self.status_code(),
+            detail,
+            version,
+        }
+    }
+}
+
+impl fmt::Display for VdomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VdomError::AccessDenied(violation) => write!(
+                f,
+                "patch at {:?} touches protected subtree {:?}",
+                violation.path, violation.protected_path
+            ),
+            VdomError::UnknownForm { name } => write!(f, "form {:?} is not registered", name),
+            VdomError::UnhandledEvent { event } => {
+                write!(f, "no handler is registered for {:?} events", event)
+            }
+            VdomError::Unauthorized { message }
+            | VdomError::BadRequest { message }
+            | VdomError::Internal { message } => write!(f, "{}", message),
+            VdomError::RateLimited => write!(f, "request rate limit exceeded"),
+            VdomError::InvalidTree { path, message } if path.is_empty() => {
+                write!(f, "{}", message)
+            }
+            VdomError::InvalidTree { path, message } => write!(f, "{} {}", path, message),
+            VdomError::InvalidInput { path, message } => write!(f, "{}: {}", path, message),
+            VdomError::NotFound => write!(f, "no route matches the request"),
+            VdomError::MethodNotAllowed => write!(f, "method is not allowed for this route"),
+            VdomError::PayloadTooLarge => write!(f, "request body is too large"),
+        }
+    }
+}
+
+impl std::error::Error for VdomError {}
+
+impl Reject for VdomError {}
+
+impl From<AclViolation> for VdomError {
+    fn from(violation: AclViolation) -> Self {
+        VdomError::AccessDenied(violation)
+    }
+}
+
+impl From<AuthError> for VdomError {
+    fn from(err: AuthError) -> Self {
+        VdomError::Unauthorized {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<InputError> for VdomError {
+    fn from(err: InputError) -> Self {
+        VdomError::InvalidInput {
+            path: err.path,
+            message: err.message,
+        }
+    }
+}
+
+impl From<BindingError> for VdomError {
+    fn from(err: BindingError) -> Self {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_json() {
+        let problem = VdomError::UnknownForm {
+            name: "login".to_string(),
+        }
+        .to_problem(Some(3));
+
+        assert_eq!(
+            serde_json::to_value(problem).unwrap(),
+            serde_json::json!({
+                "type": "/problems/unknown-form",
+                "title": "Unknown form",
+                "status": 404,
+                "detail": "form \"login\" is not registered",
+                "version": 3,
+            })
+        );
+
+        let problem = VdomError::Internal {
+            message: "unhandled rejection: Rejection(Secret)".to_string(),
+        }
+        .to_problem(None);
+        assert_eq!(problem.detail, INTERNAL_ERROR_DETAIL);
+    }
+}
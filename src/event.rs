@@ -0,0 +1,790 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::{Filter, Rejection, Reply};
+
+use crate::acl::AccessControl;
+use crate::auth::{AuthProvider, Identity};
+use crate::error::VdomError;
+use crate::form::Forms;
+use crate::iter::NodePath;
+use crate::optimistic::{reconcile, reconcile_snapshots, Prediction, Reconciliation};
+use crate::rate_limit::{rate_limit, RateLimiter};
+use crate::request_id::{self, request_id};
+use crate::sanitize::{InputError, InputPolicy};
+use crate::self_virtual_dom::{update_dom, AppResponse};
+use crate::server::error_reply;
+use crate::store::{Snapshot, Store};
+
+/**
+ * キーボードイベントと同時に押されていた修飾キー
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub alt: bool,
+    pub ctrl: bool,
+    pub meta: bool,
+    pub shift: bool,
+}
+
+/**
+ * keydown / keyup のイベント
+ * key は入力された文字や `Enter` などのキーの名前、code は物理的なキーの位置
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct KeyEvent {
+    /** イベントが発生した要素のパス */
+    pub target: NodePath,
+    pub key: String,
+    #[serde(default)]
+    pub code: String,
+    /** キーを押し続けたことによる繰り返しの場合は true */
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+}
+
+/**
+ * focus / blur のイベント
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FocusEvent {
+    pub target: NodePath,
+    /** フォーカスが移る前（focus）または移った先（blur）の要素のパス */
+    #[serde(default)]
+    pub related_target: Option<NodePath>,
+}
+
+/**
+ * 入力欄の値が確定したときの change イベント
+ * チェックボックスとラジオボタンでは checked も送る
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChangeEvent {
+    pub target: NodePath,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub checked: Option<bool>,
+}
+
+/**
+ * IME による変換のイベント
+ * data は変換中または確定した文字列
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CompositionEvent {
+    pub target: NodePath,
+    #[serde(default)]
+    pub data: String,
+}
+
+/**
+ * dragstart / dragend のイベント
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DragEvent {
+    /** ドラッグしている要素のパス */
+    pub source: NodePath,
+}
+
+/**
+ * drop のイベント
+ * source はドラッグした要素の移動前のパス、target は移動後の木でその要素を置くパス
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DropEvent {
+    pub source: NodePath,
+    pub target: NodePath,
+    /** ドラッグした要素の key。source の要素の key と一致しなければならない */
+    pub key: String,
+    /** パスを求めたときの仮想DOMのバージョン。その後に木が更新されていればパスがずれているため受け付けない */
+    pub base_version: u64,
+}
+
+/**
+ * クリップボードからの貼り付けのイベント
+ * html はクリップボードにHTMLがある場合だけ送られる
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PasteEvent {
+    pub target: NodePath,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub html: Option<String>,
+}
+
+impl PasteEvent {
+    /**
+     * 貼り付けられたテキストとHTMLに入力の規則を適用する関数
+     */
+    pub fn sanitize(self, policy: &InputPolicy) -> Result<Self, InputError> {
+        Ok(Self {
+            text: policy.sanitize_text("paste.text", &self.text)?,
+            html: self
+                .html
+                .map(|html| policy.sanitize_html("paste.html", &html))
+                .transpose()?,
+            target: self.target,
+        })
+    }
+}
+
+/**
+ * ドロップされた1つのファイルの情報
+ * 内容は含まず、必要であれば `POST /upload` で別に送る
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DroppedFile {
+    pub name: String,
+    /** バイト数 */
+    pub size: u64,
+    /** MIME タイプ。ブラウザが判定できなかった場合は空になる */
+    #[serde(default)]
+    pub mime: String,
+}
+
+/**
+ * ファイルをドロップしたイベント
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FileDropEvent {
+    pub target: NodePath,
+    pub files: Vec<DroppedFile>,
+}
+
+/**
+ * スクロールのイベント
+ * 位置と表示領域の大きさは CSS ピクセルを整数に丸めた値
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ScrollEvent {
+    /** スクロールした要素のパス。文書全体の場合は空 */
+    pub target: NodePath,
+    pub top: u32,
+    pub left: u32,
+    /** スクロールした要素の表示領域の高さ */
+    pub height: u32,
+    pub width: u32,
+}
+
+/**
+ * 要素が表示領域に入った、または表示領域から出たことを表すイベント
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VisibilityEvent {
+    pub target: NodePath,
+    pub visible: bool,
+}
+
+/**
+ * data-action 属性を持つ要素をクリックしたイベント
+ * action は要素の data-action 属性の値で、components::button などが付ける
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ActionEvent {
+    pub action: String,
+}
+
+/**
+ * クライアントから送られるイベントを表す列挙型
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientEvent {
+    /** フォームの送信 */
+    Submit {
+        form: String,
+        values: HashMap<String, String>,
+    },
+    KeyDown(KeyEvent),
+    KeyUp(KeyEvent),
+    Focus(FocusEvent),
+    Blur(FocusEvent),
+    Change(ChangeEvent),
+    CompositionStart(CompositionEvent),
+    CompositionUpdate(CompositionEvent),
+    CompositionEnd(CompositionEvent),
+    DragStart(DragEvent),
+    DragEnd(DragEvent),
+    Drop(DropEvent),
+    Paste(PasteEvent),
+    FileDrop(FileDropEvent),
+    Scroll(ScrollEvent),
+    Visibility(VisibilityEvent),
+    Action(ActionEvent),
+}
+
+/**
+ * イベントの種類を表す列挙型
+ * 処理する関数はイベントの種類ごとに登録する
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Submit,
+    KeyDown,
+    KeyUp,
+    Focus,
+    Blur,
+    Change,
+    CompositionStart,
+    CompositionUpdate,
+    CompositionEnd,
+    DragStart,
+    DragEnd,
+    Drop,
+    Paste,
+    FileDrop,
+    Scroll,
+    Visibility,
+    Action,
+}
+
+impl EventKind {
+    /**
+     * リクエストの type に書く名前を返す関数
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Submit => "submit",
+            EventKind::KeyDown => "key_down",
+            EventKind::KeyUp => "key_up",
+            EventKind::Focus => "focus",
+            EventKind::Blur => "blur",
+            EventKind::Change => "change",
+            EventKind::CompositionStart => "composition_start",
+            EventKind::CompositionUpdate => "composition_update",
+            EventKind::CompositionEnd => "composition_end",
+            EventKind::DragStart => "drag_start",
+            EventKind::DragEnd => "drag_end",
+            EventKind::Drop => "drop",
+            EventKind::Paste => "paste",
+            EventKind::FileDrop => "file_drop",
+            EventKind::Scroll => "scroll",
+            EventKind::Visibility => "visibility",
+            EventKind::Action => "action",
+        }
+    }
+}
+
+impl ClientEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            ClientEvent::Submit { .. } => EventKind::Submit,
+            ClientEvent::KeyDown(_) => EventKind::KeyDown,
+            ClientEvent::KeyUp(_) => EventKind::KeyUp,
+            ClientEvent::Focus(_) => EventKind::Focus,
+            ClientEvent::Blur(_) => EventKind::Blur,
+            ClientEvent::Change(_) => EventKind::Change,
+            ClientEvent::CompositionStart(_) => EventKind::CompositionStart,
+            ClientEvent::CompositionUpdate(_) => EventKind::CompositionUpdate,
+            ClientEvent::CompositionEnd(_) => EventKind::CompositionEnd,
+            ClientEvent::DragStart(_) => EventKind::DragStart,
+            ClientEvent::DragEnd(_) => EventKind::DragEnd,
+            ClientEvent::Drop(_) => EventKind::Drop,
+            ClientEvent::Paste(_) => EventKind::Paste,
+            ClientEvent::FileDrop(_) => EventKind::FileDrop,
+            ClientEvent::Scroll(_) => EventKind::Scroll,
+            ClientEvent::Visibility(_) => EventKind::Visibility,
+            ClientEvent::Action(_) => EventKind::Action,
+        }
+    }
+}
+
+/**
+ * イベントを処理し、ストアを更新した結果を返す関数の型
+ * 渡されるストアは作業用のもので、変更は保護の検証を通った後に元のストアへ反映される
+ */
+pub type EventHandler =
+    Box<dyn Fn(&ClientEvent, &Store) -> Result<AppResponse, VdomError> + Send + Sync>;
+
+/**
+ * イベントの種類ごとに登録された処理の一覧
+ * フォームの送信は Forms で処理するため、Submit に登録した処理は呼ばれない
+ * 貼り付けの内容は、処理に渡す前に入力の規則を適用する
+ */
+#[derive(Default)]
+pub struct EventHandlers {
+    handlers: HashMap<EventKind, EventHandler>,
+    input_policy: InputPolicy,
+}
+
+impl EventHandlers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 種類を指定して処理を登録する関数
+     * 同じ種類に登録し直した場合は後から登録した処理で置き換える
+     */
+    pub fn on(
+        mut self,
+        kind: EventKind,
+        handler: impl Fn(&ClientEvent, &Store) -> Result<AppResponse, VdomError> + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers.insert(kind, Box::new(handler));
+        self
+    }
+
+    /**
+     * 貼り付けの内容に適用する規則を設定する関数
+     */
+    pub fn with_input_policy(mut self, input_policy: InputPolicy) -> Self {
+        self.input_policy = input_policy;
+        self
+    }
+
+    /**
+     * 送信者 identity のイベントを種類に対応する処理に渡す関数
+     * 処理はストアの木を複製した作業用のストアで行い、変更が access_control の保護に違反しない場合だけストアに反映する
+     * 処理の間はストアへの書き込みを止めるため、処理は渡されたストア以外を更新してはならない
+     */
+    pub fn dispatch(
+        &self,
+        event: &ClientEvent,
+        store: &Store,
+        identity: &Identity,
+        access_control: &AccessControl,
+    ) -> Result<AppResponse, VdomError> {
+        self.dispatch_snapshots(event, store, identity, access_control)
+            .map(|(app_response, _, _)| app_response)
+    }
+
+    /**
+     * dispatch と同じくイベントを処理し、処理の直前と直後のスナップショットも返す関数
+     */
+    pub fn dispatch_snapshots(
+        &self,
+        event: &ClientEvent,
+        store: &Store,
+        identity: &Identity,
+        access_control: &AccessControl,
+    ) -> Result<(AppResponse, Snapshot, Snapshot), VdomError> {
+        let kind = event.kind();
+        let Some(handler) = self.handlers.get(&kind) else {
+            return Err(VdomError::UnhandledEvent {
+                event: kind.as_str().to_string(),
+            });
+        };
+        let event = match event {
+            ClientEvent::Paste(paste) => Cow::Owned(ClientEvent::Paste(
+                paste.clone().sanitize(&self.input_policy)?,
+            )),
+            _ => Cow::Borrowed(event),
+        };
+
+        let mut handled = None;
+        let (before, after) = store.try_modify_snapshots(|tree, version| {
+            let scratch = Store::from_snapshot(Snapshot {
+                tree: tree.clone(),
+                version,
+            });
+            let app_response = handler(&event, &scratch)?;
+            let changed = scratch.snapshot().tree;
+            let patches = update_dom(tree, &changed).diff;
+            access_control.check(
+                identity,
+                &tree.element_type,
+                &changed.element_type,
+                &patches,
+            )?;
+            *tree = changed.clone();
+            handled = Some((app_response, changed));
+            Ok::<(), VdomError>(())
+        })?;
+        let (app_response, changed) = handled.expect("the closure succeeded");
+        // ストアに登録されたパスが木を書き換えた場合は、処理の結果ではなく実際の変更の差分を返す
+        let app_response = if after.version == before.version || after.tree == changed {
+            app_response
+        } else {
+            AppResponse {
+                request_id: request_id::current(),
+                ..update_dom(&before.tree, &after.tree)
+            }
+        };
+        Ok((app_response, before, after))
+    }
+}
+
+macro_rules! impl_typed_handlers {
+    ($($method:ident => $variant:ident($payload:ty)),* $(,)?) => {
+        impl EventHandlers {
+            $(
+                #[doc = concat!("`", stringify!($variant), "` の内容を受け取る処理を登録する関数")]
+                pub fn $method(
+                    self,
+                    handler: impl Fn(&$payload, &Store) -> Result<AppResponse, VdomError>
+                        + Send
+                        + Sync
+                        + 'static,
+                ) -> Self {
+                    self.on(EventKind::$variant, move |event, store| match event {
+                        ClientEvent::$variant(payload) => handler(payload, store),
+                        _ => unreachable!("handlers are looked up by the event kind"),
+                    })
+                }
+            )*
+        }
+    };
+}
+
+impl_typed_handlers!(
+    on_key_down => KeyDown(KeyEvent),
+    on_key_up => KeyUp(KeyEvent),
+    on_focus => Focus(FocusEvent),
+    on_blur => Blur(FocusEvent),
+    on_change => Change(ChangeEvent),
+    on_composition_start => CompositionStart(CompositionEvent),
+    on_composition_update => CompositionUpdate(CompositionEvent),
+    on_composition_end => CompositionEnd(CompositionEvent),
+    on_drag_start => DragStart(DragEvent),
+    on_drag_end => DragEnd(DragEvent),
+    on_drop => Drop(DropEvent),
+    on_paste => Paste(PasteEvent),
+    on_file_drop => FileDrop(FileDropEvent),
+    on_scroll => Scroll(ScrollEvent),
+    on_visibility => Visibility(VisibilityEvent),
+    on_action => Action(ActionEvent),
+);
+
+/**
+ * `POST /event` のリクエストボディ
+ * prediction にはクライアントがイベントの結果として先に適用した差分を入れる
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EventRequest {
+    #[serde(flatten)]
+    pub event: ClientEvent,
+    #[serde(default)]
+    pub prediction: Option<Prediction>,
+}
+
+/**
+ * イベントの処理結果に、予測した差分の突き合わせ結果を加えたレスポンス
+ */
+#[derive(Debug, Serialize)]
+struct EventReply<T> {
+    #[serde(flatten)]
+    result: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconciliation: Option<Reconciliation>,
+}
+
+/**
+ * クライアントのイベントを受け取り、処理結果の差分を返す `POST /event` ルートを返す関数
+ * 送信者を auth で認証して limiter で流量を制限し、処理による変更は access_control で検証する
+ * フォームの送信は forms で、それ以外のイベントは handlers に登録された処理で扱う
+ * 予測した差分が送られた場合は、ストアの木と突き合わせた結果も返す
+ */
+pub fn event_route(
+    forms: Arc<Forms>,
+    handlers: Arc<EventHandlers>,
+    store: Arc<Store>,
+    limiter: Arc<RateLimiter>,
+    auth: Arc<dyn AuthProvider>,
+    access_control: AccessControl,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("event")
+        .and(warp::post())
+        .and(rate_limit(limiter, auth))
+        .and(request_id())
+        .and(warp::body::json())
+        .map(
+            move |identity: Identity, id: String, request: EventRequest| {
+                request_id::scope(id, || match request.event {
+                    ClientEvent::Submit { form, values } => match forms.get(&form) {
+                        Some(form) => warp::reply::json(&EventReply {
+                            result: form.submit(&values),
+                            // フォームの送信はストアの木を変更しないため、予測は現在の木と突き合わせる
+                            reconciliation: request
+                                .prediction
+                                .map(|prediction| reconcile(&store, &prediction, |_| {})),
+                        })
+                        .into_response(),
+                        None => error_reply(
+                            &VdomError::UnknownForm { name: form },
+                            Some(store.version()),
+                        ),
+                    },
+                    event => match handlers.dispatch_snapshots(
+                        &event,
+                        &store,
+                        &identity,
+                        &access_control,
+                    ) {
+                        // 予測は処理の直前と直後の木と突き合わせる
+                        Ok((app_response, before, after)) => warp::reply::json(&EventReply {
+                            result: app_response,
+                            reconciliation: request
+                                .prediction
+                                .map(|prediction| reconcile_snapshots(&prediction, before, after)),
+                        })
+                        .into_response(),
+                        Err(err) => error_reply(&err, Some(store.version())),
+                    },
+                })
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Access;
+    use crate::app::initial_tree;
+    use crate::auth::{AllowAll, StaticTokens};
+    use crate::form::{required, Field, Form};
+    use crate::rate_limit::RateLimitConfig;
+    use crate::self_virtual_dom::ElementType;
+
+    fn open_event_route(
+        forms: Arc<Forms>,
+        handlers: Arc<EventHandlers>,
+        store: Arc<Store>,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        event_route(
+            forms,
+            handlers,
+            store,
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(AllowAll),
+            AccessControl::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_submit_event() {
+        let forms = Forms::new()
+            .register(Form::new("login").field(Field::new("user", "User").with(required())));
+        let store = Arc::new(Store::new(initial_tree()));
+        let route = open_event_route(Arc::new(forms), Arc::new(EventHandlers::new()), store);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({ "type": "submit", "form": "login", "values": {} }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["errors"]["user"], "This field is required");
+        assert!(body.get("reconciliation").is_none());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({
+                "type": "submit",
+                "form": "login",
+                "values": { "user": "alice" },
+                "prediction": { "version": 0, "diff": [] },
+            }))
+            .reply(&route)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["valid"], true);
+        assert_eq!(body["reconciliation"]["type"], "Ack");
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({ "type": "submit", "form": "other", "values": {} }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_key_event_dispatch() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let handlers = EventHandlers::new()
+            .on_key_down(|event, store| {
+                if event.key != "Enter" || !event.modifiers.ctrl {
+                    return Err(VdomError::BadRequest {
+                        message: format!("unexpected key {:?}", event.key),
+                    });
+                }
+                Ok(store.modify(|tree| {
+                    if let ElementType::Element(_, attrs, _) = &mut tree.element_type {
+                        attrs.insert("data-sent".into(), event.target.len().to_string().into());
+                    }
+                }))
+            })
+            .on_action(|event, store| {
+                Ok(store.modify(|tree| {
+                    if let ElementType::Element(_, attrs, _) = &mut tree.element_type {
+                        attrs.insert("data-action".into(), event.action.clone().into());
+                    }
+                }))
+            });
+        let route = open_event_route(Arc::new(Forms::new()), Arc::new(handlers), store.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({
+                "type": "key_down",
+                "target": [0, 1],
+                "key": "Enter",
+                "code": "Enter",
+                "modifiers": { "ctrl": true },
+            }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(store.version(), 1);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({ "type": "action", "action": "confirm:open" }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(store.version(), 2);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({ "type": "blur", "target": [0] }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 404);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["type"], "/problems/unhandled-event");
+    }
+
+    #[tokio::test]
+    async fn test_prediction_is_reconciled_after_dispatch() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let handlers = EventHandlers::new().on_action(|event, store| {
+            Ok(store.modify(|tree| {
+                if let ElementType::Element(_, attrs, _) = &mut tree.element_type {
+                    attrs.insert("data-action".into(), event.action.clone().into());
+                }
+            }))
+        });
+        let route = open_event_route(Arc::new(Forms::new()), Arc::new(handlers), store.clone());
+
+        // クライアントは何も変わらないと予測したが、ハンドラーが木を変更する
+        let response = warp::test::request()
+            .method("POST")
+            .path("/event")
+            .json(&serde_json::json!({
+                "type": "action",
+                "action": "open",
+                "prediction": { "version": 0, "diff": [] },
+            }))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(store.version(), 1);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["reconciliation"]["type"], "Correct");
+        assert_eq!(body["reconciliation"]["version"], 1);
+        assert!(!body["reconciliation"]["diff"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_paste_is_sanitized() {
+        let store = Store::new(initial_tree());
+        let handlers = EventHandlers::new()
+            .with_input_policy(InputPolicy::default().with_max_length(64))
+            .on_paste(|paste, store| {
+                assert_eq!(paste.html.as_deref(), Some("<b >bold</b>"));
+                Ok(store.modify(|_| {}))
+            });
+        let event: ClientEvent = serde_json::from_value(serde_json::json!({
+            "type": "paste",
+            "target": [0],
+            "text": "bold",
+            "html": "<b onmouseover=\"x()\">bold</b><script>x()</script>",
+        }))
+        .unwrap();
+        assert!(handlers
+            .dispatch(
+                &event,
+                &store,
+                &Identity::anonymous(),
+                &AccessControl::new()
+            )
+            .is_ok());
+
+        let event: ClientEvent = serde_json::from_value(serde_json::json!({
+            "type": "paste",
+            "target": [0],
+            "text": "x".repeat(65),
+        }))
+        .unwrap();
+        let err = handlers
+            .dispatch(
+                &event,
+                &store,
+                &Identity::anonymous(),
+                &AccessControl::new(),
+            )
+            .unwrap_err();
+        assert_eq!(err.status_code(), 422);
+
+        let event: ClientEvent = serde_json::from_value(serde_json::json!({
+            "type": "file_drop",
+            "target": [0],
+            "files": [{ "name": "a.png", "size": 12, "mime": "image/png" }],
+        }))
+        .unwrap();
+        let ClientEvent::FileDrop(drop) = &event else {
+            unreachable!()
+        };
+        assert_eq!(drop.files[0].size, 12);
+    }
+
+    #[tokio::test]
+    async fn test_event_route_checks_auth_and_access() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let handlers = EventHandlers::new().on_action(|event, store| {
+            Ok(store.modify(|tree| {
+                if let ElementType::Element(_, attrs, _) = &mut tree.element_type {
+                    attrs.insert("data-action".into(), event.action.clone().into());
+                }
+            }))
+        });
+        let route = event_route(
+            Arc::new(Forms::new()),
+            Arc::new(handlers),
+            store.clone(),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(StaticTokens::new().with_token("secret", Identity::new("alice"))),
+            AccessControl::new().protect(vec![], Access::Owner(Identity::new("bob"))),
+        );
+        let request = |token: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/event")
+                .header("authorization", format!("Bearer {}", token))
+                .json(&serde_json::json!({ "type": "action", "action": "open" }))
+        };
+
+        let response = request("wrong")
+            .reply(
+                &route
+                    .clone()
+                    .recover(|err| crate::server::recover_problem(err, None)),
+            )
+            .await;
+        assert_eq!(response.status(), 401);
+
+        let response = request("secret").reply(&route).await;
+        assert_eq!(response.status(), 403);
+        assert_eq!(store.version(), 0);
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::self_virtual_dom::{explained_diff, Attributes, Diff, DiffOptions, Text, VNode};
+
+/**
+ * 属性1つ分の変更。None は属性がないことを表す
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributeChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/**
+ * 差分が出力された理由を表す列挙型
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reason {
+    /** テキストノードの内容が変わった */
+    TextChanged { old: String, new: String },
+    /** ノードの種類が変わった、またはテキスト以外の要素でないノードが変わった */
+    NodeChanged,
+    /** 同一性の判定で別のノードと判定された */
+    IdentityChanged,
+    /** 同一性の判定で対応する子要素が見つからなかった */
+    NoMatchingNode,
+    /** タグ名が変わった */
+    TagChanged { old: String, new: String },
+    /** 属性が変わった */
+    AttributesChanged { changes: Vec<AttributeChange> },
+    /** key を持たない子要素の数が変わったため、親要素ごと置き換えた */
+    ChildCountChanged { old: usize, new: usize },
+    /** 残った key の順序が入れ替わったため、親要素ごと置き換えた */
+    KeysReordered,
+    /** key を持つ子要素が取り除かれた */
+    KeyRemoved { key: String },
+    /** key を持つ子要素が加えられた */
+    KeyAdded { key: String },
+    /** 部分木の中の差分よりも部分木ごと置き換える方が安いと見積もられた */
+    CheaperToReplace { patches: usize },
+    /** key を持つ要素が別の親要素へ移動した */
+    Moved { key: String },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => "(none)".to_string(),
+        };
+        match self {
+            Reason::TextChanged { old, new } => {
+                write!(f, "text changed from {:?} to {:?}", old, new)
+            }
+            Reason::NodeChanged => write!(f, "node changed"),
+            Reason::IdentityChanged => write!(f, "nodes are not the same node"),
+            Reason::NoMatchingNode => write!(f, "no matching node on the other side"),
+            Reason::TagChanged { old, new } => write!(f, "tag changed from <{}> to <{}>", old, new),
+            Reason::AttributesChanged { changes } => {
+                let changes: Vec<String> = changes
+                    .iter()
+                    .map(|change| {
+                        format!(
+                            "attr {} changed from {} to {}",
+                            change.name,
+                            show(&change.old),
+                            show(&change.new)
+                        )
+                    })
+                    .collect();
+                write!(f, "{}", changes.join(", "))
+            }
+            Reason::ChildCountChanged { old, new } => {
+                write!(f, "child count differs ({} -> {})", old, new)
+            }
+            Reason::KeysReordered => write!(f, "keyed children were reordered"),
+            Reason::KeyRemoved { key } => write!(f, "key {:?} was removed", key),
+            Reason::KeyAdded { key } => write!(f, "key {:?} was added", key),
+            Reason::CheaperToReplace { patches } => {
+                write!(f, "replacing is cheaper than {} patches", patches)
+            }
+            Reason::Moved { key } => write!(f, "key {:?} moved to another parent", key),
+        }
+    }
+}
+
+/**
+ * 差分と、その差分が出力された理由の組
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub diff: Diff,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (action, path) = match &self.diff {
+            Diff::AddNode(path, _) => ("add", path),
+            Diff::RemoveNode(path, _) => ("remove", path),
+            Diff::MoveNode(from, to, _) => {
+                return write!(f, "move {:?} -> {:?}: {}", from, to, self.reason)
+            }
+            Diff::SetAttribute(path, _, _) => ("set", path),
+        };
+        write!(f, "{} {:?}: {}", action, path, self.reason)
+    }
+}
+
+/**
+ * update_dom と同じ差分を、それぞれが出力された理由とともに返す関数
+ * 想定外の再描画の原因を調べるときに使う
+ */
+pub fn explain_diff(old: &VNode, new: &VNode) -> Vec<Explanation> {
+    explain_diff_with(old, new, &DiffOptions::default())
+}
+
+/**
+ * 設定に従って差分とその理由を返す関数
+ */
+pub fn explain_diff_with(old: &VNode, new: &VNode, options: &DiffOptions) -> Vec<Explanation> {
+    explained_diff(&old.element_type, &new.element_type, options)
+}
+
+/**
+ * 2つの属性の一覧の違いを属性名の順に返す関数
+ */
+pub(crate) fn attribute_changes(old: &Attributes, new: &Attributes) -> Vec<AttributeChange> {
+    let names: BTreeSet<&Text> = old.keys().chain(new.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| AttributeChange {
+            name: name.to_string(),
+            old: old.get(name).map(|value| value.to_string()),
+            new: new.get(name).map(|value| value.to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::{update_dom, ElementType, KEY_ATTR};
+
+    fn element(tag: &str, attrs: &[(&str, &str)], children: Vec<ElementType>) -> ElementType {
+        ElementType::Element(
+            tag.to_string(),
+            attrs
+                .iter()
+                .map(|(name, value)| (name.to_string().into(), value.to_string().into()))
+                .collect(),
+            children,
+        )
+    }
+
+    fn vnode(element_type: ElementType) -> VNode {
+        VNode { element_type }
+    }
+
+    #[test]
+    fn test_explain_diff() {
+        let old = vnode(element(
+            "div",
+            &[],
+            vec![
+                element("p", &[("class", "a")], vec![]),
+                element("span", &[], vec![]),
+                ElementType::Text("x".into()),
+            ],
+        ));
+        let new = vnode(element(
+            "div",
+            &[],
+            vec![
+                element("p", &[("class", "b")], vec![]),
+                element("em", &[], vec![]),
+                ElementType::Text("y".into()),
+            ],
+        ));
+
+        let explanations = explain_diff(&old, &new);
+        let diff: Vec<Diff> = explanations.iter().map(|e| e.diff.clone()).collect();
+        assert_eq!(diff, update_dom(&old, &new).diff);
+
+        let reasons: Vec<String> = explanations
+            .iter()
+            .filter(|e| matches!(e.diff, Diff::AddNode(..)))
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "add [0]: attr class changed from \"a\" to \"b\"",
+                "add [1]: tag changed from <span> to <em>",
+                "add [2]: text changed from \"x\" to \"y\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_children() {
+        let list = |keys: &[&str]| {
+            vnode(element(
+                "ul",
+                &[],
+                keys.iter()
+                    .map(|key| element("li", &[(KEY_ATTR, key)], vec![]))
+                    .collect(),
+            ))
+        };
+        let reasons: Vec<Reason> = explain_diff(&list(&["a", "b"]), &list(&["a", "c"]))
+            .into_iter()
+            .map(|e| e.reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                Reason::KeyRemoved {
+                    key: "b".to_string()
+                },
+                Reason::KeyAdded {
+                    key: "c".to_string()
+                },
+            ]
+        );
+
+        let unkeyed = |count: usize| {
+            vnode(element(
+                "ul",
+                &[],
+                (0..count).map(|_| element("li", &[], vec![])).collect(),
+            ))
+        };
+        let explanations = explain_diff(&unkeyed(1), &unkeyed(2));
+        assert!(explanations
+            .iter()
+            .all(|e| e.reason == Reason::ChildCountChanged { old: 1, new: 2 }));
+    }
+}
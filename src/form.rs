@@ -0,0 +1,279 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::builder::element;
+use crate::conditional::when;
+use crate::self_virtual_dom::{update_dom, Diff, ElementType, VNode};
+
+/**
+ * フィールドの値を検証する trait
+ * 不正な値にはフィールドの横に表示するメッセージを返す
+ */
+pub trait Validator: Send + Sync {
+    fn validate(&self, value: &str) -> Result<(), String>;
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&str) -> Result<(), String> + Send + Sync,
+{
+    fn validate(&self, value: &str) -> Result<(), String> {
+        self(value)
+    }
+}
+
+/**
+ * 空でないことを検証する関数
+ */
+pub fn required() -> impl Validator {
+    |value: &str| {
+        if value.trim().is_empty() {
+            Err("This field is required".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/**
+ * 文字数が指定した数以上であることを検証する関数
+ */
+pub fn min_length(length: usize) -> impl Validator {
+    move |value: &str| {
+        if value.chars().count() < length {
+            Err(format!("Must be at least {} characters", length))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/**
+ * 文字数が指定した数以下であることを検証する関数
+ */
+pub fn max_length(length: usize) -> impl Validator {
+    move |value: &str| {
+        if value.chars().count() > length {
+            Err(format!("Must be at most {} characters", length))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/**
+ * フォームの入力欄を表す構造体
+ */
+pub struct Field {
+    pub name: String,
+    pub label: String,
+    /** input 要素の type 属性 */
+    pub input_type: String,
+    validators: Vec<Box<dyn Validator>>,
+}
+
+impl Field {
+    pub fn new(name: &str, label: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            input_type: "text".to_string(),
+            validators: Vec::new(),
+        }
+    }
+
+    pub fn with_type(mut self, input_type: &str) -> Self {
+        self.input_type = input_type.to_string();
+        self
+    }
+
+    /**
+     * 検証を追加する関数
+     * 登録順に検証し、最初に失敗したメッセージをエラーとする
+     */
+    pub fn with(mut self, validator: impl Validator + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    fn validate(&self, value: &str) -> Result<(), String> {
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator.validate(value))
+    }
+}
+
+/**
+ * 送信された値の検証結果
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormSubmission {
+    pub valid: bool,
+    /** フィールド名ごとのエラーメッセージ */
+    pub errors: BTreeMap<String, String>,
+    /** 送信前の表示からエラーメッセージを反映した表示への差分 */
+    pub diff: Vec<Diff>,
+}
+
+/**
+ * 入力欄と検証をまとめたフォーム
+ */
+pub struct Form {
+    pub name: String,
+    fields: Vec<Field>,
+}
+
+impl Form {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /**
+     * すべてのフィールドを検証し、エラーメッセージを返す関数
+     */
+    pub fn validate(&self, values: &HashMap<String, String>) -> BTreeMap<String, String> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let value = values.get(&field.name).map_or("", String::as_str);
+                field
+                    .validate(value)
+                    .err()
+                    .map(|message| (field.name.clone(), message))
+            })
+            .collect()
+    }
+
+    /**
+     * フォームを仮想DOMに変換する関数
+     * エラーメッセージの位置にはプレースホルダーを置くため、エラーの有無でほかの要素の位置は変わらない
+     */
+    pub fn render(
+        &self,
+        values: &HashMap<String, String>,
+        errors: &BTreeMap<String, String>,
+    ) -> ElementType {
+        let fields = self.fields.iter().map(|field| {
+            let value = values.get(&field.name).map_or("", String::as_str);
+            let error = errors.get(&field.name);
+            element(
+                "div",
+                &[("class", "field")],
+                (
+                    element("label", &[("for", &field.name)], &field.label),
+                    element(
+                        "input",
+                        &[
+                            ("id", &field.name),
+                            ("name", &field.name),
+                            ("type", &field.input_type),
+                            ("value", value),
+                        ],
+                        (),
+                    ),
+                    when(
+                        error.is_some(),
+                        element("p", &[("class", "error")], error.cloned()),
+                    ),
+                ),
+            )
+        });
+        element(
+            "form",
+            &[("data-form", &self.name)],
+            (
+                fields.collect::<Vec<_>>(),
+                element("button", &[("type", "submit")], "Submit"),
+            ),
+        )
+    }
+
+    /**
+     * 送信された値を検証し、エラーメッセージを差し込む差分を返す関数
+     */
+    pub fn submit(&self, values: &HashMap<String, String>) -> FormSubmission {
+        let errors = self.validate(values);
+        let submitted = VNode {
+            element_type: self.render(values, &BTreeMap::new()),
+        };
+        let validated = VNode {
+            element_type: self.render(values, &errors),
+        };
+        FormSubmission {
+            valid: errors.is_empty(),
+            diff: update_dom(&submitted, &validated).diff,
+            errors,
+        }
+    }
+}
+
+/**
+ * 名前で引けるよう登録されたフォームの一覧
+ */
+#[derive(Default)]
+pub struct Forms {
+    forms: HashMap<String, Form>,
+}
+
+impl Forms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, form: Form) -> Self {
+        self.forms.insert(form.name.clone(), form);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Form> {
+        self.forms.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submit_injects_errors() {
+        let form = Form::new("signup")
+            .field(
+                Field::new("user", "User")
+                    .with(required())
+                    .with(min_length(3)),
+            )
+            .field(
+                Field::new("email", "Email")
+                    .with_type("email")
+                    .with(required()),
+            );
+        let values: HashMap<String, String> = [
+            ("user".to_string(), "al".to_string()),
+            ("email".to_string(), "a@example.com".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let submission = form.submit(&values);
+
+        assert!(!submission.valid);
+        assert_eq!(
+            submission.errors.get("user").map(|value| value.as_ref()),
+            Some("Must be at least 3 characters")
+        );
+        let paths: Vec<_> = submission
+            .diff
+            .iter()
+            .map(|patch| patch.path().clone())
+            .collect();
+        assert_eq!(paths, vec![vec![0, 2], vec![0, 2]]);
+    }
+}
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::acl::AccessControl;
+use crate::app::{input_view, run_app};
+use crate::auth::{cookie_value, request_token, AllowAll, AuthProvider, Identity, AUTH_COOKIE};
+use crate::binding::{BindingChange, BoundView};
+use crate::error::VdomError;
+use crate::middleware::MiddlewareChain;
+use crate::pacing::{input_pacing, EventPacer};
+use crate::rate_limit::client_key;
+use crate::sanitize::InputPolicy;
+use crate::self_virtual_dom::{AppResponse, VNode};
+use crate::store::Store;
+use crate::template::TemplateSource;
+
+/**
+ * 入力の更新リクエストを表す列挙型
+ * バインドされた状態のパスと値を送るか、従来どおり `state.input` への入力値を送る
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UpdateInputRequest {
+    Change(BindingChange),
+    Input { input: String },
+}
+
+impl From<UpdateInputRequest> for BindingChange {
+    fn from(request: UpdateInputRequest) -> Self {
+        match request {
+            UpdateInputRequest::Change(change) => change,
+            UpdateInputRequest::Input { input } => BindingChange {
+                path: "state.input".to_string(),
+                value: input.into(),
+            },
+        }
+    }
+}
+
+/**
+ * Webフレームワークに依存しない仮想DOMのエンドポイントの処理をまとめた構造体
+ * 各アダプターはリクエストをこの構造体のメソッドに渡し、戻り値をレスポンスに変換する
+ */
+pub struct HttpHandler {
+    template: TemplateSource,
+    middleware: Arc<MiddlewareChain>,
+    store: Arc<Store>,
+    access_control: AccessControl,
+    view: BoundView,
+    input_policy: InputPolicy,
+    pacer: EventPacer,
+    auth: Arc<dyn AuthProvider>,
+}
+
+impl HttpHandler {
+    pub fn new(
+        template: TemplateSource,
+        middleware: Arc<MiddlewareChain>,
+        store: Arc<Store>,
+    ) -> Self {
+        Self {
+            template,
+            middleware,
+            store,
+            access_control: AccessControl::new(),
+            view: input_view(),
+            input_policy: InputPolicy::default(),
+            pacer: EventPacer::new(),
+            auth: Arc::new(AllowAll),
+        }
+    }
+
+    /**
+     * クライアントからの変更を反映するテンプレートと状態を設定する関数
+     */
+    pub fn with_view(mut self, view: BoundView) -> Self {
+        self.view = view;
+        self
+    }
+
+    /**
+     * 部分木ごとの保護を設定する関数
+     */
+    pub fn with_access_control(mut self, access_control: AccessControl) -> Self {
+        self.access_control = access_control;
+        self
+    }
+
+    /**
+     * クライアントからの入力を木に書き込む前に適用する規則を設定する関数
+     */
+    pub fn with_input_policy(mut self, input_policy: InputPolicy) -> Self {
+        self.input_policy = input_policy;
+        self
+    }
+
+    /**
+     * リクエストの送信者を認証する方法を設定する関数
+     */
+    pub fn with_auth(mut self, auth: Arc<dyn AuthProvider>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /**
+     * Authorization ヘッダーと Cookie ヘッダーの値から送信者を認証する関数
+     */
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        cookie: Option<&str>,
+    ) -> Result<Identity, VdomError> {
+        let cookie = cookie.and_then(|header| cookie_value(header, AUTH_COOKIE));
+        Ok(self
+            .auth
+            .authenticate(request_token(authorization, cookie))?)
+    }
+
+    /**
+     * トップページのHTMLを返す関数
+     */
+    pub fn index(&self) -> String {
+        self.template.load()
+    }
+
+    /**
+     * デモアプリの初期表示の差分を返す関数
+     */
+    pub fn run_app(&self) -> AppResponse {
+        self.middleware.apply(run_app(""))
+    }
+
+    /**
+     * クライアントからの変更をバインドされた状態に書き込み、描画し直した仮想DOMとの差分を返す関数
+     * 入力が規則に合わない場合や、差分が保護された部分木に触れる場合は仮想DOMを変更せずにエラーを返す
+     * 入力欄に指定されたデバウンス・スロットルの間隔より短い間隔で送られた変更も拒否する
+     * 間隔は送信者ごとに数え、匿名の送信者は接続元 remote で区別する
+     */
+    pub fn update_input(
+        &self,
+        identity: &Identity,
+        remote: Option<IpAddr>,
+        request: UpdateInputRequest,
+    ) -> Result<AppResponse, VdomError> {
+        let change = self
+            .input_policy
+            .sanitize_change(BindingChange::from(request))?;
+        if let Some(pacing) = input_pacing(self.view.template(), &change.path) {
+            let key = format!("{}:{}", client_key(identity, remote), change.path.trim());
+            if !self.pacer.check(&key, pacing) {
+                return Err(VdomError::RateLimited);
+            }
+        }
+        let app_response = self.view.apply(&self.store, &change, |old, new, patches| {
+            self.access_control
+                .check(identity, &old.element_type, &new.element_type, patches)
+                .map_err(VdomError::from)
+        })?;
+        Ok(self.middleware.apply(app_response))
+    }
+
+    /**
+     * 候補の木を現在の仮想DOMと比較し、適用した場合の差分を返す関数
+     * ストアには反映しない
+     */
+    pub fn preview(&self, tree: &VNode) -> AppResponse {
+        self.middleware.apply(self.store.update_dom_readonly(tree))
+    }
+
+    /**
+     * 状態を保持しているストアを返す関数
+     */
+    pub fn store(&self) -> &Arc<Store> {
+        &self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Access;
+    use crate::app::initial_tree;
+    use crate::auth::StaticTokens;
+    use crate::builder::element;
+    use crate::pacing::{on_input, with_event};
+
+    #[test]
+    fn test_update_input() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded("<html></html>"),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        );
+
+        assert_eq!(handler.index(), "<html></html>");
+
+        let app_response = handler
+            .update_input(
+                &Identity::anonymous(),
+                None,
+                UpdateInputRequest::Input {
+                    input: "Hi".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(app_response.html, "<div >Hi</div>");
+        assert_eq!(handler.store().version(), 1);
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_auth(Arc::new(
+            StaticTokens::new().with_token("secret", Identity::new("alice")),
+        ));
+
+        assert_eq!(
+            handler.authenticate(Some("Bearer secret"), None),
+            Ok(Identity::new("alice"))
+        );
+        assert_eq!(
+            handler.authenticate(None, Some("theme=dark; vdom_token=secret")),
+            Ok(Identity::new("alice"))
+        );
+        assert!(matches!(
+            handler.authenticate(Some("Bearer wrong"), None),
+            Err(VdomError::Unauthorized { .. })
+        ));
+    }
+
+    #[test]
+    fn test_update_input_binding_change() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        );
+        let request: UpdateInputRequest =
+            serde_json::from_str(r#"{"path": "state.input", "value": "Hi"}"#).unwrap();
+        let app_response = handler
+            .update_input(&Identity::anonymous(), None, request)
+            .unwrap();
+        assert_eq!(app_response.html, "<div >Hi</div>");
+
+        let unbound: UpdateInputRequest =
+            serde_json::from_str(r#"{"path": "state.admin", "value": true}"#).unwrap();
+        let result = handler.update_input(&Identity::anonymous(), None, unbound);
+        assert!(matches!(result, Err(VdomError::BadRequest { .. })));
+        assert_eq!(handler.store().version(), 1);
+    }
+
+    #[test]
+    fn test_update_input_policy() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_input_policy(
+            InputPolicy::default()
+                .with_max_length(16)
+                .with_mode(crate::sanitize::TextMode::PlainText),
+        );
+        let input = |input: &str| UpdateInputRequest::Input {
+            input: input.to_string(),
+        };
+
+        let app_response = handler
+            .update_input(&Identity::anonymous(), None, input("<b>Hi</b>"))
+            .unwrap();
+        assert_eq!(app_response.html, "<div >Hi</div>");
+
+        let result = handler.update_input(&Identity::anonymous(), None, input(&"x".repeat(17)));
+        let err = result.unwrap_err();
+        assert_eq!(err.status_code(), 422);
+        assert_eq!(handler.store().version(), 1);
+    }
+
+    #[test]
+    fn test_update_input_rejects_protected_subtree() {
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_access_control(AccessControl::new().protect(vec![], Access::ReadOnly));
+
+        let result = handler.update_input(
+            &Identity::anonymous(),
+            None,
+            UpdateInputRequest::Input {
+                input: "Hi".to_string(),
+            },
+        );
+
+        assert!(matches!(result, Err(VdomError::AccessDenied(_))));
+        assert_eq!(handler.store().version(), 0);
+    }
+
+    #[test]
+    fn test_update_input_enforces_debounce() {
+        let view = BoundView::new(
+            with_event(
+                element("input", &[("bind:value", "state.input")], ()),
+                &on_input("update_input").debounce_ms(60_000),
+            ),
+            serde_json::json!({ "input": "" }),
+        );
+        let handler = HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )
+        .with_view(view);
+        let input = |input: &str| UpdateInputRequest::Input {
+            input: input.to_string(),
+        };
+
+        assert!(handler
+            .update_input(&Identity::new("alice"), None, input("a"))
+            .is_ok());
+        let result = handler.update_input(&Identity::new("alice"), None, input("ab"));
+        assert!(matches!(result, Err(VdomError::RateLimited)));
+        assert!(handler
+            .update_input(&Identity::new("bob"), None, input("b"))
+            .is_ok());
+        assert_eq!(handler.store().version(), 2);
+
+        // 匿名の送信者は接続元ごとに間隔を数える
+        let peer = |last: u8| Some(IpAddr::from([10, 0, 0, last]));
+        assert!(handler
+            .update_input(&Identity::anonymous(), peer(1), input("c"))
+            .is_ok());
+        let result = handler.update_input(&Identity::anonymous(), peer(1), input("cd"));
+        assert!(matches!(result, Err(VdomError::RateLimited)));
+        assert!(handler
+            .update_input(&Identity::anonymous(), peer(2), input("e"))
+            .is_ok());
+        assert_eq!(handler.store().version(), 4);
+    }
+}
@@ -0,0 +1,215 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::builder::element;
+use crate::document::Document;
+use crate::self_virtual_dom::{ElementType, VNode};
+use crate::store::Store;
+use crate::ws::ServerMessage;
+
+/**
+ * 仮想DOMから document.head に置く内容を求める関数の型
+ */
+pub type HeadFn = Arc<dyn Fn(&VNode) -> Head + Send + Sync>;
+
+/**
+ * document.head を更新する差分を表す列挙型
+ * body の差分とは別に送り、クライアントは head の要素だけを書き換える
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum HeadPatch {
+    SetTitle(String),
+    SetMeta { name: String, content: String },
+    RemoveMeta { name: String },
+    AddLink { rel: String, href: String },
+    RemoveLink { rel: String, href: String },
+}
+
+/**
+ * コンポーネントが宣言した title、meta、link をまとめる構造体
+ * meta は name ごとに後から宣言したものが優先され、順序は名前順で決まる
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Head {
+    title: Option<String>,
+    meta: BTreeMap<String, String>,
+    links: BTreeSet<(String, String)>,
+}
+
+impl Head {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn meta(&mut self, name: &str, content: &str) -> &mut Self {
+        self.meta.insert(name.to_string(), content.to_string());
+        self
+    }
+
+    pub fn link(&mut self, rel: &str, href: &str) -> &mut Self {
+        self.links.insert((rel.to_string(), href.to_string()));
+        self
+    }
+
+    /**
+     * 別のコンポーネントが宣言した内容を統合する関数
+     * title と同じ name の meta は other の値で上書きし、link は重複を除いて合わせる
+     */
+    pub fn merge(&mut self, other: Head) -> &mut Self {
+        if other.title.is_some() {
+            self.title = other.title;
+        }
+        self.meta.extend(other.meta);
+        self.links.extend(other.links);
+        self
+    }
+
+    /**
+     * head に置く要素の一覧を返す関数
+     */
+    pub fn to_nodes(&self) -> Vec<ElementType> {
+        let meta = self
+            .meta
+            .iter()
+            .map(|(name, content)| element("meta", &[("name", name), ("content", content)], ()));
+        let links = self
+            .links
+            .iter()
+            .map(|(rel, href)| element("link", &[("rel", rel), ("href", href)], ()));
+        meta.chain(links).collect()
+    }
+
+    /**
+     * 宣言した内容を文書の head に反映する関数
+     */
+    pub fn apply(&self, mut document: Document) -> Document {
+        if let Some(title) = &self.title {
+            document.title = Some(title.clone());
+        }
+        document.head.extend(self.to_nodes());
+        document
+    }
+}
+
+/**
+ * 2つの Head の差分を求める関数
+ */
+pub fn diff_head(old: &Head, new: &Head) -> Vec<HeadPatch> {
+    let mut patches = Vec::new();
+
+    match (&old.title, &new.title) {
+        (old, Some(title)) if old.as_ref() != Some(title) => {
+            patches.push(HeadPatch::SetTitle(title.clone()))
+        }
+        // title を宣言しなくなった場合は空にする
+        (Some(_), None) => patches.push(HeadPatch::SetTitle(String::new())),
+        _ => {}
+    }
+
+    for name in old.meta.keys() {
+        if !new.meta.contains_key(name) {
+            patches.push(HeadPatch::RemoveMeta { name: name.clone() });
+        }
+    }
+    for (name, content) in &new.meta {
+        if old.meta.get(name) != Some(content) {
+            patches.push(HeadPatch::SetMeta {
+                name: name.clone(),
+                content: content.clone(),
+            });
+        }
+    }
+
+    for (rel, href) in old.links.difference(&new.links) {
+        patches.push(HeadPatch::RemoveLink {
+            rel: rel.clone(),
+            href: href.clone(),
+        });
+    }
+    for (rel, href) in new.links.difference(&old.links) {
+        patches.push(HeadPatch::AddLink {
+            rel: rel.clone(),
+            href: href.clone(),
+        });
+    }
+
+    patches
+}
+
+/**
+ * ストアが更新されるたびに head を求め直し、変わった部分を Head のメッセージとして WebSocket へ配信するタスクを起動する関数
+ * 取りこぼした更新があっても、ストアの現在の木から求め直すため最後に配信した head との差分になる
+ * タスクはストアを弱い参照で持ち、ストアが破棄されると終了する
+ */
+pub fn forward_head(
+    store: &Arc<Store>,
+    head: HeadFn,
+    sender: broadcast::Sender<ServerMessage>,
+) -> JoinHandle<()> {
+    let mut updates = store.subscribe();
+    let mut current = head(&store.snapshot().tree);
+    let store = Arc::downgrade(store);
+    tokio::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+            let Some(store) = store.upgrade() else {
+                break;
+            };
+            let next = head(&store.snapshot().tree);
+            let patches = diff_head(&current, &next);
+            current = next;
+            if !patches.is_empty() {
+                // 接続中のクライアントがいない場合の送信エラーは無視する
+                let _ = sender.send(ServerMessage::Head { patches });
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_diff_head() {
+        let mut layout = Head::new();
+        layout
+            .title("Demo")
+            .meta("description", "layout")
+            .link("stylesheet", "/app.css");
+        let mut page = Head::new();
+        page.title("Todos").meta("description", "todo list");
+
+        let old = layout.clone();
+        let mut new = layout;
+        new.merge(page).link("stylesheet", "/app.css");
+
+        assert_eq!(new.to_nodes().len(), 2);
+        assert_eq!(
+            diff_head(&old, &new),
+            vec![
+                HeadPatch::SetTitle("Todos".to_string()),
+                HeadPatch::SetMeta {
+                    name: "description".to_string(),
+                    content: "todo list".to_string(),
+                },
+            ]
+        );
+        assert_eq!(diff_head(&new, &new), vec![]);
+        assert_eq!(
+            diff_head(&new, &Head::new())[0],
+            HeadPatch::SetTitle(String::new())
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::{Filter, Rejection, Reply};
+
+use crate::iter::NodePath;
+use crate::middleware::MiddlewareChain;
+use crate::self_virtual_dom::{replace_root, virtual_dom_to_html, Diff, ElementType, VNode};
+use crate::store::Store;
+use crate::visit::{walk_mut, Transformer};
+
+/**
+ * 差分をハイパーメディア系フロントエンド向けの形式に変換する際の出力形式
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /** Turbo Streams の `<turbo-stream>` 要素 */
+    Turbo,
+    /** htmx の out-of-band swap (`hx-swap-oob`) 要素 */
+    HtmxOob,
+}
+
+/**
+ * パスから要素のIDを生成する関数
+ * ルート要素は `vdom-root` とする
+ */
+pub fn path_target(path: &[usize]) -> String {
+    if path.is_empty() {
+        return "vdom-root".to_string();
+    }
+    let segments: Vec<String> = path.iter().map(usize::to_string).collect();
+    format!("vdom-{}", segments.join("-"))
+}
+
+/**
+ * id 属性を持たない要素にパスから生成したIDを付与するパス
+ * 差分の適用先を id で指定するクライアントのため、HTMLに変換する前に適用する
+ */
+pub struct AssignPathIds;
+
+impl Transformer for AssignPathIds {
+    fn pre(&mut self, path: &[usize], node: &mut ElementType) {
+        if let ElementType::Element(_, attrs, _) = node {
+            attrs
+                .entry("id".into())
+                .or_insert_with(|| path_target(path).into());
+        }
+    }
+}
+
+/**
+ * 差分を指定した形式のHTMLに変換する関数
+ * tree は差分を適用した後の仮想DOMで、適用先の要素のIDやテキストノードの親要素の内容を求めるために使う
+ * 要素のHTMLはミドルウェアの process_html に通してから埋め込む
+ */
+pub fn render_stream(
+    format: StreamFormat,
+    tree: &ElementType,
+    patches: &[Diff],
+    middleware: &MiddlewareChain,
+) -> String {
+    let render = |node: &ElementType| middleware.process_html(virtual_dom_to_html(node));
+    swaps(tree, patches)
+        .into_iter()
+        .map(|swap| match format {
+            StreamFormat::Turbo => turbo_stream(&swap, &render),
+            StreamFormat::HtmxOob => htmx_oob(&swap, &render),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/**
+ * morphdom や idiomorph に渡す形式の差分
+ * target の要素を html の内容へモーフィングさせる
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MorphPatch {
+    /** モーフィング対象の要素のID */
+    pub target: String,
+    /** 対象の要素の変更後の outerHTML */
+    pub html: String,
+}
+
+/**
+ * 差分を morphdom 互換の形式に変換する関数
+ * 変更されたノードの親要素を単位にまとめ、祖先がすでに対象になっている要素は除く
+ * 要素のHTMLはミドルウェアの process_html に通す
+ */
+pub fn morph_patches(
+    tree: &ElementType,
+    patches: &[Diff],
+    middleware: &MiddlewareChain,
+) -> Vec<MorphPatch> {
+    let mut roots: Vec<NodePath> = Vec::new();
+    for patch in patches {
+        let path = patch.path();
+        let root = if path.is_empty() {
+            Vec::new()
+        } else {
+            path[..path.len() - 1].to_vec()
+        };
+        if roots.iter().any(|other| root.starts_with(other)) {
+            continue;
+        }
+        roots.retain(|other| !other.starts_with(&root));
+        roots.push(root);
+    }
+
+    roots
+        .into_iter()
+        .filter_map(|root| {
+            tree.get(&root).map(|node| MorphPatch {
+                target: target(tree, &root),
+                html: middleware.process_html(virtual_dom_to_html(node)),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct MorphQuery {
+    since: u64,
+}
+
+/**
+ * `GET /morph` のレスポンス。クライアントは version を次の since に使う
+ */
+#[derive(Debug, Serialize)]
+pub struct MorphResponse {
+    pub version: u64,
+    pub patches: Vec<MorphPatch>,
+}
+
+/**
+ * 指定したバージョン以降の変更を morphdom 互換の形式で返す `GET /morph?since=<バージョン>` ルートを返す関数
+ * 要素の ID は AssignPathIds で付与したものを使うため、ページもこのパスを適用したHTMLで描画しておく
+ * 複数の更新をまたぐ場合や履歴から差分を返せない場合は、パスがずれているためルート全体をモーフィングさせる
+ */
+pub fn morph_route(
+    store: Arc<Store>,
+    middleware: Arc<MiddlewareChain>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("morph")
+        .and(warp::get())
+        .and(warp::query::<MorphQuery>())
+        .map(move |query: MorphQuery| {
+            let snapshot = store.snapshot();
+            let mut tree = snapshot.tree.element_type;
+            walk_mut(&mut tree, &mut AssignPathIds);
+            let updates = store.updates_since(query.since).map(|updates| {
+                updates
+                    .into_iter()
+                    .filter(|update| update.version <= snapshot.version)
+                    .collect::<Vec<_>>()
+            });
+            let diff = match updates {
+                Some(updates) if updates.len() <= 1 => updates
+                    .into_iter()
+                    .flat_map(|update| middleware.process(update.diff))
+                    .collect(),
+                _ => replace_root(&VNode {
+                    element_type: tree.clone(),
+                }),
+            };
+            warp::reply::json(&MorphResponse {
+                version: snapshot.version,
+                patches: morph_patches(&tree, &diff, &middleware),
+            })
+        })
+}
+
+/**
+ * 要素に対する1回の差し替え操作
+ */
+#[derive(Debug, PartialEq)]
+enum Swap {
+    Replace(String, ElementType),
+    Update(String, ElementType),
+    Append(String, ElementType),
+    Remove(String),
+}
+
+fn target(tree: &ElementType, path: &[usize]) -> String {
+    match tree.get(path) {
+        Some(ElementType::Element(_, attrs, _)) => attrs
+            .get("id")
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| path_target(path)),
+        _ => path_target(path),
+    }
+}
+
+fn swaps(tree: &ElementType, patches: &[Diff]) -> Vec<Swap> {
+    let mut swaps = Vec::new();
+    let mut updated_parents: Vec<NodePath> = Vec::new();
+
+    for (index, patch) in patches.iter().enumerate() {
+        let path = patch.path();
+        let replaced = patches.iter().any(|other| {
+            matches!(other, Diff::AddNode(..))
+                && other.path() == path
+                && !std::ptr::eq(other, patch)
+        });
+
+        let is_text = patch
+            .node()
+            .is_some_and(|node| matches!(node.element_type, ElementType::Text(_)));
+        if is_text && !path.is_empty() {
+            // テキストノードは id で指定できないため、親要素の内容をまとめて更新する
+            let parent = path[..path.len() - 1].to_vec();
+            if !updated_parents.contains(&parent) {
+                if let Some(parent_node) = tree.get(&parent) {
+                    swaps.push(Swap::Update(target(tree, &parent), parent_node.clone()));
+                }
+                updated_parents.push(parent);
+            }
+            continue;
+        }
+
+        match patch {
+            Diff::RemoveNode(..) if replaced => {}
+            Diff::RemoveNode(..) => swaps.push(Swap::Remove(target(tree, path))),
+            Diff::AddNode(_, node) => {
+                let removed_before = patches[..index]
+                    .iter()
+                    .any(|other| matches!(other, Diff::RemoveNode(..)) && other.path() == path);
+                if removed_before || path.is_empty() {
+                    swaps.push(Swap::Replace(target(tree, path), node.element_type.clone()));
+                } else {
+                    let parent = &path[..path.len() - 1];
+                    swaps.push(Swap::Append(
+                        target(tree, parent),
+                        node.element_type.clone(),
+                    ));
+                }
+            }
+            Diff::MoveNode(from, to, _) => {
+                // 移動先の位置は id では指定できないため、取り外してから移動先の親の内容を更新する
+                swaps.push(Swap::Remove(target(tree, from)));
+                let parent = to[..to.len() - 1].to_vec();
+                if !updated_parents.contains(&parent) {
+                    if let Some(parent_node) = tree.get(&parent) {
+                        swaps.push(Swap::Update(target(tree, &parent), parent_node.clone()));
+                    }
+                    updated_parents.push(parent);
+                }
+            }
+            Diff::SetAttribute(..) => {
+                if let Some(node) = tree.get(path) {
+                    swaps.push(Swap::Replace(target(tree, path), node.clone()));
+                }
+            }
+        }
+    }
+
+    swaps
+}
+
+fn inner_html(node: &ElementType, render: &impl Fn(&ElementType) -> String) -> String {
+    match node {
+        ElementType::Element(_, _, children) => children.iter().map(render).collect(),
+        _ => render(node),
+    }
+}
+
+fn turbo_stream(swap: &Swap, render: &impl Fn(&ElementType) -> String) -> String {
+    let (action, target, content) = match swap {
+        Swap::Replace(target, node) => ("replace", target, Some(render(node))),
+        Swap::Update(target, node) => ("update", target, Some(inner_html(node, render))),
+        Swap::Append(target, node) => ("append", target, Some(render(node))),
+        Swap::Remove(target) => ("remove", target, None),
+    };
+    match content {
+        Some(content) => format!(
+            "<turbo-stream action=\"{}\" target=\"{}\"><template>{}</template></turbo-stream>",
+            action, target, content
+        ),
+        None => format!(
+            "<turbo-stream action=\"{}\" target=\"{}\"></turbo-stream>",
+            action, target
+        ),
+    }
+}
+
+fn htmx_oob(swap: &Swap, render: &impl Fn(&ElementType) -> String) -> String {
+    let (strategy, target, content) = match swap {
+        Swap::Replace(target, node) => ("outerHTML", target, render(node)),
+        Swap::Update(target, node) => ("innerHTML", target, inner_html(node, render)),
+        Swap::Append(target, node) => ("beforeend", target, render(node)),
+        Swap::Remove(target) => ("delete", target, String::new()),
+    };
+    format!(
+        "<template><div hx-swap-oob=\"{}:#{}\">{}</div></template>",
+        strategy, target, content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+    use crate::middleware::StripAttributes;
+    use crate::self_virtual_dom::Attributes;
+    use crate::self_virtual_dom::VNode;
+
+    fn list(items: &[&str]) -> ElementType {
+        ElementType::Element(
+            "ul".to_string(),
+            [("id".into(), "todos".into())].into_iter().collect(),
+            items
+                .iter()
+                .map(|item| {
+                    ElementType::Element(
+                        "li".to_string(),
+                        Attributes::new(),
+                        vec![ElementType::Text(item.to_string().into())],
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_turbo_stream() {
+        let mut tree = list(&["a", "b"]);
+        walk_mut(&mut tree, &mut AssignPathIds);
+        let li = |path: &[usize], text: &str| VNode {
+            element_type: tree
+                .get(path)
+                .cloned()
+                .unwrap_or(ElementType::Text(text.to_string().into())),
+        };
+
+        let patches = vec![
+            Diff::RemoveNode(vec![0], li(&[0], "a")),
+            Diff::AddNode(vec![0], li(&[0], "a")),
+            Diff::AddNode(vec![1], li(&[1], "b")),
+            Diff::AddNode(vec![1, 0], li(&[1, 0], "b")),
+        ];
+
+        let middleware = MiddlewareChain::new();
+        let html = render_stream(StreamFormat::Turbo, &tree, &patches, &middleware);
+        let lines: Vec<&str> = html.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"<turbo-stream action="replace" target="vdom-0"><template><li id="vdom-0">a</li></template></turbo-stream>"#,
+                r#"<turbo-stream action="append" target="todos"><template><li id="vdom-1">b</li></template></turbo-stream>"#,
+                r#"<turbo-stream action="update" target="vdom-1"><template>b</template></turbo-stream>"#,
+            ]
+        );
+
+        let html = render_stream(
+            StreamFormat::HtmxOob,
+            &tree,
+            &[Diff::RemoveNode(vec![1], li(&[1], "b"))],
+            &middleware,
+        );
+        assert_eq!(
+            html,
+            r#"<template><div hx-swap-oob="delete:#vdom-1"></div></template>"#
+        );
+    }
+
+    #[test]
+    fn test_morph_patches() {
+        let tree = list(&["a", "b"]);
+        let text = |value: &str| VNode {
+            element_type: ElementType::Text(value.to_string().into()),
+        };
+
+        let patches = vec![
+            Diff::AddNode(vec![1, 0], text("b")),
+            Diff::RemoveNode(vec![2], text("c")),
+        ];
+
+        assert_eq!(
+            morph_patches(&tree, &patches, &MiddlewareChain::new()),
+            vec![MorphPatch {
+                target: "todos".to_string(),
+                html: r#"<ul id="todos"><li >a</li><li >b</li></ul>"#.to_string(),
+            }]
+        );
+
+        let mut middleware = MiddlewareChain::new();
+        middleware.register(StripAttributes::with_prefix("id"));
+        assert_eq!(
+            morph_patches(&tree, &patches, &middleware)[0].html,
+            "<ul ><li >a</li><li >b</li></ul>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_morph_route() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let route = morph_route(store.clone(), Arc::new(MiddlewareChain::new()));
+        store.update(render_input("a"));
+        store.update(render_input("b"));
+
+        let response = warp::test::request()
+            .path("/morph?since=1")
+            .reply(&route)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["version"], 2);
+        assert_eq!(body["patches"].as_array().unwrap().len(), 1);
+        assert!(body["patches"][0]["html"]
+            .as_str()
+            .unwrap()
+            .ends_with(">b</div>"));
+
+        let response = warp::test::request()
+            .path("/morph?since=2")
+            .reply(&route)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["patches"], serde_json::json!([]));
+
+        let response = warp::test::request()
+            .path("/morph?since=0")
+            .reply(&route)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["patches"].as_array().unwrap().len(), 1);
+        assert_eq!(body["patches"][0]["target"], "vdom-root");
+        assert!(body["patches"][0]["html"]
+            .as_str()
+            .unwrap()
+            .starts_with(r#"<div id="vdom-root">"#));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::self_virtual_dom::{Diff, ElementType, VNode};
+use crate::visit::Transformer;
+
+/**
+ * ロケールごとの翻訳メッセージをまとめたカタログ
+ * メッセージ中の `{name}` は I18n ノードの引数で置き換える
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_message(mut self, locale: &str, key: &str, message: &str) -> Self {
+        self.messages
+            .entry(locale.to_string())
+            .or_default()
+            .insert(key.to_string(), message.to_string());
+        self
+    }
+
+    /**
+     * キーに対応するメッセージを引数で埋めて返す関数
+     * カタログにないキーはキーそのものを返す
+     */
+    pub fn translate(&self, locale: &str, key: &str, args: &HashMap<String, String>) -> String {
+        let Some(message) = self
+            .messages
+            .get(locale)
+            .and_then(|messages| messages.get(key))
+        else {
+            return key.to_string();
+        };
+        args.iter().fold(message.clone(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        })
+    }
+}
+
+/**
+ * I18n ノードを指定したロケールのテキストノードに置き換えるパス
+ */
+pub struct TranslatePass {
+    catalog: Arc<Catalog>,
+    locale: String,
+}
+
+impl TranslatePass {
+    pub fn new(catalog: Arc<Catalog>, locale: &str) -> Self {
+        Self {
+            catalog,
+            locale: locale.to_string(),
+        }
+    }
+}
+
+impl Transformer for TranslatePass {
+    fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+        if let ElementType::I18n(key, args) = node {
+            *node = ElementType::Text(self.catalog.translate(&self.locale, key, args).into());
+        }
+    }
+}
+
+/**
+ * ロケールを切り替えたときの差分を求める関数
+ * I18n ノードは同じ位置のテキストノードに置き換わるため、翻訳が変わるノードだけを比較する
+ */
+pub fn switch_locale(template: &ElementType, catalog: &Catalog, old: &str, new: &str) -> Vec<Diff> {
+    let mut patches = Vec::new();
+    for (path, node) in template.iter_with_paths() {
+        let ElementType::I18n(key, args) = node else {
+            continue;
+        };
+        let before = catalog.translate(old, key, args);
+        let after = catalog.translate(new, key, args);
+        if before != after {
+            patches.push(Diff::RemoveNode(
+                path.clone(),
+                VNode {
+                    element_type: ElementType::Text(before.into()),
+                },
+            ));
+            patches.push(Diff::AddNode(
+                path,
+                VNode {
+                    element_type: ElementType::Text(after.into()),
+                },
+            ));
+        }
+    }
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::visit::Pipeline;
+
+    #[test]
+    fn test_translate_and_switch_locale() {
+        let catalog = Arc::new(
+            Catalog::new()
+                .with_message("en", "greeting", "Hello, {name}")
+                .with_message("ja", "greeting", "こんにちは、{name}")
+                .with_message("en", "brand", "Demo")
+                .with_message("ja", "brand", "Demo"),
+        );
+        let args = [("name".to_string(), "Alice".to_string())]
+            .into_iter()
+            .collect();
+        let template = element(
+            "div",
+            &[],
+            (
+                ElementType::I18n("brand".to_string(), HashMap::new()),
+                element("p", &[], ElementType::I18n("greeting".to_string(), args)),
+            ),
+        );
+
+        let html = Pipeline::new()
+            .with(TranslatePass::new(catalog.clone(), "ja"))
+            .render(template.clone());
+        assert_eq!(html, "<div >Demo<p >こんにちは、Alice</p></div>");
+
+        let patches = switch_locale(&template, &catalog, "en", "ja");
+        assert_eq!(
+            patches
+                .iter()
+                .map(|patch| patch.path().clone())
+                .collect::<Vec<_>>(),
+            vec![vec![1, 0], vec![1, 0]]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+
+use crate::self_virtual_dom::{Attributes, ElementType};
+use crate::visit::Transformer;
+
+/**
+ * script 要素の読み込み方法を指定する構造体
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptOptions {
+    /** Content-Security-Policy の nonce 属性 */
+    pub nonce: Option<String>,
+    pub defer: bool,
+    pub async_: bool,
+    /** type="module" として読み込む */
+    pub module: bool,
+}
+
+impl ScriptOptions {
+    fn attrs(&self) -> Attributes {
+        let mut attrs = Attributes::new();
+        if let Some(nonce) = &self.nonce {
+            attrs.insert("nonce".into(), nonce.clone().into());
+        }
+        if self.defer {
+            attrs.insert("defer".into(), "".into());
+        }
+        if self.async_ {
+            attrs.insert("async".into(), "".into());
+        }
+        if self.module {
+            attrs.insert("type".into(), "module".into());
+        }
+        attrs
+    }
+}
+
+/**
+ * script や style 要素の内容として埋め込めない文字列が含まれていることを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTextError {
+    pub tag: String,
+    pub message: String,
+}
+
+impl fmt::Display for RawTextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot inline <{}>: {}", self.tag, self.message)
+    }
+}
+
+impl std::error::Error for RawTextError {}
+
+/**
+ * 要素の内容が終了タグやコメントの開始として解釈される文字列を含まないかを確かめる関数
+ * 書き換えるとコードの意味が変わることがあるため、含む場合はエスケープせずに拒否する
+ * 文字列の中では `<\/script` や `\x3C!--` のように書けば同じ値になる
+ */
+fn check_raw_text(text: &str, tag: &str) -> Result<(), RawTextError> {
+    let lower = text.to_ascii_lowercase();
+    let forbidden = [format!("</{}", tag), "<!--".to_string()];
+    match forbidden
+        .iter()
+        .find(|pattern| lower.contains(pattern.as_str()))
+    {
+        Some(pattern) => Err(RawTextError {
+            tag: tag.to_string(),
+            message: format!("content must not contain {:?}", pattern),
+        }),
+        None => Ok(()),
+    }
+}
+
+/**
+ * インラインの script 要素を生成する関数
+ */
+pub fn inline_script(code: &str, options: &ScriptOptions) -> Result<ElementType, RawTextError> {
+    check_raw_text(code, "script")?;
+    Ok(ElementType::Element(
+        "script".to_string(),
+        options.attrs(),
+        vec![ElementType::Text(code.to_string().into())],
+    ))
+}
+
+/**
+ * 外部スクリプトを読み込む script 要素を生成する関数
+ */
+pub fn external_script(src: &str, options: &ScriptOptions) -> ElementType {
+    let mut attrs = options.attrs();
+    attrs.insert("src".into(), src.to_string().into());
+    ElementType::Element("script".to_string(), attrs, vec![])
+}
+
+/**
+ * インラインの style 要素を生成する関数
+ */
+pub fn inline_style(css: &str, nonce: Option<&str>) -> Result<ElementType, RawTextError> {
+    check_raw_text(css, "style")?;
+    let mut attrs = Attributes::new();
+    if let Some(nonce) = nonce {
+        attrs.insert("nonce".into(), nonce.to_string().into());
+    }
+    Ok(ElementType::Element(
+        "style".to_string(),
+        attrs,
+        vec![ElementType::Text(css.to_string().into())],
+    ))
+}
+
+/**
+ * 信頼できない木に含まれる script と style 要素の扱いを決める trait
+ * None を返した要素は取り除かれる
+ */
+pub trait ScriptPolicy: Send {
+    fn rewrite(&self, node: ElementType) -> Option<ElementType>;
+}
+
+impl<F> ScriptPolicy for F
+where
+    F: Fn(ElementType) -> Option<ElementType> + Send,
+{
+    fn rewrite(&self, node: ElementType) -> Option<ElementType> {
+        self(node)
+    }
+}
+
+/**
+ * script と style 要素をすべて取り除くポリシー
+ */
+pub struct StripScripts;
+
+impl ScriptPolicy for StripScripts {
+    fn rewrite(&self, _node: ElementType) -> Option<ElementType> {
+        None
+    }
+}
+
+/**
+ * script と style 要素に指定した nonce を付け直すポリシー
+ */
+pub struct WithNonce(pub String);
+
+impl ScriptPolicy for WithNonce {
+    fn rewrite(&self, mut node: ElementType) -> Option<ElementType> {
+        if let ElementType::Element(_, attrs, _) = &mut node {
+            attrs.insert("nonce".into(), self.0.clone().into());
+        }
+        Some(node)
+    }
+}
+
+fn is_script_or_style(node: &ElementType) -> bool {
+    matches!(node, ElementType::Element(tag, _, _)
+        if tag.eq_ignore_ascii_case("script") || tag.eq_ignore_ascii_case("style"))
+}
+
+/**
+ * 木に含まれる script と style 要素にポリシーを適用するパス
+ */
+pub struct ScriptSanitizer<P: ScriptPolicy> {
+    policy: P,
+}
+
+impl<P: ScriptPolicy> ScriptSanitizer<P> {
+    pub fn new(policy: P) -> Self {
+        Self { policy }
+    }
+}
+
+impl<P: ScriptPolicy> Transformer for ScriptSanitizer<P> {
+    fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+        if let ElementType::Element(_, _, children) = node {
+            *children = std::mem::take(children)
+                .into_iter()
+                .filter_map(|child| {
+                    if is_script_or_style(&child) {
+                        self.policy.rewrite(child)
+                    } else {
+                        Some(child)
+                    }
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+    use crate::visit::Pipeline;
+
+    #[test]
+    fn test_inline_script_escaping() {
+        let options = ScriptOptions {
+            nonce: Some("abc".to_string()),
+            ..ScriptOptions::default()
+        };
+        // 正しいコードは書き換えずにそのまま埋め込む
+        let code = r"if (a[b[0]]>1 && c < d) f('<\/script>');";
+        let script = inline_script(code, &options).unwrap();
+        assert_eq!(
+            virtual_dom_to_html(&script),
+            r#"<script nonce="abc">if (a[b[0]]>1 && c < d) f('<\/script>');</script>"#
+        );
+
+        assert!(inline_script("const s = '</SCRIPT>';", &options).is_err());
+        assert!(inline_script("x<!--y", &options).is_err());
+        assert!(inline_style("p::after { content: '</style>' }", None).is_err());
+    }
+
+    #[test]
+    fn test_script_sanitizer() {
+        let tree = element(
+            "div",
+            &[],
+            (
+                inline_style("p { color: red }", None).unwrap(),
+                element(
+                    "p",
+                    &[],
+                    (external_script("/x.js", &ScriptOptions::default()), "text"),
+                ),
+            ),
+        );
+
+        let html = Pipeline::new()
+            .with(ScriptSanitizer::new(StripScripts))
+            .render(tree.clone());
+        assert_eq!(html, "<div ><p >text</p></div>");
+
+        let html = Pipeline::new()
+            .with(ScriptSanitizer::new(WithNonce("n1".to_string())))
+            .render(tree);
+        assert!(html.starts_with(r#"<div ><style nonce="n1">"#));
+    }
+}
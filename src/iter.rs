@@ -0,0 +1,263 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::self_virtual_dom::{Attributes, ElementType, Text, VNode};
+
+/**
+ * ルートからの子インデックスの列でノードの位置を表す型
+ */
+pub type NodePath = Vec<usize>;
+
+/**
+ * 仮想DOMの要素を深さ優先（行きがけ順）で走査するイテレータ
+ */
+pub struct Iter<'a> {
+    inner: PathIter<'a>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a ElementType;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, node)| node)
+    }
+}
+
+/**
+ * 仮想DOMの要素をパス付きで深さ優先（行きがけ順）に走査するイテレータ
+ */
+pub struct PathIter<'a> {
+    stack: Vec<(NodePath, &'a ElementType)>,
+}
+
+impl<'a> Iterator for PathIter<'a> {
+    type Item = (NodePath, &'a ElementType);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        if let ElementType::Element(_, _, children) = node {
+            for (index, child) in children.iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(index);
+                self.stack.push((child_path, child));
+            }
+        }
+        Some((path, node))
+    }
+}
+
+/**
+ * 仮想DOMの要素をパス付きで幅優先に走査するイテレータ
+ */
+pub struct BfsIter<'a> {
+    queue: VecDeque<(NodePath, &'a ElementType)>,
+}
+
+impl<'a> Iterator for BfsIter<'a> {
+    type Item = (NodePath, &'a ElementType);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.queue.pop_front()?;
+        if let ElementType::Element(_, _, children) = node {
+            for (index, child) in children.iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(index);
+                self.queue.push_back((child_path, child));
+            }
+        }
+        Some((path, node))
+    }
+}
+
+/**
+ * 走査中のノード自身が持つデータへの可変参照
+ * 子要素はイテレータが順に返すため含まない
+ */
+#[derive(Debug)]
+pub enum ElementMut<'a> {
+    Text(&'a mut Text),
+    Element(&'a mut String, &'a mut Attributes),
+    I18n(&'a mut String, &'a mut HashMap<String, String>),
+    Comment(&'a mut String),
+}
+
+/**
+ * 仮想DOMの要素を深さ優先（行きがけ順）に可変参照で走査するイテレータ
+ */
+pub struct IterMut<'a> {
+    stack: Vec<(NodePath, &'a mut ElementType)>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (NodePath, ElementMut<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        let element = match node {
+            ElementType::Text(text) => ElementMut::Text(text),
+            ElementType::I18n(key, args) => ElementMut::I18n(key, args),
+            ElementType::Comment(text) => ElementMut::Comment(text),
+            ElementType::Element(tag, attrs, children) => {
+                for (index, child) in children.iter_mut().enumerate().rev() {
+                    let mut child_path = path.clone();
+                    child_path.push(index);
+                    self.stack.push((child_path, child));
+                }
+                ElementMut::Element(tag, attrs)
+            }
+        };
+        Some((path, element))
+    }
+}
+
+impl ElementType {
+    /**
+     * 自身と子孫を深さ優先で走査するイテレータを返す関数
+     */
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.iter_with_paths(),
+        }
+    }
+
+    /**
+     * 自身と子孫をパス付きで深さ優先に走査するイテレータを返す関数
+     */
+    pub fn iter_with_paths(&self) -> PathIter<'_> {
+        PathIter {
+            stack: vec![(Vec::new(), self)],
+        }
+    }
+
+    /**
+     * 自身と子孫をパス付きで幅優先に走査するイテレータを返す関数
+     */
+    pub fn iter_bfs(&self) -> BfsIter<'_> {
+        BfsIter {
+            queue: VecDeque::from([(Vec::new(), self)]),
+        }
+    }
+
+    /**
+     * 自身と子孫を可変参照で深さ優先に走査するイテレータを返す関数
+     */
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut {
+            stack: vec![(Vec::new(), self)],
+        }
+    }
+
+    /**
+     * パスが指すノードを取得する関数
+     */
+    pub fn get(&self, path: &[usize]) -> Option<&ElementType> {
+        path.iter().try_fold(self, |node, index| match node {
+            ElementType::Element(_, _, children) => children.get(*index),
+            ElementType::Text(_) | ElementType::I18n(..) | ElementType::Comment(_) => None,
+        })
+    }
+
+    /**
+     * パスが指すノードの可変参照を取得する関数
+     */
+    pub fn get_mut(&mut self, path: &[usize]) -> Option<&mut ElementType> {
+        path.iter().try_fold(self, |node, index| match node {
+            ElementType::Element(_, _, children) => children.get_mut(*index),
+            ElementType::Text(_) | ElementType::I18n(..) | ElementType::Comment(_) => None,
+        })
+    }
+}
+
+impl VNode {
+    /**
+     * ノード配下の要素を深さ優先で走査するイテレータを返す関数
+     */
+    pub fn iter(&self) -> Iter<'_> {
+        self.element_type.iter()
+    }
+
+    /**
+     * ノード配下の要素をパス付きで深さ優先に走査するイテレータを返す関数
+     */
+    pub fn iter_with_paths(&self) -> PathIter<'_> {
+        self.element_type.iter_with_paths()
+    }
+
+    /**
+     * ノード配下の要素をパス付きで幅優先に走査するイテレータを返す関数
+     */
+    pub fn iter_bfs(&self) -> BfsIter<'_> {
+        self.element_type.iter_bfs()
+    }
+
+    /**
+     * ノード配下の要素を可変参照で深さ優先に走査するイテレータを返す関数
+     */
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        self.element_type.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ElementType {
+        ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![
+                ElementType::Element(
+                    "p".to_string(),
+                    Attributes::new(),
+                    vec![ElementType::Text("Hello".into())],
+                ),
+                ElementType::Text("World".into()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_iter_orders() {
+        let tree = sample();
+
+        let dfs: Vec<NodePath> = tree.iter_with_paths().map(|(path, _)| path).collect();
+        assert_eq!(dfs, vec![vec![], vec![0], vec![0, 0], vec![1]]);
+
+        let bfs: Vec<NodePath> = tree.iter_bfs().map(|(path, _)| path).collect();
+        assert_eq!(bfs, vec![vec![], vec![0], vec![1], vec![0, 0]]);
+
+        assert_eq!(tree.get(&[0, 0]), Some(&ElementType::Text("Hello".into())));
+        assert_eq!(tree.get(&[1, 0]), None);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut tree = sample();
+
+        for (_, node) in tree.iter_mut() {
+            match node {
+                ElementMut::Text(text) => *text = text.to_uppercase().into(),
+                ElementMut::Element(_, attrs) => {
+                    attrs.insert("data-seen".into(), "true".into());
+                }
+                ElementMut::I18n(..) | ElementMut::Comment(_) => {}
+            }
+        }
+
+        let texts: Vec<&ElementType> = tree
+            .iter()
+            .filter(|node| matches!(node, ElementType::Text(_)))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                &ElementType::Text("HELLO".into()),
+                &ElementType::Text("WORLD".into()),
+            ]
+        );
+        assert!(tree.iter().all(|node| match node {
+            ElementType::Element(_, attrs, _) => attrs.contains_key("data-seen"),
+            ElementType::Text(_) | ElementType::I18n(..) | ElementType::Comment(_) => true,
+        }));
+    }
+}
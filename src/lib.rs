@@ -1 +1,83 @@
+extern crate self as minimal_virtual_dom_library;
+
+pub mod access_log;
+pub mod acl;
+#[cfg(feature = "actix")]
+pub mod actix_adapter;
+pub mod app;
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod auth;
+#[cfg(feature = "axum")]
+pub mod axum_adapter;
+pub mod backend;
+pub mod binding;
+pub mod broadcaster;
+pub mod builder;
+pub mod cache;
+pub mod clock;
+pub mod cluster;
+pub mod components;
+pub mod conditional;
+pub mod dataset;
+pub mod datasource;
+pub mod dev;
+pub mod document;
+pub mod drag;
+pub mod error;
+pub mod event;
+pub mod explain;
+pub mod form;
+pub mod handler;
+pub mod head;
+pub mod hypermedia;
+pub mod i18n;
+pub mod inject;
+pub mod iter;
+pub mod middleware;
+pub mod minify;
+pub mod normalize;
+pub mod optimistic;
+pub mod pacing;
+pub mod parser;
+pub mod patch;
+pub mod plugin;
+pub mod poll;
+pub mod prerender;
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod query;
+pub mod rate_limit;
+pub mod react;
+pub mod render;
+pub mod request_id;
+pub mod router;
+pub mod rsx;
+pub mod rtl;
+pub mod sanitize;
+pub mod schema;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod selector;
 pub mod self_virtual_dom;
+pub mod server;
+pub mod service;
+pub mod squash;
+pub mod sse;
+pub mod ssg;
+pub mod store;
+pub mod suspense;
+pub mod template;
+pub mod tenant;
+#[cfg(feature = "tera")]
+pub mod tera_adapter;
+pub mod theme;
+pub mod ticker;
+pub mod transition;
+pub mod upload;
+pub mod vec_map;
+pub mod viewport;
+pub mod visit;
+pub mod ws;
+#[cfg(feature = "yew")]
+pub mod yew_interop;
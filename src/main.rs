@@ -1,97 +1,84 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use minimal_virtual_dom_library::app::{demo_router, initial_tree, DEMO_ROUTES};
+use minimal_virtual_dom_library::dev;
+use minimal_virtual_dom_library::document::Document;
+use minimal_virtual_dom_library::middleware::{MiddlewareChain, StripAttributes};
+use minimal_virtual_dom_library::rsx::{self, RSX_COMMAND};
+use minimal_virtual_dom_library::server::{recover_all, routes, Config};
+use minimal_virtual_dom_library::ssg::{self, DEFAULT_OUT_DIR, SSG_COMMAND};
+use minimal_virtual_dom_library::store::Store;
+use minimal_virtual_dom_library::template::is_dev_mode;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use warp::Filter;
-mod self_virtual_dom;
-use self_virtual_dom::{update_dom, virtual_dom_to_html, AppResponse, ElementType, VNode};
-
-#[derive(Deserialize)]
-struct Input {
-    input: String,
-}
-
-const HTML_TEMPLATE: &str = include_str!("index.html");
 
 #[tokio::main]
 async fn main() {
-    let html_route = warp::path::end().map(|| warp::reply::html(HTML_TEMPLATE));
-
-    let run_app_route = warp::path("run_app").map(|| {
-        let app_response = run_app("");
-        warp::reply::json(&app_response)
-    });
-
-    let update_input_route = warp::path("update_input")
-        .and(warp::post())
-        .and(warp::body::json())
-        .map(|input: Input| {
-            let app_response = update_input(input.input);
-            warp::reply::json(&app_response)
-        });
-    let routes = warp::any().and(html_route.or(run_app_route).or(update_input_route));
-
-    let addr = ([127, 0, 0, 1], 3030);
-    warp::serve(routes).run(addr).await;
-}
-
-pub fn run_app(dynamic_input: &str) -> AppResponse {
-    let old_dom = VNode {
-        element_type: ElementType::Element(
-            "div".to_string(),
-            HashMap::new(),
-            vec![
-                ElementType::Text(dynamic_input.to_string()),
-                ElementType::Element(
-                    "input".to_string(),
-                    [("id".to_string(), "myInput".to_string())]
-                        .iter()
-                        .cloned()
-                        .collect(),
-                    vec![],
-                ),
-            ],
-        ),
-    };
-
-    let new_dom = VNode {
-        element_type: ElementType::Element(
-            "div".to_string(),
-            HashMap::new(),
-            vec![ElementType::Text(dynamic_input.to_string())],
-        ),
-    };
-
-    // 仮想DOMの更新の差分を取得
-    let diff = update_dom(&old_dom, &new_dom);
-
-    diff
-}
-
-pub fn update_input(input: String) -> AppResponse {
-    let old_dom = VNode {
-        element_type: ElementType::Element(
-            "div".to_string(),
-            HashMap::new(),
-            vec![ElementType::Text("".to_string())],
-        ),
-    };
-
-    let new_dom = VNode {
-        element_type: ElementType::Element(
-            "div".to_string(),
-            HashMap::new(),
-            if input.is_empty() {
-                vec![]
-            } else {
-                vec![ElementType::Text(input.clone())]
-            },
-        ),
+    // `ssg [出力先]` ではサーバーを起動せず、デモのページを静的なHTMLとして書き出す
+    // `rsx <入力先> <出力先>` では `.rsx` のテンプレートを Rust のコードにコンパイルする
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some(SSG_COMMAND) => {
+            let out_dir = PathBuf::from(args.next().unwrap_or_else(|| DEFAULT_OUT_DIR.to_string()));
+            let shell = Document::new().with_title("Self Virtual DOM DEMO");
+            match ssg::export(&demo_router(), DEMO_ROUTES, &shell, &out_dir) {
+                Ok(written) => {
+                    for path in written {
+                        println!("wrote {}", path.display());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(RSX_COMMAND) => {
+            let (Some(src_dir), Some(out_dir)) = (args.next(), args.next()) else {
+                eprintln!("usage: {} <templates dir> <out dir>", RSX_COMMAND);
+                std::process::exit(2);
+            };
+            match rsx::compile_dir(src_dir.as_ref(), out_dir.as_ref()) {
+                Ok(written) => {
+                    for path in written {
+                        println!("wrote {}", path.display());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    // クライアントへ送信する前の差分に適用するミドルウェアを登録
+    let mut middleware = MiddlewareChain::new();
+    middleware.register(StripAttributes::with_prefix("data-debug"));
+
+    let config = Config {
+        middleware: Arc::new(middleware),
+        router: Arc::new(demo_router()),
+        ..Config::default()
     };
 
-    let diff = update_dom(&old_dom, &new_dom);
+    // 開発モードではテンプレートをリクエストごとにディスクから読み込み、
+    // ソースの変更を検知したら接続中のブラウザを再読み込みさせる
+    if is_dev_mode() {
+        dev::watch(
+            dev::default_roots(),
+            Duration::from_millis(500),
+            config.messages.clone(),
+        );
+    }
 
-    let html: String = virtual_dom_to_html(&new_dom.element_type);
+    let store = Arc::new(Store::new(initial_tree()));
 
-    println!("HTML PREVIEW:{:?}", html);
-
-    diff
+    let addr = ([127, 0, 0, 1], 3030);
+    warp::serve(routes(config, store).recover(recover_all))
+        .run(addr)
+        .await;
 }
@@ -0,0 +1,228 @@
+use crate::iter::ElementMut;
+use crate::parser::parse_html;
+use crate::self_virtual_dom::{virtual_dom_to_html, AppResponse, Diff, ElementType, VNode};
+
+/**
+ * クライアントへ送信する前の差分を加工するミドルウェアの trait
+ */
+pub trait PatchMiddleware: Send + Sync {
+    fn process(&self, patches: Vec<Diff>) -> Vec<Diff>;
+
+    /**
+     * レスポンスのHTMLを加工する関数
+     * 既定では何もしない
+     */
+    fn process_html(&self, html: String) -> String {
+        html
+    }
+}
+
+impl<F> PatchMiddleware for F
+where
+    F: Fn(Vec<Diff>) -> Vec<Diff> + Send + Sync,
+{
+    fn process(&self, patches: Vec<Diff>) -> Vec<Diff> {
+        self(patches)
+    }
+}
+
+/**
+ * 登録されたミドルウェアを登録順に適用する構造体
+ */
+#[derive(Default)]
+pub struct MiddlewareChain {
+    middlewares: Vec<Box<dyn PatchMiddleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * ミドルウェアを末尾に登録する関数
+     */
+    pub fn register<M: PatchMiddleware + 'static>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /**
+     * 差分にすべてのミドルウェアを適用する関数
+     */
+    pub fn process(&self, patches: Vec<Diff>) -> Vec<Diff> {
+        self.middlewares
+            .iter()
+            .fold(patches, |patches, middleware| middleware.process(patches))
+    }
+
+    /**
+     * HTMLにすべてのミドルウェアを適用する関数
+     */
+    pub fn process_html(&self, html: String) -> String {
+        self.middlewares
+            .iter()
+            .fold(html, |html, middleware| middleware.process_html(html))
+    }
+
+    /**
+     * レスポンスの差分とHTMLにすべてのミドルウェアを適用する関数
+     */
+    pub fn apply(&self, response: AppResponse) -> AppResponse {
+        AppResponse {
+            diff: self.process(response.diff),
+            html: self.process_html(response.html),
+            ..response
+        }
+    }
+}
+
+/**
+ * 送信される差分をログに出力するミドルウェア
+ */
+pub struct LoggingMiddleware;
+
+impl PatchMiddleware for LoggingMiddleware {
+    fn process(&self, patches: Vec<Diff>) -> Vec<Diff> {
+        println!("Sending {} patch(es): {:?}", patches.len(), patches);
+        patches
+    }
+}
+
+/**
+ * 指定した接頭辞を持つ属性を差分から取り除くミドルウェア
+ */
+pub struct StripAttributes {
+    prefix: String,
+}
+
+impl StripAttributes {
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn strip(&self, mut node: VNode) -> VNode {
+        for (_, element) in node.iter_mut() {
+            if let ElementMut::Element(_, attrs) = element {
+                attrs.retain(|key, _| !key.starts_with(&self.prefix));
+            }
+        }
+        node
+    }
+}
+
+impl PatchMiddleware for StripAttributes {
+    fn process(&self, patches: Vec<Diff>) -> Vec<Diff> {
+        patches
+            .into_iter()
+            .filter_map(|patch| match patch {
+                Diff::AddNode(path, node) => Some(Diff::AddNode(path, self.strip(node))),
+                Diff::RemoveNode(path, node) => Some(Diff::RemoveNode(path, self.strip(node))),
+                Diff::MoveNode(from, to, node) => Some(Diff::MoveNode(from, to, self.strip(node))),
+                Diff::SetAttribute(_, name, _) if name.starts_with(&self.prefix) => None,
+                patch @ Diff::SetAttribute(..) => Some(patch),
+            })
+            .collect()
+    }
+
+    /**
+     * HTMLを解析して属性を取り除き、描画し直す関数
+     * 解析できないHTMLはそのまま返す
+     */
+    fn process_html(&self, html: String) -> String {
+        match parse_html(&html) {
+            Ok(element_type) => {
+                virtual_dom_to_html(&self.strip(VNode { element_type }).element_type)
+            }
+            Err(_) => html,
+        }
+    }
+}
+
+/**
+ * 空のテキストノードを追加する差分を取り除くミドルウェア
+ */
+pub fn drop_empty_text(patches: Vec<Diff>) -> Vec<Diff> {
+    patches
+        .into_iter()
+        .filter(|patch| match patch {
+            Diff::AddNode(
+                _,
+                VNode {
+                    element_type: ElementType::Text(text),
+                },
+            ) => !text.is_empty(),
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_middleware_chain() {
+        let mut chain = MiddlewareChain::new();
+        chain
+            .register(StripAttributes::with_prefix("data-debug"))
+            .register(drop_empty_text);
+
+        let patches = vec![
+            Diff::AddNode(
+                vec![0],
+                VNode {
+                    element_type: ElementType::Element(
+                        "div".to_string(),
+                        [
+                            ("id".into(), "a".into()),
+                            ("data-debug-source".into(), "main.rs".into()),
+                        ]
+                        .into_iter()
+                        .collect(),
+                        vec![],
+                    ),
+                },
+            ),
+            Diff::AddNode(
+                vec![1],
+                VNode {
+                    element_type: ElementType::Text("".into()),
+                },
+            ),
+        ];
+
+        let processed = chain.process(patches);
+
+        assert_eq!(
+            processed,
+            vec![Diff::AddNode(
+                vec![0],
+                VNode {
+                    element_type: ElementType::Element(
+                        "div".to_string(),
+                        [("id".into(), "a".into())].into_iter().collect(),
+                        vec![],
+                    ),
+                },
+            )]
+        );
+    }
+
+    #[test]
+    fn test_strip_attributes_from_response_html() {
+        let mut chain = MiddlewareChain::new();
+        chain.register(StripAttributes::with_prefix("data-debug"));
+
+        let response = chain.apply(AppResponse {
+            diff: vec![],
+            html: r#"<div data-debug-source="main.rs" id="a"><p data-debug-line="1">Hi</p></div>"#
+                .to_string(),
+            request_id: None,
+        });
+
+        assert_eq!(response.html, r#"<div id="a"><p >Hi</p></div>"#);
+    }
+}
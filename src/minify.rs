@@ -0,0 +1,116 @@
+use crate::self_virtual_dom::{
+    collapse_whitespace, is_insignificant_whitespace, neighbor_tag, ElementType,
+    WHITESPACE_SENSITIVE_ELEMENTS,
+};
+use crate::visit::Transformer;
+
+/**
+ * 省略しても意味の変わらない属性の既定値（タグ名、属性名、値）
+ */
+pub const DEFAULT_ATTRIBUTES: [(&str, &str, &str); 7] = [
+    ("input", "type", "text"),
+    ("button", "type", "submit"),
+    ("form", "method", "get"),
+    ("script", "type", "text/javascript"),
+    ("style", "type", "text/css"),
+    ("link", "type", "text/css"),
+    ("ol", "type", "1"),
+];
+
+/**
+ * 表示に影響しない部分を取り除いた木を返す関数
+ *
+ * - 隣り合うテキストノードを1つにまとめる
+ * - ブロック要素に接する空白だけのテキストノードを取り除き、テキスト中の連続する空白を1つにまとめる
+ * - インライン要素の間の空白だけのテキストノードは、単語の区切りになるため1つの空白として残す
+ * - 既定値と同じ属性を取り除く
+ *
+ * pre・textarea・script・style の中の空白はそのまま残す
+ */
+pub fn minify(node: ElementType) -> ElementType {
+    minify_node(node, false)
+}
+
+fn minify_node(node: ElementType, preserve_whitespace: bool) -> ElementType {
+    let ElementType::Element(tag, mut attrs, children) = node else {
+        return match node {
+            ElementType::Text(text) if !preserve_whitespace => {
+                ElementType::Text(collapse_whitespace(&text).into())
+            }
+            node => node,
+        };
+    };
+
+    attrs.retain(|name, value| {
+        !DEFAULT_ATTRIBUTES
+            .iter()
+            .any(|default| (default.0, default.1, default.2) == (&tag, name, value))
+    });
+
+    let preserve_whitespace =
+        preserve_whitespace || WHITESPACE_SENSITIVE_ELEMENTS.contains(&tag.as_str());
+    let mut minified: Vec<ElementType> = Vec::with_capacity(children.len());
+    for child in children {
+        let child = minify_node(child, preserve_whitespace);
+        match (minified.last_mut(), child) {
+            (Some(ElementType::Text(previous)), ElementType::Text(text)) => {
+                previous.to_mut().push_str(&text)
+            }
+            (_, child) => minified.push(child),
+        }
+    }
+    if !preserve_whitespace {
+        let insignificant: Vec<bool> = (0..minified.len())
+            .map(|index| {
+                matches!(&minified[index], ElementType::Text(text) if text.trim().is_empty())
+                    && is_insignificant_whitespace(
+                        neighbor_tag(&tag, index.checked_sub(1).map(|i| &minified[i])),
+                        neighbor_tag(&tag, minified.get(index + 1)),
+                    )
+            })
+            .collect();
+        let mut insignificant = insignificant.into_iter();
+        minified.retain(|_| !insignificant.next().unwrap_or(false));
+    }
+
+    ElementType::Element(tag, attrs, minified)
+}
+
+/**
+ * Pipeline で木全体に minify を適用するパス
+ */
+pub struct MinifyPass;
+
+impl Transformer for MinifyPass {
+    fn pre(&mut self, path: &[usize], node: &mut ElementType) {
+        if path.is_empty() {
+            let taken = std::mem::replace(node, ElementType::Text("".into()));
+            *node = minify(taken);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_html;
+
+    #[test]
+    fn test_minify() {
+        let tree = parse_html(
+            "<form method=\"get\">\n  <input type=\"text\" name=\"q\">\n  <pre>  a\n  b</pre>\n  <p>Hello,   <!-- x --> world</p>\n</form>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            minify(tree).to_string(),
+            "<form ><input name=\"q\"><pre >  a\n  b</pre><p >Hello, <!-- x --> world</p></form>"
+        );
+
+        let tree = parse_html("<div>\n  <p><b>a</b>\n  <i>b</i></p>\n</div>").unwrap();
+        assert_eq!(
+            minify(tree).to_string(),
+            "<div ><p ><b >a</b> <i >b</i></p></div>"
+        );
+    }
+}
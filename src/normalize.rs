@@ -0,0 +1,105 @@
+use crate::self_virtual_dom::{
+    update_dom, AppResponse, ElementType, VNode, WHITESPACE_SENSITIVE_ELEMENTS,
+};
+use crate::visit::Transformer;
+
+/**
+ * テキストノードの正規化の設定
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    /** テキストの前後の空白を取り除く（pre などの中は除く） */
+    pub trim: bool,
+}
+
+/**
+ * 木の組み立て方による見た目に現れない違いをなくす関数
+ * 隣り合うテキストノードを1つにまとめ、空のテキストノードを取り除く
+ */
+pub fn normalize(node: &mut ElementType) {
+    normalize_with(node, &NormalizeOptions::default());
+}
+
+/**
+ * 設定に従ってテキストノードを正規化する関数
+ */
+pub fn normalize_with(node: &mut ElementType, options: &NormalizeOptions) {
+    normalize_node(node, options, false);
+}
+
+fn normalize_node(node: &mut ElementType, options: &NormalizeOptions, preserve_whitespace: bool) {
+    let ElementType::Element(tag, _, children) = node else {
+        return;
+    };
+    let preserve_whitespace =
+        preserve_whitespace || WHITESPACE_SENSITIVE_ELEMENTS.contains(&tag.as_str());
+
+    let mut normalized: Vec<ElementType> = Vec::with_capacity(children.len());
+    for mut child in children.drain(..) {
+        normalize_node(&mut child, options, preserve_whitespace);
+        match (normalized.last_mut(), child) {
+            (Some(ElementType::Text(previous)), ElementType::Text(text)) => {
+                previous.to_mut().push_str(&text)
+            }
+            (_, child) => normalized.push(child),
+        }
+    }
+    for child in &mut normalized {
+        if let ElementType::Text(text) = child {
+            if options.trim && !preserve_whitespace {
+                *text = text.trim().to_string().into();
+            }
+        }
+    }
+    normalized.retain(|child| !matches!(child, ElementType::Text(text) if text.is_empty()));
+    *children = normalized;
+}
+
+/**
+ * 両方の木を正規化してから差分を取る関数
+ * 差分のパスは正規化後の木に対するものになる
+ */
+pub fn update_dom_normalized(old: &VNode, new: &VNode) -> AppResponse {
+    let (mut old, mut new) = (old.clone(), new.clone());
+    normalize(&mut old.element_type);
+    normalize(&mut new.element_type);
+    update_dom(&old, &new)
+}
+
+/**
+ * Pipeline で木全体を正規化するパス
+ */
+#[derive(Default)]
+pub struct NormalizePass {
+    pub options: NormalizeOptions,
+}
+
+impl Transformer for NormalizePass {
+    fn pre(&mut self, path: &[usize], node: &mut ElementType) {
+        if path.is_empty() {
+            normalize_with(node, &self.options);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+
+    #[test]
+    fn test_normalize_removes_spurious_patches() {
+        let old = VNode {
+            element_type: element("p", &[], ("Hello, ", "", "world")),
+        };
+        let new = VNode {
+            element_type: element("p", &[], "Hello, world"),
+        };
+        assert!(!update_dom(&old, &new).diff.is_empty());
+        assert!(update_dom_normalized(&old, &new).diff.is_empty());
+
+        let mut padded = element("div", &[], ("  a ", element("pre", &[], " b ")));
+        normalize_with(&mut padded, &NormalizeOptions { trim: true });
+        assert_eq!(padded.to_string(), "<div >a<pre > b </pre></div>");
+    }
+}
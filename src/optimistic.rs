@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::patch::apply_patches;
+use crate::self_virtual_dom::{update_dom, virtual_dom_to_html, Diff, VNode};
+use crate::store::{Snapshot, Store};
+
+/**
+ * クライアントがイベントの結果を予測して先に適用した差分
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+    /** 予測を適用する前にクライアントが持っていた木のバージョン */
+    pub version: u64,
+    pub diff: Vec<Diff>,
+}
+
+/**
+ * 予測した差分に対するサーバーの応答
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Reconciliation {
+    /** 予測が正しかったため、クライアントは何もしなくてよい */
+    Ack { version: u64 },
+    /** 予測を適用した木から正しい木への差分 */
+    Correct { version: u64, diff: Vec<Diff> },
+    /** 予測の元になった木を復元できないため、現在のHTML全体で置き換えさせる */
+    Resync { version: u64, html: String },
+}
+
+/**
+ * イベントの処理 effect をストアに適用し、クライアントの予測と突き合わせる関数
+ * 予測の元になったバージョンが処理前のバージョンと異なる場合や、予測が元の木に適用できない場合は Resync を返す
+ */
+pub fn reconcile(
+    store: &Store,
+    prediction: &Prediction,
+    effect: impl FnOnce(&mut VNode),
+) -> Reconciliation {
+    let (base, current) = store.modify_snapshots(effect);
+    reconcile_snapshots(prediction, base, current)
+}
+
+/**
+ * 処理前の base と処理後の current をクライアントの予測と突き合わせる関数
+ * ストアを別の方法で更新した場合に、その前後のスナップショットを渡して使う
+ */
+pub fn reconcile_snapshots(
+    prediction: &Prediction,
+    base: Snapshot,
+    current: Snapshot,
+) -> Reconciliation {
+    let resync = || Reconciliation::Resync {
+        version: current.version,
+        html: virtual_dom_to_html(&current.tree.element_type),
+    };
+    if prediction.version != base.version {
+        return resync();
+    }
+
+    let mut predicted = base.tree.element_type;
+    if apply_patches(&mut predicted, &prediction.diff).is_err() {
+        return resync();
+    }
+    // 空のテキストノードは差分に現れないため、描画結果で比較する
+    if virtual_dom_to_html(&predicted) == virtual_dom_to_html(&current.tree.element_type) {
+        return Reconciliation::Ack {
+            version: current.version,
+        };
+    }
+    Reconciliation::Correct {
+        version: current.version,
+        diff: update_dom(
+            &VNode {
+                element_type: predicted,
+            },
+            &current.tree,
+        )
+        .diff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::ElementType;
+
+    fn counter(value: u32) -> VNode {
+        VNode {
+            element_type: element("div", &[], element("span", &[], value)),
+        }
+    }
+
+    fn set_counter(value: u32) -> impl FnOnce(&mut VNode) {
+        move |tree| *tree = counter(value)
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let store = Store::new(counter(0));
+        let predict = |version, value| Prediction {
+            version,
+            diff: update_dom(&store.snapshot().tree, &counter(value)).diff,
+        };
+
+        let prediction = predict(0, 1);
+        assert_eq!(
+            reconcile(&store, &prediction, set_counter(1)),
+            Reconciliation::Ack { version: 1 }
+        );
+
+        let prediction = predict(1, 2);
+        let Reconciliation::Correct { version, diff } =
+            reconcile(&store, &prediction, set_counter(3))
+        else {
+            panic!("expected a corrective diff");
+        };
+        assert_eq!(version, 2);
+        assert_eq!(
+            diff[1].node().unwrap().element_type,
+            ElementType::Text("3".into())
+        );
+
+        assert_eq!(
+            reconcile(&store, &prediction, |_| {}),
+            Reconciliation::Resync {
+                version: 2,
+                html: "<div ><span >3</span></div>".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::binding::BIND_PREFIX;
+use crate::self_virtual_dom::ElementType;
+
+/**
+ * イベントを送る先の処理の名前を持つ属性名の接頭辞。`data-on-input="search"` のように書く
+ */
+pub const ON_PREFIX: &str = "data-on-";
+
+/**
+ * デバウンスの間隔（ミリ秒）を持つ属性名の接尾辞。`data-on-input-debounce-ms="150"` のように書く
+ */
+pub const DEBOUNCE_SUFFIX: &str = "-debounce-ms";
+
+/**
+ * スロットルの間隔（ミリ秒）を持つ属性名の接尾辞
+ */
+pub const THROTTLE_SUFFIX: &str = "-throttle-ms";
+
+/**
+ * 頻繁に発生するイベントを送る間隔の指定
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    /** 最後のイベントから指定の時間だけ次のイベントがなかったときに送る */
+    Debounce(u64),
+    /** 指定の時間に最大1回だけ送る */
+    Throttle(u64),
+}
+
+impl Pacing {
+    /**
+     * クライアントが守っていれば、連続して送られるイベントの間に必ず空く間隔を返す関数
+     */
+    pub fn interval(&self) -> Duration {
+        match self {
+            Pacing::Debounce(ms) | Pacing::Throttle(ms) => Duration::from_millis(*ms),
+        }
+    }
+}
+
+/**
+ * 要素のイベントと、それを送る先の処理の宣言
+ * 属性としてノードに保存されるため、HTMLと差分にそのまま含まれてクライアントに届く
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventBinding {
+    pub event: String,
+    pub handler: String,
+    pub pacing: Option<Pacing>,
+}
+
+/**
+ * イベントを処理の名前に結び付ける関数
+ */
+pub fn on(event: &str, handler: &str) -> EventBinding {
+    EventBinding {
+        event: event.to_string(),
+        handler: handler.to_string(),
+        pacing: None,
+    }
+}
+
+/**
+ * input イベントを処理の名前に結び付ける関数
+ */
+pub fn on_input(handler: &str) -> EventBinding {
+    on("input", handler)
+}
+
+impl EventBinding {
+    pub fn debounce_ms(mut self, ms: u64) -> Self {
+        self.pacing = Some(Pacing::Debounce(ms));
+        self
+    }
+
+    pub fn throttle_ms(mut self, ms: u64) -> Self {
+        self.pacing = Some(Pacing::Throttle(ms));
+        self
+    }
+
+    /**
+     * 要素の属性にイベントの宣言を書き込む関数
+     * 要素以外のノードは属性を持てないため変更しない
+     */
+    pub fn apply(&self, node: &mut ElementType) {
+        let ElementType::Element(_, attrs, _) = node else {
+            return;
+        };
+        let name = format!("{}{}", ON_PREFIX, self.event);
+        let debounce = format!("{}{}", name, DEBOUNCE_SUFFIX);
+        let throttle = format!("{}{}", name, THROTTLE_SUFFIX);
+        attrs.remove(debounce.as_str());
+        attrs.remove(throttle.as_str());
+        match self.pacing {
+            Some(Pacing::Debounce(ms)) => attrs.insert(debounce.into(), ms.to_string().into()),
+            Some(Pacing::Throttle(ms)) => attrs.insert(throttle.into(), ms.to_string().into()),
+            None => None,
+        };
+        attrs.insert(name.into(), self.handler.clone().into());
+    }
+
+    /**
+     * 要素の属性から event のイベントの宣言を読み取る関数
+     */
+    pub fn of(node: &ElementType, event: &str) -> Option<Self> {
+        let ElementType::Element(_, attrs, _) = node else {
+            return None;
+        };
+        let name = format!("{}{}", ON_PREFIX, event);
+        let handler = attrs.get(name.as_str())?.to_string();
+        let ms = |suffix: &str| {
+            attrs
+                .get(format!("{}{}", name, suffix).as_str())
+                .and_then(|value| value.parse().ok())
+        };
+        let pacing = ms(DEBOUNCE_SUFFIX)
+            .map(Pacing::Debounce)
+            .or_else(|| ms(THROTTLE_SUFFIX).map(Pacing::Throttle));
+        Some(Self {
+            event: event.to_string(),
+            handler,
+            pacing,
+        })
+    }
+}
+
+/**
+ * イベントの宣言を付与した要素を返す関数
+ */
+pub fn with_event(mut node: ElementType, binding: &EventBinding) -> ElementType {
+    binding.apply(&mut node);
+    node
+}
+
+/**
+ * 状態の path にバインドされた入力欄の input イベントに指定された間隔を返す関数
+ */
+pub fn input_pacing(template: &ElementType, path: &str) -> Option<Pacing> {
+    template.iter_with_paths().find_map(|(_, node)| {
+        let ElementType::Element(_, attrs, _) = node else {
+            return None;
+        };
+        let bound = attrs
+            .iter()
+            .any(|(name, value)| name.starts_with(BIND_PREFIX) && value.trim() == path.trim());
+        if !bound {
+            return None;
+        }
+        EventBinding::of(node, "input")?.pacing
+    })
+}
+
+/**
+ * クライアントが間隔の指定を守っているかを、キーごとに最後に受け付けた時刻から判定する構造体
+ * ネットワークの遅延で到着の間隔が縮むことがあるため、指定の半分の間隔までは受け付ける
+ */
+#[derive(Debug, Default)]
+pub struct EventPacer {
+    accepted: Mutex<HashMap<String, Instant>>,
+}
+
+impl EventPacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * キーのイベントを受け付けてよいかを判定し、受け付ける場合は時刻を記録する関数
+     */
+    pub fn check(&self, key: &str, pacing: Pacing) -> bool {
+        self.check_at(key, pacing, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, pacing: Pacing, now: Instant) -> bool {
+        let mut accepted = self.accepted.lock().unwrap();
+        if let Some(last) = accepted.get(key) {
+            if now.saturating_duration_since(*last) < pacing.interval() / 2 {
+                return false;
+            }
+        }
+        accepted.insert(key.to_string(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+
+    #[test]
+    fn test_event_binding_attributes() {
+        let input = with_event(
+            element("input", &[("bind:value", "state.query")], ()),
+            &on_input("search").debounce_ms(150),
+        );
+        assert_eq!(
+            virtual_dom_to_html(&input),
+            r#"<input bind:value="state.query" data-on-input="search" data-on-input-debounce-ms="150">"#
+        );
+        assert_eq!(
+            EventBinding::of(&input, "input"),
+            Some(on_input("search").debounce_ms(150))
+        );
+        assert_eq!(
+            input_pacing(&element("form", &[], input), "state.query"),
+            Some(Pacing::Debounce(150))
+        );
+        assert_eq!(input_pacing(&element("form", &[], ()), "state.query"), None);
+    }
+
+    #[test]
+    fn test_event_pacer() {
+        let pacer = EventPacer::new();
+        let pacing = Pacing::Throttle(100);
+        let start = Instant::now();
+
+        assert!(pacer.check_at("a", pacing, start));
+        assert!(!pacer.check_at("a", pacing, start + Duration::from_millis(10)));
+        assert!(pacer.check_at("b", pacing, start + Duration::from_millis(10)));
+        assert!(pacer.check_at("a", pacing, start + Duration::from_millis(60)));
+    }
+}
@@ -0,0 +1,342 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::self_virtual_dom::{Attributes, ElementType, VNode, RAW_TEXT_ELEMENTS, VOID_ELEMENTS};
+
+/**
+ * HTMLの解析に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /** 入力の先頭からのバイト位置 */
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid HTML at {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/**
+ * HTMLを解析して1つのノードを返す関数
+ * テキストと属性値の文字参照は展開するため、virtual_dom_to_html でエスケープした出力を元の木に読み戻せる
+ * script と style の内容は文字参照を含めてそのまま扱う
+ */
+pub fn parse_html(html: &str) -> Result<ElementType, ParseError> {
+    let mut parser = Parser { html, position: 0 };
+    parser.skip_prolog();
+    let node = parser
+        .parse_node()?
+        .ok_or_else(|| parser.error("expected a node"))?;
+    parser.skip_prolog();
+    if parser.position < html.len() {
+        return Err(parser.error("expected a single root node"));
+    }
+    Ok(node)
+}
+
+struct Parser<'a> {
+    html: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.html[self.position..]
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /**
+     * 前後の空白、コメント、DOCTYPE を読み飛ばす
+     */
+    fn skip_prolog(&mut self) {
+        loop {
+            self.skip_whitespace();
+            if !self.skip_comment() && !self.skip_doctype() {
+                break;
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) -> bool {
+        if !self.rest().starts_with("<!--") {
+            return false;
+        }
+        match self.rest().find("-->") {
+            Some(end) => self.position += end + 3,
+            None => self.position = self.html.len(),
+        }
+        true
+    }
+
+    fn skip_doctype(&mut self) -> bool {
+        if !self.rest().starts_with("<!") {
+            return false;
+        }
+        match self.rest().find('>') {
+            Some(end) => self.position += end + 1,
+            None => self.position = self.html.len(),
+        }
+        true
+    }
+
+    fn parse_node(&mut self) -> Result<Option<ElementType>, ParseError> {
+        let rest = self.rest();
+        if rest.is_empty() || rest.starts_with("</") {
+            return Ok(None);
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let Some(end) = comment.find("-->") else {
+                return Err(self.error("unterminated comment"));
+            };
+            self.position += end + 7;
+            return Ok(Some(ElementType::Comment(comment[..end].to_string())));
+        }
+        if rest.starts_with('<') {
+            return self.parse_element().map(Some);
+        }
+        let end = rest.find('<').unwrap_or(rest.len());
+        self.position += end;
+        Ok(Some(ElementType::Text(
+            decode_char_refs(&rest[..end]).into_owned().into(),
+        )))
+    }
+
+    fn parse_name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '/' | '='))
+            .unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+
+    fn parse_element(&mut self) -> Result<ElementType, ParseError> {
+        self.position += 1;
+        let tag = self.parse_name().to_string();
+        if tag.is_empty() {
+            return Err(self.error("expected a tag name"));
+        }
+
+        let mut attrs = Attributes::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                return Ok(ElementType::Element(tag, attrs, vec![]));
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            if rest.is_empty() {
+                return Err(self.error(&format!("unterminated start tag <{}>", tag)));
+            }
+            let name = self.parse_name().to_string();
+            if name.is_empty() {
+                return Err(self.error("expected an attribute name"));
+            }
+            self.skip_whitespace();
+            let value = if self.rest().starts_with('=') {
+                self.position += 1;
+                self.skip_whitespace();
+                self.parse_attr_value()?
+            } else {
+                String::new()
+            };
+            attrs.insert(name.into(), value.into());
+        }
+
+        if VOID_ELEMENTS.contains(&tag.to_ascii_lowercase().as_str()) {
+            // 空要素に書かれた余分な終了タグは読み飛ばす
+            let closing = format!("</{}>", tag);
+            if self.rest().starts_with(&closing) {
+                self.position += closing.len();
+            }
+            return Ok(ElementType::Element(tag, attrs, vec![]));
+        }
+
+        let closing = format!("</{}", tag);
+        let mut children = Vec::new();
+        if RAW_TEXT_ELEMENTS.contains(&tag.to_ascii_lowercase().as_str()) {
+            // 内容はタグとして解釈せず、終了タグまでをそのままテキストにする
+            let rest = self.rest();
+            let end = rest.find(&closing).unwrap_or(rest.len());
+            if end > 0 {
+                children.push(ElementType::Text(rest[..end].to_string().into()));
+            }
+            self.position += end;
+        } else {
+            while let Some(child) = self.parse_node()? {
+                children.push(child);
+            }
+        }
+
+        if !self.rest().starts_with(&closing) {
+            return Err(self.error(&format!("expected closing tag for <{}>", tag)));
+        }
+        self.position += closing.len();
+        self.skip_whitespace();
+        if !self.rest().starts_with('>') {
+            return Err(self.error(&format!("unterminated closing tag for <{}>", tag)));
+        }
+        self.position += 1;
+        Ok(ElementType::Element(tag, attrs, children))
+    }
+
+    fn parse_attr_value(&mut self) -> Result<String, ParseError> {
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let Some(end) = rest[1..].find(quote) else {
+                    return Err(self.error("unterminated attribute value"));
+                };
+                self.position += end + 2;
+                Ok(decode_char_refs(&rest[1..end + 1]).into_owned())
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                self.position += end;
+                Ok(decode_char_refs(&rest[..end]).into_owned())
+            }
+        }
+    }
+}
+
+/**
+ * `&amp;` `&#60;` `&#x3C;` などの文字参照を展開する関数
+ * 名前付きの参照は HTML の特殊文字と空白の一部だけを扱い、解釈できない参照はそのまま残す
+ */
+pub fn decode_char_refs(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index..];
+        let reference = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 32)
+            .and_then(|end| Some((end + 2, char_ref(&rest[1..end + 1])?)));
+        match reference {
+            Some((len, ch)) => {
+                decoded.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+fn char_ref(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        // NUL と範囲外の値は置換文字として扱う
+        return Some(
+            char::from_u32(code)
+                .filter(|ch| *ch != '\0')
+                .unwrap_or('\u{FFFD}'),
+        );
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{A0}'),
+        "colon" => Some(':'),
+        "Tab" => Some('\t'),
+        "NewLine" => Some('\n'),
+        _ => None,
+    }
+}
+
+impl FromStr for ElementType {
+    type Err = ParseError;
+
+    fn from_str(html: &str) -> Result<Self, Self::Err> {
+        parse_html(html)
+    }
+}
+
+impl FromStr for VNode {
+    type Err = ParseError;
+
+    fn from_str(html: &str) -> Result<Self, Self::Err> {
+        parse_html(html).map(|element_type| VNode { element_type })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trip() {
+        let tree: ElementType = "<!DOCTYPE html><div class=\"card\"><h1 >Title</h1><br><input disabled value='x'/>text<!-- note --></div>"
+            .parse()
+            .unwrap();
+
+        let ElementType::Element(tag, attrs, children) = &tree else {
+            panic!("expected element");
+        };
+        assert_eq!(tag, "div");
+        assert_eq!(attrs.get("class").map(|value| value.as_ref()), Some("card"));
+        assert_eq!(children.len(), 5);
+        assert_eq!(children[3], ElementType::Text("text".into()));
+        assert_eq!(children[4], ElementType::Comment(" note ".to_string()));
+
+        assert_eq!(tree.to_string().parse::<ElementType>().unwrap(), tree);
+
+        // 文字参照は展開し、エスケープして書き出した木は元の木に読み戻せる
+        let tree: ElementType =
+            r#"<p title="a&amp;b &quot;c&quot;">&lt;b&gt; &#38; &#x3c; &unknown;<script>a&&b<c</script></p>"#
+                .parse()
+                .unwrap();
+        let ElementType::Element(_, attrs, children) = &tree else {
+            panic!("expected element");
+        };
+        assert_eq!(
+            attrs.get("title").map(|value| value.as_ref()),
+            Some(r#"a&b "c""#)
+        );
+        assert_eq!(children[0], ElementType::Text("<b> & < &unknown;".into()));
+        assert_eq!(children[1].to_string(), "<script >a&&b<c</script>");
+        assert_eq!(tree.to_string().parse::<ElementType>().unwrap(), tree);
+
+        assert_eq!(
+            "<div><p></div>".parse::<VNode>().unwrap_err().message,
+            "expected closing tag for <p>"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt;
+
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{virtual_dom_to_html, Diff, ElementType};
+
+/**
+ * 差分を木に適用できなかったことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError {
+    pub path: NodePath,
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot apply patch at {:?}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/**
+ * 差分を適用した結果が新しい木と一致しなかったことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /** 差分を適用できなかった */
+    Patch(PatchError),
+    /** 適用した結果が新しい木と異なる。path は最初に食い違ったノードのパスで、ノードは HTML で持つ */
+    Mismatch {
+        path: NodePath,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Patch(error) => write!(f, "{}", error),
+            VerifyError::Mismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "patched tree differs at {:?}: expected {}, got {}",
+                path, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<PatchError> for VerifyError {
+    fn from(error: PatchError) -> Self {
+        VerifyError::Patch(error)
+    }
+}
+
+/**
+ * 差分を先頭から順に木へ適用する関数
+ * 削除は古い木のパス、追加は新しい木のパスで解釈するため、sort_patches の順序で並んでいる必要がある
+ * 移動する要素は削除の段階で取り外し、追加の段階で挿入先の順番が来たときに挿入する
+ * 削除するノードが差分の内容と一致しない場合はエラーを返す
+ */
+pub fn apply_patches(tree: &mut ElementType, patches: &[Diff]) -> Result<(), PatchError> {
+    // 取り外した要素と挿入先のパス。挿入先のパスの順に並べる
+    let mut moving: Vec<(NodePath, ElementType)> = Vec::new();
+    for patch in patches {
+        if !patch.is_removal() {
+            attach_moved(tree, &mut moving, Some(patch.path()))?;
+        }
+        match patch {
+            Diff::RemoveNode(path, node) => {
+                detach(tree, path, &node.element_type)?;
+            }
+            Diff::MoveNode(from, to, node) => {
+                let moved = detach(tree, from, &node.element_type)?;
+                let index = moving.partition_point(|(path, _)| path < to);
+                moving.insert(index, (to.clone(), moved));
+            }
+            Diff::AddNode(path, node) => insert(tree, path, node.element_type.clone())?,
+            Diff::SetAttribute(path, name, value) => {
+                let Some(ElementType::Element(_, attrs, _)) = tree.get_mut(path) else {
+                    return Err(patch_error(path, "target is not an element"));
+                };
+                match value {
+                    Some(value) => attrs.insert(name.clone().into(), value.clone().into()),
+                    None => attrs.remove(name.as_str()),
+                };
+            }
+        }
+    }
+    attach_moved(tree, &mut moving, None)
+}
+
+fn patch_error(path: &[usize], message: &str) -> PatchError {
+    PatchError {
+        path: path.to_vec(),
+        message: message.to_string(),
+    }
+}
+
+/**
+ * 取り外した要素のうち、挿入先が until 以前のものを挿入する関数
+ * until が None の場合は残りをすべて挿入する
+ */
+fn attach_moved(
+    tree: &mut ElementType,
+    moving: &mut Vec<(NodePath, ElementType)>,
+    until: Option<&NodePath>,
+) -> Result<(), PatchError> {
+    let count = match until {
+        Some(until) => moving.partition_point(|(path, _)| path <= until),
+        None => moving.len(),
+    };
+    for (path, node) in moving.drain(..count) {
+        insert(tree, &path, node)?;
+    }
+    Ok(())
+}
+
+fn detach(
+    tree: &mut ElementType,
+    path: &[usize],
+    expected: &ElementType,
+) -> Result<ElementType, PatchError> {
+    let Some((index, parent_path)) = path.split_last() else {
+        if tree != expected {
+            return Err(patch_error(path, "root does not match the removed node"));
+        }
+        return Ok(std::mem::replace(tree, ElementType::Text("".into())));
+    };
+    let Some(ElementType::Element(_, _, children)) = tree.get_mut(parent_path) else {
+        return Err(patch_error(path, "parent is not an element"));
+    };
+    if children.get(*index) != Some(expected) {
+        return Err(patch_error(path, "node does not match the removed node"));
+    }
+    Ok(children.remove(*index))
+}
+
+fn insert(tree: &mut ElementType, path: &[usize], node: ElementType) -> Result<(), PatchError> {
+    let Some((index, parent_path)) = path.split_last() else {
+        *tree = node;
+        return Ok(());
+    };
+    let Some(ElementType::Element(_, _, children)) = tree.get_mut(parent_path) else {
+        return Err(patch_error(path, "parent is not an element"));
+    };
+    if *index > children.len() {
+        return Err(patch_error(path, "index is out of range"));
+    }
+    children.insert(*index, node);
+    Ok(())
+}
+
+/**
+ * 古い木に差分を適用し、新しい木と完全に一致するかを確かめる関数
+ * 差分アルゴリズムのテストや、デバッグビルドでの自己検査に使う
+ */
+pub fn verify_diff(
+    old: &ElementType,
+    new: &ElementType,
+    patches: &[Diff],
+) -> Result<(), VerifyError> {
+    let mut tree = old.clone();
+    apply_patches(&mut tree, patches)?;
+    match first_mismatch(new, &tree, &mut Vec::new()) {
+        None => Ok(()),
+        Some((path, expected, actual)) => Err(VerifyError::Mismatch {
+            path,
+            expected: virtual_dom_to_html(expected),
+            actual: virtual_dom_to_html(actual),
+        }),
+    }
+}
+
+/**
+ * 2つの木で最初に食い違ったノードのパスと、それぞれのノードを返す関数
+ */
+fn first_mismatch<'a>(
+    expected: &'a ElementType,
+    actual: &'a ElementType,
+    path: &mut NodePath,
+) -> Option<(NodePath, &'a ElementType, &'a ElementType)> {
+    if expected == actual {
+        return None;
+    }
+    if let (
+        ElementType::Element(expected_tag, expected_attrs, expected_children),
+        ElementType::Element(actual_tag, actual_attrs, actual_children),
+    ) = (expected, actual)
+    {
+        if expected_tag == actual_tag
+            && expected_attrs == actual_attrs
+            && expected_children.len() == actual_children.len()
+        {
+            for (index, (expected, actual)) in
+                expected_children.iter().zip(actual_children).enumerate()
+            {
+                path.push(index);
+                let mismatch = first_mismatch(expected, actual, path);
+                path.pop();
+                if mismatch.is_some() {
+                    return mismatch;
+                }
+            }
+        }
+    }
+    Some((path.clone(), expected, actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::{
+        update_dom, update_dom_with, DiffOptions, EmptyTextPolicy, VNode,
+    };
+
+    #[test]
+    fn test_apply_patches_reproduces_new_tree() {
+        let old = element(
+            "ul",
+            &[],
+            [
+                element("li", &[("key", "a")], "A"),
+                element("li", &[("key", "b")], "B"),
+                element("li", &[("key", "c")], "C"),
+            ],
+        );
+        let new = element(
+            "ul",
+            &[],
+            [
+                element("li", &[("key", "z")], "Z"),
+                element("li", &[("key", "a")], "A!"),
+                element("li", &[("key", "c")], "C"),
+            ],
+        );
+        let patches = update_dom(
+            &VNode {
+                element_type: old.clone(),
+            },
+            &VNode {
+                element_type: new.clone(),
+            },
+        )
+        .diff;
+
+        let mut tree = old;
+        apply_patches(&mut tree, &patches).unwrap();
+        assert_eq!(tree, new);
+
+        let error = apply_patches(&mut tree, &patches).unwrap_err();
+        assert_eq!(error.path, patches[0].path().clone());
+    }
+
+    #[test]
+    fn test_verify_diff() {
+        let cases = [
+            (
+                element("div", &[], [element("p", &[], "a"), element("p", &[], "b")]),
+                element("div", &[], [element("p", &[], "a")]),
+            ),
+            (
+                element("div", &[], [element("p", &[], "a")]),
+                element(
+                    "section",
+                    &[],
+                    [element("p", &[], "a"), element("p", &[], "b")],
+                ),
+            ),
+            (
+                element("ul", &[], [element("li", &[("key", "a")], "A")]),
+                element(
+                    "ul",
+                    &[],
+                    [
+                        element("li", &[("key", "b")], "B"),
+                        element("li", &[("key", "a")], "A"),
+                    ],
+                ),
+            ),
+        ];
+        let options = DiffOptions::default().with_empty_text(EmptyTextPolicy::Track);
+        for (old, new) in cases {
+            let patches = update_dom_with(
+                &VNode {
+                    element_type: old.clone(),
+                },
+                &VNode {
+                    element_type: new.clone(),
+                },
+                &options,
+            )
+            .diff;
+            assert_eq!(verify_diff(&old, &new, &patches), Ok(()));
+        }
+
+        let old = element("div", &[], [element("p", &[], "a")]);
+        let new = element("div", &[], [element("p", &[], "b")]);
+        let error = verify_diff(&old, &new, &[]).unwrap_err();
+        assert!(matches!(error, VerifyError::Mismatch { path, .. } if path == vec![0, 0]));
+    }
+}
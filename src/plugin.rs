@@ -0,0 +1,208 @@
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+
+use crate::middleware::{MiddlewareChain, PatchMiddleware};
+use crate::store::Store;
+use crate::visit::Transformer;
+
+/**
+ * ルート・木の変換・差分のミドルウェアをまとめて登録する拡張機能の trait
+ * 開発者向けのパネルやメトリクス、入力の検査などを独立した単位で有効にできる
+ */
+pub trait Plugin: Send + Sync {
+    /** ログなどで拡張機能を識別する名前 */
+    fn name(&self) -> &str;
+
+    /**
+     * 拡張機能のルート・変換・ミドルウェアを registry に登録する関数
+     * サーバーのルートを組み立てるときに、PluginHost に追加した順に1回ずつ呼ばれる
+     */
+    fn register(&self, registry: &mut PluginRegistry);
+}
+
+/**
+ * 拡張機能が登録したものを集める構造体
+ */
+pub struct PluginRegistry {
+    store: Arc<Store>,
+    routes: Vec<BoxedFilter<(Box<dyn Reply>,)>>,
+    middleware: MiddlewareChain,
+}
+
+impl PluginRegistry {
+    fn new(store: Arc<Store>) -> Self {
+        Self {
+            store,
+            routes: Vec::new(),
+            middleware: MiddlewareChain::new(),
+        }
+    }
+
+    /**
+     * ルートを組み立てる対象のストアを返す関数
+     */
+    pub fn store(&self) -> &Arc<Store> {
+        &self.store
+    }
+
+    /**
+     * ルートを登録する関数
+     * 組み込みのルートのいずれにも一致しなかったリクエストで、登録順に試す
+     */
+    pub fn route<F, R>(&mut self, filter: F) -> &mut Self
+    where
+        F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+        R: Reply + 'static,
+    {
+        self.routes.push(
+            filter
+                .map(|reply: R| Box::new(reply) as Box<dyn Reply>)
+                .boxed(),
+        );
+        self
+    }
+
+    /**
+     * 以降のストアの更新で、差分を取る前に新しい木へ適用するパスを登録する関数
+     */
+    pub fn transform<T: Transformer + Send + 'static>(&mut self, pass: T) -> &mut Self {
+        self.store.register_transform(pass);
+        self
+    }
+
+    /**
+     * クライアントへ送信する前の差分に適用するミドルウェアを登録する関数
+     * 設定のミドルウェアの後に、登録順に適用する
+     */
+    pub fn middleware<M: PatchMiddleware + 'static>(&mut self, middleware: M) -> &mut Self {
+        self.middleware.register(middleware);
+        self
+    }
+}
+
+/**
+ * サーバーで有効にする拡張機能の一覧
+ */
+#[derive(Clone, Default)]
+pub struct PluginHost {
+    plugins: Vec<Arc<dyn Plugin>>,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 拡張機能を末尾に追加する関数
+     */
+    pub fn with<P: Plugin + 'static>(mut self, plugin: P) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name())
+    }
+
+    /**
+     * すべての拡張機能を登録し、ルートと、middleware の後に拡張機能のミドルウェアを適用するチェーンを返す関数
+     */
+    pub(crate) fn install(
+        &self,
+        store: Arc<Store>,
+        middleware: Arc<MiddlewareChain>,
+    ) -> (BoxedFilter<(Box<dyn Reply>,)>, Arc<MiddlewareChain>) {
+        let mut registry = PluginRegistry::new(store);
+        for plugin in &self.plugins {
+            plugin.register(&mut registry);
+            tracing::debug!(plugin = plugin.name(), "plugin registered");
+        }
+
+        let routes = registry.routes.into_iter().fold(
+            warp::any()
+                .and_then(|| async { Err::<Box<dyn Reply>, Rejection>(warp::reject::not_found()) })
+                .boxed(),
+            |routes, route| routes.or(route).unify().boxed(),
+        );
+        if self.plugins.is_empty() {
+            return (routes, middleware);
+        }
+        let plugin_middleware = registry.middleware;
+        let mut chain = MiddlewareChain::new();
+        chain
+            .register(move |patches| middleware.process(patches))
+            .register(move |patches| plugin_middleware.process(patches));
+        (routes, Arc::new(chain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+    use crate::self_virtual_dom::{Diff, ElementType};
+    use crate::server::{routes, Config};
+
+    /** 要素に data-plugin 属性を付け、ストアのバージョンを返すルートを持つ拡張機能 */
+    struct Marker;
+
+    struct MarkPass;
+
+    impl Transformer for MarkPass {
+        fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+            if let ElementType::Element(_, attrs, _) = node {
+                attrs.insert("data-plugin".into(), "marker".into());
+            }
+        }
+    }
+
+    impl Plugin for Marker {
+        fn name(&self) -> &str {
+            "marker"
+        }
+
+        fn register(&self, registry: &mut PluginRegistry) {
+            let store = registry.store().clone();
+            registry
+                .route(warp::path!("marker" / "version").map(move || store.version().to_string()))
+                .transform(MarkPass)
+                .middleware(|patches: Vec<Diff>| {
+                    patches
+                        .into_iter()
+                        .filter(|patch| !patch.path().is_empty())
+                        .collect()
+                });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_host() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let config = Config {
+            plugins: PluginHost::new().with(Marker),
+            ..Config::default()
+        };
+        assert_eq!(config.plugins.names().collect::<Vec<_>>(), ["marker"]);
+        let filter = routes(config, store.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/update_input")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        // 変換で div の属性が変わるためルートの置き換えになり、ミドルウェアがそれを取り除く
+        assert_eq!(body["diff"], serde_json::json!([]));
+        assert_eq!(body["html"], "<div data-plugin=\"marker\">Hello</div>");
+
+        let response = warp::test::request()
+            .path("/marker/version")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.body(), "1");
+    }
+}
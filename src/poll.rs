@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use warp::{Filter, Rejection, Reply};
+
+use crate::middleware::MiddlewareChain;
+use crate::self_virtual_dom::virtual_dom_to_html;
+use crate::store::{Store, Update};
+
+/**
+ * ロングポーリングの既定の待機時間
+ */
+pub const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_secs(25);
+
+#[derive(Debug, Deserialize)]
+struct PollQuery {
+    since: u64,
+}
+
+/**
+ * ロングポーリングのレスポンスを表す構造体
+ * 履歴から差分を返せない場合は updates の代わりに resync に現在のHTMLを入れる
+ */
+#[derive(Debug, Serialize)]
+pub struct PollResponse {
+    pub version: u64,
+    pub updates: Vec<Update>,
+    pub resync: Option<String>,
+}
+
+/**
+ * 指定したバージョン以降の差分が発生するまで待機して返すロングポーリングのルートを返す関数
+ */
+pub fn poll_route(
+    store: Arc<Store>,
+    middleware: Arc<MiddlewareChain>,
+    timeout: Duration,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("poll")
+        .and(warp::get())
+        .and(warp::query::<PollQuery>())
+        .then(move |query: PollQuery| {
+            let store = store.clone();
+            let middleware = middleware.clone();
+            async move {
+                let response = wait_for_updates(&store, &middleware, query.since, timeout).await;
+                warp::reply::json(&response)
+            }
+        })
+}
+
+/**
+ * 指定したバージョン以降の差分をまとめて返す関数
+ * まだ差分がない場合は新しい更新が届くかタイムアウトするまで待機する
+ */
+pub async fn wait_for_updates(
+    store: &Store,
+    middleware: &MiddlewareChain,
+    since: u64,
+    timeout: Duration,
+) -> PollResponse {
+    // 履歴を読む前に購読しておき、その間に発生した更新を取りこぼさないようにする
+    let mut receiver = store.subscribe();
+
+    if matches!(store.updates_since(since), Some(updates) if updates.is_empty()) {
+        // タイムアウトした場合も現在の状態をそのまま返し、クライアントに再度ポーリングさせる
+        let _ = tokio::time::timeout(timeout, receiver.recv()).await;
+    }
+
+    match store.updates_since(since) {
+        Some(updates) => PollResponse {
+            version: updates.last().map_or(since, |update| update.version),
+            updates: updates
+                .into_iter()
+                .map(|update| Update {
+                    diff: middleware.process(update.diff),
+                    // 状態の復元にだけ使う差分はクライアントへ送らない
+                    replay: None,
+                    ..update
+                })
+                .collect(),
+            resync: None,
+        },
+        None => {
+            let snapshot = store.snapshot();
+            PollResponse {
+                version: snapshot.version,
+                updates: vec![],
+                resync: Some(
+                    middleware.process_html(virtual_dom_to_html(&snapshot.tree.element_type)),
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+
+    #[tokio::test]
+    async fn test_wait_for_updates() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let middleware = MiddlewareChain::new();
+
+        let response = wait_for_updates(&store, &middleware, 0, Duration::from_millis(10)).await;
+        assert_eq!(response.version, 0);
+        assert!(response.updates.is_empty());
+
+        let writer = store.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            writer.update(render_input("a"));
+        });
+        let response = wait_for_updates(&store, &middleware, 0, Duration::from_secs(5)).await;
+        assert_eq!(response.version, 1);
+        assert_eq!(response.updates.len(), 1);
+
+        let response = wait_for_updates(&store, &middleware, 9, Duration::from_millis(10)).await;
+        assert_eq!(response.resync.as_deref(), Some("<div >a</div>"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::self_virtual_dom::{update_dom, virtual_dom_to_html, Diff, ElementType, VNode};
+
+/**
+ * 描画済みの部分木とそのHTML
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub tree: ElementType,
+    pub html: String,
+}
+
+impl Rendered {
+    /**
+     * 以前の描画結果からの差分を返す関数
+     * キャッシュから同じ描画結果が返された場合は木を比較せずに空の差分を返す
+     */
+    pub fn diff_from(self: &Arc<Self>, old: &Arc<Self>) -> Vec<Diff> {
+        if Arc::ptr_eq(self, old) {
+            return Vec::new();
+        }
+        update_dom(
+            &VNode {
+                element_type: old.tree.clone(),
+            },
+            &VNode {
+                element_type: self.tree.clone(),
+            },
+        )
+        .diff
+    }
+}
+
+/**
+ * キャッシュの利用状況
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/**
+ * コンポーネントの描画結果を (コンポーネント名, props のハッシュ) で保持するキャッシュ
+ * コンポーネントごとに最新の props の結果だけを持つため、props が変わると古い結果は自動的に捨てられる
+ * ヘッダーやフッターのように入力の変わらない部分の描画と差分の計算を省くために使う
+ */
+#[derive(Default)]
+pub struct RenderCache {
+    entries: Mutex<HashMap<String, (u64, Arc<Rendered>)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * props が前回と同じであればキャッシュした結果を、異なれば描画し直した結果を返す関数
+     */
+    pub fn render<P: Hash + ?Sized>(
+        &self,
+        component: &str,
+        props: &P,
+        render: impl FnOnce(&P) -> ElementType,
+    ) -> Arc<Rendered> {
+        let key = props_hash(props);
+        if let Some((hash, rendered)) = self.entries.lock().unwrap().get(component) {
+            if *hash == key {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return rendered.clone();
+            }
+        }
+
+        // 描画中はロックを持たないため、同じコンポーネントが同時に描画されることはある
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let tree = render(props);
+        let rendered = Arc::new(Rendered {
+            html: virtual_dom_to_html(&tree),
+            tree,
+        });
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(component.to_string(), (key, rendered.clone()));
+        rendered
+    }
+
+    /**
+     * コンポーネントの描画結果を捨てる関数
+     * props 以外の入力（翻訳やテーマなど）が変わったときに使う
+     */
+    pub fn invalidate(&self, component: &str) {
+        self.entries.lock().unwrap().remove(component);
+    }
+
+    /**
+     * すべての描画結果を捨てる関数
+     */
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn props_hash<P: Hash + ?Sized>(props: &P) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    props.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+
+    #[test]
+    fn test_render_cache() {
+        let cache = RenderCache::new();
+        let header = |title: &str| element("header", &[], title.to_string());
+
+        let first = cache.render("header", "Home", |title| header(title));
+        let second = cache.render("header", "Home", |_| unreachable!());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(second.diff_from(&first).is_empty());
+        assert_eq!(first.html, "<header >Home</header>");
+
+        let third = cache.render("header", "About", |title| header(title));
+        assert_eq!(third.diff_from(&second).len(), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+
+        cache.invalidate("header");
+        cache.render("header", "About", |title| header(title));
+        assert_eq!(cache.stats().misses, 3);
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Instant;
+
+use tracing::span::EnteredSpan;
+
+/**
+ * 部分木ごとの計測イベントを出力する tracing のターゲット
+ * `RUST_LOG=minimal_virtual_dom_library::profile=trace` のように絞り込んで使う
+ */
+pub const PROFILE_TARGET: &str = "minimal_virtual_dom_library::profile";
+
+/**
+ * 1つの部分木の差分の計算やHTMLへの変換にかかった時間を計る値
+ * 計測中は subtree スパンに入るため、tracing-flame などでは木の形のままフレームグラフになる
+ * drop したときに経過時間をイベントとして出力する
+ */
+pub(crate) struct SubtreeTimer {
+    phase: &'static str,
+    tag: String,
+    path: Option<Vec<usize>>,
+    start: Instant,
+    _span: EnteredSpan,
+}
+
+impl SubtreeTimer {
+    /**
+     * 計測を始める関数
+     * HTMLへの変換ではパスを追わないため None を渡す
+     */
+    pub(crate) fn start(phase: &'static str, tag: &str, path: Option<&[usize]>) -> Self {
+        let span =
+            tracing::trace_span!(target: PROFILE_TARGET, "subtree", phase, tag, path = ?path);
+        Self {
+            phase,
+            tag: tag.to_string(),
+            path: path.map(<[usize]>::to_vec),
+            start: Instant::now(),
+            _span: span.entered(),
+        }
+    }
+}
+
+impl Drop for SubtreeTimer {
+    fn drop(&mut self) {
+        tracing::trace!(
+            target: PROFILE_TARGET,
+            phase = self.phase,
+            tag = %self.tag,
+            path = ?self.path,
+            elapsed_us = self.start.elapsed().as_micros() as u64,
+            "subtree done",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::{update_dom, VNode};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /** スパンの名前と計測イベントの数だけを記録する Subscriber */
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        events: Arc<AtomicU64>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            if event.metadata().target() == PROFILE_TARGET {
+                self.events.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_profile_spans() {
+        let old = VNode {
+            element_type: element(
+                "ul",
+                &[],
+                (element("li", &[], "a"), element("li", &[], "b")),
+            ),
+        };
+        let new = VNode {
+            element_type: element(
+                "ul",
+                &[],
+                (element("li", &[], "a"), element("li", &[], "c")),
+            ),
+        };
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || update_dom(&old, &new));
+
+        let spans = recorder.spans.lock().unwrap();
+        for name in ["update_dom", "diff", "sort_patches", "serialize"] {
+            assert!(spans.contains(&name), "missing span {}", name);
+        }
+        // 差分では ul と変わった li の2つ、HTMLへの変換では ul と2つの li を計測する
+        assert_eq!(recorder.events.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::iter::NodePath;
+use crate::selector::{Selector, SelectorError};
+use crate::self_virtual_dom::{virtual_dom_to_html, ElementType};
+use crate::store::{Snapshot, Store};
+
+/**
+ * 部分木を返す形式
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryFormat {
+    #[default]
+    Json,
+    Html,
+}
+
+/**
+ * 部分木の取得リクエスト
+ * selector（CSS風のセレクタ）か path（`/0/1` 形式のパス式）のどちらかを指定する
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryRequest {
+    pub selector: Option<String>,
+    pub path: Option<String>,
+    #[serde(default)]
+    pub format: QueryFormat,
+}
+
+/**
+ * 条件に一致した部分木
+ */
+#[derive(Debug, Serialize)]
+pub struct QueryMatch {
+    pub path: NodePath,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<ElementType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+/**
+ * 部分木の取得結果
+ */
+#[derive(Debug, Serialize)]
+pub struct QueryResponse {
+    pub version: u64,
+    pub matches: Vec<QueryMatch>,
+}
+
+/**
+ * `/0/1` 形式のパス式を解析する関数
+ */
+pub fn parse_path(expression: &str) -> Result<NodePath, SelectorError> {
+    expression
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            segment.parse().map_err(|_| SelectorError {
+                selector: expression.to_string(),
+                message: format!("invalid path segment {:?}", segment),
+            })
+        })
+        .collect()
+}
+
+/**
+ * スナップショットからリクエストに一致する部分木を取得する関数
+ */
+pub fn query(snapshot: &Snapshot, request: &QueryRequest) -> Result<QueryResponse, SelectorError> {
+    let root = &snapshot.tree.element_type;
+
+    let found: Vec<(NodePath, &ElementType)> = match (&request.selector, &request.path) {
+        (Some(selector), _) => Selector::parse(selector)?.select(root),
+        (None, Some(expression)) => {
+            let path = parse_path(expression)?;
+            root.get(&path)
+                .map(|node| (path, node))
+                .into_iter()
+                .collect()
+        }
+        (None, None) => {
+            return Err(SelectorError {
+                selector: String::new(),
+                message: "either selector or path is required".to_string(),
+            })
+        }
+    };
+
+    let matches = found
+        .into_iter()
+        .map(|(path, node)| match request.format {
+            QueryFormat::Json => QueryMatch {
+                path,
+                node: Some(node.clone()),
+                html: None,
+            },
+            QueryFormat::Html => QueryMatch {
+                path,
+                node: None,
+                html: Some(virtual_dom_to_html(node)),
+            },
+        })
+        .collect();
+
+    Ok(QueryResponse {
+        version: snapshot.version,
+        matches,
+    })
+}
+
+/**
+ * 現在の仮想DOMから部分木を取得する POST /query ルートを返す関数
+ */
+pub fn query_route(
+    store: Arc<Store>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("query")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(
+            move |request: QueryRequest| match query(&store.snapshot(), &request) {
+                Ok(response) => warp::reply::json(&response).into_response(),
+                Err(err) => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": err.to_string() })),
+                    StatusCode::BAD_REQUEST,
+                )
+                .into_response(),
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+
+    #[tokio::test]
+    async fn test_query_route() {
+        let store = Arc::new(Store::new(initial_tree()));
+        store.update(render_input("Hello"));
+        let filter = query_route(store);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/query")
+            .json(&serde_json::json!({ "path": "/0", "format": "html" }))
+            .reply(&filter)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["version"], 1);
+        assert_eq!(body["matches"][0]["html"], "Hello");
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/query")
+            .json(&serde_json::json!({ "selector": "div" }))
+            .reply(&filter)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["matches"][0]["path"], serde_json::json!([]));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/query")
+            .json(&serde_json::json!({ "selector": "div[" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
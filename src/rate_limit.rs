@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use warp::reject::Reject;
+use warp::{Filter, Rejection};
+
+use crate::auth::{authenticate, AuthProvider, Identity};
+
+/**
+ * トークンバケットの設定を表す構造体
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /** バケットに貯められるトークンの最大数（瞬間的に許可するリクエスト数） */
+    pub capacity: f64,
+    /** 1秒あたりに補充されるトークンの数 */
+    pub refill_per_sec: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 20.0,
+            refill_per_sec: 10.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    swept_at: Instant,
+}
+
+/**
+ * 送信者またはIPアドレスごとにトークンバケットでリクエストを制限する構造体
+ * トークンが満杯まで補充されたバケットは新しいバケットと同じため、定期的に取り除いて件数を抑える
+ */
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+        }
+    }
+
+    /**
+     * 保持しているバケットの数を返す関数
+     */
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * キーに対応するバケットからトークンを1つ消費できるかを判定する関数
+     */
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        self.sweep(&mut buckets, now);
+        let bucket = buckets.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.config.capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now
+            .saturating_duration_since(bucket.updated_at)
+            .as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * self.config.refill_per_sec).min(self.config.capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * 空のバケットが満杯になるまでの時間ごとに、満杯まで補充されたバケットを取り除く関数
+     * 補充されない設定ではバケットを取り除くと制限が解除されるため、取り除かない
+     */
+    fn sweep(&self, buckets: &mut Buckets, now: Instant) {
+        if self.config.refill_per_sec <= 0.0 {
+            return;
+        }
+        let refill_secs = self.config.capacity / self.config.refill_per_sec;
+        if now
+            .saturating_duration_since(buckets.swept_at)
+            .as_secs_f64()
+            < refill_secs
+        {
+            return;
+        }
+        buckets.swept_at = now;
+        buckets.buckets.retain(|_, bucket| {
+            let elapsed = now
+                .saturating_duration_since(bucket.updated_at)
+                .as_secs_f64();
+            bucket.tokens + elapsed * self.config.refill_per_sec < self.config.capacity
+        });
+    }
+}
+
+/**
+ * リクエストが制限を超えたことを表す Rejection
+ */
+#[derive(Debug)]
+pub struct RateLimited;
+
+impl Reject for RateLimited {}
+
+/**
+ * 送信者ごとの制限に使うキーを返す関数
+ * 匿名の送信者はすべて同じ ID になるため、接続元のIPアドレスで区別する
+ */
+pub fn client_key(identity: &Identity, remote: Option<IpAddr>) -> String {
+    if *identity == Identity::anonymous() {
+        format!(
+            "addr:{}",
+            remote.map(|addr| addr.to_string()).unwrap_or_default()
+        )
+    } else {
+        format!("identity:{}", identity.id)
+    }
+}
+
+/**
+ * 送信者を認証し、送信者ごとにリクエストを制限するフィルタ
+ * クライアントが自由に変えられる値で制限を逃れられないよう、匿名の送信者は接続元のIPアドレスで区別する
+ */
+pub fn rate_limit(
+    limiter: Arc<RateLimiter>,
+    provider: Arc<dyn AuthProvider>,
+) -> impl Filter<Extract = (Identity,), Error = Rejection> + Clone {
+    authenticate(provider).and(warp::addr::remote()).and_then(
+        move |identity: Identity, remote: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                if limiter.check(&client_key(&identity, remote.map(|addr| addr.ip()))) {
+                    Ok(identity)
+                } else {
+                    Err(warp::reject::custom(RateLimited))
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AllowAll;
+    use crate::server::recover_problem;
+    use std::time::Duration;
+    use warp::http::StatusCode;
+
+    #[test]
+    fn test_token_bucket_refill() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            capacity: 2.0,
+            refill_per_sec: 1.0,
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check_at("a", start));
+        assert!(limiter.check_at("a", start));
+        assert!(!limiter.check_at("a", start));
+        assert!(limiter.check_at("b", start));
+        assert!(limiter.check_at("a", start + Duration::from_secs(1)));
+        assert_eq!(limiter.len(), 2);
+
+        // 満杯まで補充されたバケットは取り除かれる
+        assert!(limiter.check_at("c", start + Duration::from_secs(10)));
+        assert_eq!(limiter.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_filter() {
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+            capacity: 1.0,
+            refill_per_sec: 0.0,
+        }));
+        let route = warp::path("update_input")
+            .and(rate_limit(limiter, Arc::new(AllowAll)))
+            .map(|_: Identity| "ok")
+            .recover(|err| recover_problem(err, None));
+
+        let request = |session: &str, ip: [u8; 4]| {
+            warp::test::request()
+                .path("/update_input")
+                .header("x-session-id", session)
+                .remote_addr(SocketAddr::from((ip, 8080)))
+        };
+
+        assert_eq!(
+            request("a", [10, 0, 0, 1]).reply(&route).await.status(),
+            200
+        );
+        // クライアントが送るセッションIDを変えても、同じ接続元からは制限される
+        assert_eq!(
+            request("b", [10, 0, 0, 1]).reply(&route).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            request("b", [10, 0, 0, 2]).reply(&route).await.status(),
+            200
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use serde_json::{Map, Value};
+use std::fmt;
+
+use crate::self_virtual_dom::{virtual_dom_to_html, Attributes, ElementType};
+
+/**
+ * React の要素JSONの変換に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactJsonError {
+    pub message: String,
+}
+
+impl fmt::Display for ReactJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid React element: {}", self.message)
+    }
+}
+
+impl std::error::Error for ReactJsonError {}
+
+/**
+ * HTMLの属性名と React の props 名の対応
+ */
+const RENAMED_PROPS: [(&str, &str); 2] = [("class", "className"), ("for", "htmlFor")];
+
+fn to_prop_name(attr: &str) -> &str {
+    RENAMED_PROPS
+        .iter()
+        .find(|(html, _)| *html == attr)
+        .map_or(attr, |(_, react)| react)
+}
+
+fn to_attr_name(prop: &str) -> &str {
+    RENAMED_PROPS
+        .iter()
+        .find(|(_, react)| *react == prop)
+        .map_or(prop, |(html, _)| html)
+}
+
+/**
+ * 仮想DOMを React の要素JSON（`{type, key, props: {children, …}}`）に変換する関数
+ * テキストノードは文字列になり、key 属性は要素の key に移す
+ */
+pub fn to_react_element(node: &ElementType) -> Value {
+    match node {
+        ElementType::Text(text) => Value::String(text.to_string()),
+        ElementType::I18n(..) => Value::String(virtual_dom_to_html(node)),
+        // React の要素JSONにはコメントがないため、描画されない null にする
+        ElementType::Comment(_) => Value::Null,
+        ElementType::Element(tag, attrs, children) => {
+            let mut props = Map::new();
+            let mut key = Value::Null;
+            for (name, value) in attrs {
+                if name == "key" {
+                    key = Value::String(value.to_string());
+                } else {
+                    props.insert(
+                        to_prop_name(name).to_string(),
+                        Value::String(value.to_string()),
+                    );
+                }
+            }
+            match children.as_slice() {
+                [] => {}
+                [child] => {
+                    props.insert("children".to_string(), to_react_element(child));
+                }
+                children => {
+                    props.insert(
+                        "children".to_string(),
+                        Value::Array(children.iter().map(to_react_element).collect()),
+                    );
+                }
+            }
+
+            let mut element = Map::new();
+            element.insert("type".to_string(), Value::String(tag.clone()));
+            element.insert("key".to_string(), key);
+            element.insert("props".to_string(), Value::Object(props));
+            Value::Object(element)
+        }
+    }
+}
+
+/**
+ * React の要素JSONを仮想DOMに変換する関数
+ * 数値と真偽値の props は文字列にし、null と false の props や子要素は取り除く
+ */
+pub fn from_react_element(value: &Value) -> Result<ElementType, ReactJsonError> {
+    let error = |message: String| ReactJsonError { message };
+
+    match value {
+        Value::String(text) => Ok(ElementType::Text(text.clone().into())),
+        Value::Number(number) => Ok(ElementType::Text(number.to_string().into())),
+        Value::Object(element) => {
+            let tag = match element.get("type") {
+                Some(Value::String(tag)) => tag.clone(),
+                Some(other) => {
+                    return Err(error(format!(
+                        "type must be a host element name, found {}",
+                        other
+                    )))
+                }
+                None => return Err(error("missing type".to_string())),
+            };
+
+            let mut attrs = Attributes::new();
+            match element.get("key") {
+                None | Some(Value::Null) => {}
+                Some(Value::String(key)) => {
+                    attrs.insert("key".into(), key.clone().into());
+                }
+                Some(Value::Number(key)) => {
+                    attrs.insert("key".into(), key.to_string().into());
+                }
+                Some(other) => return Err(error(format!("invalid key {}", other))),
+            }
+
+            let mut children = Vec::new();
+            if let Some(props) = element.get("props") {
+                let Value::Object(props) = props else {
+                    return Err(error(format!("props of <{}> must be an object", tag)));
+                };
+                for (name, value) in props {
+                    if name == "children" {
+                        collect_children(value, &mut children)?;
+                        continue;
+                    }
+                    let value = match value {
+                        Value::Null | Value::Bool(false) => continue,
+                        Value::Bool(true) => String::new(),
+                        Value::String(value) => value.clone(),
+                        Value::Number(value) => value.to_string(),
+                        _ => {
+                            return Err(error(format!(
+                                "prop {} of <{}> must be a scalar",
+                                name, tag
+                            )))
+                        }
+                    };
+                    attrs.insert(to_attr_name(name).to_string().into(), value.into());
+                }
+            }
+
+            Ok(ElementType::Element(tag, attrs, children))
+        }
+        other => Err(error(format!("unsupported node {}", other))),
+    }
+}
+
+fn collect_children(value: &Value, children: &mut Vec<ElementType>) -> Result<(), ReactJsonError> {
+    match value {
+        Value::Null | Value::Bool(_) => Ok(()),
+        Value::Array(values) => values
+            .iter()
+            .try_for_each(|value| collect_children(value, children)),
+        value => {
+            children.push(from_react_element(value)?);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_react_element_round_trip() {
+        let value = json!({
+            "type": "ul",
+            "key": null,
+            "props": {
+                "className": "todos",
+                "children": [
+                    { "type": "li", "key": "1", "props": { "children": "a" } },
+                    false,
+                    { "type": "li", "key": 2, "props": { "hidden": true, "children": ["b", 3] } }
+                ]
+            }
+        });
+
+        let tree = from_react_element(&value).unwrap();
+        let ElementType::Element(tag, attrs, children) = &tree else {
+            panic!("expected element");
+        };
+        assert_eq!(tag, "ul");
+        assert_eq!(
+            attrs.get("class").map(|value| value.as_ref()),
+            Some("todos")
+        );
+        assert_eq!(children.len(), 2);
+        assert_eq!(
+            children[1],
+            ElementType::Element(
+                "li".to_string(),
+                [("key".into(), "2".into()), ("hidden".into(), "".into())]
+                    .into_iter()
+                    .collect(),
+                vec![ElementType::Text("b".into()), ElementType::Text("3".into())],
+            )
+        );
+
+        assert_eq!(from_react_element(&to_react_element(&tree)).unwrap(), tree);
+        assert!(from_react_element(&json!({ "type": {} })).is_err());
+    }
+}
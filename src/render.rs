@@ -0,0 +1,69 @@
+use crate::self_virtual_dom::{ElementType, VNode};
+
+pub use minimal_virtual_dom_derive::Render;
+
+/**
+ * 値を仮想DOMの要素として描画する trait
+ * 構造体には `#[derive(Render)]` で実装を生成できる
+ */
+pub trait Render {
+    fn render(&self) -> ElementType;
+
+    /**
+     * 描画した要素を VNode として返す関数
+     */
+    fn render_vnode(&self) -> VNode {
+        VNode {
+            element_type: self.render(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+
+    #[derive(Render)]
+    #[render(tag = "span", class = "price")]
+    struct Price(u32);
+
+    #[derive(Render)]
+    #[render(tag = "li", class = "item")]
+    struct Item {
+        #[render(attr = "data-id")]
+        id: u32,
+        #[render(tag = "strong")]
+        name: String,
+        #[render(nested)]
+        price: Price,
+        #[render(skip)]
+        #[allow(dead_code)]
+        internal: bool,
+    }
+
+    #[test]
+    fn test_derive_render() {
+        let item = Item {
+            id: 7,
+            name: "Apple".to_string(),
+            price: Price(120),
+            internal: true,
+        };
+
+        let ElementType::Element(tag, attrs, children) = item.render() else {
+            panic!("expected element");
+        };
+        assert_eq!(tag, "li");
+        assert_eq!(attrs.get("class").map(|value| value.as_ref()), Some("item"));
+        assert_eq!(attrs.get("data-id").map(|value| value.as_ref()), Some("7"));
+        let html: Vec<String> = children.iter().map(virtual_dom_to_html).collect();
+        assert_eq!(
+            html,
+            vec![
+                r#"<strong class="name">Apple</strong>"#,
+                r#"<span class="price"><span class="price"><span >120</span></span></span>"#,
+            ]
+        );
+    }
+}
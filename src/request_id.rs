@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use warp::{Filter, Rejection};
+
+/**
+ * リクエストを識別するヘッダー名
+ */
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/**
+ * クライアントから受け取るリクエストIDの最大長
+ */
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/**
+ * 新しいリクエストIDを生成する関数
+ */
+pub fn generate() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    format!(
+        "{:x}-{:x}",
+        nanos,
+        NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/**
+ * クライアントが指定したリクエストIDとして受け付けられるかを判定する関数
+ */
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/**
+ * `X-Request-Id` ヘッダーのリクエストIDを取り出し、なければ生成するフィルタ
+ */
+pub fn request_id() -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(REQUEST_ID_HEADER).map(|id: Option<String>| match id {
+        Some(id) if is_valid(&id) => id,
+        _ => generate(),
+    })
+}
+
+/**
+ * f の実行中に発生したストアの更新にリクエストIDを関連付ける関数
+ */
+pub fn scope<T>(id: String, f: impl FnOnce() -> T) -> T {
+    REQUEST_ID.sync_scope(id, f)
+}
+
+/**
+ * 実行中の処理に関連付けられたリクエストIDを返す関数
+ */
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let filter = request_id();
+
+        let id = warp::test::request()
+            .header(REQUEST_ID_HEADER, "client-1")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(id, "client-1");
+
+        let id = warp::test::request()
+            .header(REQUEST_ID_HEADER, "bad id")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_ne!(id, "bad id");
+
+        assert_eq!(current(), None);
+        assert_eq!(scope(id.clone(), current), Some(id));
+    }
+}
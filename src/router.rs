@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::{Filter, Rejection, Reply};
+
+use crate::builder::{children, IntoChildren};
+use crate::error::VdomError;
+use crate::self_virtual_dom::{update_dom_with, Attributes, Diff, DiffOptions, ElementType, VNode};
+use crate::server::error_reply;
+
+/**
+ * クライアント側でページ遷移として扱うリンクに付ける属性名
+ */
+pub const LINK_ATTR: &str = "data-link";
+
+/**
+ * URL のパターンから取り出したパラメーター
+ */
+pub type Params = HashMap<String, String>;
+
+/**
+ * パラメーターからページの仮想DOMを描画する処理を表す trait
+ */
+pub trait Page: Send + Sync {
+    fn render(&self, params: &Params) -> VNode;
+}
+
+impl<F> Page for F
+where
+    F: Fn(&Params) -> VNode + Send + Sync,
+{
+    fn render(&self, params: &Params) -> VNode {
+        self(params)
+    }
+}
+
+/**
+ * URL のパターンの1要素
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+struct Route {
+    segments: Vec<Segment>,
+    page: Arc<dyn Page>,
+    options: DiffOptions,
+}
+
+/**
+ * URL のパスをページの描画処理に対応付けるルーター
+ * パターンは `/users/:id` のように書き、`:` で始まる要素はパラメーターとして取り出す
+ */
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * パターンとページを登録する関数
+     * 複数のパターンに一致する場合は先に登録したものを使う
+     */
+    pub fn route(self, pattern: &str, page: impl Page + 'static) -> Self {
+        self.route_with(pattern, page, DiffOptions::default())
+    }
+
+    /**
+     * 遷移先になったときの差分の取り方を指定してページを登録する関数
+     * 大きく変わるページで差分の上限を設定し、ルート全体の置き換えに切り替えるために使う
+     */
+    pub fn route_with(
+        mut self,
+        pattern: &str,
+        page: impl Page + 'static,
+        options: DiffOptions,
+    ) -> Self {
+        let segments = split_path(pattern)
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => Segment::Param(name.to_string()),
+                None => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        self.routes.push(Route {
+            segments,
+            page: Arc::new(page),
+            options,
+        });
+        self
+    }
+
+    /**
+     * パスに一致するページを描画する関数
+     * クエリ文字列とフラグメントは無視する
+     */
+    pub fn render(&self, path: &str) -> Option<VNode> {
+        self.render_route(path).map(|(page, _)| page)
+    }
+
+    fn render_route(&self, path: &str) -> Option<(VNode, &DiffOptions)> {
+        self.routes.iter().find_map(|route| {
+            let params = match_route(&route.segments, path)?;
+            Some((route.page.render(&params), &route.options))
+        })
+    }
+
+    /**
+     * 遷移元と遷移先のページを描画し、その差分を返す関数
+     * 遷移元のページが見つからない場合は遷移先のページ全体を追加する差分になる
+     */
+    pub fn navigate(&self, from: &str, to: &str) -> Result<Navigation, VdomError> {
+        self.handle(&NavigateRequest {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind: NavigationKind::Push,
+            scroll: None,
+        })
+    }
+
+    /**
+     * クライアントからのページ遷移のメッセージを処理する関数
+     * 差分に加えて、履歴の更新方法とスクロール位置の扱いをクライアントに指示する
+     */
+    pub fn handle(&self, request: &NavigateRequest) -> Result<Navigation, VdomError> {
+        let (new_page, options) = self.render_route(&request.to).ok_or(VdomError::NotFound)?;
+        let old_page = self.render(&request.from).unwrap_or(VNode {
+            element_type: ElementType::Text("".into()),
+        });
+        let app_response = update_dom_with(&old_page, &new_page, options);
+
+        let history = match request.kind {
+            NavigationKind::Push => Some(HistoryAction::Push {
+                url: request.to.clone(),
+            }),
+            NavigationKind::Replace => Some(HistoryAction::Replace {
+                url: request.to.clone(),
+            }),
+            // 戻る・進むではブラウザが既に URL を変えている
+            NavigationKind::Pop => None,
+        };
+        let fragment = request
+            .to
+            .split_once('#')
+            .map(|(_, fragment)| fragment)
+            .filter(|fragment| !fragment.is_empty());
+        let scroll = match (request.kind, request.scroll, fragment) {
+            (NavigationKind::Pop, Some(position), _) => ScrollHint::Restore(position),
+            (_, _, Some(fragment)) => ScrollHint::Anchor {
+                id: fragment.to_string(),
+            },
+            (NavigationKind::Replace, _, None) => ScrollHint::Keep,
+            _ => ScrollHint::Top,
+        };
+
+        Ok(Navigation {
+            path: request.to.clone(),
+            diff: app_response.diff,
+            html: app_response.html,
+            history,
+            scroll,
+        })
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn match_route(segments: &[Segment], path: &str) -> Option<Params> {
+    let parts: Vec<&str> = split_path(path).collect();
+    if parts.len() != segments.len() {
+        return None;
+    }
+    let mut params = Params::new();
+    for (segment, part) in segments.iter().zip(parts) {
+        match segment {
+            Segment::Literal(literal) if literal == part => {}
+            Segment::Literal(_) => return None,
+            Segment::Param(name) => {
+                params.insert(name.clone(), part.to_string());
+            }
+        }
+    }
+    Some(params)
+}
+
+/**
+ * ページ遷移のリンクを組み立てる関数
+ * クライアントはこの属性を持つリンクのクリックを `POST /navigate` に置き換える
+ */
+pub fn link(href: &str, values: impl IntoChildren) -> ElementType {
+    ElementType::Element(
+        "a".to_string(),
+        Attributes::from([
+            ("href".into(), href.to_string().into()),
+            (LINK_ATTR.into(), "".into()),
+        ]),
+        children(values),
+    )
+}
+
+/**
+ * ページ遷移のきっかけ
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NavigationKind {
+    /** リンクのクリックなどによる新しい履歴への遷移 */
+    #[default]
+    Push,
+    /** 現在の履歴を置き換える遷移 */
+    Replace,
+    /** 戻る・進むボタン（popstate）による遷移 */
+    Pop,
+}
+
+/**
+ * スクロール位置
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScrollPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+/**
+ * クライアントから送られるページ遷移のメッセージ
+ * 戻る・進むの場合は、履歴に保存しておいたスクロール位置を scroll に入れる
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NavigateRequest {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub kind: NavigationKind,
+    #[serde(default)]
+    pub scroll: Option<ScrollPosition>,
+}
+
+/**
+ * クライアントに行わせる履歴の更新（history.pushState / replaceState）
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryAction {
+    Push { url: String },
+    Replace { url: String },
+}
+
+/**
+ * 差分を適用した後のスクロール位置の扱い
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScrollHint {
+    /** ページの先頭へ移動する */
+    Top,
+    /** 現在の位置を保つ */
+    Keep,
+    /** 履歴に保存されていた位置へ戻す */
+    Restore(ScrollPosition),
+    /** フラグメントが指す要素へ移動する */
+    Anchor { id: String },
+}
+
+/**
+ * ページ遷移の結果。遷移元のページに適用する差分と遷移先のページのHTML
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Navigation {
+    pub path: String,
+    pub diff: Vec<Diff>,
+    pub html: String,
+    /** None の場合は履歴を変更しない */
+    pub history: Option<HistoryAction>,
+    pub scroll: ScrollHint,
+}
+
+/**
+ * ページ遷移の差分を返す `POST /navigate` ルートを返す関数
+ */
+pub fn navigate_route(
+    router: Arc<Router>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("navigate")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(
+            move |request: NavigateRequest| match router.handle(&request) {
+                Ok(navigation) => warp::reply::json(&navigation).into_response(),
+                Err(err) => error_reply(&err, None),
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+
+    fn router() -> Router {
+        Router::new()
+            .route("/", |_: &Params| VNode {
+                element_type: element(
+                    "main",
+                    &[],
+                    (element("h1", &[], "Home"), link("/users/1", "User")),
+                ),
+            })
+            .route("/users/:id", |params: &Params| VNode {
+                element_type: element(
+                    "main",
+                    &[],
+                    (
+                        element("h1", &[], format!("User {}", params["id"])),
+                        link("/", "Home"),
+                    ),
+                ),
+            })
+    }
+
+    #[test]
+    fn test_navigate() {
+        let router = router();
+        assert!(router.render("/users/1?tab=posts").is_some());
+        assert!(router.render("/users").is_none());
+
+        let navigation = router.navigate("/", "/users/7").unwrap();
+        assert!(navigation.html.starts_with("<main ><h1 >User 7</h1><a "));
+        // ページの骨組みは共通なので、変わった部分だけが差分になる
+        assert!(navigation.diff.iter().all(|diff| !diff.path().is_empty()));
+
+        assert_eq!(
+            router.navigate("/", "/missing").unwrap_err(),
+            VdomError::NotFound
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let router = router();
+        let navigation = router.navigate("/", "/users/7#posts").unwrap();
+        assert_eq!(
+            navigation.history,
+            Some(HistoryAction::Push {
+                url: "/users/7#posts".to_string()
+            })
+        );
+        assert_eq!(
+            navigation.scroll,
+            ScrollHint::Anchor {
+                id: "posts".to_string()
+            }
+        );
+
+        let back: NavigateRequest = serde_json::from_value(serde_json::json!({
+            "from": "/users/7",
+            "to": "/",
+            "kind": "pop",
+            "scroll": { "x": 0.0, "y": 320.0 },
+        }))
+        .unwrap();
+        let navigation = router.handle(&back).unwrap();
+        assert_eq!(navigation.history, None);
+        assert_eq!(
+            serde_json::to_value(&navigation.scroll).unwrap(),
+            serde_json::json!({ "type": "restore", "x": 0.0, "y": 320.0 })
+        );
+    }
+
+    #[test]
+    fn test_route_patch_limit() {
+        let router = router().route_with(
+            "/report",
+            |_: &Params| VNode {
+                element_type: element("main", &[], (element("h1", &[], "Report"), "...")),
+            },
+            DiffOptions::default().with_max_patches(1),
+        );
+        let navigation = router.navigate("/", "/report").unwrap();
+        assert_eq!(navigation.diff.len(), 1);
+        assert!(navigation.diff[0].path().is_empty());
+        // 上限を設定していないページへの遷移は通常の差分になる
+        assert!(router.navigate("/report", "/").unwrap().diff.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_navigate_route() {
+        let filter = navigate_route(Arc::new(router()));
+        let response = warp::test::request()
+            .method("POST")
+            .path("/navigate")
+            .json(&serde_json::json!({ "from": "/users/1", "to": "/" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["path"], "/");
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::parser::ParseError;
+use crate::self_virtual_dom::{is_insignificant_whitespace, VOID_ELEMENTS};
+
+/**
+ * `.rsx` のテンプレートをコンパイルするコマンドライン引数（`cargo run -- rsx <入力先> <出力先>`）
+ */
+pub const RSX_COMMAND: &str = "rsx";
+
+/**
+ * テンプレートのファイルの拡張子
+ */
+pub const RSX_EXTENSION: &str = "rsx";
+
+/**
+ * `.rsx` のテンプレートを、仮想DOMを組み立てる Rust の式に変換する関数
+ * テンプレートは1つのルート要素を持つHTMLで、テキストと属性値に `{}` で Rust の式を書ける
+ * テキストの式は IntoChildren、属性値の式は ToString を実装している必要がある
+ * ブロック要素に接する空白だけのテキストは出力せず、インライン要素の間の空白は1つの空白として出力する
+ *
+ * ```text
+ * <li class={item.class} key={item.id}>{&item.name} ({item.count})</li>
+ * ```
+ */
+pub fn compile_rsx(source: &str) -> Result<String, ParseError> {
+    let mut compiler = Compiler {
+        source,
+        position: 0,
+        code: String::new(),
+    };
+    compiler.skip_whitespace();
+    if !compiler.rest().starts_with('<') {
+        return Err(compiler.error("expected a root element"));
+    }
+    compiler.code.push_str("{\n");
+    compiler.element(1)?;
+    compiler.code.push_str("\n}\n");
+    compiler.skip_whitespace();
+    if compiler.position < source.len() {
+        return Err(compiler.error("expected a single root element"));
+    }
+    Ok(compiler.code)
+}
+
+/**
+ * src_dir の下にあるすべての `.rsx` をコンパイルし、out_dir に同じ名前の `.rs` として書き出す関数
+ * build.rs から `compile_dir("templates", env::var("OUT_DIR"))` のように呼び、
+ * 生成したファイルを `include!(concat!(env!("OUT_DIR"), "/card.rs"))` で式として読み込む
+ */
+pub fn compile_dir(src_dir: &Path, out_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    let mut dirs = vec![src_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some(RSX_EXTENSION) {
+                continue;
+            }
+            let source = fs::read_to_string(&path)?;
+            let code = compile_rsx(&source).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), err),
+                )
+            })?;
+            let relative = path.strip_prefix(src_dir).unwrap_or(&path);
+            let out = out_dir.join(relative).with_extension("rs");
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(
+                &out,
+                format!("// generated from {}\n{}", relative.display(), code),
+            )?;
+            written.push(out);
+        }
+    }
+    written.sort();
+    Ok(written)
+}
+
+struct Compiler<'a> {
+    source: &'a str,
+    position: usize,
+    code: String,
+}
+
+impl<'a> Compiler<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn parse_name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(is_name_end).unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+
+    /**
+     * `{` から対応する `}` までの式を読み取る
+     * 文字列リテラルの中の括弧は数えない
+     */
+    fn parse_expr(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.position;
+        let rest = self.rest();
+        let mut depth = 0;
+        let mut chars = rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.position += index + 1;
+                        let expr = rest[1..index].trim();
+                        if expr.is_empty() {
+                            self.position = start;
+                            return Err(self.error("empty expression"));
+                        }
+                        return Ok(expr);
+                    }
+                }
+                '"' => {
+                    while let Some((_, c)) = chars.next() {
+                        match c {
+                            '\\' => {
+                                chars.next();
+                            }
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated expression"))
+    }
+
+    fn element(&mut self, indent: usize) -> Result<(), ParseError> {
+        self.position += 1;
+        let tag = self.parse_name();
+        if tag.is_empty() {
+            return Err(self.error("expected a tag name"));
+        }
+
+        let mut attrs = Vec::new();
+        let self_closing = loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                break true;
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break false;
+            }
+            if rest.is_empty() {
+                return Err(self.error(&format!("unterminated start tag <{}>", tag)));
+            }
+            let name = self.parse_name();
+            if name.is_empty() {
+                return Err(self.error("expected an attribute name"));
+            }
+            self.skip_whitespace();
+            let value = if self.rest().starts_with('=') {
+                self.position += 1;
+                self.skip_whitespace();
+                self.attr_value()?
+            } else {
+                "\"\".into()".to_string()
+            };
+            attrs.push(format!("({:?}.into(), {})", name, value));
+        };
+
+        let pad = "    ".repeat(indent);
+        let _ = write!(
+            self.code,
+            "{pad}::minimal_virtual_dom_library::self_virtual_dom::ElementType::Element(\n\
+             {pad}    {:?}.to_string(),\n\
+             {pad}    {},\n",
+            tag,
+            if attrs.is_empty() {
+                "::minimal_virtual_dom_library::self_virtual_dom::Attributes::new()".to_string()
+            } else {
+                format!("[{}].into_iter().collect()", attrs.join(", "))
+            },
+        );
+        if self_closing || VOID_ELEMENTS.contains(&tag.to_ascii_lowercase().as_str()) {
+            let _ = write!(self.code, "{pad}    ::std::vec::Vec::new(),\n{pad})");
+        } else {
+            let _ = writeln!(
+                self.code,
+                "{pad}    {{\n{pad}        #[allow(unused_mut)]\n{pad}        let mut children = ::std::vec::Vec::new();"
+            );
+            self.children(tag, indent + 2)?;
+            let _ = write!(self.code, "{pad}        children\n{pad}    }},\n{pad})");
+        }
+        Ok(())
+    }
+
+    fn attr_value(&mut self) -> Result<String, ParseError> {
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some('{') => Ok(format!(
+                "::std::string::ToString::to_string(&({})).into()",
+                self.parse_expr()?
+            )),
+            Some(quote @ ('"' | '\'')) => {
+                let Some(end) = rest[1..].find(quote) else {
+                    return Err(self.error("unterminated attribute value"));
+                };
+                self.position += end + 2;
+                Ok(format!("{:?}.into()", &rest[1..end + 1]))
+            }
+            _ => Err(self.error("expected a quoted value or an expression")),
+        }
+    }
+
+    fn children(&mut self, tag: &str, indent: usize) -> Result<(), ParseError> {
+        let pad = "    ".repeat(indent);
+        // 直前の子のタグ名。親の先頭では親のタグ名、要素でない子の後では None
+        let mut previous = Some(tag.to_string());
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("expected closing tag for <{}>", tag)));
+            }
+            if let Some(closing) = rest.strip_prefix("</") {
+                if !closing.starts_with(tag) || !closing[tag.len()..].trim_start().starts_with('>')
+                {
+                    return Err(self.error(&format!("expected closing tag for <{}>", tag)));
+                }
+                self.position += rest.find('>').unwrap_or(rest.len()) + 1;
+                return Ok(());
+            }
+            if let Some(start) = rest.strip_prefix('<') {
+                previous =
+                    Some(start[..start.find(is_name_end).unwrap_or(start.len())].to_string());
+                self.code.push_str(&format!("{pad}children.push(\n"));
+                self.element(indent + 1)?;
+                self.code.push_str(");\n");
+                continue;
+            }
+            let child = if rest.starts_with('{') {
+                self.parse_expr()?
+            } else {
+                let end = rest.find(['<', '{']).unwrap_or(rest.len());
+                self.position += end;
+                let text = &rest[..end];
+                if text.trim().is_empty() {
+                    let next = &rest[end..];
+                    let next = match next.strip_prefix('<') {
+                        Some(closing) if closing.starts_with('/') => Some(tag),
+                        Some(start) => {
+                            Some(&start[..start.find(is_name_end).unwrap_or(start.len())])
+                        }
+                        None => None,
+                    };
+                    if is_insignificant_whitespace(previous.as_deref(), next) {
+                        continue;
+                    }
+                    "\" \""
+                } else {
+                    &format!("{:?}", text)
+                }
+            };
+            previous = None;
+            let _ = writeln!(
+                self.code,
+                "{pad}::minimal_virtual_dom_library::builder::IntoChildren::push_children({}, &mut children);",
+                child
+            );
+        }
+    }
+}
+
+/**
+ * タグ名や属性名の終わりの文字かを判定する関数
+ */
+fn is_name_end(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '>' | '/' | '=' | '{')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_rsx() {
+        let code = compile_rsx(
+            r#"
+            <ul class="items">
+                <li key={item.id} hidden>{&item.name} ({ item.count })</li>
+                <br/>
+            </ul>
+            "#,
+        )
+        .unwrap();
+        assert!(code.contains(r#""li".to_string()"#));
+        assert!(code
+            .contains(r#"("key".into(), ::std::string::ToString::to_string(&(item.id)).into())"#));
+        assert!(code.contains(r#"("hidden".into(), "".into())"#));
+        assert!(code.contains("push_children(&item.name, &mut children)"));
+        assert!(code.contains("push_children(item.count, &mut children)"));
+        assert!(code.contains(r#"push_children(" (", &mut children)"#));
+
+        // インライン要素の間の空白は残し、ブロック要素に接する空白は出力しない
+        let code = compile_rsx("<div>\n  <p><b>a</b> <i>b</i></p>\n</div>").unwrap();
+        assert_eq!(
+            code.matches(r#"push_children(" ", &mut children)"#).count(),
+            1
+        );
+
+        let err = compile_rsx("<div>{ \"}\" </div>").unwrap_err();
+        assert_eq!(err.message, "unterminated expression");
+        assert!(compile_rsx("<div><span></div>").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::self_virtual_dom::ElementType;
+use crate::visit::Transformer;
+
+/**
+ * 右から左に書く言語のコード
+ */
+const RTL_LANGUAGES: [&str; 10] = ["ar", "dv", "fa", "he", "ku", "ps", "sd", "ug", "ur", "yi"];
+
+/**
+ * 文章の書字方向
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /**
+     * ロケール（`ar-EG` など）から書字方向を判定する関数
+     */
+    pub fn for_locale(locale: &str) -> Self {
+        let language = locale.split(['-', '_']).next().unwrap_or_default();
+        if RTL_LANGUAGES
+            .iter()
+            .any(|rtl| rtl.eq_ignore_ascii_case(language))
+        {
+            Direction::Rtl
+        } else {
+            Direction::Ltr
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+}
+
+/**
+ * 左右を入れ替えた文字列を返す関数
+ */
+fn mirror_side(value: &str) -> String {
+    if let Some(prefix) = value.strip_suffix("left") {
+        format!("{}right", prefix)
+    } else if let Some(prefix) = value.strip_suffix("right") {
+        format!("{}left", prefix)
+    } else if let Some((before, after)) = value.split_once("-left-") {
+        format!("{}-right-{}", before, after)
+    } else if let Some((before, after)) = value.split_once("-right-") {
+        format!("{}-left-{}", before, after)
+    } else {
+        value.to_string()
+    }
+}
+
+/**
+ * インラインスタイルの左右に依存する宣言を反転する関数
+ * margin-left や border-right-width などのプロパティ名と、text-align・float・clear の値を入れ替える
+ */
+pub fn mirror_style(style: &str) -> String {
+    style
+        .split(';')
+        .map(str::trim)
+        .filter(|declaration| !declaration.is_empty())
+        .map(|declaration| {
+            let Some((property, value)) = declaration.split_once(':') else {
+                return declaration.to_string();
+            };
+            let (property, value) = (property.trim(), value.trim());
+            let value = match property {
+                "text-align" | "float" | "clear" => mirror_side(value),
+                _ => value.to_string(),
+            };
+            format!("{}: {}", mirror_side(property), value)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/**
+ * ロケールに合わせて根の要素に lang と dir を設定し、右から左に書く言語ではインラインスタイルを反転するパス
+ */
+pub struct DirectionPass {
+    locale: String,
+    direction: Direction,
+}
+
+impl DirectionPass {
+    pub fn new(locale: &str) -> Self {
+        Self {
+            locale: locale.to_string(),
+            direction: Direction::for_locale(locale),
+        }
+    }
+}
+
+impl Transformer for DirectionPass {
+    fn pre(&mut self, path: &[usize], node: &mut ElementType) {
+        let ElementType::Element(_, attrs, _) = node else {
+            return;
+        };
+        if path.is_empty() {
+            attrs.insert("lang".into(), self.locale.clone().into());
+            attrs.insert("dir".into(), self.direction.as_str().to_string().into());
+        }
+        if self.direction == Direction::Rtl {
+            if let Some(style) = attrs.get_mut("style") {
+                *style = mirror_style(style).into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::visit::Pipeline;
+
+    #[test]
+    fn test_direction_pass() {
+        assert_eq!(Direction::for_locale("ar-EG"), Direction::Rtl);
+        assert_eq!(Direction::for_locale("en_US"), Direction::Ltr);
+
+        let tree = element(
+            "main",
+            &[],
+            element(
+                "p",
+                &[(
+                    "style",
+                    "margin-left: 4px; border-right-width: 1px; text-align: left",
+                )],
+                "مرحبا",
+            ),
+        );
+
+        let html = Pipeline::new().with(DirectionPass::new("ar")).render(tree);
+        assert!(html.starts_with("<main "));
+        assert!(html.contains(r#"dir="rtl""#) && html.contains(r#"lang="ar""#));
+        assert!(html.contains(
+            r#"<p style="margin-right: 4px; border-left-width: 1px; text-align: right">"#
+        ));
+    }
+}
@@ -0,0 +1,442 @@
+use serde_json::Value;
+use std::fmt;
+
+use crate::binding::BindingChange;
+use crate::parser::parse_html;
+use crate::self_virtual_dom::{virtual_dom_to_html, ElementType};
+
+/**
+ * 貼り付けられたHTMLに残す要素
+ * それ以外の要素は UNSAFE_ELEMENTS を除いてタグだけを取り除き、内容は残す
+ */
+const ALLOWED_ELEMENTS: [&str; 36] = [
+    "a",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "del",
+    "div",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/**
+ * 貼り付けられたHTMLから内容ごと取り除く要素
+ */
+const UNSAFE_ELEMENTS: [&str; 16] = [
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "template", "noscript",
+    "meta", "link", "base", "svg", "math", "textarea", "title",
+];
+
+/**
+ * すべての要素に残す属性
+ */
+const ALLOWED_ATTRIBUTES: [&str; 4] = ["class", "title", "lang", "dir"];
+
+/**
+ * 要素ごとに残す属性。href と src は安全なURLの場合だけ残す
+ */
+const ALLOWED_ELEMENT_ATTRIBUTES: [(&str, &str); 10] = [
+    ("a", "href"),
+    ("img", "src"),
+    ("img", "alt"),
+    ("img", "width"),
+    ("img", "height"),
+    ("td", "colspan"),
+    ("td", "rowspan"),
+    ("th", "colspan"),
+    ("th", "rowspan"),
+    ("ol", "start"),
+];
+
+/**
+ * リンク先やリソースのURLを持つ属性
+ */
+const URL_ATTRIBUTES: [&str; 2] = ["href", "src"];
+
+/**
+ * 入力されたテキストの扱い方
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextMode {
+    /** 入力をそのまま使う */
+    #[default]
+    Raw,
+    /** HTMLのタグと Markdown の記法を取り除き、書かれた文字だけを残す */
+    PlainText,
+    /** Markdown の記法は残し、HTMLのタグと `javascript:` のリンク先を取り除く */
+    Markdown,
+}
+
+/**
+ * クライアントから送られた入力を木に書き込む前に適用する規則
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputPolicy {
+    /** 入力の最大文字数。超えた場合は入力を拒否する */
+    pub max_length: Option<usize>,
+    /** 改行とタブ以外の制御文字を取り除く */
+    pub strip_control: bool,
+    pub mode: TextMode,
+}
+
+/**
+ * 規則に合わないため拒否した入力を表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputError {
+    /** 入力を書き込もうとした状態のパス */
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for InputError {}
+
+impl InputPolicy {
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn with_strip_control(mut self) -> Self {
+        self.strip_control = true;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: TextMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /**
+     * 1つの入力に規則を適用する関数
+     * 長さは制御文字などを取り除く前の文字数で判定する
+     */
+    pub fn sanitize(&self, path: &str, input: &str) -> Result<String, InputError> {
+        let mut text = self.limit(path, input)?;
+        if self.mode != TextMode::Raw {
+            text = strip_tags(&text);
+        }
+        match self.mode {
+            TextMode::Raw => Ok(text),
+            TextMode::PlainText => Ok(strip_markdown(&text)),
+            TextMode::Markdown => Ok(strip_script_links(&text)),
+        }
+    }
+
+    /**
+     * 貼り付けられたテキストなど、入力欄を通さずに送られたテキストに規則を適用する関数
+     * モードに関係なく制御文字を取り除き、Raw でもタグを取り除く
+     */
+    pub fn sanitize_text(&self, path: &str, input: &str) -> Result<String, InputError> {
+        let text = Self {
+            strip_control: true,
+            ..*self
+        }
+        .sanitize(path, input)?;
+        Ok(match self.mode {
+            TextMode::Raw => strip_tags(&text),
+            _ => text,
+        })
+    }
+
+    /**
+     * 貼り付けられたHTMLに規則を適用する関数
+     * モードに関係なく、許可された要素と属性だけを残し、http(s) と mailto 以外のリンク先を取り除く
+     * PlainText ではタグをすべて取り除き、HTMLとして解析できない場合もタグを取り除いたテキストにする
+     */
+    pub fn sanitize_html(&self, path: &str, html: &str) -> Result<String, InputError> {
+        if self.mode == TextMode::PlainText {
+            return self.sanitize(path, html);
+        }
+        let html = self.limit(path, html)?;
+        let Ok(mut root) = parse_html(&format!("<div>{}</div>", html)) else {
+            return Ok(strip_tags(&html));
+        };
+        remove_unsafe_html(&mut root);
+        let ElementType::Element(_, _, children) = root else {
+            unreachable!("the fragment is wrapped in a div")
+        };
+        Ok(children.iter().map(virtual_dom_to_html).collect())
+    }
+
+    /**
+     * 文字数の上限を確かめ、指定されていれば制御文字を取り除く関数
+     */
+    fn limit(&self, path: &str, input: &str) -> Result<String, InputError> {
+        let length = input.chars().count();
+        if let Some(max) = self.max_length.filter(|max| length > *max) {
+            return Err(InputError {
+                path: path.to_string(),
+                message: format!("input is {} characters long, the limit is {}", length, max),
+            });
+        }
+        Ok(if self.strip_control {
+            input
+                .chars()
+                .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+                .collect()
+        } else {
+            input.to_string()
+        })
+    }
+
+    /**
+     * バインドされた状態への変更に規則を適用する関数
+     * 文字列以外の値はそのまま通す
+     */
+    pub fn sanitize_change(&self, change: BindingChange) -> Result<BindingChange, InputError> {
+        match change.value {
+            Value::String(value) => Ok(BindingChange {
+                value: Value::String(self.sanitize(&change.path, &value)?),
+                path: change.path,
+            }),
+            _ => Ok(change),
+        }
+    }
+}
+
+/**
+ * 要素の子孫から許可されていない要素と属性を取り除く関数
+ * 許可されていない要素はタグだけを取り除いて内容を親に移し、スクリプトなどを含みうる要素は内容ごと取り除く
+ */
+fn remove_unsafe_html(node: &mut ElementType) {
+    if let ElementType::Element(_, _, children) = node {
+        remove_unsafe_children(children);
+    }
+}
+
+fn remove_unsafe_children(children: &mut Vec<ElementType>) {
+    let mut kept = Vec::with_capacity(children.len());
+    for child in std::mem::take(children) {
+        match child {
+            ElementType::Element(tag, mut attrs, mut grandchildren) => {
+                let tag_name = tag.to_ascii_lowercase();
+                if UNSAFE_ELEMENTS.contains(&tag_name.as_str()) {
+                    continue;
+                }
+                remove_unsafe_children(&mut grandchildren);
+                if !ALLOWED_ELEMENTS.contains(&tag_name.as_str()) {
+                    kept.append(&mut grandchildren);
+                    continue;
+                }
+                attrs.retain(|name, value| {
+                    let name = name.to_ascii_lowercase();
+                    let allowed = ALLOWED_ATTRIBUTES.contains(&name.as_str())
+                        || ALLOWED_ELEMENT_ATTRIBUTES.contains(&(tag_name.as_str(), name.as_str()));
+                    allowed && (!URL_ATTRIBUTES.contains(&name.as_str()) || is_safe_url(value))
+                });
+                kept.push(ElementType::Element(tag, attrs, grandchildren));
+            }
+            ElementType::Comment(_) => {}
+            other => kept.push(other),
+        }
+    }
+    *children = kept;
+}
+
+/**
+ * 相対URLか http(s) / mailto のURLかを判定する関数
+ * url は文字参照を展開した値で、ブラウザと同じく空白と制御文字を無視してスキームを判定する
+ */
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    // スキームは最初の `/` `?` `#` より前にある `:` までで、それがなければ相対URLになる
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => {
+            ["http", "https", "mailto"].contains(&&url[..end])
+        }
+        _ => true,
+    }
+}
+
+/**
+ * `<` から `>` までを取り除く関数
+ * 閉じられていない `<` は以降がタグの途中とみなして取り除く
+ */
+fn strip_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/**
+ * 強調・コード・見出し・引用の記号を取り除き、リンクと画像は表示される文字だけを残す関数
+ */
+fn strip_markdown(text: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| {
+            let line = line.trim_start_matches(['#', '>']).trim_start();
+            let line = strip_links(line, |label, _| label.to_string());
+            line.chars()
+                .filter(|c| !matches!(c, '*' | '_' | '`' | '~'))
+                .collect()
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/**
+ * `javascript:` などのスクリプトを実行するリンク先を持つリンクを、表示される文字だけにする関数
+ */
+fn strip_script_links(text: &str) -> String {
+    strip_links(text, |label, target| {
+        let scheme = target.trim_start().to_ascii_lowercase();
+        if ["javascript:", "vbscript:", "data:"]
+            .iter()
+            .any(|unsafe_scheme| scheme.starts_with(unsafe_scheme))
+        {
+            label.to_string()
+        } else {
+            format!("[{}]({})", label, target)
+        }
+    })
+}
+
+/**
+ * `[label](target)` と `![label](target)` の形のリンクを置き換える関数
+ */
+fn strip_links(text: &str, replace: impl Fn(&str, &str) -> String) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let link = rest[open..].find("](").and_then(|close| {
+            let target_start = open + close + 2;
+            let target_end = target_start + rest[target_start..].find(')')?;
+            Some((open + close, target_start, target_end))
+        });
+        let Some((close, target_start, target_end)) = link else {
+            break;
+        };
+        let prefix = rest[..open].strip_suffix('!').unwrap_or(&rest[..open]);
+        result.push_str(prefix);
+        result.push_str(&replace(
+            &rest[open + 1..close],
+            &rest[target_start..target_end],
+        ));
+        rest = &rest[target_end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        let policy = InputPolicy::default()
+            .with_max_length(40)
+            .with_strip_control();
+        assert_eq!(
+            policy.sanitize("state.input", "a\u{0}b\nc").unwrap(),
+            "ab\nc"
+        );
+        assert!(policy.sanitize("state.input", &"x".repeat(41)).is_err());
+
+        let plain = policy.with_mode(TextMode::PlainText);
+        assert_eq!(
+            plain
+                .sanitize("state.input", "# **Hi** <script>x</script>[docs](/d)")
+                .unwrap(),
+            "Hi xdocs"
+        );
+
+        let markdown = policy.with_mode(TextMode::Markdown);
+        assert_eq!(
+            markdown
+                .sanitize("state.input", "**a** [x](JavaScript:void) [y](/y)")
+                .unwrap(),
+            "**a** x [y](/y)"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let policy = InputPolicy::default();
+        assert_eq!(
+            policy
+                .sanitize_html(
+                    "paste.html",
+                    r#"<p onclick="x()" class="a">Hi <a href="javascript:x()">x</a><a href="https://e.com">e</a></p><script>x()</script><!-- c -->"#
+                )
+                .unwrap(),
+            r#"<p class="a">Hi <a >x</a><a href="https://e.com">e</a></p>"#
+        );
+        assert_eq!(
+            policy.sanitize_html("paste.html", "<p>open <b>x").unwrap(),
+            "open x"
+        );
+        // 文字参照や空白で隠したスキームと、許可されていない要素の属性も取り除く
+        assert_eq!(
+            policy
+                .sanitize_html(
+                    "paste.html",
+                    r#"<a href="&#106;avascript:x()">a</a><a href="java&#9;script:x()">b</a><a href="/p?q=1&amp;r=2">c</a><font color="red">d</font><svg><animate attributeName="href" values="javascript:x()"/></svg>"#
+                )
+                .unwrap(),
+            r#"<a >a</a><a >b</a><a href="/p?q=1&amp;r=2">c</a>d"#
+        );
+        assert_eq!(
+            policy
+                .sanitize_text("paste.text", "a\u{0}<img src=x onerror=y()>b")
+                .unwrap(),
+            "ab"
+        );
+        assert_eq!(
+            policy
+                .with_mode(TextMode::PlainText)
+                .sanitize_html("paste.html", "<b>**x**</b>")
+                .unwrap(),
+            "x"
+        );
+    }
+}
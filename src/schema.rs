@@ -0,0 +1,242 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use warp::reject::Reject;
+use warp::{Filter, Rejection};
+
+use crate::self_virtual_dom::{Attributes, ElementType, VNode};
+
+/**
+ * JSONの仮想DOMが期待する形をしていないことを表すエラー
+ * path は問題のある値の位置（`children[2].attrs` など）を表す
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl Reject for SchemaError {}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn error(path: &str, message: &str) -> SchemaError {
+    SchemaError {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+impl VNode {
+    /**
+     * serde_json::Value から仮想DOMを検証しながら組み立てる関数
+     * 形式は VNode をシリアライズした JSON と同じで、誤りがあれば位置付きのエラーを返す
+     */
+    pub fn from_json_value(value: Value) -> Result<Self, SchemaError> {
+        let Value::Object(mut object) = value else {
+            return Err(error(
+                "",
+                &format!("must be an object, found {}", kind(&value)),
+            ));
+        };
+        let element_type = object
+            .remove("element_type")
+            .ok_or_else(|| error("element_type", "is required"))?;
+        Ok(VNode {
+            element_type: element_from_value(element_type, "")?,
+        })
+    }
+}
+
+fn element_from_value(value: Value, path: &str) -> Result<ElementType, SchemaError> {
+    let Value::Object(object) = value else {
+        return Err(error(
+            path,
+            &format!(
+                "must be an object with a Text, Element, I18n or Comment key, found {}",
+                kind(&value)
+            ),
+        ));
+    };
+    let mut entries = object.into_iter();
+    let (Some((variant, value)), None) = (entries.next(), entries.next()) else {
+        return Err(error(
+            path,
+            "must have exactly one Text, Element, I18n or Comment key",
+        ));
+    };
+
+    match variant.as_str() {
+        "Text" => match value {
+            Value::String(text) => Ok(ElementType::Text(text.into())),
+            other => Err(error(
+                &join(path, "Text"),
+                &format!("must be a string, found {}", kind(&other)),
+            )),
+        },
+        "Comment" => match value {
+            Value::String(text) => Ok(ElementType::Comment(text)),
+            other => Err(error(
+                &join(path, "Comment"),
+                &format!("must be a string, found {}", kind(&other)),
+            )),
+        },
+        "I18n" => {
+            let Value::Array(fields) = value else {
+                return Err(error(
+                    &join(path, "I18n"),
+                    "must be an array of [key, args]",
+                ));
+            };
+            let Ok([key, args]) = <[Value; 2]>::try_from(fields) else {
+                return Err(error(
+                    &join(path, "I18n"),
+                    "must be an array of [key, args]",
+                ));
+            };
+            let Value::String(key) = key else {
+                return Err(error(
+                    &join(path, "key"),
+                    &format!("must be a string, found {}", kind(&key)),
+                ));
+            };
+            let args = attrs_from_value(args, &join(path, "args"))?
+                .into_iter()
+                .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                .collect();
+            Ok(ElementType::I18n(key, args))
+        }
+        "Element" => {
+            let Value::Array(fields) = value else {
+                return Err(error(
+                    &join(path, "Element"),
+                    "must be an array of [tag, attrs, children]",
+                ));
+            };
+            let Ok([tag, attrs, children]) = <[Value; 3]>::try_from(fields) else {
+                return Err(error(
+                    &join(path, "Element"),
+                    "must be an array of [tag, attrs, children]",
+                ));
+            };
+
+            let Value::String(tag) = tag else {
+                return Err(error(
+                    &join(path, "tag"),
+                    &format!("must be a string, found {}", kind(&tag)),
+                ));
+            };
+            let attrs = attrs_from_value(attrs, &join(path, "attrs"))?;
+            let Value::Array(children) = children else {
+                return Err(error(
+                    &join(path, "children"),
+                    &format!("must be an array, found {}", kind(&children)),
+                ));
+            };
+            let children = children
+                .into_iter()
+                .enumerate()
+                .map(|(index, child)| {
+                    element_from_value(child, &format!("{}[{}]", join(path, "children"), index))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(ElementType::Element(tag, attrs, children))
+        }
+        other => Err(error(
+            path,
+            &format!(
+                "has unknown node type {:?}, expected Text, Element, I18n or Comment",
+                other
+            ),
+        )),
+    }
+}
+
+fn attrs_from_value(value: Value, path: &str) -> Result<Attributes, SchemaError> {
+    let Value::Object(attrs) = value else {
+        return Err(error(
+            path,
+            &format!("must be an object, found {}", kind(&value)),
+        ));
+    };
+    attrs
+        .into_iter()
+        .map(|(name, value)| match value {
+            Value::String(value) => Ok((name.into(), value.into())),
+            other => Err(error(
+                &join(path, &name),
+                &format!("must be a string, found {}", kind(&other)),
+            )),
+        })
+        .collect()
+}
+
+/**
+ * リクエストボディの JSON を検証して仮想DOMとして取り出すフィルタ
+ */
+pub fn vnode_body() -> impl Filter<Extract = (VNode,), Error = Rejection> + Clone {
+    warp::body::json::<Value>().and_then(|value: Value| async move {
+        VNode::from_json_value(value).map_err(warp::reject::custom)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_json_value() {
+        let node = VNode {
+            element_type: ElementType::Element(
+                "ul".to_string(),
+                Attributes::new(),
+                vec![ElementType::Text("a".into())],
+            ),
+        };
+        let value = serde_json::to_value(&node).unwrap();
+        assert_eq!(VNode::from_json_value(value).unwrap(), node);
+
+        let value = json!({
+            "element_type": { "Element": ["ul", {}, [
+                { "Text": "a" },
+                { "Text": "b" },
+                { "Element": ["li", [], []] }
+            ]] }
+        });
+        assert_eq!(
+            VNode::from_json_value(value).unwrap_err().to_string(),
+            "children[2].attrs must be an object, found an array"
+        );
+    }
+}
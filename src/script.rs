@@ -0,0 +1,284 @@
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::router::{Page, Params};
+use crate::self_virtual_dom::{Attributes, ElementType, VNode};
+
+/**
+ * 1回の描画でスクリプトが実行できる命令数の上限
+ * 無限ループなどでリクエストを処理するスレッドを占有しないようにする
+ */
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/**
+ * スクリプトの関数呼び出しの深さの上限
+ */
+pub const MAX_CALL_LEVELS: usize = 64;
+
+/**
+ * スクリプトで作れる文字列の長さ、配列とマップの要素数の上限
+ */
+pub const MAX_VALUE_SIZE: usize = 100_000;
+
+/**
+ * 描画に失敗したページに表示する説明
+ * エラーの詳細はログにだけ出力する
+ */
+pub const SCRIPT_ERROR_MESSAGE: &str = "このページを表示できませんでした";
+
+/**
+ * スクリプトの読み込みや実行に失敗したことを表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /** 失敗したスクリプトのファイル名または関数名 */
+    pub name: String,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/**
+ * rhai のスクリプトで書かれた描画関数をまとめた構造体
+ * スクリプトからは `element(tag, attrs, children)` と `text(value)` で仮想DOMを組み立てる
+ * 子要素の配列に文字列を入れた場合はテキストノードになる
+ *
+ * ```text
+ * fn user(params) {
+ *     element("h1", #{ class: "title" }, ["Hello " + params.id])
+ * }
+ * ```
+ */
+pub struct Scripts {
+    engine: Engine,
+    ast: RwLock<AST>,
+    /** ファイルから読み込んだ場合のパス。reload で読み直す */
+    path: Option<PathBuf>,
+}
+
+impl Scripts {
+    /**
+     * スクリプトのソースをコンパイルする関数
+     */
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let engine = vdom_engine();
+        let ast = engine.compile(source).map_err(|err| ScriptError {
+            name: "<source>".to_string(),
+            message: err.to_string(),
+        })?;
+        Ok(Self {
+            engine,
+            ast: RwLock::new(ast),
+            path: None,
+        })
+    }
+
+    /**
+     * スクリプトのファイルを読み込んでコンパイルする関数
+     */
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let path = path.as_ref().to_path_buf();
+        let engine = vdom_engine();
+        let ast = compile_file(&engine, &path)?;
+        Ok(Self {
+            engine,
+            ast: RwLock::new(ast),
+            path: Some(path),
+        })
+    }
+
+    /**
+     * ファイルを読み直し、以降の描画で新しい関数を使う関数
+     * コンパイルに失敗した場合は以前の関数を使い続ける
+     */
+    pub fn reload(&self) -> Result<(), ScriptError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let ast = compile_file(&self.engine, path)?;
+        *self.ast.write().unwrap() = ast;
+        Ok(())
+    }
+
+    /**
+     * スクリプトの関数 function にパラメーターを渡して仮想DOMを描画する関数
+     * コンポーネントとして使う場合は、props をパラメーターとして渡す
+     */
+    pub fn render(&self, function: &str, params: &Params) -> Result<VNode, ScriptError> {
+        let params: Map = params
+            .iter()
+            .map(|(name, value)| (name.as_str().into(), Dynamic::from(value.clone())))
+            .collect();
+        let ast = self.ast.read().unwrap();
+        let element_type = self
+            .engine
+            .call_fn::<ElementType>(&mut Scope::new(), &ast, function, (params,))
+            .map_err(|err| ScriptError {
+                name: function.to_string(),
+                message: err.to_string(),
+            })?;
+        Ok(VNode { element_type })
+    }
+
+    /**
+     * スクリプトの関数をルーターに登録できるページにする関数
+     */
+    pub fn page(self: &Arc<Self>, function: &str) -> ScriptPage {
+        ScriptPage {
+            scripts: self.clone(),
+            function: function.to_string(),
+        }
+    }
+}
+
+/**
+ * スクリプトの1つの関数で描画するページ
+ * 実行に失敗した場合はエラーをログに出力し、固定の説明を表示する要素を描画する
+ */
+pub struct ScriptPage {
+    scripts: Arc<Scripts>,
+    function: String,
+}
+
+impl Page for ScriptPage {
+    fn render(&self, params: &Params) -> VNode {
+        self.scripts
+            .render(&self.function, params)
+            .unwrap_or_else(|err| {
+                tracing::warn!(error = %err, "script render failed");
+                VNode {
+                    element_type: ElementType::Element(
+                        "pre".to_string(),
+                        [("class".into(), "script-error".into())].into(),
+                        vec![ElementType::Text(SCRIPT_ERROR_MESSAGE.into())],
+                    ),
+                }
+            })
+    }
+}
+
+fn compile_file(engine: &Engine, path: &Path) -> Result<AST, ScriptError> {
+    engine
+        .compile_file(path.to_path_buf())
+        .map_err(|err| ScriptError {
+            name: path.display().to_string(),
+            message: err.to_string(),
+        })
+}
+
+/**
+ * 仮想DOMを組み立てる関数を登録したエンジンを返す関数
+ * 命令数・呼び出しの深さ・値の大きさを制限し、上限を超えたスクリプトはエラーにする
+ */
+fn vdom_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_CALL_LEVELS, MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_VALUE_SIZE)
+        .set_max_array_size(MAX_VALUE_SIZE)
+        .set_max_map_size(MAX_VALUE_SIZE)
+        .register_type_with_name::<ElementType>("Node")
+        .register_fn("text", |value: &str| {
+            ElementType::Text(value.to_string().into())
+        })
+        .register_fn("element", |tag: &str, attrs: Map| {
+            script_element(tag, attrs, Array::new())
+        })
+        .register_fn("element", script_element);
+    engine
+}
+
+fn script_element(
+    tag: &str,
+    attrs: Map,
+    children: Array,
+) -> Result<ElementType, Box<EvalAltResult>> {
+    let attrs: Attributes = attrs
+        .into_iter()
+        .map(|(name, value)| (name.to_string().into(), value.to_string().into()))
+        .collect();
+    let children = children
+        .into_iter()
+        .map(|child| {
+            if child.is::<ElementType>() {
+                Ok(child.cast::<ElementType>())
+            } else if child.is_string() {
+                Ok(ElementType::Text(child.to_string().into()))
+            } else {
+                Err(format!("<{}> cannot have a {} child", tag, child.type_name()).into())
+            }
+        })
+        .collect::<Result<Vec<_>, Box<EvalAltResult>>>()?;
+    Ok(ElementType::Element(tag.to_string(), attrs, children))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+
+    #[test]
+    fn test_script_page() {
+        let scripts = Arc::new(
+            Scripts::compile(
+                r#"
+                fn badge(params) {
+                    element("span", #{ class: "badge" }, [params.id])
+                }
+                fn user(params) {
+                    element("div", #{}, [text("User "), badge(params)])
+                }
+                fn broken(params) {
+                    element("div", #{}, [1])
+                }
+                "#,
+            )
+            .unwrap(),
+        );
+        let params = Params::from([("id".to_string(), "42".to_string())]);
+
+        let html = virtual_dom_to_html(&scripts.page("user").render(&params).element_type);
+        assert_eq!(html, "<div >User <span class=\"badge\">42</span></div>");
+
+        let err = scripts.render("broken", &params).unwrap_err();
+        assert!(err.message.contains("cannot have a i64 child"), "{}", err);
+        let html = virtual_dom_to_html(&scripts.page("broken").render(&params).element_type);
+        assert!(!html.contains("i64"), "{}", html);
+        assert!(html.contains(SCRIPT_ERROR_MESSAGE));
+        assert!(Scripts::compile("fn user(params) {").is_err());
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let scripts = Scripts::compile(
+            r#"
+            fn spin(params) {
+                loop {}
+            }
+            fn deep(params) {
+                deep(params)
+            }
+            fn huge(params) {
+                let value = "x";
+                loop { value += value; }
+            }
+            "#,
+        )
+        .unwrap();
+        let params = Params::new();
+
+        for function in ["spin", "deep", "huge"] {
+            assert!(scripts.render(function, &params).is_err(), "{}", function);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::iter::NodePath;
+use crate::self_virtual_dom::ElementType;
+
+/**
+ * セレクタの解析に失敗したことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorError {
+    pub selector: String,
+    pub message: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid selector {:?}: {}", self.selector, self.message)
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+/**
+ * 1つの要素に対する条件（`div.item#main[data-id=1]` など）
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn matches(&self, node: &ElementType) -> bool {
+        let ElementType::Element(tag, attrs, _) = node else {
+            return false;
+        };
+        if self.tag.as_ref().is_some_and(|expected| expected != tag) {
+            return false;
+        }
+        if self.id.as_ref().is_some_and(|expected| {
+            attrs.get("id").map(|id| id.as_ref()) != Some(expected.as_str())
+        }) {
+            return false;
+        }
+        let classes: Vec<&str> = attrs
+            .get("class")
+            .map(|class| class.split_whitespace().collect())
+            .unwrap_or_default();
+        if !self
+            .classes
+            .iter()
+            .all(|expected| classes.contains(&expected.as_str()))
+        {
+            return false;
+        }
+        self.attrs.iter().all(|(key, expected)| match expected {
+            Some(expected) => {
+                attrs.get(key.as_str()).map(|value| value.as_ref()) == Some(expected.as_str())
+            }
+            None => attrs.contains_key(key.as_str()),
+        })
+    }
+}
+
+/**
+ * 仮想DOMの要素を選択するCSS風のセレクタ
+ * タグ名、`#id`、`.class`、`[attr]`、`[attr=value]` と子孫結合子（空白）に対応する
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    compounds: Vec<Compound>,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, SelectorError> {
+        let error = |message: &str| SelectorError {
+            selector: selector.to_string(),
+            message: message.to_string(),
+        };
+
+        let compounds = selector
+            .split_whitespace()
+            .map(|part| parse_compound(part).map_err(|message| error(&message)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if compounds.is_empty() {
+            return Err(error("empty selector"));
+        }
+        Ok(Self { compounds })
+    }
+
+    /**
+     * root からパスで指定したノードがセレクタに一致するかを判定する関数
+     */
+    pub fn matches(&self, root: &ElementType, path: &[usize]) -> bool {
+        let Some((last, ancestors)) = self.compounds.split_last() else {
+            return false;
+        };
+        if !root.get(path).is_some_and(|node| last.matches(node)) {
+            return false;
+        }
+
+        // 祖先を近い順にたどり、残りの条件を後ろから順に満たすかを確認する
+        let mut remaining = ancestors.iter().rev().peekable();
+        for depth in (0..path.len()).rev() {
+            let Some(compound) = remaining.peek() else {
+                break;
+            };
+            if root
+                .get(&path[..depth])
+                .is_some_and(|node| compound.matches(node))
+            {
+                remaining.next();
+            }
+        }
+        remaining.peek().is_none()
+    }
+
+    /**
+     * root 配下でセレクタに一致するノードを文書順に取得する関数
+     */
+    pub fn select<'a>(&self, root: &'a ElementType) -> Vec<(NodePath, &'a ElementType)> {
+        root.iter_with_paths()
+            .filter(|(path, _)| self.matches(root, path))
+            .collect()
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        Self::parse(selector)
+    }
+}
+
+fn parse_compound(part: &str) -> Result<Compound, String> {
+    let mut compound = Compound::default();
+    let mut rest = part;
+
+    let tag_end = rest.find(['#', '.', '[']).unwrap_or(rest.len());
+    let (tag, tail) = rest.split_at(tag_end);
+    if !tag.is_empty() && tag != "*" {
+        compound.tag = Some(tag.to_string());
+    }
+    rest = tail;
+
+    while let Some(marker) = rest.chars().next() {
+        rest = &rest[marker.len_utf8()..];
+        match marker {
+            '#' | '.' => {
+                let end = rest.find(['#', '.', '[']).unwrap_or(rest.len());
+                let (name, tail) = rest.split_at(end);
+                if name.is_empty() {
+                    return Err(format!("missing name after {:?}", marker));
+                }
+                if marker == '#' {
+                    compound.id = Some(name.to_string());
+                } else {
+                    compound.classes.push(name.to_string());
+                }
+                rest = tail;
+            }
+            '[' => {
+                let end = rest.find(']').ok_or("unclosed attribute selector")?;
+                let (body, tail) = rest.split_at(end);
+                let attr = match body.split_once('=') {
+                    Some((key, value)) => (
+                        key.to_string(),
+                        Some(value.trim_matches(['"', '\'']).to_string()),
+                    ),
+                    None => (body.to_string(), None),
+                };
+                if attr.0.is_empty() {
+                    return Err("missing attribute name".to_string());
+                }
+                compound.attrs.push(attr);
+                rest = &tail[1..];
+            }
+            _ => return Err(format!("unexpected character {:?}", marker)),
+        }
+    }
+
+    Ok(compound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::Attributes;
+
+    fn element(tag: &str, attrs: &[(&str, &str)], children: Vec<ElementType>) -> ElementType {
+        ElementType::Element(
+            tag.to_string(),
+            attrs
+                .iter()
+                .map(|(key, value)| (key.to_string().into(), value.to_string().into()))
+                .collect::<Attributes>(),
+            children,
+        )
+    }
+
+    #[test]
+    fn test_select() {
+        let tree = element(
+            "div",
+            &[("id", "app")],
+            vec![
+                element(
+                    "ul",
+                    &[],
+                    vec![element("li", &[("class", "item active")], vec![])],
+                ),
+                element("li", &[("class", "item"), ("data-id", "2")], vec![]),
+            ],
+        );
+
+        let paths = |selector: &str| -> Vec<NodePath> {
+            Selector::parse(selector)
+                .unwrap()
+                .select(&tree)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect()
+        };
+
+        assert_eq!(paths("li.item"), vec![vec![0, 0], vec![1]]);
+        assert_eq!(paths("#app ul .active"), vec![vec![0, 0]]);
+        assert_eq!(paths("[data-id=2]"), vec![vec![1]]);
+        assert_eq!(paths("ul li[data-id]"), Vec::<NodePath>::new());
+        assert!(Selector::parse("div[").is_err());
+        assert!(Selector::parse("  ").is_err());
+        // 複数バイトの文字もパニックせずにエラーとして扱う
+        assert!(Selector::parse("[x]é").is_err());
+        assert!(Selector::parse("li.é").is_ok());
+    }
+}
@@ -1,14 +1,36 @@
 use serde::{Deserialize, Serialize};
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+
+use crate::explain::{attribute_changes, Explanation, Reason};
+use crate::iter::NodePath;
+use crate::vec_map::VecMap;
+
+/**
+ * テキストノードと属性の文字列
+ * マクロやビルダーに書かれた文字列リテラルは確保せずにそのまま借用する
+ */
+pub type Text = Cow<'static, str>;
+
+/**
+ * 要素の属性の一覧
+ * 属性は数個のことが多いため、名前の順に並べた Vec で持つ
+ */
+pub type Attributes = VecMap<Text, Text>;
 
 /**
  * 仮想DOMの要素を表す列挙型
  */
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum ElementType {
-    Text(String),
-    Element(String, HashMap<String, String>, Vec<ElementType>),
+    Text(Text),
+    Element(String, Attributes, Vec<ElementType>),
+    /** メッセージカタログのキーと引数。描画時に翻訳されたテキストに置き換える */
+    I18n(String, HashMap<String, String>),
+    /** コメントノード。非表示の要素の位置を保つプレースホルダーに使う */
+    Comment(String),
 }
 
 /**
@@ -21,122 +43,889 @@ pub struct VNode {
 
 /**
  * 仮想DOMの更新の差分を表す列挙型
+ * 各差分は適用先のノードのルートからのパスを持つ
  */
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Diff {
-    AddNode(VNode),
-    RemoveNode(VNode),
+    AddNode(NodePath, VNode),
+    RemoveNode(NodePath, VNode),
+    /**
+     * 古い木のパス (from) の要素を取り外し、新しい木のパス (to) に挿入する差分
+     * 要素を作り直さないため、入力欄の値などの状態が保たれる
+     * 動画や iframe の状態は取り外すと失われるため、クライアントが Element.moveBefore で移動できる場合だけ保たれる
+     * ノードは移動する要素の古い木での内容で、属性の変更は後に続く SetAttribute で適用する
+     */
+    MoveNode(NodePath, NodePath, VNode),
+    /** 新しい木のパスにある要素の属性を設定する差分。値が None の場合は属性を取り除く */
+    SetAttribute(NodePath, String, Option<String>),
 }
 
-impl PartialEq for Diff {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Diff::AddNode(node1), Diff::AddNode(node2)) => node1 == node2,
-            (Diff::RemoveNode(node1), Diff::RemoveNode(node2)) => node1 == node2,
-            _ => false,
+impl Diff {
+    /**
+     * 差分の適用先のパスを取得する関数
+     * MoveNode では取り外す位置（古い木のパス）を返す
+     */
+    pub fn path(&self) -> &NodePath {
+        match self {
+            Diff::AddNode(path, _)
+            | Diff::RemoveNode(path, _)
+            | Diff::MoveNode(path, _, _)
+            | Diff::SetAttribute(path, _, _) => path,
         }
     }
+
+    /**
+     * 差分が追加・削除・移動するノードを取得する関数
+     */
+    pub fn node(&self) -> Option<&VNode> {
+        match self {
+            Diff::AddNode(_, node) | Diff::RemoveNode(_, node) | Diff::MoveNode(_, _, node) => {
+                Some(node)
+            }
+            Diff::SetAttribute(..) => None,
+        }
+    }
+
+    /**
+     * 削除の段階で適用する差分かを判定する関数
+     * RemoveNode と MoveNode の取り外しは古い木のパス、それ以外は新しい木のパスで解釈する
+     */
+    pub fn is_removal(&self) -> bool {
+        matches!(self, Diff::RemoveNode(..) | Diff::MoveNode(..))
+    }
+}
+
+/**
+ * 差分のうち root_path 配下に関係するものだけを取り出し、パスを root_path からの相対パスに変換する関数
+ * root_path を含む祖先の置き換えは、置き換え前後の root_path の部分木に対する差分に変換する
+ * root_path の外との間の移動は、root_path 側での削除または追加に変換する
+ */
+pub fn filter_diff(patches: &[Diff], root_path: &[usize]) -> Vec<Diff> {
+    let subtree = |path: &[usize], node: &VNode| -> Option<(NodePath, VNode)> {
+        let (relative, element_type) = if let Some(relative) = path.strip_prefix(root_path) {
+            (relative.to_vec(), node.element_type.clone())
+        } else if let Some(rest) = root_path.strip_prefix(path) {
+            (Vec::new(), node.element_type.get(rest)?.clone())
+        } else {
+            return None;
+        };
+        Some((relative, VNode { element_type }))
+    };
+    let mut converted = false;
+    let mut filtered: Vec<Diff> = patches
+        .iter()
+        .filter_map(|patch| match patch {
+            Diff::AddNode(path, node) => {
+                subtree(path, node).map(|(path, node)| Diff::AddNode(path, node))
+            }
+            Diff::RemoveNode(path, node) => {
+                subtree(path, node).map(|(path, node)| Diff::RemoveNode(path, node))
+            }
+            Diff::MoveNode(from, to, node) => {
+                match (from.strip_prefix(root_path), to.strip_prefix(root_path)) {
+                    (Some(from), Some(to)) => {
+                        Some(Diff::MoveNode(from.to_vec(), to.to_vec(), node.clone()))
+                    }
+                    (Some(from), None) => Some(Diff::RemoveNode(from.to_vec(), node.clone())),
+                    (None, Some(to)) => {
+                        converted = true;
+                        Some(Diff::AddNode(to.to_vec(), node.clone()))
+                    }
+                    (None, None) => None,
+                }
+            }
+            Diff::SetAttribute(path, name, value) => path
+                .strip_prefix(root_path)
+                .map(|path| Diff::SetAttribute(path.to_vec(), name.clone(), value.clone())),
+        })
+        .collect();
+    // 移動を変換した追加は削除の段階の位置にあるため、追加の段階の位置に並べ直す
+    if converted {
+        sort_patches(&mut filtered);
+    }
+    filtered
 }
 
 /**
  * 仮想DOMの更新の結果を表す構造体
  */
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AppResponse {
-    diff: Vec<Diff>,
-    html: String,
+    pub diff: Vec<Diff>,
+    pub html: String,
+    /** 更新を発生させたリクエストのID */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 /**
- * 仮想DOMの更新の差分を取得する関数
+ * 空のテキストノードの差分の扱い
  */
-pub fn update_dom(old: &VNode, new: &VNode) -> AppResponse {
-    let mut diff = Vec::new();
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyTextPolicy {
+    /** 描画されないため差分に含めない */
+    #[default]
+    Skip,
+    /** 他のノードと同じように追加・削除を差分に含める */
+    Track,
+}
+
+/**
+ * 2つのノードが同じものかを判定する関数の型
+ */
+pub type SameNode = fn(&ElementType, &ElementType) -> bool;
+
+/**
+ * 部分木の差分と置き換えのどちらが安いかを見積もるためのコスト
+ * 差分1つのコストは per_patch に、差分に含まれるノードの数と per_node の積を加えたものになる
+ * 置き換えは古い部分木の削除と新しい部分木の追加の2つの差分として見積もる
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    /** 差分1つを送って適用する固定のコスト */
+    pub per_patch: usize,
+    /** 差分に含まれるノード1つあたりのコスト */
+    pub per_node: usize,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            per_patch: 4,
+            per_node: 1,
+        }
+    }
+}
+
+impl CostModel {
+    /**
+     * ノードを削除または追加する差分1つのコストを返す関数
+     */
+    pub fn patch_cost(&self, node: &ElementType) -> usize {
+        self.per_patch + self.per_node * node_count(node)
+    }
+}
+
+fn node_count(node: &ElementType) -> usize {
+    match node {
+        ElementType::Element(_, _, children) => 1 + children.iter().map(node_count).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+/**
+ * key を持つ要素の移動を対応付ける範囲
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MoveScope {
+    /** 移動を対応付けず、削除と追加を出力する */
+    #[default]
+    Off,
+    /** key で対応付けた子要素の一覧から取り除かれた要素と、別の一覧に加えられた要素だけを対応付ける */
+    Keyed,
+    /**
+     * 置き換えられた部分木の中も含め、木全体で key が一致する要素を対応付ける
+     * 離れたコンテナの間の移動や、key の順序の入れ替えで親要素ごと置き換えた場合も要素を作り直さない
+     */
+    Global,
+}
+
+/**
+ * 差分の取り方の設定
+ */
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    pub empty_text: EmptyTextPolicy,
+    /**
+     * ノードの同一性の判定。指定すると、同じと判定された要素だけをその場で更新し、
+     * key を持たない子要素もこの判定で古い子要素と対応付ける
+     */
+    pub same_node: Option<SameNode>,
+    /**
+     * 差分の上限。差分の数かJSONにしたときのバイト数がこれを超える場合は、
+     * 細かな差分を適用するよりも速いため、ルート全体を置き換える1つの差分にまとめる
+     */
+    pub max_patches: Option<usize>,
+    pub max_patch_bytes: Option<usize>,
+    /**
+     * 部分木の置き換えを選ぶためのコスト。指定すると、子孫の差分のコストが
+     * 部分木ごと置き換えるコストを超える要素は、削除と追加の2つの差分に置き換える
+     */
+    pub cost_model: Option<CostModel>,
+    /**
+     * key を持つ要素が移動した場合に、削除と追加の代わりに MoveNode と属性の差分を出力する範囲
+     * 移動の前後でタグ名と子要素が同じ要素だけを移動として扱い、子要素が変わった要素は削除と追加のままにする
+     * 要素を作り直さないため、入力欄の値などの状態が移動先でも保たれる
+     */
+    pub moves: MoveScope,
+}
 
-    let removed_nodes = find_removed_nodes(old, new);
+impl DiffOptions {
+    pub fn with_moves(self) -> Self {
+        self.with_move_scope(MoveScope::Keyed)
+    }
+
+    pub fn with_move_scope(mut self, scope: MoveScope) -> Self {
+        self.moves = scope;
+        self
+    }
+
+    pub fn with_same_node(mut self, same_node: SameNode) -> Self {
+        self.same_node = Some(same_node);
+        self
+    }
+
+    pub fn with_empty_text(mut self, policy: EmptyTextPolicy) -> Self {
+        self.empty_text = policy;
+        self
+    }
+
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = Some(cost_model);
+        self
+    }
+
+    pub fn with_max_patches(mut self, max_patches: usize) -> Self {
+        self.max_patches = Some(max_patches);
+        self
+    }
+
+    pub fn with_max_patch_bytes(mut self, max_patch_bytes: usize) -> Self {
+        self.max_patch_bytes = Some(max_patch_bytes);
+        self
+    }
 
-    for removed_node in removed_nodes {
-        diff.push(Diff::RemoveNode(removed_node.clone()));
+    /**
+     * 差分が上限を超えているかを判定する関数
+     * バイト数は上限が設定されている場合だけ数える
+     */
+    pub fn exceeds_limit(&self, diff: &[Diff]) -> bool {
+        self.max_patches.is_some_and(|max| diff.len() > max)
+            || self
+                .max_patch_bytes
+                .is_some_and(|max| serde_json::to_vec(diff).is_ok_and(|json| json.len() > max))
     }
+}
 
-    let added_nodes = find_added_nodes(old, new);
+/**
+ * 仮想DOMの更新の差分を取得する関数
+ */
+pub fn update_dom(old: &VNode, new: &VNode) -> AppResponse {
+    update_dom_with(old, new, &DiffOptions::default())
+}
 
-    for added_node in added_nodes {
-        diff.push(Diff::AddNode(added_node.clone()));
+/**
+ * 設定に従って仮想DOMの更新の差分を取得する関数
+ */
+#[tracing::instrument(name = "update_dom", level = "debug", skip_all)]
+pub fn update_dom_with(old: &VNode, new: &VNode, options: &DiffOptions) -> AppResponse {
+    let diff: Vec<Diff> = explained_diff(&old.element_type, &new.element_type, options)
+        .into_iter()
+        .map(|explanation| explanation.diff)
+        .collect();
+
+    #[cfg(debug_assertions)]
+    if options.empty_text == EmptyTextPolicy::Track {
+        if let Err(error) = crate::patch::verify_diff(&old.element_type, &new.element_type, &diff) {
+            panic!("diff does not reproduce the new tree: {}", error);
+        }
     }
 
+    let diff = if options.exceeds_limit(&diff) {
+        tracing::debug!(
+            patches = diff.len(),
+            "diff exceeds the limit; replacing the root"
+        );
+        replace_root(new)
+    } else {
+        diff
+    };
+
     let html = virtual_dom_to_html(&new.element_type);
 
     for change in &diff {
         match change {
-            Diff::AddNode(node) => println!("Added Node: {:?}", node),
-            Diff::RemoveNode(node) => println!("Removed Node: {:?}", node),
+            Diff::AddNode(path, node) => println!("Added Node at {:?}: {:?}", path, node),
+            Diff::RemoveNode(path, node) => println!("Removed Node at {:?}: {:?}", path, node),
+            Diff::MoveNode(from, to, _) => println!("Moved Node from {:?} to {:?}", from, to),
+            Diff::SetAttribute(path, name, value) => {
+                println!("Set Attribute {} at {:?}: {:?}", name, path, value)
+            }
         }
     }
 
-    AppResponse { diff, html }
+    AppResponse {
+        diff,
+        html,
+        request_id: None,
+    }
 }
 
 /**
-* 仮想DOMに追加されたノードを取得する関数
-*/
-fn find_added_nodes(old: &VNode, new: &VNode) -> Vec<VNode> {
-    let mut added_nodes = Vec::new();
-    find_added_nodes_recursive(&old.element_type, &new.element_type, &mut added_nodes);
-    added_nodes
+ * ルート全体を新しい木に置き換える差分を返す関数
+ * パスが空の AddNode はルートの置き換えとして適用される
+ */
+pub fn replace_root(new: &VNode) -> Vec<Diff> {
+    vec![Diff::AddNode(vec![], new.clone())]
 }
 
 /**
- * 仮想DOMに追加されたノードを再帰的に取得する関数
-*/
-fn find_added_nodes_recursive(old: &ElementType, new: &ElementType, added_nodes: &mut Vec<VNode>) {
-    if old != new {
-        if !new.is_empty_text_node() {
-            added_nodes.push(VNode {
-                element_type: new.clone(),
+ * 差分を適用順に並べ替える関数
+ *
+ * 1つの AppResponse に含まれる差分は、この順に先頭から適用する
+ * - すべての削除をすべての追加より先に適用する
+ * - 削除は古い木のパスで、深いノードから、同じ深さでは後ろの兄弟から適用する
+ * - 追加は新しい木のパスで、親から、同じ親の中では前の兄弟から適用する
+ * - MoveNode は削除の段階で from から取り外し、追加の段階で to の順番が来たときに挿入する
+ * - SetAttribute は追加の段階で、同じパスへの挿入の後に適用する
+ *
+ * この順序であれば、先に適用した差分によって後の差分のパスがずれることはない
+ */
+pub fn sort_patches(patches: &mut [Diff]) {
+    patches.sort_by(patch_order);
+}
+
+fn patch_order(a: &Diff, b: &Diff) -> std::cmp::Ordering {
+    let is_attribute = |patch: &Diff| matches!(patch, Diff::SetAttribute(..));
+    match (a.is_removal(), b.is_removal()) {
+        (true, true) => b.path().cmp(a.path()),
+        (false, false) => a
+            .path()
+            .cmp(b.path())
+            .then_with(|| is_attribute(a).cmp(&is_attribute(b))),
+        (true, false) => std::cmp::Ordering::Less,
+        (false, true) => std::cmp::Ordering::Greater,
+    }
+}
+
+/**
+ * 差分を取り、それぞれの差分が出力された理由とともに適用順で返す関数
+ * 木をたどって削除と追加を集める diff スパンと、適用順に並べる sort_patches スパンを記録する
+ */
+#[tracing::instrument(
+    name = "diff",
+    level = "debug",
+    skip_all,
+    fields(removed = tracing::field::Empty, added = tracing::field::Empty)
+)]
+pub(crate) fn explained_diff(
+    old: &ElementType,
+    new: &ElementType,
+    options: &DiffOptions,
+) -> Vec<Explanation> {
+    let mut changes = Changes {
+        removed: Vec::new(),
+        added: Vec::new(),
+        options,
+    };
+    diff_nodes(old, new, &mut Vec::new(), &mut Vec::new(), &mut changes);
+    let span = tracing::Span::current();
+    span.record("removed", changes.removed.len());
+    span.record("added", changes.added.len());
+
+    let mut explanations = if options.moves == MoveScope::Off {
+        Vec::new()
+    } else {
+        pair_moves(&mut changes.removed, &mut changes.added, options.moves)
+    };
+    let _sort = tracing::debug_span!("sort_patches").entered();
+    explanations.extend(
+        changes
+            .removed
+            .into_iter()
+            .map(|(path, node, reason)| Explanation {
+                diff: Diff::RemoveNode(path, node),
+                reason,
+            })
+            .chain(
+                changes
+                    .added
+                    .into_iter()
+                    .map(|(path, node, reason)| Explanation {
+                        diff: Diff::AddNode(path, node),
+                        reason,
+                    }),
+            ),
+    );
+    explanations.sort_by(|a, b| patch_order(&a.diff, &b.diff));
+    explanations
+}
+
+/**
+ * 取り除かれた要素と加えられた要素のうち key が一致するものを、MoveNode と属性の差分に置き換える関数
+ * 移動先で作り直さずに済むよう、タグ名と子要素が同じ要素だけを組にする
+ * 子要素が1つでも変わった要素は組にせず、削除と追加のまま残す。属性の違いだけを SetAttribute で埋める
+ * 組にした要素は削除・追加の一覧から取り除き、部分木の中にあった場合はその部分木から取り除く
+ */
+fn pair_moves(
+    removed: &mut Vec<(NodePath, VNode, Reason)>,
+    added: &mut Vec<(NodePath, VNode, Reason)>,
+    scope: MoveScope,
+) -> Vec<Explanation> {
+    let old_candidates = move_candidates(removed, scope);
+    let new_candidates = move_candidates(added, scope);
+    let mut pairs: Vec<(&MoveCandidate, &MoveCandidate)> = Vec::new();
+    for old in &old_candidates {
+        let old_node = removed[old.entry].1.element_type.get(&old.path);
+        if pairs.iter().any(|(paired, _)| paired.overlaps(old)) {
+            continue;
+        }
+        let matched = new_candidates.iter().find(|new| {
+            new.key == old.key
+                && !pairs.iter().any(|(_, paired)| paired.overlaps(new))
+                && same_shape(old_node, added[new.entry].1.element_type.get(&new.path))
+        });
+        if let Some(new) = matched {
+            pairs.push((old, new));
+        }
+    }
+
+    let mut moves = Vec::new();
+    for (old, new) in &pairs {
+        let from = [removed[old.entry].0.as_slice(), &old.path].concat();
+        let to = [added[new.entry].0.as_slice(), &new.path].concat();
+        let (
+            Some(old_node @ ElementType::Element(_, old_attrs, _)),
+            Some(ElementType::Element(_, new_attrs, _)),
+        ) = (
+            removed[old.entry].1.element_type.get(&old.path),
+            added[new.entry].1.element_type.get(&new.path),
+        )
+        else {
+            unreachable!("move candidates are elements");
+        };
+        for change in attribute_changes(old_attrs, new_attrs) {
+            moves.push(Explanation {
+                diff: Diff::SetAttribute(to.clone(), change.name.clone(), change.new.clone()),
+                reason: Reason::AttributesChanged {
+                    changes: vec![change],
+                },
             });
         }
-    } else if let ElementType::Element(_, _, old_children) = old {
-        if let ElementType::Element(_, _, new_children) = new {
-            for (old_child, new_child) in old_children.iter().zip(new_children.iter()) {
-                find_added_nodes_recursive(old_child, new_child, added_nodes);
+        moves.push(Explanation {
+            diff: Diff::MoveNode(
+                from,
+                to,
+                VNode {
+                    element_type: old_node.clone(),
+                },
+            ),
+            reason: Reason::Moved {
+                key: old.key.to_string(),
+            },
+        });
+    }
+
+    let (old_taken, new_taken) = pairs
+        .iter()
+        .map(|(old, new)| ((old.entry, old.path.clone()), (new.entry, new.path.clone())))
+        .unzip();
+    take_moved(removed, old_taken);
+    take_moved(added, new_taken);
+    moves
+}
+
+/**
+ * 移動として対応付けられる、削除・追加された部分木の中の key を持つ要素
+ */
+struct MoveCandidate {
+    /** 削除・追加の一覧の中の位置 */
+    entry: usize,
+    /** 部分木の根からの相対パス */
+    path: NodePath,
+    key: Text,
+}
+
+impl MoveCandidate {
+    /**
+     * 同じ部分木の中で、どちらかがもう一方の祖先または同じ要素かを判定する関数
+     */
+    fn overlaps(&self, other: &MoveCandidate) -> bool {
+        self.entry == other.entry
+            && (self.path.starts_with(&other.path) || other.path.starts_with(&self.path))
+    }
+}
+
+/**
+ * 移動の候補を、一覧の順と部分木の中の前順で集める関数
+ * MoveScope::Keyed では key で対応付けた子要素の一覧から取り除かれた・加えられた要素だけを候補にする
+ */
+fn move_candidates(changes: &[(NodePath, VNode, Reason)], scope: MoveScope) -> Vec<MoveCandidate> {
+    let mut candidates = Vec::new();
+    for (entry, (path, node, reason)) in changes.iter().enumerate() {
+        match scope {
+            MoveScope::Off => {}
+            MoveScope::Keyed => {
+                if let (
+                    Reason::KeyRemoved { key } | Reason::KeyAdded { key },
+                    ElementType::Element(..),
+                ) = (reason, &node.element_type)
+                {
+                    candidates.push(MoveCandidate {
+                        entry,
+                        path: Vec::new(),
+                        key: key.clone().into(),
+                    });
+                }
+            }
+            MoveScope::Global => {
+                for (relative, node) in node.element_type.iter_with_paths() {
+                    let ElementType::Element(_, attrs, _) = node else {
+                        continue;
+                    };
+                    // ルートは位置を指定して挿入できないため移動の対象にしない
+                    if path.is_empty() && relative.is_empty() {
+                        continue;
+                    }
+                    if let Some(key) = attrs.get(KEY_ATTR) {
+                        candidates.push(MoveCandidate {
+                            entry,
+                            path: relative,
+                            key: key.clone(),
+                        });
+                    }
+                }
             }
         }
     }
+    candidates
+}
+
+fn same_shape(old: Option<&ElementType>, new: Option<&ElementType>) -> bool {
+    matches!(
+        (old, new),
+        (
+            Some(ElementType::Element(old_tag, _, old_children)),
+            Some(ElementType::Element(new_tag, _, new_children)),
+        ) if old_tag == new_tag && old_children == new_children
+    )
+}
+
+/**
+ * 移動した要素を削除・追加の一覧から取り除く関数
+ * 部分木の根が移動した場合は一覧から、部分木の中の要素が移動した場合はその部分木から取り除く
+ */
+fn take_moved(changes: &mut Vec<(NodePath, VNode, Reason)>, mut taken: Vec<(usize, NodePath)>) {
+    // 後ろから取り除けば、残りの位置とパスは変わらない
+    taken.sort_by(|a, b| b.cmp(a));
+    for (entry, path) in taken {
+        match path.split_last() {
+            None => {
+                changes.remove(entry);
+            }
+            Some((index, parent)) => {
+                if let Some(ElementType::Element(_, _, children)) =
+                    changes[entry].1.element_type.get_mut(parent)
+                {
+                    children.remove(*index);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * 差分を取る間に集めた削除・追加されたノードとその理由
+ */
+struct Changes<'a> {
+    removed: Vec<(NodePath, VNode, Reason)>,
+    added: Vec<(NodePath, VNode, Reason)>,
+    options: &'a DiffOptions,
 }
 
 /**
- * 仮想DOMの削除されたノードを取得する関数
+ * 変更されたノードを一覧に追加する関数
+ * 既定では空のテキストノードは描画されないため差分に含めない
  */
-fn find_removed_nodes(old: &VNode, new: &VNode) -> Vec<VNode> {
-    let mut removed_nodes = Vec::new();
-    find_removed_nodes_recursive(&old.element_type, &new.element_type, &mut removed_nodes);
-    removed_nodes
+fn push_changed(
+    nodes: &mut Vec<(NodePath, VNode, Reason)>,
+    path: &[usize],
+    node: &ElementType,
+    reason: Reason,
+    options: &DiffOptions,
+) {
+    if options.empty_text == EmptyTextPolicy::Track || !node.is_empty_text_node() {
+        nodes.push((
+            path.to_vec(),
+            VNode {
+                element_type: node.clone(),
+            },
+            reason,
+        ));
+    }
+}
+
+impl Changes<'_> {
+    /**
+     * 古いノードを削除し新しいノードを追加する差分を、同じ理由で記録する関数
+     */
+    fn replace(
+        &mut self,
+        old_path: &[usize],
+        old: &ElementType,
+        new_path: &[usize],
+        new: &ElementType,
+        reason: Reason,
+    ) {
+        push_changed(
+            &mut self.removed,
+            old_path,
+            old,
+            reason.clone(),
+            self.options,
+        );
+        push_changed(&mut self.added, new_path, new, reason, self.options);
+    }
 }
 
 /**
- * 仮想DOMの削除されたノードを再帰的に取得する関数
+ * 2つのノードを比較し、削除されたノードを古い木のパスで、追加されたノードを新しい木のパスで集める関数
+ * タグ名と属性が一致する要素は置き換えずに子要素を比較する
+ * 子要素がすべて key を持つ場合は key で対応を取り、子要素の数が同じ場合は位置で対応を取る
+ * どちらにも当てはまらない場合は要素全体を置き換える
  */
-fn find_removed_nodes_recursive(
+fn diff_nodes(
     old: &ElementType,
     new: &ElementType,
-    removed_nodes: &mut Vec<VNode>,
+    old_path: &mut NodePath,
+    new_path: &mut NodePath,
+    changes: &mut Changes,
 ) {
-    if old != new {
-        if !old.is_empty_text_node() {
-            removed_nodes.push(VNode {
-                element_type: old.clone(),
-            });
+    if old == new {
+        return;
+    }
+
+    let (
+        ElementType::Element(old_tag, old_attrs, old_children),
+        ElementType::Element(new_tag, new_attrs, new_children),
+    ) = (old, new)
+    else {
+        let reason = match (old, new) {
+            (ElementType::Text(old_text), ElementType::Text(new_text)) => Reason::TextChanged {
+                old: old_text.to_string(),
+                new: new_text.to_string(),
+            },
+            _ => Reason::NodeChanged,
+        };
+        changes.replace(old_path, old, new_path, new, reason);
+        return;
+    };
+    #[cfg(feature = "profile")]
+    let _timer = crate::profile::SubtreeTimer::start("diff", new_tag, Some(new_path));
+    if changes
+        .options
+        .same_node
+        .is_some_and(|same_node| !same_node(old, new))
+    {
+        changes.replace(old_path, old, new_path, new, Reason::IdentityChanged);
+        return;
+    }
+    if old_tag != new_tag {
+        let reason = Reason::TagChanged {
+            old: old_tag.clone(),
+            new: new_tag.clone(),
+        };
+        changes.replace(old_path, old, new_path, new, reason);
+        return;
+    }
+    if old_attrs != new_attrs {
+        let reason = Reason::AttributesChanged {
+            changes: attribute_changes(old_attrs, new_attrs),
+        };
+        changes.replace(old_path, old, new_path, new, reason);
+        return;
+    }
+
+    let (old_keys, new_keys) = (child_keys(old_children), child_keys(new_children));
+    let pairs = match (&old_keys, &new_keys) {
+        (Some(old_keys), Some(new_keys)) => {
+            keyed_pairs(old_keys, new_keys).ok_or(Reason::KeysReordered)
+        }
+        _ if changes.options.same_node.is_some() => Ok(identity_pairs(
+            old_children,
+            new_children,
+            changes.options.same_node.unwrap(),
+        )),
+        _ if old_children.len() == new_children.len() => Ok((0..old_children.len())
+            .map(|i| (Some(i), Some(i)))
+            .collect()),
+        _ => Err(Reason::ChildCountChanged {
+            old: old_children.len(),
+            new: new_children.len(),
+        }),
+    };
+    let pairs = match pairs {
+        Ok(pairs) => pairs,
+        Err(reason) => {
+            changes.replace(old_path, old, new_path, new, reason);
+            return;
         }
-    } else if let ElementType::Element(_, _, old_children) = old {
-        if let ElementType::Element(_, _, new_children) = new {
-            for (old_child, new_child) in old_children.iter().zip(new_children.iter()) {
-                find_removed_nodes_recursive(old_child, new_child, removed_nodes);
+    };
+
+    let (removed_before, added_before) = (changes.removed.len(), changes.added.len());
+    for pair in pairs {
+        match pair {
+            (Some(old_index), Some(new_index)) => {
+                old_path.push(old_index);
+                new_path.push(new_index);
+                diff_nodes(
+                    &old_children[old_index],
+                    &new_children[new_index],
+                    old_path,
+                    new_path,
+                    changes,
+                );
+                old_path.pop();
+                new_path.pop();
             }
+            (Some(old_index), None) => {
+                old_path.push(old_index);
+                let reason = match (&old_keys, &new_keys) {
+                    (Some(keys), Some(_)) => Reason::KeyRemoved {
+                        key: keys[old_index].to_string(),
+                    },
+                    _ => Reason::NoMatchingNode,
+                };
+                push_changed(
+                    &mut changes.removed,
+                    old_path,
+                    &old_children[old_index],
+                    reason,
+                    changes.options,
+                );
+                old_path.pop();
+            }
+            (None, Some(new_index)) => {
+                new_path.push(new_index);
+                let reason = match (&old_keys, &new_keys) {
+                    (Some(_), Some(keys)) => Reason::KeyAdded {
+                        key: keys[new_index].to_string(),
+                    },
+                    _ => Reason::NoMatchingNode,
+                };
+                push_changed(
+                    &mut changes.added,
+                    new_path,
+                    &new_children[new_index],
+                    reason,
+                    changes.options,
+                );
+                new_path.pop();
+            }
+            (None, None) => {}
+        }
+    }
+
+    // 子孫の差分は深さ優先で末尾に積まれるため、この要素の分は記録前の長さ以降にある
+    if let Some(model) = changes.options.cost_model {
+        let subtree: Vec<&(NodePath, VNode, Reason)> = changes.removed[removed_before..]
+            .iter()
+            .chain(&changes.added[added_before..])
+            .collect();
+        let patch_cost: usize = subtree
+            .iter()
+            .map(|(_, node, _)| model.patch_cost(&node.element_type))
+            .sum();
+        if patch_cost > model.patch_cost(old) + model.patch_cost(new) {
+            let reason = Reason::CheaperToReplace {
+                patches: subtree.len(),
+            };
+            changes.removed.truncate(removed_before);
+            changes.added.truncate(added_before);
+            changes.replace(old_path, old, new_path, new, reason);
         }
     }
 }
 
+/**
+ * 子要素がすべて重複のない key 属性を持つ場合に key の一覧を返す関数
+ */
+fn child_keys(children: &[ElementType]) -> Option<Vec<&str>> {
+    let mut keys = Vec::with_capacity(children.len());
+    for child in children {
+        let ElementType::Element(_, attrs, _) = child else {
+            return None;
+        };
+        let key = attrs.get(KEY_ATTR)?;
+        if keys.contains(&key.as_ref()) {
+            return None;
+        }
+        keys.push(key.as_ref());
+    }
+    (!keys.is_empty()).then_some(keys)
+}
+
+/**
+ * key を元に古い子要素と新しい子要素の位置を対応付ける関数
+ * 残った key の順序が入れ替わっている場合は移動を表せないため None を返す
+ */
+fn keyed_pairs(
+    old_keys: &[&str],
+    new_keys: &[&str],
+) -> Option<Vec<(Option<usize>, Option<usize>)>> {
+    let kept_old: Vec<&str> = old_keys
+        .iter()
+        .copied()
+        .filter(|key| new_keys.contains(key))
+        .collect();
+    let kept_new: Vec<&str> = new_keys
+        .iter()
+        .copied()
+        .filter(|key| old_keys.contains(key))
+        .collect();
+    if kept_old != kept_new {
+        return None;
+    }
+
+    let mut pairs: Vec<(Option<usize>, Option<usize>)> = old_keys
+        .iter()
+        .enumerate()
+        .map(|(old_index, key)| {
+            (
+                Some(old_index),
+                new_keys.iter().position(|new_key| new_key == key),
+            )
+        })
+        .collect();
+    pairs.extend(
+        new_keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| !old_keys.contains(key))
+            .map(|(new_index, _)| (None, Some(new_index))),
+    );
+    Some(pairs)
+}
+
+/**
+ * 同一性の判定を元に古い子要素と新しい子要素の位置を対応付ける関数
+ * 移動は表せないため、順序を保ったまま前から順に対応付け、対応しなかったものは削除・追加とする
+ */
+fn identity_pairs(
+    old_children: &[ElementType],
+    new_children: &[ElementType],
+    same_node: SameNode,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut pairs = Vec::new();
+    let mut next_new = 0;
+    for (old_index, old_child) in old_children.iter().enumerate() {
+        let matched = new_children[next_new..]
+            .iter()
+            .position(|new_child| same_node(old_child, new_child))
+            .map(|offset| next_new + offset);
+        match matched {
+            Some(new_index) => {
+                pairs.extend((next_new..new_index).map(|index| (None, Some(index))));
+                pairs.push((Some(old_index), Some(new_index)));
+                next_new = new_index + 1;
+            }
+            None => pairs.push((Some(old_index), None)),
+        }
+    }
+    pairs.extend((next_new..new_children.len()).map(|index| (None, Some(index))));
+    pairs
+}
+
 /**
 * 仮想DOMの要素が空のテキストノードかどうかを判定する関数
 */
@@ -150,28 +939,441 @@ impl ElementType {
     }
 }
 
+/**
+ * 子要素の対応付けに使う属性名
+ */
+pub const KEY_ATTR: &str = "key";
+
+/**
+ * 終了タグを持たない要素
+ */
+pub const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/**
+ * 空白をそのまま保つ必要がある要素
+ */
+pub const WHITESPACE_SENSITIVE_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+/**
+ * 前後で行が分かれるため、接する空白が表示に影響しない要素
+ */
+pub const BLOCK_ELEMENTS: [&str; 39] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/**
+ * 名前だけで真を表すHTMLの真偽値属性
+ */
+pub const BOOLEAN_ATTRIBUTES: [&str; 25] = [
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "hidden",
+    "inert",
+    "ismap",
+    "itemscope",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "playsinline",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+/**
+ * 内容をエスケープせずにそのまま解釈する要素
+ */
+pub const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/**
+ * 属性値を囲む引用符
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuoteStyle {
+    #[default]
+    Double,
+    Single,
+}
+
+impl QuoteStyle {
+    fn as_char(self) -> char {
+        match self {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+        }
+    }
+}
+
+/**
+ * HTMLへの変換方法の設定
+ * 既定値はこれまでの virtual_dom_to_html と同じ出力になる
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /** 子要素を改行し、1階層ごとにこの数の空白で字下げする */
+    pub indent: Option<usize>,
+    pub quote: QuoteStyle,
+    /** 属性を名前順に並べる */
+    pub sort_attributes: bool,
+    /** 要素の間の空白だけのテキストを取り除き、連続する空白を1つにまとめる */
+    pub minify: bool,
+    /** 真偽値属性のうち、値が空または属性名と同じものを `disabled` のように名前だけで出力する */
+    pub boolean_shorthand: bool,
+    /** 属性がなくてもタグ名の後に空白を入れる（`<div >`）これまでの形式で出力する */
+    pub legacy_spacing: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            indent: None,
+            quote: QuoteStyle::Double,
+            sort_attributes: false,
+            minify: false,
+            boolean_shorthand: false,
+            legacy_spacing: true,
+        }
+    }
+}
+
+impl RenderOptions {
+    /**
+     * 本番環境向けに最小のHTMLを出力する設定
+     */
+    pub fn minified() -> Self {
+        Self {
+            sort_attributes: true,
+            minify: true,
+            boolean_shorthand: true,
+            legacy_spacing: false,
+            ..Self::default()
+        }
+    }
+
+    /**
+     * 読みやすさのために字下げして出力する設定
+     */
+    pub fn pretty(indent: usize) -> Self {
+        Self {
+            indent: Some(indent),
+            sort_attributes: true,
+            legacy_spacing: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_quote(mut self, quote: QuoteStyle) -> Self {
+        self.quote = quote;
+        self
+    }
+}
+
 /**
  * 仮想DOMの要素をHTMLに変換する関数
+ * 子を持たない空要素は終了タグを出力しない
  */
 pub fn virtual_dom_to_html(node: &ElementType) -> String {
+    virtual_dom_to_html_with(node, &RenderOptions::default())
+}
+
+/**
+ * 設定に従って仮想DOMの要素をHTMLに変換する関数
+ */
+#[tracing::instrument(name = "serialize", level = "debug", skip_all)]
+pub fn virtual_dom_to_html_with(node: &ElementType, options: &RenderOptions) -> String {
+    let mut html = String::new();
+    write_html(&mut html, node, options, 0, false, false);
+    html
+}
+
+fn write_attrs(html: &mut String, attrs: &Attributes, options: &RenderOptions) {
+    let mut attrs: Vec<(&Text, &Text)> = attrs.iter().collect();
+    if options.sort_attributes {
+        attrs.sort();
+    }
+    let quote = options.quote.as_char();
+    let escaped_quote = match options.quote {
+        QuoteStyle::Double => "&quot;",
+        QuoteStyle::Single => "&#39;",
+    };
+
+    let attrs_str = attrs
+        .iter()
+        .map(|(key, value)| {
+            if options.boolean_shorthand
+                && BOOLEAN_ATTRIBUTES.contains(&key.as_ref())
+                && (value.is_empty() || value == key)
+            {
+                key.to_string()
+            } else {
+                let value = value.replace('&', "&amp;").replace(quote, escaped_quote);
+                format!("{}={}{}{}", key, quote, value, quote)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    if options.legacy_spacing || !attrs_str.is_empty() {
+        html.push(' ');
+    }
+    html.push_str(&attrs_str);
+}
+
+/**
+ * コメントの内容がコメントを閉じないよう書き換える関数
+ * コメントの中では文字参照が展開されないため、連続する - の間に空白を入れ、
+ * > や -> で始まる内容と <!- で終わる内容には空白を補う
+ */
+pub(crate) fn escape_comment(text: &str) -> Cow<'_, str> {
+    let unsafe_start = text.starts_with('>') || text.starts_with("->");
+    if !text.contains("--") && !unsafe_start && !text.ends_with("<!-") {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    if unsafe_start {
+        escaped.push(' ');
+    }
+    for ch in text.chars() {
+        if ch == '-' && escaped.ends_with('-') {
+            escaped.push(' ');
+        }
+        escaped.push(ch);
+    }
+    if escaped.ends_with("<!-") {
+        escaped.push(' ');
+    }
+    Cow::Owned(escaped)
+}
+
+/**
+ * テキストがタグや文字参照として解釈されないよう & < > をエスケープする関数
+ */
+pub(crate) fn escape_text(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(ch),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/**
+ * 連続する空白を1つの空白にまとめる関数
+ */
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut previous_space = false;
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            if !previous_space {
+                collapsed.push(' ');
+            }
+            previous_space = true;
+        } else {
+            collapsed.push(ch);
+            previous_space = false;
+        }
+    }
+    collapsed
+}
+
+/**
+ * 空白だけのテキストの隣にあるノードを、is_insignificant_whitespace に渡すタグ名に変換する関数
+ * 隣がない場合は親のタグ名、要素でない場合は None を返す
+ */
+pub(crate) fn neighbor_tag<'a>(
+    parent: &'a str,
+    neighbor: Option<&'a ElementType>,
+) -> Option<&'a str> {
+    match neighbor {
+        None => Some(parent),
+        Some(ElementType::Element(tag, _, _)) => Some(tag),
+        Some(_) => None,
+    }
+}
+
+/**
+ * 空白だけのテキストを取り除いても表示が変わらないかを判定する関数
+ * previous と next は前後のノードのタグ名で、どちらかがブロック要素であれば取り除ける
+ * インライン要素の間の空白は単語の区切りになるため、取り除かずに1つの空白にまとめる
+ */
+pub(crate) fn is_insignificant_whitespace(previous: Option<&str>, next: Option<&str>) -> bool {
+    [previous, next]
+        .into_iter()
+        .any(|tag| tag.is_some_and(|tag| BLOCK_ELEMENTS.contains(&tag)))
+}
+
+fn write_html(
+    html: &mut String,
+    node: &ElementType,
+    options: &RenderOptions,
+    depth: usize,
+    preserve_whitespace: bool,
+    raw_text: bool,
+) {
     match node {
-        ElementType::Text(text) => text.clone(),
+        ElementType::Text(text) if raw_text => html.push_str(text),
+        ElementType::Text(text) if options.minify && !preserve_whitespace => {
+            html.push_str(&escape_text(&collapse_whitespace(text)))
+        }
+        ElementType::Text(text) => html.push_str(&escape_text(text)),
+        // 翻訳されずに残ったノードはキーをそのまま出力する
+        ElementType::I18n(key, _) => html.push_str(&escape_text(key)),
+        ElementType::Comment(text) => {
+            html.push_str("<!--");
+            html.push_str(&escape_comment(text));
+            html.push_str("-->");
+        }
         ElementType::Element(tag, attrs, children) => {
-            let attrs_str = attrs
-                .iter()
-                .map(|(key, value)| format!("{}=\"{}\"", key, value))
-                .collect::<Vec<_>>()
-                .join(" ");
-            let children_str = children
+            #[cfg(feature = "profile")]
+            let _timer = crate::profile::SubtreeTimer::start("serialize", tag, None);
+            html.push('<');
+            html.push_str(tag);
+            write_attrs(html, attrs, options);
+            html.push('>');
+            if VOID_ELEMENTS.contains(&tag.as_str()) && children.is_empty() {
+                return;
+            }
+
+            let preserve_whitespace =
+                preserve_whitespace || WHITESPACE_SENSITIVE_ELEMENTS.contains(&tag.as_str());
+            let raw_text = RAW_TEXT_ELEMENTS.contains(&tag.as_str());
+            let children: Vec<&ElementType> = children
                 .iter()
-                .map(|child| virtual_dom_to_html(child))
-                .collect::<Vec<_>>()
-                .join("");
-            format!("<{} {}>{}</{}>", tag, attrs_str, children_str, tag)
+                .enumerate()
+                .filter(|(index, child)| {
+                    // ブロック要素に接する空白だけのテキストは表示に影響しないため取り除く
+                    !(options.minify
+                        && !preserve_whitespace
+                        && matches!(child, ElementType::Text(text) if text.trim().is_empty())
+                        && is_insignificant_whitespace(
+                            neighbor_tag(tag, index.checked_sub(1).map(|i| &children[i])),
+                            neighbor_tag(tag, children.get(index + 1)),
+                        ))
+                })
+                .map(|(_, child)| child)
+                .collect();
+            // テキストだけを持つ要素は字下げせずに1行で出力する
+            let indent = options.indent.filter(|_| {
+                !preserve_whitespace
+                    && children
+                        .iter()
+                        .any(|child| matches!(child, ElementType::Element(..)))
+            });
+
+            for child in children {
+                if let Some(indent) = indent {
+                    html.push('\n');
+                    html.push_str(&" ".repeat(indent * (depth + 1)));
+                }
+                write_html(
+                    html,
+                    child,
+                    options,
+                    depth + 1,
+                    preserve_whitespace,
+                    raw_text,
+                );
+            }
+            if let Some(indent) = indent {
+                html.push('\n');
+                html.push_str(&" ".repeat(indent * depth));
+            }
+            html.push_str("</");
+            html.push_str(tag);
+            html.push('>');
         }
     }
 }
 
+impl fmt::Display for ElementType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", virtual_dom_to_html(self))
+    }
+}
+
+impl fmt::Display for VNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.element_type.fmt(f)
+    }
+}
+
+/**
+ * 追加・置き換えされるノードごとにHTMLの断片を生成する関数
+ * JavaScriptの実行環境を持たないクライアントがパスに対応する要素を差し替えるために利用する
+ * 要素の移動は断片の差し替えでは表せないため、このクライアントには移動を有効にしない差分を渡す
+ */
+pub fn render_patch_fragments(patches: &[Diff]) -> Vec<(NodePath, String)> {
+    patches
+        .iter()
+        .filter_map(|patch| match patch {
+            Diff::AddNode(path, node) => {
+                Some((path.clone(), virtual_dom_to_html(&node.element_type)))
+            }
+            Diff::RemoveNode(..) | Diff::MoveNode(..) | Diff::SetAttribute(..) => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -181,65 +1383,417 @@ mod tests {
         let old_dom = VNode {
             element_type: ElementType::Element(
                 "div".to_string(),
-                HashMap::new(),
-                vec![ElementType::Text("Hello".to_string())],
+                Attributes::new(),
+                vec![ElementType::Text("Hello".into())],
             ),
         };
 
         let new_dom = VNode {
             element_type: ElementType::Element(
                 "div".to_string(),
-                HashMap::new(),
+                Attributes::new(),
                 vec![
-                    ElementType::Text("World".to_string()),
+                    ElementType::Text("World".into()),
                     ElementType::Element(
                         "span".to_string(),
-                        HashMap::new(),
-                        vec![ElementType::Text("!".to_string())],
+                        Attributes::new(),
+                        vec![ElementType::Text("!".into())],
                     ),
                 ],
             ),
         };
 
         let expected_diff = vec![
-            Diff::RemoveNode(VNode {
-                element_type: ElementType::Element(
-                    "div".to_string(),
-                    HashMap::new(),
-                    vec![ElementType::Text("Hello".to_string())],
-                ),
-            }),
-            Diff::AddNode(VNode {
-                element_type: ElementType::Element(
-                    "div".to_string(),
-                    HashMap::new(),
-                    vec![
-                        ElementType::Text("World".to_string()),
-                        ElementType::Element(
-                            "span".to_string(),
-                            HashMap::new(),
-                            vec![ElementType::Text("!".to_string())],
-                        ),
-                    ],
-                ),
-            }),
+            Diff::RemoveNode(
+                vec![],
+                VNode {
+                    element_type: ElementType::Element(
+                        "div".to_string(),
+                        Attributes::new(),
+                        vec![ElementType::Text("Hello".into())],
+                    ),
+                },
+            ),
+            Diff::AddNode(
+                vec![],
+                VNode {
+                    element_type: ElementType::Element(
+                        "div".to_string(),
+                        Attributes::new(),
+                        vec![
+                            ElementType::Text("World".into()),
+                            ElementType::Element(
+                                "span".to_string(),
+                                Attributes::new(),
+                                vec![ElementType::Text("!".into())],
+                            ),
+                        ],
+                    ),
+                },
+            ),
         ];
         let app_response = update_dom(&old_dom, &new_dom);
 
         assert!(app_response.diff == expected_diff);
     }
 
+    #[test]
+    fn test_empty_text_policy() {
+        let paragraph = |text: &str| VNode {
+            element_type: ElementType::Element(
+                "p".to_string(),
+                Attributes::new(),
+                vec![ElementType::Text(text.to_string().into())],
+            ),
+        };
+        let (old, new) = (paragraph(""), paragraph("x"));
+
+        assert_eq!(update_dom(&old, &new).diff.len(), 1);
+        let tracked = update_dom_with(
+            &old,
+            &new,
+            &DiffOptions::default().with_empty_text(EmptyTextPolicy::Track),
+        )
+        .diff;
+        assert_eq!(
+            tracked,
+            vec![
+                Diff::RemoveNode(
+                    vec![0],
+                    VNode {
+                        element_type: ElementType::Text(String::new().into()),
+                    },
+                ),
+                Diff::AddNode(
+                    vec![0],
+                    VNode {
+                        element_type: ElementType::Text("x".into()),
+                    },
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_patch_limit() {
+        let list = |items: &[&'static str]| VNode {
+            element_type: crate::builder::element(
+                "ul",
+                &[],
+                items
+                    .iter()
+                    .map(|item| crate::builder::element("li", &[], *item))
+                    .collect::<Vec<_>>(),
+            ),
+        };
+        let old = list(&["a", "b", "c"]);
+        let new = list(&["x", "y", "z"]);
+        assert_eq!(update_dom(&old, &new).diff.len(), 6);
+
+        let options = DiffOptions::default().with_max_patches(4);
+        let collapsed = update_dom_with(&old, &new, &options).diff;
+        assert_eq!(collapsed, replace_root(&new));
+        let mut tree = old.element_type.clone();
+        crate::patch::apply_patches(&mut tree, &collapsed).unwrap();
+        assert_eq!(tree, new.element_type);
+
+        let options = DiffOptions::default().with_max_patch_bytes(64);
+        assert_eq!(update_dom_with(&old, &new, &options).diff.len(), 1);
+        let options = DiffOptions::default().with_max_patches(6);
+        assert_eq!(update_dom_with(&old, &new, &options).diff.len(), 6);
+    }
+
+    #[test]
+    fn test_cost_model() {
+        let list = |items: &[&'static str]| VNode {
+            element_type: crate::builder::element(
+                "ul",
+                &[],
+                items
+                    .iter()
+                    .map(|item| crate::builder::element("li", &[], *item))
+                    .collect::<Vec<_>>(),
+            ),
+        };
+        let old = list(&["a", "b", "c", "d"]);
+        let options = DiffOptions::default().with_cost_model(CostModel::default());
+
+        // 1つだけ変わった場合はテキストの差分の方が安い
+        let one_changed = list(&["a", "b", "x", "d"]);
+        assert_eq!(update_dom_with(&old, &one_changed, &options).diff.len(), 2);
+
+        // すべて変わった場合は8つの差分よりも ul ごと置き換える方が安い
+        let all_changed = list(&["w", "x", "y", "z"]);
+        assert_eq!(update_dom(&old, &all_changed).diff.len(), 8);
+        let explanations = crate::explain::explain_diff_with(&old, &all_changed, &options);
+        assert_eq!(explanations.len(), 2);
+        assert!(explanations.iter().all(|explanation| {
+            explanation.diff.path().is_empty()
+                && explanation.reason == Reason::CheaperToReplace { patches: 8 }
+        }));
+    }
+
+    #[test]
+    fn test_moves() {
+        use crate::builder::element;
+        let board = |todo: ElementType, done: ElementType| VNode {
+            element_type: element("div", &[], (todo, done)),
+        };
+        let card = |key: &'static str, class: &'static str| {
+            element(
+                "li",
+                &[("key", key), ("class", class)],
+                element("video", &[], ()),
+            )
+        };
+        let old = board(
+            element("ul", &[], (card("a", "todo"), card("b", "todo"))),
+            element("ul", &[], [card("c", "done")]),
+        );
+        let new = board(
+            element("ul", &[], [card("a", "todo")]),
+            element("ul", &[], (card("b", "done"), card("c", "done"))),
+        );
+        let options = DiffOptions::default().with_moves();
+        let diff = update_dom_with(&old, &new, &options).diff;
+        assert_eq!(
+            diff,
+            vec![
+                Diff::MoveNode(
+                    vec![0, 1],
+                    vec![1, 0],
+                    VNode {
+                        element_type: card("b", "todo")
+                    }
+                ),
+                Diff::SetAttribute(vec![1, 0], "class".to_string(), Some("done".to_string())),
+            ]
+        );
+        crate::patch::verify_diff(&old.element_type, &new.element_type, &diff).unwrap();
+
+        // 既定では削除と追加になる
+        assert_eq!(update_dom(&old, &new).diff.len(), 2);
+        assert!(update_dom(&old, &new)
+            .diff
+            .iter()
+            .all(|patch| patch.node().is_some()));
+
+        // 子要素が変わった要素は移動として扱わない
+        let changed = board(
+            element("ul", &[], [card("a", "todo")]),
+            element(
+                "ul",
+                &[],
+                (
+                    element("li", &[("key", "b")], element("p", &[], ())),
+                    card("c", "done"),
+                ),
+            ),
+        );
+        assert!(!update_dom_with(&old, &changed, &options)
+            .diff
+            .iter()
+            .any(|patch| matches!(patch, Diff::MoveNode(..))));
+    }
+
+    #[test]
+    fn test_global_moves() {
+        use crate::builder::element;
+        let item = |key: &'static str| element("li", &[("key", key)], element("video", &[], ()));
+        let options = DiffOptions::default().with_move_scope(MoveScope::Global);
+
+        // key の順序の入れ替えで ul ごと置き換える場合も、li は作り直さない
+        let old = VNode {
+            element_type: element("ul", &[], (item("a"), item("b"), item("c"))),
+        };
+        let new = VNode {
+            element_type: element("ul", &[], (item("c"), item("a"), item("b"))),
+        };
+        let diff = update_dom_with(&old, &new, &options).diff;
+        assert_eq!(
+            diff.iter()
+                .filter(|patch| matches!(patch, Diff::MoveNode(..)))
+                .count(),
+            3
+        );
+        crate::patch::verify_diff(&old.element_type, &new.element_type, &diff).unwrap();
+        let keyed = DiffOptions::default().with_moves();
+        assert_eq!(update_dom_with(&old, &new, &keyed).diff.len(), 2);
+
+        // 離れたコンテナの間の移動
+        let old = VNode {
+            element_type: element(
+                "div",
+                &[],
+                (
+                    element("ul", &[], [item("a")]),
+                    element("div", &[], element("p", &[], "empty")),
+                ),
+            ),
+        };
+        let new = VNode {
+            element_type: element(
+                "div",
+                &[],
+                (
+                    element("ul", &[], ()),
+                    element("div", &[], element("section", &[], [item("a")])),
+                ),
+            ),
+        };
+        let diff = update_dom_with(&old, &new, &options).diff;
+        assert!(diff.contains(&Diff::MoveNode(
+            vec![0, 0],
+            vec![1, 0, 0],
+            VNode {
+                element_type: item("a")
+            }
+        )));
+        crate::patch::verify_diff(&old.element_type, &new.element_type, &diff).unwrap();
+    }
+
+    #[test]
+    fn test_same_node() {
+        let item = |id: &str, text: &str| {
+            ElementType::Element(
+                "li".to_string(),
+                Attributes::from([("data-id".into(), id.to_string().into())]),
+                vec![ElementType::Text(text.to_string().into())],
+            )
+        };
+        let list = |items: Vec<ElementType>| VNode {
+            element_type: ElementType::Element("ul".to_string(), Attributes::new(), items),
+        };
+        let old = list(vec![item("1", "a"), item("2", "b")]);
+        let new = list(vec![item("0", "z"), item("1", "a"), item("2", "b!")]);
+
+        let same_id: SameNode = |a, b| match (a, b) {
+            (ElementType::Element(_, a, _), ElementType::Element(_, b, _)) => {
+                a.get("data-id") == b.get("data-id")
+            }
+            _ => true,
+        };
+        let options = DiffOptions::default().with_same_node(same_id);
+        assert_eq!(
+            update_dom_with(&old, &new, &options).diff,
+            vec![
+                Diff::RemoveNode(
+                    vec![1, 0],
+                    VNode {
+                        element_type: ElementType::Text("b".into()),
+                    },
+                ),
+                Diff::AddNode(
+                    vec![0],
+                    VNode {
+                        element_type: item("0", "z"),
+                    },
+                ),
+                Diff::AddNode(
+                    vec![2, 0],
+                    VNode {
+                        element_type: ElementType::Text("b!".into()),
+                    },
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_patches() {
+        let text = |value: &str| VNode {
+            element_type: ElementType::Text(value.to_string().into()),
+        };
+        let expected = vec![
+            Diff::RemoveNode(vec![2, 0], text("a")),
+            Diff::RemoveNode(vec![2], text("b")),
+            Diff::RemoveNode(vec![1, 3], text("c")),
+            Diff::RemoveNode(vec![1, 1], text("d")),
+            Diff::AddNode(vec![0], text("e")),
+            Diff::AddNode(vec![0, 2], text("f")),
+            Diff::AddNode(vec![1], text("g")),
+            Diff::AddNode(vec![1, 0], text("h")),
+        ];
+
+        for shift in 0..expected.len() {
+            let mut shuffled = expected.clone();
+            shuffled.rotate_left(shift);
+            shuffled.swap(0, shift);
+            sort_patches(&mut shuffled);
+            assert_eq!(shuffled, expected);
+        }
+    }
+
+    #[test]
+    fn test_filter_diff() {
+        let text = |value: &str| VNode {
+            element_type: ElementType::Text(value.to_string().into()),
+        };
+        let list = |items: Vec<ElementType>| VNode {
+            element_type: ElementType::Element("ul".to_string(), Attributes::new(), items),
+        };
+
+        let patches = vec![
+            Diff::RemoveNode(vec![1, 2], text("old")),
+            Diff::AddNode(vec![1, 2], text("new")),
+            Diff::AddNode(vec![0], text("outside")),
+            Diff::AddNode(
+                vec![],
+                list(vec![
+                    ElementType::Text("a".into()),
+                    ElementType::Text("b".into()),
+                ]),
+            ),
+        ];
+
+        assert_eq!(
+            filter_diff(&patches, &[1]),
+            vec![
+                Diff::RemoveNode(vec![2], text("old")),
+                Diff::AddNode(vec![2], text("new")),
+                Diff::AddNode(vec![], text("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_patch_fragments() {
+        let patches = vec![
+            Diff::RemoveNode(
+                vec![0],
+                VNode {
+                    element_type: ElementType::Text("old".into()),
+                },
+            ),
+            Diff::AddNode(
+                vec![0],
+                VNode {
+                    element_type: ElementType::Element(
+                        "b".to_string(),
+                        Attributes::new(),
+                        vec![ElementType::Text("new".into())],
+                    ),
+                },
+            ),
+        ];
+
+        assert_eq!(
+            render_patch_fragments(&patches),
+            vec![(vec![0], "<b >new</b>".to_string())]
+        );
+    }
+
     #[test]
     fn test_virtual_dom_to_html() {
         let element = ElementType::Element(
             "div".to_string(),
-            HashMap::new(),
+            Attributes::new(),
             vec![
-                ElementType::Text("Hello".to_string()),
+                ElementType::Text("Hello".into()),
                 ElementType::Element(
                     "span".to_string(),
-                    HashMap::new(),
-                    vec![ElementType::Text("World".to_string())],
+                    Attributes::new(),
+                    vec![ElementType::Text("World".into())],
                 ),
             ],
         );
@@ -248,5 +1802,95 @@ mod tests {
 
         let generated_html = virtual_dom_to_html(&element);
         assert_eq!(generated_html, expected_html);
+
+        let comment = ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![
+                ElementType::Comment("--><script>alert(1)</script><!--".to_string()),
+                ElementType::Comment("->".to_string()),
+            ],
+        );
+        assert_eq!(
+            virtual_dom_to_html(&comment),
+            "<div ><!--- -><script>alert(1)</script><!- ---><!-- ->--></div>"
+        );
+        let parsed = crate::parser::parse_html(&virtual_dom_to_html(&comment)).unwrap();
+        assert!(
+            matches!(&parsed, ElementType::Element(_, _, children)
+                if children.iter().all(|child| matches!(child, ElementType::Comment(_)))),
+            "{:?}",
+            parsed
+        );
+    }
+
+    #[test]
+    fn test_render_options() {
+        let attrs = |pairs: &[(&str, &str)]| -> Attributes {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string().into(), value.to_string().into()))
+                .collect()
+        };
+        let form = ElementType::Element(
+            "form".to_string(),
+            Attributes::new(),
+            vec![
+                ElementType::Text("\n  ".into()),
+                ElementType::Element(
+                    "input".to_string(),
+                    attrs(&[
+                        ("type", "text"),
+                        ("disabled", ""),
+                        ("title", "it's"),
+                        ("value", "value"),
+                    ]),
+                    vec![],
+                ),
+                ElementType::Text("\n  ".into()),
+                ElementType::Element(
+                    "p".to_string(),
+                    Attributes::new(),
+                    vec![ElementType::Text("a   b".into())],
+                ),
+            ],
+        );
+
+        assert_eq!(
+            virtual_dom_to_html_with(&form, &RenderOptions::minified()),
+            r#"<form><input disabled title="it's" type="text" value="value"><p>a b</p></form>"#
+        );
+        assert_eq!(
+            virtual_dom_to_html_with(
+                &form,
+                &RenderOptions {
+                    minify: true,
+                    ..RenderOptions::pretty(2).with_quote(QuoteStyle::Single)
+                }
+            ),
+            "<form>\n  <input disabled='' title='it&#39;s' type='text' value='value'>\n  <p>a b</p>\n</form>"
+        );
+
+        // インライン要素の間の空白は単語の区切りになるため、1つの空白として残す
+        let inline = |tag: &str, text: &'static str| {
+            ElementType::Element(
+                tag.to_string(),
+                Attributes::new(),
+                vec![ElementType::Text(text.into())],
+            )
+        };
+        let paragraph = ElementType::Element(
+            "p".to_string(),
+            Attributes::new(),
+            vec![
+                inline("b", "a"),
+                ElementType::Text("\n  ".into()),
+                inline("i", "b"),
+            ],
+        );
+        assert_eq!(
+            virtual_dom_to_html_with(&paragraph, &RenderOptions::minified()),
+            "<p><b>a</b> <i>b</i></p>"
+        );
     }
 }
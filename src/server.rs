@@ -0,0 +1,416 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use warp::{Filter, Rejection, Reply};
+
+use crate::access_log::{access_log, patch_reply, AccessLogSink, StdoutSink};
+use crate::acl::AccessControl;
+use crate::auth::{AllowAll, AuthError, AuthProvider, Identity};
+use crate::broadcaster::Broadcaster;
+use crate::error::{VdomError, PROBLEM_CONTENT_TYPE};
+use crate::event::{event_route, EventHandlers};
+use crate::form::Forms;
+use crate::handler::{HttpHandler, UpdateInputRequest};
+use crate::head::{forward_head, HeadFn};
+use crate::hypermedia::morph_route;
+use crate::middleware::MiddlewareChain;
+use crate::plugin::PluginHost;
+use crate::poll::{poll_route, DEFAULT_POLL_TIMEOUT};
+use crate::query::query_route;
+use crate::rate_limit::{rate_limit, RateLimitConfig, RateLimited, RateLimiter};
+use crate::request_id::{self, request_id};
+use crate::router::{navigate_route, Router};
+use crate::sanitize::InputPolicy;
+use crate::schema::{vnode_body, SchemaError};
+use crate::self_virtual_dom::VNode;
+use crate::sse::sse_route;
+use crate::store::Store;
+use crate::template::TemplateSource;
+use crate::ws::{ws_route, ServerMessage};
+
+/**
+ * 仮想DOMのルートの設定を表す構造体
+ */
+#[derive(Clone)]
+pub struct Config {
+    /** トップページのHTMLテンプレートの読み込み元 */
+    pub template: TemplateSource,
+    /** 入力の更新ルートとイベントのルートに適用する流量制限 */
+    pub rate_limit: RateLimitConfig,
+    /** 入力の更新ルートで木に書き込む前に入力へ適用する規則 */
+    pub input_policy: InputPolicy,
+    /** クライアントへ送信する前の差分に適用するミドルウェア */
+    pub middleware: Arc<MiddlewareChain>,
+    /** 仮想DOMを変更するルートで送信者を認証する仕組み */
+    pub auth: Arc<dyn AuthProvider>,
+    /** 部分木ごとの保護 */
+    pub access_control: AccessControl,
+    /** アクセスログの出力先 */
+    pub access_log: Arc<dyn AccessLogSink>,
+    /** ロングポーリングで新しい差分を待機する時間 */
+    pub poll_timeout: Duration,
+    /** WebSocket で配信するメッセージのチャネル */
+    pub messages: broadcast::Sender<ServerMessage>,
+    /** WebSocket の購読者へ差分を配信する構造体 */
+    pub broadcaster: Arc<Broadcaster>,
+    /** `POST /event` で送信を受け付けるフォーム */
+    pub forms: Arc<Forms>,
+    /** `POST /event` でフォームの送信以外のイベントを処理する関数 */
+    pub events: Arc<EventHandlers>,
+    /** `POST /navigate` でページ遷移の差分を返すルーター */
+    pub router: Arc<Router>,
+    /** ルート・木の変換・差分のミドルウェアを追加する拡張機能 */
+    pub plugins: PluginHost,
+    /** 指定すると、更新のたびに仮想DOMから head を求め、変わった部分を WebSocket で配信する */
+    pub head: Option<HeadFn>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            template: TemplateSource::from_env(),
+            rate_limit: RateLimitConfig::default(),
+            input_policy: InputPolicy::default(),
+            middleware: Arc::new(MiddlewareChain::new()),
+            auth: Arc::new(AllowAll),
+            access_control: AccessControl::new(),
+            access_log: Arc::new(StdoutSink),
+            poll_timeout: DEFAULT_POLL_TIMEOUT,
+            messages: broadcast::channel(16).0,
+            broadcaster: Arc::new(Broadcaster::default()),
+            forms: Arc::new(Forms::new()),
+            events: Arc::new(EventHandlers::new()),
+            router: Arc::new(Router::new()),
+            plugins: PluginHost::new(),
+            head: None,
+        }
+    }
+}
+
+/**
+ * 仮想DOMのエンドポイントをまとめたフィルタを返す関数
+ * 既存の warp アプリケーションに組み込んで利用できる
+ * ストアの更新を WebSocket の購読者へ配信するタスクを起動するため、tokio のランタイム内で呼び出す
+ */
+pub fn routes(
+    config: Config,
+    store: Arc<Store>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // 拡張機能のミドルウェアは、配信を含むすべてのルートで設定のミドルウェアの後に適用する
+    let (plugin_routes, middleware) = config
+        .plugins
+        .install(store.clone(), config.middleware.clone());
+    let config = Config {
+        middleware,
+        ..config
+    };
+    config
+        .broadcaster
+        .forward_from(store.clone(), config.middleware.clone());
+    if let Some(head) = config.head.clone() {
+        forward_head(&store, head, config.messages.clone());
+    }
+
+    let query_route = query_route(store.clone());
+    let events_route = sse_route(store.clone(), config.middleware.clone());
+    let morph_route = morph_route(store.clone(), config.middleware.clone());
+    let poll_route = poll_route(
+        store.clone(),
+        config.middleware.clone(),
+        config.poll_timeout,
+    );
+
+    // 入力の更新ルートとイベントのルートは送信者ごとに流量を制限する
+    let limiter = Arc::new(RateLimiter::new(config.rate_limit));
+    let event_route = event_route(
+        config.forms,
+        config.events,
+        store.clone(),
+        limiter.clone(),
+        config.auth.clone(),
+        config.access_control.clone(),
+    );
+    let store_for_errors = store.clone();
+
+    let handler = Arc::new(
+        HttpHandler::new(config.template, config.middleware, store)
+            .with_access_control(config.access_control)
+            .with_input_policy(config.input_policy)
+            .with_auth(config.auth.clone()),
+    );
+    let with_handler = warp::any().map(move || handler.clone());
+
+    let html_route = warp::path::end()
+        .and(with_handler.clone())
+        .map(|handler: Arc<HttpHandler>| warp::reply::html(handler.index()));
+
+    let run_app_route = warp::path("run_app")
+        .and(with_handler.clone())
+        .map(|handler: Arc<HttpHandler>| patch_reply(&handler.run_app()));
+
+    // 候補の木との差分を返すだけで、ストアには反映しない
+    let preview_route = warp::path("preview")
+        .and(warp::post())
+        .and(request_id())
+        .and(vnode_body())
+        .and(with_handler.clone())
+        .map(|id: String, tree: VNode, handler: Arc<HttpHandler>| {
+            patch_reply(&request_id::scope(id, || handler.preview(&tree)))
+        });
+
+    let update_input_route = warp::path("update_input")
+        .and(warp::post())
+        .and(rate_limit(limiter, config.auth.clone()))
+        .and(warp::addr::remote())
+        .and(request_id())
+        .and(warp::body::json())
+        .and(with_handler)
+        .map(
+            |identity: Identity,
+             remote: Option<SocketAddr>,
+             id: String,
+             request: UpdateInputRequest,
+             handler: Arc<HttpHandler>| {
+                let remote = remote.map(|addr| addr.ip());
+                // 更新の差分と、それを配信するメッセージにリクエストIDを関連付ける
+                match request_id::scope(id, || handler.update_input(&identity, remote, request)) {
+                    Ok(app_response) => patch_reply(&app_response),
+                    Err(err) => error_reply(&err, Some(handler.store().version())),
+                }
+            },
+        );
+
+    let routes = html_route
+        .or(run_app_route)
+        .or(preview_route)
+        .or(update_input_route)
+        .or(query_route)
+        .or(events_route)
+        .or(morph_route)
+        .or(poll_route)
+        .or(event_route)
+        .or(navigate_route(config.router))
+        .or(ws_route(config.messages, config.broadcaster, config.auth))
+        .or(plugin_routes)
+        .recover(move |err| recover_problem(err, Some(store_for_errors.version())));
+
+    access_log(config.access_log, routes)
+}
+
+/**
+ * VdomError を対応するステータスコードの `application/problem+json` レスポンスに変換する関数
+ */
+pub fn error_reply(err: &VdomError, version: Option<u64>) -> warp::reply::Response {
+    let status = warp::http::StatusCode::from_u16(err.status_code())
+        .unwrap_or(warp::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response =
+        warp::reply::with_status(warp::reply::json(&err.to_problem(version)), status)
+            .into_response();
+    response.headers_mut().insert(
+        warp::http::header::CONTENT_TYPE,
+        warp::http::HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+    );
+    response
+}
+
+/**
+ * Rejection を対応する VdomError に変換する関数
+ */
+pub fn rejection_error(err: &Rejection) -> VdomError {
+    if let Some(err) = err.find::<VdomError>() {
+        err.clone()
+    } else if let Some(err) = err.find::<AuthError>() {
+        VdomError::from(err.clone())
+    } else if err.find::<RateLimited>().is_some() {
+        VdomError::RateLimited
+    } else if let Some(err) = err.find::<SchemaError>() {
+        VdomError::InvalidTree {
+            path: err.path.clone(),
+            message: err.message.clone(),
+        }
+    } else if err.is_not_found() {
+        VdomError::NotFound
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        VdomError::MethodNotAllowed
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        VdomError::PayloadTooLarge
+    } else if let Some(err) = err.find::<warp::body::BodyDeserializeError>() {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<warp::reject::InvalidQuery>() {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<warp::reject::MissingHeader>() {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<warp::reject::InvalidHeader>() {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    } else if let Some(err) = err.find::<warp::reject::UnsupportedMediaType>() {
+        VdomError::BadRequest {
+            message: err.to_string(),
+        }
+    } else {
+        VdomError::Internal {
+            message: format!("unhandled rejection: {:?}", err),
+        }
+    }
+}
+
+/**
+ * Rejection を `application/problem+json` のレスポンスに変換する関数
+ * 既存の warp アプリケーションの他のルートを試せるよう、一致するルートがなかった場合は変換しない
+ */
+pub async fn recover_problem(
+    err: Rejection,
+    version: Option<u64>,
+) -> Result<warp::reply::Response, Rejection> {
+    if err.is_not_found() {
+        return Err(err);
+    }
+    Ok(error_reply(&rejection_error(&err), version))
+}
+
+/**
+ * 残ったすべての Rejection を `application/problem+json` のレスポンスに変換する関数
+ * アプリケーション全体のフィルタの最後に使う
+ */
+pub async fn recover_all(err: Rejection) -> Result<warp::reply::Response, Infallible> {
+    Ok(error_reply(&rejection_error(&err), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+    use crate::self_virtual_dom::virtual_dom_to_html;
+
+    #[tokio::test]
+    async fn test_update_input_commits_to_store() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let filter = routes(Config::default(), store.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/update_input")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["html"], "<div >Hello</div>");
+
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.version, 1);
+        assert!(response.headers().contains_key("x-request-id"));
+        assert_eq!(
+            body["request_id"],
+            response.headers()["x-request-id"].to_str().unwrap()
+        );
+        assert_eq!(
+            virtual_dom_to_html(&snapshot.tree.element_type),
+            "<div >Hello</div>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_patches_reach_websocket() {
+        use crate::head::{Head, HeadPatch};
+
+        let store = Arc::new(Store::new(initial_tree()));
+        let head: HeadFn = Arc::new(|tree: &VNode| {
+            let mut head = Head::new();
+            head.title(&virtual_dom_to_html(&tree.element_type));
+            head
+        });
+        let filter = routes(
+            Config {
+                head: Some(head),
+                ..Config::default()
+            },
+            store.clone(),
+        );
+        let mut client = warp::test::ws()
+            .path("/ws")
+            .handshake(filter)
+            .await
+            .unwrap();
+
+        store.update(crate::app::render_input("Hi"));
+
+        let expected = ServerMessage::Head {
+            patches: vec![HeadPatch::SetTitle("<div >Hi</div>".to_string())],
+        };
+        let expected = serde_json::to_string(&expected).unwrap();
+        // 同じ更新の差分も届くため、Head のメッセージが届くまで読み進める
+        let received = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+            loop {
+                let message = client.recv().await.unwrap();
+                if message.to_str().unwrap() == expected {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(received.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_preview_does_not_commit() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let filter = routes(Config::default(), store.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/preview")
+            .json(&serde_json::json!({
+                "element_type": { "Element": ["div", {}, [{ "Text": "Draft" }]] }
+            }))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["html"], "<div >Draft</div>");
+        assert_eq!(body["diff"].as_array().unwrap().len(), 1);
+        assert_eq!(store.version(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_problem_json() {
+        let store = Arc::new(Store::new(initial_tree()));
+        let filter = routes(Config::default(), store);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/update_input")
+            .json(&serde_json::json!({ "text": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 400);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/problem+json"
+        );
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["type"], "/problems/bad-request");
+        assert_eq!(body["version"], 0);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/preview")
+            .json(&serde_json::json!({ "element_type": { "Element": ["div", [], []] } }))
+            .reply(&filter)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["type"], "/problems/invalid-tree");
+    }
+}
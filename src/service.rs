@@ -0,0 +1,107 @@
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+use crate::auth::Identity;
+use crate::error::VdomError;
+use crate::handler::{HttpHandler, UpdateInputRequest};
+use crate::self_virtual_dom::AppResponse;
+use crate::store::Snapshot;
+
+/**
+ * 差分エンジンへのリクエストを表す列挙型
+ */
+#[derive(Debug, Clone)]
+pub enum VdomRequest {
+    /** デモアプリの初期表示の差分を取得する */
+    RunApp,
+    /** 送信者と接続元として入力値を反映して差分を取得する */
+    UpdateInput(Identity, Option<IpAddr>, UpdateInputRequest),
+    /** 現在の仮想DOMとバージョンを取得する */
+    Snapshot,
+}
+
+/**
+ * 差分エンジンからのレスポンスを表す列挙型
+ */
+#[derive(Debug)]
+pub enum VdomResponse {
+    Patches(AppResponse),
+    Snapshot(Snapshot),
+}
+
+/**
+ * 差分エンジンを tower の Service として扱うための構造体
+ * timeout や load-shed などの tower のミドルウェアと組み合わせて利用できる
+ */
+#[derive(Clone)]
+pub struct VdomService {
+    handler: Arc<HttpHandler>,
+}
+
+impl VdomService {
+    pub fn new(handler: Arc<HttpHandler>) -> Self {
+        Self { handler }
+    }
+}
+
+impl Service<VdomRequest> for VdomService {
+    type Response = VdomResponse;
+    type Error = VdomError;
+    type Future = Ready<Result<VdomResponse, VdomError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: VdomRequest) -> Self::Future {
+        let response = match request {
+            VdomRequest::RunApp => Ok(VdomResponse::Patches(self.handler.run_app())),
+            VdomRequest::UpdateInput(identity, remote, request) => self
+                .handler
+                .update_input(&identity, remote, request)
+                .map(VdomResponse::Patches),
+            VdomRequest::Snapshot => Ok(VdomResponse::Snapshot(self.handler.store().snapshot())),
+        };
+        ready(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+    use crate::middleware::MiddlewareChain;
+    use crate::store::Store;
+    use crate::template::TemplateSource;
+    use futures_util::future::poll_fn;
+
+    #[tokio::test]
+    async fn test_vdom_service() {
+        let mut service = VdomService::new(Arc::new(HttpHandler::new(
+            TemplateSource::Embedded(""),
+            Arc::new(MiddlewareChain::new()),
+            Arc::new(Store::new(initial_tree())),
+        )));
+
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let response = service
+            .call(VdomRequest::UpdateInput(
+                Identity::anonymous(),
+                None,
+                UpdateInputRequest::Input {
+                    input: "Hi".to_string(),
+                },
+            ))
+            .await
+            .unwrap();
+        assert!(
+            matches!(response, VdomResponse::Patches(app_response) if app_response.html == "<div >Hi</div>")
+        );
+
+        let response = service.call(VdomRequest::Snapshot).await.unwrap();
+        assert!(matches!(response, VdomResponse::Snapshot(snapshot) if snapshot.version == 1));
+    }
+}
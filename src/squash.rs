@@ -0,0 +1,122 @@
+use crate::self_virtual_dom::Diff;
+
+/**
+ * 連続して発生した差分を、同じ結果になるより少ない差分にまとめる関数
+ *
+ * - 追加した後に削除されたノードの差分は打ち消し合い、その部分木の中に対する差分も取り除く
+ * - 打ち消すと他の差分のパスがずれる場合は、まとめずにそのまま残す
+ * - 同じ位置に対する削除や追加は、後ろの兄弟がずれた別のノードに対するものなので、まとめない
+ * - 移動と属性の変更はまとめずにそのまま残す
+ */
+pub fn squash(patches: Vec<Diff>) -> Vec<Diff> {
+    let mut squashed: Vec<Diff> = Vec::new();
+
+    for patch in patches {
+        if let Diff::RemoveNode(path, _) = &patch {
+            let added_at = squashed.iter().rposition(|existing| {
+                matches!(existing, Diff::AddNode(..)) && existing.path() == path
+            });
+            if let Some(index) = added_at.filter(|index| {
+                squashed[index + 1..]
+                    .iter()
+                    .all(|later| later.path().starts_with(path) || is_independent(later, path))
+            }) {
+                // 追加したノードの削除なので、追加とその部分木の中に対する差分ごと取り消す
+                squashed.remove(index);
+                let mut position = 0;
+                squashed.retain(|existing| {
+                    position += 1;
+                    position <= index || !existing.path().starts_with(path)
+                });
+                continue;
+            }
+        }
+        squashed.push(patch);
+    }
+
+    squashed
+}
+
+/**
+ * path に追加したノードを取り消しても、パスを変えずに適用できる差分かを判定する関数
+ * 取り消すノードより前の兄弟の部分木の中と、親の部分木の外に対する差分だけが該当する
+ */
+fn is_independent(patch: &Diff, path: &[usize]) -> bool {
+    let Some((index, parent)) = path.split_last() else {
+        return false;
+    };
+    let other = patch.path();
+    match patch {
+        Diff::MoveNode(..) => false,
+        _ if !other.starts_with(parent) => true,
+        Diff::SetAttribute(..) => other.get(parent.len()).is_none_or(|other| other < index),
+        _ => other.len() > path.len() && other[parent.len()] < *index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::{ElementType, VNode};
+
+    fn text(value: &str) -> VNode {
+        VNode {
+            element_type: ElementType::Text(value.to_string().into()),
+        }
+    }
+
+    #[test]
+    fn test_squash_repeated_replacements() {
+        let patches = vec![
+            Diff::RemoveNode(vec![0], text("a")),
+            Diff::AddNode(vec![0], text("b")),
+            Diff::RemoveNode(vec![0], text("b")),
+            Diff::AddNode(vec![0], text("c")),
+            Diff::AddNode(vec![1], text("x")),
+            Diff::RemoveNode(vec![1], text("x")),
+        ];
+
+        assert_eq!(
+            squash(patches),
+            vec![
+                Diff::RemoveNode(vec![0], text("a")),
+                Diff::AddNode(vec![0], text("c")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_squash_drops_changes_inside_removed_subtree() {
+        let patches = vec![
+            Diff::RemoveNode(vec![0], text("a")),
+            Diff::AddNode(vec![2], text("parent")),
+            Diff::AddNode(vec![2, 0], text("child")),
+            Diff::RemoveNode(vec![2], text("parent")),
+        ];
+        assert_eq!(squash(patches), vec![Diff::RemoveNode(vec![0], text("a"))]);
+
+        // 追加していない部分木の中に対する差分は残す
+        let patches = vec![
+            Diff::AddNode(vec![2, 0], text("child")),
+            Diff::RemoveNode(vec![2], text("parent")),
+        ];
+        assert_eq!(squash(patches.clone()), patches);
+    }
+
+    #[test]
+    fn test_squash_keeps_removals_of_shifted_siblings() {
+        let patches = vec![
+            Diff::RemoveNode(vec![0], text("a")),
+            Diff::RemoveNode(vec![0], text("b")),
+        ];
+        assert_eq!(squash(patches.clone()), patches);
+
+        // 取り消すと後の追加の位置がずれるため、打ち消さない
+        let patches = vec![
+            Diff::AddNode(vec![0], text("x")),
+            Diff::AddNode(vec![1], text("y")),
+            Diff::RemoveNode(vec![0], text("x")),
+        ];
+        assert_eq!(squash(patches.clone()), patches);
+    }
+}
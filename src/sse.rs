@@ -0,0 +1,148 @@
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use warp::sse::Event;
+use warp::{Filter, Rejection, Reply};
+
+use crate::middleware::MiddlewareChain;
+use crate::self_virtual_dom::virtual_dom_to_html;
+use crate::store::{Snapshot, Store, Update};
+
+/**
+ * 差分の履歴から再開できない場合にクライアントへ送る全体の再同期データ
+ */
+#[derive(Debug, Serialize)]
+struct Resync {
+    version: u64,
+    html: String,
+}
+
+/**
+ * 差分を text/event-stream で配信する SSE ルートを返す関数
+ * イベントIDには更新後のバージョンを使い、Last-Event-ID から配信を再開する
+ */
+pub fn sse_route(
+    store: Arc<Store>,
+    middleware: Arc<MiddlewareChain>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("events")
+        .and(warp::get())
+        .and(warp::header::optional::<u64>("last-event-id"))
+        .map(move |last_event_id: Option<u64>| {
+            let events = update_events(&store, middleware.clone(), last_event_id);
+            warp::sse::reply(warp::sse::keep_alive().stream(events))
+        })
+}
+
+/**
+ * 再開位置以降の差分と、その後に発生する差分を順に返すストリームを生成する関数
+ * 配信が遅れて差分を取りこぼした場合は、resync のイベントで全体を送り直してから差分の配信を続ける
+ */
+fn update_events(
+    store: &Arc<Store>,
+    middleware: Arc<MiddlewareChain>,
+    last_event_id: Option<u64>,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+    // 履歴を読む前に購読しておき、その間に発生した更新を取りこぼさないようにする
+    let receiver = store.subscribe();
+
+    let (initial, mut last_version) = match last_event_id {
+        None => (vec![], store.version()),
+        Some(version) => match store.updates_since(version) {
+            Some(updates) => {
+                let last_version = updates.last().map_or(version, |update| update.version);
+                let events = updates
+                    .into_iter()
+                    .map(|update| patch_event(&middleware, update))
+                    .collect();
+                (events, last_version)
+            }
+            None => {
+                let snapshot = store.snapshot();
+                let version = snapshot.version;
+                (vec![resync_event(&middleware, snapshot)], version)
+            }
+        },
+    };
+
+    let store = Arc::downgrade(store);
+    let live = BroadcastStream::new(receiver).filter_map(move |update| {
+        let event = match update {
+            Ok(update) if update.version > last_version => {
+                last_version = update.version;
+                Some(patch_event(&middleware, update))
+            }
+            Ok(_) => None,
+            // 取りこぼした差分は送れないため、現在の仮想DOM全体を送り直す
+            Err(BroadcastStreamRecvError::Lagged(_)) => store.upgrade().map(|store| {
+                let snapshot = store.snapshot();
+                last_version = snapshot.version;
+                resync_event(&middleware, snapshot)
+            }),
+        };
+        async move { event }
+    });
+
+    stream::iter(initial).chain(live).map(Ok)
+}
+
+fn resync_event(middleware: &MiddlewareChain, snapshot: Snapshot) -> Event {
+    Event::default()
+        .id(snapshot.version.to_string())
+        .event("resync")
+        .json_data(Resync {
+            version: snapshot.version,
+            html: middleware.process_html(virtual_dom_to_html(&snapshot.tree.element_type)),
+        })
+        .unwrap_or_default()
+}
+
+fn patch_event(middleware: &MiddlewareChain, update: Update) -> Event {
+    Event::default()
+        .id(update.version.to_string())
+        .event("patch")
+        .json_data(middleware.process(update.diff))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+
+    #[tokio::test]
+    async fn test_resume_from_last_event_id() {
+        let store = Arc::new(Store::new(initial_tree()));
+        store.update(render_input("a"));
+        store.update(render_input("b"));
+
+        let middleware = Arc::new(MiddlewareChain::new());
+        let events = update_events(&store, middleware, Some(1));
+        store.update(render_input("c"));
+
+        let events: Vec<String> = events
+            .take(2)
+            .map(|event| event.unwrap().to_string())
+            .collect()
+            .await;
+        assert!(events[0].contains("id:2\n"));
+        assert!(events[1].contains("id:3\n"));
+        assert!(events[1].contains("event:patch\n"));
+    }
+
+    #[tokio::test]
+    async fn test_resync_after_lag() {
+        let store = Arc::new(Store::with_history_limit(initial_tree(), 1));
+        let events = update_events(&store, Arc::new(MiddlewareChain::new()), None);
+        for input in ["a", "b", "c"] {
+            store.update(render_input(input));
+        }
+
+        let event = Box::pin(events).next().await.unwrap().unwrap().to_string();
+        assert!(event.contains("event:resync\n"));
+        assert!(event.contains("id:3\n"));
+    }
+}
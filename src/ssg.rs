@@ -0,0 +1,131 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::builder::element;
+use crate::document::{render_document, Document};
+use crate::router::Router;
+
+/**
+ * 静的サイトの書き出しに使うコマンドライン引数（`cargo run -- ssg <出力先>`）
+ */
+pub const SSG_COMMAND: &str = "ssg";
+
+/**
+ * 出力先を省略したときのディレクトリ
+ */
+pub const DEFAULT_OUT_DIR: &str = "dist";
+
+/**
+ * ページを書き出せなかったことを表すエラー
+ */
+#[derive(Debug)]
+pub struct SsgError {
+    pub route: String,
+    pub message: String,
+}
+
+impl fmt::Display for SsgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot export {}: {}", self.route, self.message)
+    }
+}
+
+impl std::error::Error for SsgError {}
+
+/**
+ * ルートのパスに対応する出力ファイルのパスを返す関数
+ * `/` は `index.html` に、`/about` は `about/index.html` になる
+ */
+pub fn output_path(out_dir: &Path, route: &str) -> PathBuf {
+    route
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(out_dir.to_path_buf(), |path, segment| path.join(segment))
+        .join("index.html")
+}
+
+/**
+ * ルートごとにページを描画し、完全なHTML文書として出力先のディレクトリに書き出す関数
+ * ページは `#page` の中に置くため、書き出したページからも `POST /navigate` による遷移ができる
+ * 書き出したファイルのパスを返す
+ */
+pub fn export(
+    router: &Router,
+    routes: &[&str],
+    shell: &Document,
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>, SsgError> {
+    let mut written = Vec::with_capacity(routes.len());
+    for route in routes {
+        let error = |message: String| SsgError {
+            route: route.to_string(),
+            message,
+        };
+        let page = router
+            .render(route)
+            .ok_or_else(|| error("no page matches the route".to_string()))?;
+        let document =
+            shell
+                .clone()
+                .with_body(element("div", &[("id", "page")], page.element_type));
+
+        let path = output_path(out_dir, route);
+        write_file(&path, &render_document(&document)).map_err(|err| error(err.to_string()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn write_file(path: &Path, html: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(html.as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Params;
+    use crate::self_virtual_dom::VNode;
+
+    #[test]
+    fn test_export() {
+        let router = Router::new()
+            .route("/", |_: &Params| VNode {
+                element_type: element("h1", &[], "Home"),
+            })
+            .route("/posts/:id", |params: &Params| VNode {
+                element_type: element("h1", &[], format!("Post {}", params["id"])),
+            });
+        let out_dir = std::env::temp_dir().join(format!("vdom-ssg-{}", std::process::id()));
+
+        let written = export(
+            &router,
+            &["/", "/posts/1"],
+            &Document::new().with_title("Blog"),
+            &out_dir,
+        )
+        .unwrap();
+        assert_eq!(
+            written,
+            vec![
+                out_dir.join("index.html"),
+                out_dir.join("posts").join("1").join("index.html"),
+            ]
+        );
+        let html = fs::read_to_string(&written[1]).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<div id=\"page\"><h1 >Post 1</h1></div>"));
+
+        assert!(export(&router, &["/missing"], &Document::new(), &out_dir).is_err());
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Mutex, RwLock};
+use tokio::sync::broadcast;
+
+use crate::clock::{Conflict, ConflictPolicy, Resolution, VectorClock};
+use crate::request_id;
+use crate::self_virtual_dom::{
+    update_dom, update_dom_with, virtual_dom_to_html, AppResponse, Diff, DiffOptions, ElementType,
+    EmptyTextPolicy, VNode,
+};
+use crate::visit::{Pipeline, Transformer};
+
+/**
+ * 差分の履歴として保持する更新の既定の件数
+ */
+pub const DEFAULT_HISTORY_LIMIT: usize = 256;
+
+/**
+ * サーバーが保持する現在の仮想DOMとそのバージョン
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tree: VNode,
+    pub version: u64,
+}
+
+/**
+ * 1回の更新で発生した差分と更新後のバージョン
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Update {
+    pub version: u64,
+    pub diff: Vec<Diff>,
+    /** 更新後のベクタークロック */
+    pub clock: VectorClock,
+    /** 更新を発生させたリクエストのID */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /**
+     * 空のテキストノードも含めて計算した差分
+     * diff は描画されない空のテキストノードを含まないため、diff を適用し直しても木を再現できない場合だけ持つ
+     */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<Vec<Diff>>,
+}
+
+impl Update {
+    /**
+     * 更新前の木に適用し直すと更新後の木を再現できる差分を返す関数
+     */
+    pub fn replayable_diff(&self) -> &[Diff] {
+        self.replay.as_deref().unwrap_or(&self.diff)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    snapshot: Snapshot,
+    history: VecDeque<Update>,
+    clock: VectorClock,
+}
+
+/**
+ * 仮想DOMの状態を保持し、更新のたびに差分を計算して購読者へ配信する構造体
+ */
+#[derive(Debug)]
+pub struct Store {
+    state: RwLock<State>,
+    history_limit: usize,
+    sender: broadcast::Sender<Update>,
+    /** 差分を取る前に新しい木へ適用するパス */
+    transforms: Mutex<Pipeline>,
+}
+
+impl Store {
+    pub fn new(tree: VNode) -> Self {
+        Self::with_history_limit(tree, DEFAULT_HISTORY_LIMIT)
+    }
+
+    /**
+     * 保存先から復元したスナップショットからストアを生成する関数
+     * バージョンを引き継ぐため、クライアントは再接続後も差分を受け取り続けられる
+     */
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let store = Self::new(snapshot.tree);
+        store.state.write().unwrap().snapshot.version = snapshot.version;
+        store
+    }
+
+    /**
+     * 保持する差分の履歴の件数を指定してストアを生成する関数
+     */
+    pub fn with_history_limit(tree: VNode, history_limit: usize) -> Self {
+        Self {
+            state: RwLock::new(State {
+                snapshot: Snapshot { tree, version: 0 },
+                history: VecDeque::new(),
+                clock: VectorClock::new(),
+            }),
+            history_limit,
+            sender: broadcast::channel(history_limit.max(1)).0,
+            transforms: Mutex::new(Pipeline::new()),
+        }
+    }
+
+    /**
+     * 以降の更新で差分を取る前に新しい木へ適用するパスを登録する関数
+     * 登録前に保持していた木には適用しない
+     */
+    pub fn register_transform<T: Transformer + Send + 'static>(&self, pass: T) {
+        self.transforms.lock().unwrap().push(pass);
+    }
+
+    /**
+     * 現在の仮想DOMとバージョンを取得する関数
+     */
+    pub fn snapshot(&self) -> Snapshot {
+        self.state.read().unwrap().snapshot.clone()
+    }
+
+    /**
+     * 現在のバージョンを取得する関数
+     */
+    pub fn version(&self) -> u64 {
+        self.state.read().unwrap().snapshot.version
+    }
+
+    /**
+     * 仮想DOMを新しい木に置き換え、置き換え前との差分を返す関数
+     */
+    pub fn update(&self, tree: VNode) -> AppResponse {
+        match self.try_update(tree, |_, _, _| Ok::<(), Infallible>(())) {
+            Ok(app_response) => app_response,
+            Err(never) => match never {},
+        }
+    }
+
+    /**
+     * 現在の仮想DOMと新しい木との差分を計算する関数
+     * 登録されたパスは適用するが、ストアの状態は変更せず、購読者へも配信しない
+     */
+    pub fn update_dom_readonly(&self, tree: &VNode) -> AppResponse {
+        let mut tree = tree.clone();
+        self.transforms.lock().unwrap().run(&mut tree.element_type);
+        AppResponse {
+            request_id: request_id::current(),
+            ..update_dom(&self.state.read().unwrap().snapshot.tree, &tree)
+        }
+    }
+
+    /**
+     * 置き換え前後の木と差分を check で検証し、問題がなければ仮想DOMを置き換える関数
+     * check がエラーを返した場合は状態を変更しない
+     */
+    pub fn try_update<E>(
+        &self,
+        tree: VNode,
+        check: impl FnOnce(&VNode, &VNode, &[Diff]) -> Result<(), E>,
+    ) -> Result<AppResponse, E> {
+        let mut state = self.state.write().unwrap();
+        self.commit(&mut state, tree, check)
+    }
+
+    /**
+     * 現在の仮想DOMを書き換え、書き換え前との差分を返す関数
+     * 読み取りから置き換えまでを1回のロックで行うため、並行する更新で変更が失われない
+     * 木が変わらなかった場合はバージョンを進めず、空の差分を返す
+     */
+    pub fn modify(&self, f: impl FnOnce(&mut VNode)) -> AppResponse {
+        let mut state = self.state.write().unwrap();
+        let mut tree = state.snapshot.tree.clone();
+        f(&mut tree);
+        if tree == state.snapshot.tree {
+            return AppResponse {
+                diff: Vec::new(),
+                html: virtual_dom_to_html(&tree.element_type),
+                request_id: request_id::current(),
+            };
+        }
+        match self.commit(&mut state, tree, |_, _, _| Ok::<(), Infallible>(())) {
+            Ok(app_response) => app_response,
+            Err(never) => match never {},
+        }
+    }
+
+    /**
+     * 書き込み元 writer として仮想DOMを置き換え、差分と更新後のベクタークロックを返す関数
+     * seen は書き込み元が最後に受け取ったクロックで、ストアに writer の知らない更新がある場合は policy で扱いを決める
+     * policy が書き込みを取り消した場合は状態を変更せずに Conflict を返す
+     */
+    pub fn write(
+        &self,
+        writer: &str,
+        seen: &VectorClock,
+        tree: VNode,
+        policy: &dyn ConflictPolicy,
+    ) -> Result<(AppResponse, VectorClock), Conflict> {
+        let mut state = self.state.write().unwrap();
+        let tree = if seen.dominates(&state.clock) {
+            tree
+        } else {
+            let conflict = Conflict {
+                writer: writer.to_string(),
+                seen: seen.clone(),
+                current: state.clock.clone(),
+            };
+            match policy.resolve(&conflict, &state.snapshot.tree, tree) {
+                Resolution::Apply(tree) => tree,
+                Resolution::Reject => return Err(conflict),
+            }
+        };
+
+        state.clock.tick(writer);
+        match self.commit(&mut state, tree, |_, _, _| Ok::<(), Infallible>(())) {
+            Ok(app_response) => Ok((app_response, state.clock.clone())),
+            Err(never) => match never {},
+        }
+    }
+
+    /**
+     * modify と同じく現在の仮想DOMを書き換え、書き換え前と書き換え後のスナップショットを返す関数
+     */
+    pub fn modify_snapshots(&self, f: impl FnOnce(&mut VNode)) -> (Snapshot, Snapshot) {
+        match self.try_modify_snapshots(|tree, _| {
+            f(tree);
+            Ok::<(), Infallible>(())
+        }) {
+            Ok(snapshots) => snapshots,
+            Err(never) => match never {},
+        }
+    }
+
+    /**
+     * modify_snapshots と同じく現在の仮想DOMを書き換える関数
+     * f は書き換え前のバージョンも受け取り、エラーを返した場合は状態を変更しない
+     */
+    pub fn try_modify_snapshots<E>(
+        &self,
+        f: impl FnOnce(&mut VNode, u64) -> Result<(), E>,
+    ) -> Result<(Snapshot, Snapshot), E> {
+        let mut state = self.state.write().unwrap();
+        let before = state.snapshot.clone();
+        let mut tree = before.tree.clone();
+        f(&mut tree, before.version)?;
+        if tree != before.tree {
+            match self.commit(&mut state, tree, |_, _, _| Ok::<(), Infallible>(())) {
+                Ok(_) => {}
+                Err(never) => match never {},
+            }
+        }
+        Ok((before, state.snapshot.clone()))
+    }
+
+    fn commit<E>(
+        &self,
+        state: &mut State,
+        mut tree: VNode,
+        check: impl FnOnce(&VNode, &VNode, &[Diff]) -> Result<(), E>,
+    ) -> Result<AppResponse, E> {
+        self.transforms.lock().unwrap().run(&mut tree.element_type);
+        let app_response = AppResponse {
+            request_id: request_id::current(),
+            ..update_dom(&state.snapshot.tree, &tree)
+        };
+        check(&state.snapshot.tree, &tree, &app_response.diff)?;
+        let replay = replay_diff(&state.snapshot.tree, &tree, &app_response.diff);
+        state.snapshot.tree = tree;
+        state.snapshot.version += 1;
+
+        let update = Update {
+            version: state.snapshot.version,
+            diff: app_response.diff.clone(),
+            clock: state.clock.clone(),
+            request_id: app_response.request_id.clone(),
+            replay,
+        };
+        state.history.push_back(update.clone());
+        while state.history.len() > self.history_limit {
+            state.history.pop_front();
+        }
+        // 購読者がいない場合の送信エラーは無視する
+        let _ = self.sender.send(update);
+
+        Ok(app_response)
+    }
+
+    /**
+     * 以降の更新を受け取るための受信側を返す関数
+     */
+    pub fn subscribe(&self) -> broadcast::Receiver<Update> {
+        self.sender.subscribe()
+    }
+
+    /**
+     * 指定したバージョンより後の更新を履歴から取得する関数
+     * 履歴が既に破棄されていて連続した差分を返せない場合は None を返す
+     */
+    pub fn updates_since(&self, version: u64) -> Option<Vec<Update>> {
+        let state = self.state.read().unwrap();
+        if version > state.snapshot.version {
+            return None;
+        }
+        let oldest = state
+            .history
+            .front()
+            .map_or(state.snapshot.version, |update| update.version - 1);
+        if version < oldest {
+            return None;
+        }
+        Some(
+            state
+                .history
+                .iter()
+                .filter(|update| update.version > version)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/**
+ * diff を old に適用し直しても new を再現できない場合に、空のテキストノードも含めた差分を返す関数
+ * 空のテキストノードがどちらの木にもなければ diff で再現できるため、差分を計算し直さない
+ */
+fn replay_diff(old: &VNode, new: &VNode, diff: &[Diff]) -> Option<Vec<Diff>> {
+    let has_empty_text = |tree: &VNode| {
+        tree.iter()
+            .any(|node| matches!(node, ElementType::Text(text) if text.is_empty()))
+    };
+    if !has_empty_text(old) && !has_empty_text(new) {
+        return None;
+    }
+    let tracked = update_dom_with(
+        old,
+        new,
+        &DiffOptions::default().with_empty_text(EmptyTextPolicy::Track),
+    )
+    .diff;
+    (tracked != diff).then_some(tracked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{initial_tree, render_input};
+
+    #[test]
+    fn test_updates_since() {
+        let store = Store::with_history_limit(initial_tree(), 2);
+        for input in ["a", "b", "c"] {
+            store.update(render_input(input));
+        }
+
+        assert_eq!(store.version(), 3);
+        let versions = |updates: Vec<Update>| -> Vec<u64> {
+            updates.iter().map(|update| update.version).collect()
+        };
+        assert_eq!(store.updates_since(1).map(versions), Some(vec![2, 3]));
+        assert_eq!(store.updates_since(3).map(versions), Some(vec![]));
+        assert!(store.updates_since(0).is_none());
+        assert!(store.updates_since(4).is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+use crate::builder::element;
+use crate::self_virtual_dom::ElementType;
+use crate::store::Store;
+
+/**
+ * 読み込み中のプレースホルダーを識別する属性名
+ */
+pub const SUSPENSE_ATTR: &str = "data-suspense";
+
+static NEXT_SUSPENSE_ID: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * 非同期に読み込む部分木を表す構造体
+ * 読み込みが終わるまでは pending を表示し、終わった時点で差分を配信して置き換える
+ */
+pub struct Suspense<F> {
+    pub pending: ElementType,
+    pub future: F,
+}
+
+impl<F> Suspense<F>
+where
+    F: Future<Output = ElementType> + Send + 'static,
+{
+    pub fn new(pending: ElementType, future: F) -> Self {
+        Self { pending, future }
+    }
+
+    /**
+     * path のノードをプレースホルダーに置き換え、読み込みが終わったら結果で置き換えるタスクを起動する関数
+     * 待機中に木が変わっても置き換えられるよう、プレースホルダーは位置ではなく属性で探す
+     * プレースホルダーが既に取り除かれていた場合、読み込み結果は捨てる
+     */
+    pub fn mount(self, store: Arc<Store>, path: Vec<usize>) -> JoinHandle<()> {
+        let id = NEXT_SUSPENSE_ID.fetch_add(1, Ordering::Relaxed).to_string();
+        let placeholder = match self.pending {
+            ElementType::Element(tag, mut attrs, children) => {
+                attrs.insert(SUSPENSE_ATTR.into(), id.clone().into());
+                ElementType::Element(tag, attrs, children)
+            }
+            pending => element("span", &[(SUSPENSE_ATTR, &id)], pending),
+        };
+        store.modify(|tree| {
+            if let Some(node) = tree.element_type.get_mut(&path) {
+                *node = placeholder;
+            }
+        });
+
+        let future = self.future;
+        tokio::spawn(async move {
+            let resolved = future.await;
+            store.modify(|tree| {
+                let found = tree
+                    .element_type
+                    .iter_with_paths()
+                    .find_map(|(path, node)| {
+                        matches!(node, ElementType::Element(_, attrs, _)
+                        if attrs.get(SUSPENSE_ATTR).map(|value| value.as_ref()) == Some(id.as_str()))
+                        .then_some(path)
+                    });
+                if let Some(node) = found.and_then(|path| tree.element_type.get_mut(&path)) {
+                    *node = resolved;
+                }
+            });
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::VNode;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_suspense_replaces_placeholder() {
+        let store = Arc::new(Store::new(VNode {
+            element_type: element("main", &[], (element("h1", &[], "Title"), "slot")),
+        }));
+        let mut updates = store.subscribe();
+
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let handle = Suspense::new(ElementType::Text("Loading...".into()), async move {
+            receiver
+                .await
+                .unwrap_or_else(|_| ElementType::Text("failed".into()))
+        })
+        .mount(store.clone(), vec![1]);
+
+        let pending = updates.recv().await.unwrap();
+        assert_eq!(pending.version, 1);
+        assert!(store.snapshot().tree.to_string().contains("Loading..."));
+
+        // 読み込み中に前の兄弟要素が増えても、プレースホルダーを置き換えられる
+        store.modify(|tree| {
+            if let ElementType::Element(_, _, children) = &mut tree.element_type {
+                children.insert(0, element("nav", &[], ()));
+            }
+        });
+        sender
+            .send(element("ul", &[], element("li", &[], "item")))
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            store.snapshot().tree.to_string(),
+            "<main ><nav ></nav><h1 >Title</h1><ul ><li >item</li></ul></main>"
+        );
+    }
+}
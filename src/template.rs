@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+/**
+ * コンパイル時に埋め込まれたHTMLテンプレート
+ */
+pub const EMBEDDED_TEMPLATE: &str = include_str!("index.html");
+
+/**
+ * 開発モードを有効にする環境変数名
+ */
+pub const DEV_ENV: &str = "VDOM_DEV";
+
+/**
+ * 開発モードを有効にするコマンドライン引数
+ */
+pub const DEV_FLAG: &str = "--dev";
+
+/**
+ * 開発モードで読み込んだテンプレートの head に挿入する目印
+ * クライアントはこの要素がある場合だけ Reload を受けてページを読み直す
+ */
+pub const DEV_MARKER: &str = r#"<meta name="vdom-dev" content="1">"#;
+
+/**
+ * 開発モードで起動されたかを判定する関数
+ */
+pub fn is_dev_mode() -> bool {
+    std::env::args().any(|arg| arg == DEV_FLAG)
+        || std::env::var(DEV_ENV).is_ok_and(|value| value != "0")
+}
+
+/**
+ * HTMLテンプレートの読み込み元を表す列挙型
+ */
+#[derive(Debug, Clone)]
+pub enum TemplateSource {
+    /** バイナリに埋め込まれたテンプレートを使う */
+    Embedded(&'static str),
+    /** リクエストのたびにディスクから読み込む */
+    Disk(PathBuf, &'static str),
+}
+
+impl TemplateSource {
+    /**
+     * 起動モードに応じてテンプレートの読み込み元を決める関数
+     * 開発モードではソースツリーの index.html をリクエストごとに読み込む
+     */
+    pub fn from_env() -> Self {
+        if is_dev_mode() {
+            Self::dev()
+        } else {
+            Self::Embedded(EMBEDDED_TEMPLATE)
+        }
+    }
+
+    /**
+     * ソースツリーの index.html を読み込む開発用の読み込み元を返す関数
+     */
+    pub fn dev() -> Self {
+        Self::Disk(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/index.html"),
+            EMBEDDED_TEMPLATE,
+        )
+    }
+
+    /**
+     * テンプレートを読み込む関数
+     * ディスクから読み込めなかった場合は埋め込みのテンプレートを返す
+     * ディスクから読み込む開発モードでは、head の末尾に DEV_MARKER を挿入する
+     */
+    pub fn load(&self) -> String {
+        match self {
+            Self::Embedded(template) => template.to_string(),
+            Self::Disk(path, fallback) => {
+                let template = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                    eprintln!("Failed to read template {:?}: {}", path, err);
+                    fallback.to_string()
+                });
+                template.replacen("</head>", &format!("{}</head>", DEV_MARKER), 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_template_reload() {
+        let path = std::env::temp_dir().join(format!("vdom-template-{}.html", std::process::id()));
+        let source = TemplateSource::Disk(path.clone(), "fallback");
+
+        assert_eq!(source.load(), "fallback");
+
+        std::fs::write(&path, "<p>v1</p>").unwrap();
+        assert_eq!(source.load(), "<p>v1</p>");
+
+        std::fs::write(&path, "<p>v2</p>").unwrap();
+        assert_eq!(source.load(), "<p>v2</p>");
+
+        std::fs::write(&path, "<head></head><p>v3</p>").unwrap();
+        assert_eq!(
+            source.load(),
+            format!("<head>{}</head><p>v3</p>", DEV_MARKER)
+        );
+        assert!(!TemplateSource::Embedded("<head></head>")
+            .load()
+            .contains(DEV_MARKER));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+
+use crate::server::{routes, Config};
+use crate::store::Store;
+
+/**
+ * テナントを指定するリクエストヘッダー名
+ */
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/**
+ * リクエストからテナントを選ぶ方法
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenantSelector {
+    /**
+     * パスの先頭の要素でテナントを選ぶ。`/{tenant}/update_input` のようになる
+     * クライアントのスクリプトは `/update_input` などの絶対パスを使うため、テナントごとのテンプレートでパスを合わせる
+     */
+    PathPrefix,
+    /** リクエストヘッダーの値でテナントを選ぶ。パスは1つのアプリの場合と変わらない */
+    Header(&'static str),
+}
+
+/**
+ * 1つのテナントのストアと設定
+ * セッションの流量制限、WebSocket の配信、フォームやルーターなどの登録はすべて設定ごとに作られる
+ */
+struct Tenant {
+    name: String,
+    config: Config,
+    store: Arc<Store>,
+}
+
+/**
+ * 1つのサーバーで配信するテナントの一覧
+ * テナントごとのフィルタはそのテナントのストアと設定だけを持つため、
+ * 他のテナントの差分やセッションには型の上で触れられない
+ */
+#[derive(Default)]
+pub struct Tenants {
+    tenants: Vec<Tenant>,
+}
+
+impl Tenants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * テナントを登録する関数
+     * 同じ名前のテナントを2回登録した場合は panic する
+     */
+    pub fn tenant(mut self, name: impl Into<String>, config: Config, store: Arc<Store>) -> Self {
+        let name = name.into();
+        assert!(
+            self.store(&name).is_none(),
+            "tenant {:?} is already registered",
+            name
+        );
+        self.tenants.push(Tenant {
+            name,
+            config,
+            store,
+        });
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tenants.iter().map(|tenant| tenant.name.as_str())
+    }
+
+    /**
+     * テナントのストアを返す関数
+     */
+    pub fn store(&self, name: &str) -> Option<&Arc<Store>> {
+        self.tenants
+            .iter()
+            .find(|tenant| tenant.name == name)
+            .map(|tenant| &tenant.store)
+    }
+
+    /**
+     * すべてのテナントの仮想DOMのエンドポイントをまとめたフィルタを返す関数
+     * 選ばれたテナントのルートだけを試すため、どのテナントにも当てはまらないリクエストは 404 になる
+     * 各テナントの配信タスクを起動するため、tokio のランタイム内で呼び出す
+     */
+    pub fn routes(self, selector: TenantSelector) -> BoxedFilter<(Box<dyn Reply>,)> {
+        let mut filter: BoxedFilter<(Box<dyn Reply>,)> = warp::any()
+            .and_then(|| async { Err::<Box<dyn Reply>, Rejection>(warp::reject::not_found()) })
+            .boxed();
+        for Tenant {
+            name,
+            config,
+            store,
+        } in self.tenants
+        {
+            let tenant = select(selector, name)
+                .and(routes(config, store))
+                .map(|reply| Box::new(reply) as Box<dyn Reply>);
+            filter = filter.or(tenant).unify().boxed();
+        }
+        filter
+    }
+}
+
+/**
+ * リクエストが name のテナントに向けたものの場合だけ通すフィルタを返す関数
+ */
+fn select(selector: TenantSelector, name: String) -> BoxedFilter<()> {
+    match selector {
+        TenantSelector::PathPrefix => warp::path(name).boxed(),
+        TenantSelector::Header(header) => warp::header::optional::<String>(header)
+            .and_then(move |value: Option<String>| {
+                let matched = value.as_deref() == Some(name.as_str());
+                async move {
+                    if matched {
+                        Ok(())
+                    } else {
+                        Err(warp::reject::not_found())
+                    }
+                }
+            })
+            .untuple_one()
+            .boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::initial_tree;
+
+    fn tenants() -> (Tenants, Arc<Store>, Arc<Store>) {
+        let (a, b) = (
+            Arc::new(Store::new(initial_tree())),
+            Arc::new(Store::new(initial_tree())),
+        );
+        let tenants = Tenants::new()
+            .tenant("a", Config::default(), a.clone())
+            .tenant("b", Config::default(), b.clone());
+        (tenants, a, b)
+    }
+
+    #[tokio::test]
+    async fn test_path_prefix_tenants() {
+        let (tenants, a, b) = tenants();
+        let filter = tenants.routes(TenantSelector::PathPrefix);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/a/update_input")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!((a.version(), b.version()), (1, 0));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/c/update_input")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_header_tenants() {
+        let (tenants, a, b) = tenants();
+        let filter = tenants.routes(TenantSelector::Header(TENANT_HEADER));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/update_input")
+            .header(TENANT_HEADER, "b")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!((a.version(), b.version()), (0, 1));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/update_input")
+            .json(&serde_json::json!({ "input": "Hello" }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,137 @@
+use std::error::Error as _;
+use std::fmt;
+use tera::{Context, Tera};
+
+use crate::parser::parse_html;
+use crate::self_virtual_dom::VNode;
+
+/**
+ * テンプレートの描画または描画結果の解析に失敗したことを表す構造体
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    /** 描画しようとしたテンプレートの名前 */
+    pub name: String,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "template {:?}: {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/**
+ * Tera のテンプレートを描画し、その結果を仮想DOMとして返す構造体
+ * 既存のテンプレートを Rust の builder に書き直さずに、差分の計算と適用に使える
+ * テンプレートは1つのルート要素を出力する必要がある
+ */
+pub struct TeraTemplates {
+    tera: Tera,
+}
+
+impl TeraTemplates {
+    /**
+     * `templates/**/*.html` のようなパターンに一致するテンプレートを読み込む関数
+     */
+    pub fn load(glob: &str) -> Result<Self, TemplateError> {
+        let tera = Tera::new(glob).map_err(|err| template_error(glob, &err))?;
+        Ok(Self { tera })
+    }
+
+    /**
+     * 設定済みの Tera から作る関数
+     */
+    pub fn from_tera(tera: Tera) -> Self {
+        Self { tera }
+    }
+
+    /**
+     * 文字列のテンプレートを追加する関数
+     * 名前が `.html` で終わる場合は、Tera の既定どおり値をエスケープする
+     */
+    pub fn with_template(mut self, name: &str, source: &str) -> Result<Self, TemplateError> {
+        self.tera
+            .add_raw_template(name, source)
+            .map_err(|err| template_error(name, &err))?;
+        Ok(self)
+    }
+
+    /**
+     * テンプレートを描画し、出力したHTMLを解析して仮想DOMを返す関数
+     */
+    pub fn render_template_tree(
+        &self,
+        name: &str,
+        context: &Context,
+    ) -> Result<VNode, TemplateError> {
+        let html = self
+            .tera
+            .render(name, context)
+            .map_err(|err| template_error(name, &err))?;
+        let element_type = parse_html(&html).map_err(|err| TemplateError {
+            name: name.to_string(),
+            message: err.to_string(),
+        })?;
+        Ok(VNode { element_type })
+    }
+}
+
+/**
+ * Tera のエラーは原因を source に持つため、原因までつなげたメッセージにする
+ */
+fn template_error(name: &str, err: &tera::Error) -> TemplateError {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    TemplateError {
+        name: name.to_string(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::{update_dom, Diff, ElementType};
+
+    #[test]
+    fn test_render_template_tree() {
+        let templates = TeraTemplates::from_tera(Tera::default())
+            .with_template(
+                "list.html",
+                "<ul>{% for item in items %}<li>{{ item }}</li>{% endfor %}</ul>",
+            )
+            .unwrap();
+        let render = |items: &[&str]| {
+            let mut context = Context::new();
+            context.insert("items", items);
+            templates.render_template_tree("list.html", &context)
+        };
+
+        let old = render(&["a", "b"]).unwrap();
+        let new = render(&["a", "<c>"]).unwrap();
+        let app_response = update_dom(&old, &new);
+        assert_eq!(app_response.diff.len(), 2);
+        // Tera がエスケープした文字参照は展開され、差分のテキストノードには元の文字列が入る
+        assert!(app_response.diff.iter().any(|patch| matches!(
+            patch,
+            Diff::AddNode(_, node) if node.element_type == ElementType::Text("<c>".into())
+        )));
+        assert_eq!(
+            app_response.html,
+            "<ul ><li >a</li><li >&lt;c&gt;</li></ul>"
+        );
+
+        let err = templates
+            .render_template_tree("missing.html", &Context::new())
+            .unwrap_err();
+        assert_eq!(err.name, "missing.html");
+    }
+}
@@ -0,0 +1,190 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::iter::NodePath;
+use crate::self_virtual_dom::{ElementType, Text};
+use crate::visit::Transformer;
+
+/**
+ * `var(--token)` の置き換えに使うトークンと値の対応
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Theme {
+    tokens: HashMap<String, String>,
+}
+
+impl Theme {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * トークンの値を設定する関数
+     * name は先頭の `--` を付けずに指定する
+     */
+    pub fn with(mut self, name: &str, value: &str) -> Self {
+        self.tokens.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /**
+     * 属性値に含まれる `var(--token)` と `var(--token, fallback)` を置き換える関数
+     * テーマにないトークンは fallback があればその値にし、なければブラウザで解決させるためそのまま残す
+     * fallback に入れ子の `var()` がある場合はそれも置き換える
+     */
+    pub fn resolve(&self, value: &str) -> String {
+        let mut resolved = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("var(--") {
+            resolved.push_str(&rest[..start]);
+            let body_start = start + "var(--".len();
+            let Some(length) = closing_paren(&rest[body_start..]) else {
+                rest = &rest[start..];
+                break;
+            };
+            let body = &rest[body_start..body_start + length];
+            let (name, fallback) = match body.split_once(',') {
+                Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+                None => (body.trim(), None),
+            };
+            match (self.tokens.get(name), fallback) {
+                (Some(value), _) => resolved.push_str(value),
+                (None, Some(fallback)) => resolved.push_str(&self.resolve(fallback)),
+                (None, None) => resolved.push_str(&rest[start..body_start + length + 1]),
+            }
+            rest = &rest[body_start + length + 1..];
+        }
+        resolved.push_str(rest);
+        resolved
+    }
+}
+
+/**
+ * `var(` の直後から始まる文字列で、対応する `)` の位置を返す関数
+ * 入れ子の括弧は数えて読み飛ばす
+ */
+fn closing_paren(body: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, ch) in body.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(index),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/**
+ * 要素の属性値に含まれる `var(--token)` をテーマの値で置き換えるパス
+ */
+pub struct ThemePass {
+    theme: Theme,
+}
+
+impl ThemePass {
+    pub fn new(theme: Theme) -> Self {
+        Self { theme }
+    }
+}
+
+impl Transformer for ThemePass {
+    fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+        if let ElementType::Element(_, attrs, _) = node {
+            for value in attrs.values_mut() {
+                if value.contains("var(--") {
+                    *value = self.theme.resolve(value).into();
+                }
+            }
+        }
+    }
+}
+
+/**
+ * 1つの属性値の変更を表す差分
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributePatch {
+    pub path: NodePath,
+    pub name: String,
+    pub value: String,
+}
+
+/**
+ * テーマを切り替えたときに値が変わる属性だけを差分として返す関数
+ * template はトークンを置き換える前の木を渡す
+ */
+pub fn theme_patches(template: &ElementType, old: &Theme, new: &Theme) -> Vec<AttributePatch> {
+    let mut patches = Vec::new();
+    for (path, node) in template.iter_with_paths() {
+        let ElementType::Element(_, attrs, _) = node else {
+            continue;
+        };
+        let mut names: Vec<&Text> = attrs
+            .iter()
+            .filter(|(_, value)| value.contains("var(--"))
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        for name in names {
+            let value = new.resolve(&attrs[name]);
+            if old.resolve(&attrs[name]) != value {
+                patches.push(AttributePatch {
+                    path: path.clone(),
+                    name: name.to_string(),
+                    value,
+                });
+            }
+        }
+    }
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::visit::Pipeline;
+
+    #[test]
+    fn test_theme_pass_and_patches() {
+        let template = element(
+            "div",
+            &[("style", "color: var(--fg); background: var(--bg, white)")],
+            element("a", &[("data-accent", "var(--accent)")], "link"),
+        );
+        let light = Theme::new().with("fg", "black");
+        let dark = Theme::new().with("fg", "white").with("bg", "black");
+
+        let html = Pipeline::new()
+            .with(ThemePass::new(light.clone()))
+            .render(template.clone());
+        assert_eq!(
+            html,
+            r#"<div style="color: black; background: white"><a data-accent="var(--accent)">link</a></div>"#
+        );
+
+        assert_eq!(
+            theme_patches(&template, &light, &dark),
+            vec![AttributePatch {
+                path: vec![],
+                name: "style".to_string(),
+                value: "color: white; background: black".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_resolve_nested_fallback() {
+        let theme = Theme::new().with("b", "red");
+
+        assert_eq!(theme.resolve("color: var(--a, var(--b));"), "color: red;");
+        assert_eq!(
+            theme.resolve("color: var(--a, var(--c, rgb(0, 0, 0))) !important"),
+            "color: rgb(0, 0, 0) !important"
+        );
+        assert_eq!(theme.resolve("var(--a, var(--c))"), "var(--c)");
+        assert_eq!(theme.resolve("var(--a, var(--b)"), "var(--a, var(--b)");
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::self_virtual_dom::VNode;
+use crate::store::Store;
+
+/**
+ * 一定間隔でストアの仮想DOMを更新するタイマー
+ * 更新による差分はストアの購読者（WebSocket や SSE）へそのまま配信される
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Ticker {
+    interval: Duration,
+}
+
+impl Ticker {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    /**
+     * タイマーを起動する関数
+     * update には何回目の実行かと現在の仮想DOMを渡し、木が変わった場合だけ差分が配信される
+     * 処理が間に合わなかった回は詰めて実行せずに読み飛ばす
+     */
+    pub fn spawn<F>(self, store: Arc<Store>, mut update: F) -> JoinHandle<()>
+    where
+        F: FnMut(u64, &mut VNode) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut count = 0;
+            loop {
+                ticker.tick().await;
+                store.modify(|tree| update(count, tree));
+                count += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::ElementType;
+
+    #[tokio::test]
+    async fn test_ticker_pushes_updates() {
+        let store = Arc::new(Store::new(VNode {
+            element_type: element("time", &[], "0"),
+        }));
+        let mut updates = store.subscribe();
+
+        let handle = Ticker::new(Duration::from_millis(5)).spawn(store.clone(), |count, tree| {
+            // 2回目以降は同じ値を描画するため、差分は1回だけ配信される
+            tree.element_type = element("time", &[], count.min(1));
+        });
+
+        let update = tokio::time::timeout(Duration::from_secs(2), updates.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        handle.abort();
+
+        assert_eq!(update.version, 1);
+        assert_eq!(store.version(), 1);
+        assert_eq!(
+            store.snapshot().tree.element_type.get(&[0]),
+            Some(&ElementType::Text("1".into()))
+        );
+    }
+}
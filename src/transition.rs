@@ -0,0 +1,160 @@
+use serde::Serialize;
+
+use crate::self_virtual_dom::{Diff, ElementType};
+
+/**
+ * 追加時に付与するクラスを持つ属性名
+ */
+pub const ENTER_CLASS_ATTR: &str = "data-enter-class";
+
+/**
+ * 削除前に付与するクラスを持つ属性名
+ */
+pub const EXIT_CLASS_ATTR: &str = "data-exit-class";
+
+/**
+ * トランジションの長さ（ミリ秒）を持つ属性名
+ */
+pub const DURATION_ATTR: &str = "data-transition-ms";
+
+/**
+ * ノードの追加・削除時のアニメーションの指定
+ * 属性としてノードに保存されるため、AddNode / RemoveNode の差分にそのまま含まれる
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub enter_class: Option<String>,
+    pub exit_class: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl Transition {
+    pub fn new(duration_ms: u64) -> Self {
+        Self {
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_enter_class(mut self, class: &str) -> Self {
+        self.enter_class = Some(class.to_string());
+        self
+    }
+
+    pub fn with_exit_class(mut self, class: &str) -> Self {
+        self.exit_class = Some(class.to_string());
+        self
+    }
+
+    /**
+     * 要素の属性にトランジションの指定を書き込む関数
+     * 要素以外のノードは属性を持てないため変更しない
+     */
+    pub fn apply(&self, node: &mut ElementType) {
+        let ElementType::Element(_, attrs, _) = node else {
+            return;
+        };
+        for (name, value) in [
+            (ENTER_CLASS_ATTR, &self.enter_class),
+            (EXIT_CLASS_ATTR, &self.exit_class),
+        ] {
+            match value {
+                Some(value) => attrs.insert(name.to_string().into(), value.clone().into()),
+                None => attrs.remove(name),
+            };
+        }
+        attrs.insert(DURATION_ATTR.into(), self.duration_ms.to_string().into());
+    }
+
+    /**
+     * 要素の属性からトランジションの指定を読み取る関数
+     */
+    pub fn of(node: &ElementType) -> Option<Self> {
+        let ElementType::Element(_, attrs, _) = node else {
+            return None;
+        };
+        let enter_class = attrs.get(ENTER_CLASS_ATTR).map(|value| value.to_string());
+        let exit_class = attrs.get(EXIT_CLASS_ATTR).map(|value| value.to_string());
+        if enter_class.is_none() && exit_class.is_none() {
+            return None;
+        }
+        Some(Self {
+            enter_class,
+            exit_class,
+            duration_ms: attrs
+                .get(DURATION_ATTR)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/**
+ * トランジションの指定を付与した要素を返す関数
+ */
+pub fn with_transition(mut node: ElementType, transition: &Transition) -> ElementType {
+    transition.apply(&mut node);
+    node
+}
+
+impl Diff {
+    /**
+     * 差分が追加・削除するノードに指定されたトランジションを取得する関数
+     * 追加では enter_class、削除では exit_class が指定されている場合だけ返す
+     */
+    pub fn transition(&self) -> Option<Transition> {
+        let transition = Transition::of(&self.node()?.element_type)?;
+        let animated = match self {
+            Diff::AddNode(..) => transition.enter_class.is_some(),
+            Diff::RemoveNode(..) => transition.exit_class.is_some(),
+            // 移動する要素は作り直さないため、入退場のトランジションは行わない
+            Diff::MoveNode(..) | Diff::SetAttribute(..) => false,
+        };
+        animated.then_some(transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::{update_dom, VNode};
+
+    #[test]
+    fn test_transition_carried_through_patches() {
+        let fade = Transition::new(200)
+            .with_enter_class("fade-in")
+            .with_exit_class("fade-out");
+        let old = VNode {
+            element_type: element(
+                "ul",
+                &[],
+                with_transition(element("li", &[("key", "a")], "A"), &fade),
+            ),
+        };
+        let new = VNode {
+            element_type: element(
+                "ul",
+                &[],
+                with_transition(element("li", &[("key", "b")], "B"), &fade),
+            ),
+        };
+
+        let diff = update_dom(&old, &new).diff;
+
+        assert_eq!(diff.len(), 2);
+        assert!(matches!(diff[0], Diff::RemoveNode(..)));
+        assert_eq!(diff[0].transition(), Some(fade.clone()));
+        assert_eq!(diff[1].transition(), Some(fade));
+        assert_eq!(
+            Diff::AddNode(
+                vec![0],
+                VNode {
+                    element_type: element("li", &[], "plain"),
+                }
+            )
+            .transition(),
+            None
+        );
+    }
+}
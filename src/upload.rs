@@ -0,0 +1,265 @@
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::sync::Arc;
+use warp::hyper::body::Buf;
+use warp::multipart::FormData;
+use warp::{Filter, Rejection, Reply};
+
+use crate::error::VdomError;
+use crate::iter::NodePath;
+use crate::self_virtual_dom::ElementType;
+use crate::server::error_reply;
+use crate::store::Store;
+
+/**
+ * アップロードを受け付けるリクエストボディの最大サイズ
+ */
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 64 * 1024 * 1024;
+
+/**
+ * Content-Length が分からない場合に progress 要素を更新するバイト数の間隔
+ */
+pub const PROGRESS_STEP_BYTES: u64 = 1024 * 1024;
+
+/**
+ * アップロードされたデータの書き込み先を表す trait
+ */
+pub trait UploadSink: Send + Sync {
+    fn write(&self, field: &str, filename: Option<&str>, chunk: &[u8]);
+}
+
+/**
+ * 受け取ったデータを保存せずに捨てる書き込み先
+ */
+pub struct DiscardSink;
+
+impl UploadSink for DiscardSink {
+    fn write(&self, _field: &str, _filename: Option<&str>, _chunk: &[u8]) {}
+}
+
+/**
+ * 受け取った1つのファイルの情報
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UploadedFile {
+    pub field: String,
+    pub filename: Option<String>,
+    pub bytes: u64,
+}
+
+/**
+ * アップロードの進捗を仮想DOMの progress 要素に反映する構造体
+ * 進捗の割合が変わったときだけストアを更新するため、チャンクごとに差分が送られることはない
+ * total には Content-Length を渡すため、割合は multipart の区切りを含むリクエスト全体に対するものになる
+ * total が分からない場合は、受け取ったバイト数を PROGRESS_STEP_BYTES ごとに反映する
+ */
+pub struct UploadProgress {
+    store: Arc<Store>,
+    path: NodePath,
+    total: Option<u64>,
+    received: u64,
+    percent: Option<u64>,
+}
+
+impl UploadProgress {
+    pub fn new(store: Arc<Store>, path: NodePath, total: Option<u64>) -> Self {
+        Self {
+            store,
+            path,
+            total,
+            received: 0,
+            percent: None,
+        }
+    }
+
+    /**
+     * 受け取ったバイト数を加算し、必要であれば progress 要素を更新する関数
+     */
+    pub fn advance(&mut self, bytes: u64) {
+        self.received += bytes;
+        let percent = match self.total {
+            Some(total) if total > 0 => (self.received * 100 / total).min(100),
+            _ => self.received / PROGRESS_STEP_BYTES,
+        };
+        if self.percent != Some(percent) {
+            self.percent = Some(percent);
+            self.render(percent);
+        }
+    }
+
+    /**
+     * アップロードの完了を progress 要素に反映する関数
+     */
+    pub fn finish(&mut self) {
+        match self.total {
+            Some(_) if self.percent != Some(100) => {
+                self.percent = Some(100);
+                self.render(100);
+            }
+            Some(_) => {}
+            // 間隔の途中までに受け取ったバイト数を反映する。変わっていなければストアは更新されない
+            None => self.render(self.received),
+        }
+    }
+
+    fn render(&self, percent: u64) {
+        let (value, max) = match self.total {
+            Some(_) => (percent.to_string(), Some("100")),
+            None => (self.received.to_string(), None),
+        };
+        let path = self.path.clone();
+        self.store.modify(move |tree| {
+            if let Some(ElementType::Element(_, attrs, _)) = tree.element_type.get_mut(&path) {
+                attrs.insert("value".into(), value.into());
+                if let Some(max) = max {
+                    attrs.insert("max".into(), max.to_string().into());
+                }
+            }
+        });
+    }
+}
+
+/**
+ * multipart/form-data のアップロードを受け付ける `POST /upload` ルートを返す関数
+ * チャンクを受け取るたびに progress_path の要素を更新し、差分は既存の WebSocket・SSE の配信に乗る
+ */
+pub fn upload_route(
+    store: Arc<Store>,
+    progress_path: NodePath,
+    sink: Arc<dyn UploadSink>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("upload")
+        .and(warp::post())
+        .and(warp::header::optional::<u64>("content-length"))
+        .and(warp::multipart::form().max_length(DEFAULT_MAX_UPLOAD_BYTES))
+        .and_then(move |total: Option<u64>, form: FormData| {
+            let store = store.clone();
+            let progress_path = progress_path.clone();
+            let sink = sink.clone();
+            async move {
+                let mut progress = UploadProgress::new(store.clone(), progress_path, total);
+                let reply = match receive_files(form, &mut progress, sink.as_ref()).await {
+                    Ok(files) => {
+                        progress.finish();
+                        warp::reply::json(&files).into_response()
+                    }
+                    Err(err) => error_reply(&err, Some(store.version())),
+                };
+                Ok::<_, Rejection>(reply)
+            }
+        })
+}
+
+/**
+ * multipart の各パートを書き込み先へ渡す関数
+ * 途中でリクエストボディを読み取れなくなった場合は、受け取ったファイルを返さずにエラーを返す
+ */
+async fn receive_files(
+    mut form: FormData,
+    progress: &mut UploadProgress,
+    sink: &dyn UploadSink,
+) -> Result<Vec<UploadedFile>, VdomError> {
+    let bad_request = |err: warp::Error| VdomError::BadRequest {
+        message: format!("invalid multipart body: {}", err),
+    };
+    let mut files = Vec::new();
+    while let Some(part) = form.next().await {
+        let part = part.map_err(bad_request)?;
+        let mut file = UploadedFile {
+            field: part.name().to_string(),
+            filename: part.filename().map(str::to_string),
+            bytes: 0,
+        };
+        let mut stream = part.stream();
+        while let Some(chunk) = stream.next().await {
+            let mut chunk = chunk.map_err(bad_request)?;
+            while chunk.has_remaining() {
+                let bytes = chunk.chunk();
+                let length = bytes.len();
+                sink.write(&file.field, file.filename.as_deref(), bytes);
+                file.bytes += length as u64;
+                progress.advance(length as u64);
+                chunk.advance(length);
+            }
+        }
+        files.push(file);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::element;
+    use crate::self_virtual_dom::VNode;
+
+    #[tokio::test]
+    async fn test_upload_updates_progress() {
+        let store = Arc::new(Store::new(VNode {
+            element_type: element("div", &[], element("progress", &[], ())),
+        }));
+        let mut updates = store.subscribe();
+        let route = upload_route(store.clone(), vec![0], Arc::new(DiscardSink));
+
+        let body = "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nhello\r\n--b--\r\n";
+        let response = warp::test::request()
+            .method("POST")
+            .path("/upload")
+            .header("content-type", "multipart/form-data; boundary=b")
+            .header("content-length", body.len().to_string())
+            .body(body)
+            .reply(&route)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let files: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(files[0]["filename"], "a.txt");
+        assert_eq!(files[0]["bytes"], 5);
+
+        assert!(updates.try_recv().is_ok());
+        let tree = store.snapshot().tree.element_type;
+        let Some(ElementType::Element(_, attrs, _)) = tree.get(&[0]) else {
+            panic!("expected progress element");
+        };
+        assert_eq!(attrs.get("value").map(|value| value.as_ref()), Some("100"));
+        assert_eq!(attrs.get("max").map(|value| value.as_ref()), Some("100"));
+
+        // 終端の区切りがない場合は、途中までのファイルを返さずに problem を返す
+        let response = warp::test::request()
+            .method("POST")
+            .path("/upload")
+            .header("content-type", "multipart/form-data; boundary=b")
+            .body(&body[..body.len() - 8])
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 400);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/problem+json"
+        );
+    }
+
+    #[test]
+    fn test_progress_without_total_is_throttled() {
+        let store = Arc::new(Store::new(VNode {
+            element_type: element("progress", &[], ()),
+        }));
+        let mut progress = UploadProgress::new(store.clone(), vec![], None);
+        for _ in 0..1024 {
+            progress.advance(1024);
+        }
+        // 最初のチャンクと 1MiB に達したときだけ更新する
+        assert_eq!(store.version(), 2);
+        progress.advance(10);
+        assert_eq!(store.version(), 2);
+        progress.finish();
+        assert_eq!(store.version(), 3);
+        let ElementType::Element(_, attrs, _) = store.snapshot().tree.element_type else {
+            panic!("expected progress element");
+        };
+        assert_eq!(
+            attrs.get("value").map(|value| value.as_ref()),
+            Some("1048586")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::builder::{element, keyed_list, IntoVNode};
+use crate::conditional::when;
+use crate::event::{ScrollEvent, VisibilityEvent};
+use crate::iter::NodePath;
+use crate::self_virtual_dom::ElementType;
+
+/**
+ * 表示領域に入ったか・出たかをクライアントが visibility イベントで送る要素に付ける属性名
+ */
+pub const OBSERVE_ATTR: &str = "data-observe";
+
+/**
+ * スクロール位置をクライアントが scroll イベントで送る要素に付ける属性名
+ */
+pub const SCROLL_ATTR: &str = "data-scroll";
+
+/**
+ * クライアントから送られた、要素ごとのスクロール位置と表示されている要素
+ * 次に描画するときに、どの部分を描画してどの部分をプレースホルダーにするかを決めるために使う
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Viewport {
+    scroll: HashMap<NodePath, ScrollEvent>,
+    visible: HashSet<NodePath>,
+}
+
+impl Viewport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * スクロール位置を記録し、位置か表示領域の大きさが変わったかを返す関数
+     */
+    pub fn scroll(&mut self, event: &ScrollEvent) -> bool {
+        let previous = self.scroll.insert(event.target.clone(), event.clone());
+        previous.as_ref() != Some(event)
+    }
+
+    /**
+     * 要素が表示されているかを記録し、前回から変わったかを返す関数
+     */
+    pub fn visibility(&mut self, event: &VisibilityEvent) -> bool {
+        if event.visible {
+            self.visible.insert(event.target.clone())
+        } else {
+            self.visible.remove(&event.target)
+        }
+    }
+
+    /**
+     * 最後に送られた要素のスクロール位置を返す関数
+     */
+    pub fn scroll_of(&self, path: &[usize]) -> Option<&ScrollEvent> {
+        self.scroll.get(path)
+    }
+
+    pub fn is_visible(&self, path: &[usize]) -> bool {
+        self.visible.contains(path)
+    }
+}
+
+/**
+ * 表示されるまで内容を描画しない要素を返す関数
+ * 要素自体は常に置かれてクライアントに監視されるため、表示されたときに visibility イベントが送られる
+ */
+pub fn lazy(visible: bool, node: impl IntoVNode) -> ElementType {
+    element("div", &[(OBSERVE_ATTR, "")], when(visible, node))
+}
+
+/**
+ * 高さの揃った項目のリストで、スクロール位置から描画する項目の範囲を求める関数
+ * 表示領域の前後 overscan 個の項目も含める
+ */
+pub fn visible_range(
+    scroll: &ScrollEvent,
+    item_height: u32,
+    total: usize,
+    overscan: usize,
+) -> Range<usize> {
+    let item_height = item_height.max(1) as usize;
+    let first = scroll.top as usize / item_height;
+    let last = (scroll.top.saturating_add(scroll.height) as usize).div_ceil(item_height);
+    first.saturating_sub(overscan).min(total)..(last + overscan).min(total)
+}
+
+/**
+ * range の項目だけを描画し、前後を同じ高さの空の要素で埋めたリストを返す関数
+ * 項目は key 付きのため、スクロールしても範囲に出入りした項目だけが差分になる
+ */
+pub fn virtual_list<T, K, N>(
+    items: &[T],
+    item_height: u32,
+    range: Range<usize>,
+    key: impl Fn(&T) -> K,
+    render: impl Fn(&T) -> N,
+) -> ElementType
+where
+    K: ToString,
+    N: IntoVNode,
+{
+    let range = range.start.min(items.len())..range.end.min(items.len());
+    let spacer = |count: usize| {
+        let style = format!("height:{}px", count * item_height as usize);
+        element("div", &[("style", &style)], ())
+    };
+    element(
+        "div",
+        &[(SCROLL_ATTR, ""), ("style", "overflow-y:auto")],
+        (
+            spacer(range.start),
+            element(
+                "div",
+                &[],
+                keyed_list(
+                    items[range.clone()].iter(),
+                    |item| key(item),
+                    |item| render(item),
+                ),
+            ),
+            spacer(items.len() - range.end),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::{update_dom, virtual_dom_to_html, VNode};
+
+    #[test]
+    fn test_virtual_list_follows_scroll() {
+        let items: Vec<u32> = (0..100).collect();
+        let mut viewport = Viewport::new();
+        let view = |viewport: &Viewport| {
+            let scroll = viewport.scroll_of(&[]).cloned().unwrap_or_default();
+            VNode {
+                element_type: virtual_list(
+                    &items,
+                    20,
+                    visible_range(&scroll, 20, items.len(), 1),
+                    |item| *item,
+                    |item| element("p", &[], *item),
+                ),
+            }
+        };
+
+        let scroll = |top: u32| ScrollEvent {
+            top,
+            height: 60,
+            ..Default::default()
+        };
+        assert!(viewport.scroll(&scroll(0)));
+        assert!(!viewport.scroll(&scroll(0)));
+        let before = view(&viewport);
+        assert!(before
+            .to_string()
+            .starts_with(r#"<div data-scroll="" style="overflow-y:auto"><div style="height:0px"></div><div ><p key="0">0</p>"#));
+
+        viewport.scroll(&scroll(40));
+        let after = view(&viewport);
+        assert_eq!(visible_range(&scroll(40), 20, 100, 1), 1..6);
+        // 範囲から出た 0 と範囲に入った 4, 5 と、前後の空の要素の高さだけが変わる
+        assert_eq!(update_dom(&before, &after).diff.len(), 7);
+    }
+
+    #[test]
+    fn test_lazy_subtree() {
+        let mut viewport = Viewport::new();
+        let event = |visible: bool| VisibilityEvent {
+            target: vec![0, 1],
+            visible,
+        };
+        assert_eq!(
+            virtual_dom_to_html(&lazy(viewport.is_visible(&[0, 1]), "comments")),
+            r#"<div data-observe=""><!----></div>"#
+        );
+        assert!(viewport.visibility(&event(true)));
+        assert!(!viewport.visibility(&event(true)));
+        assert_eq!(
+            virtual_dom_to_html(&lazy(viewport.is_visible(&[0, 1]), "comments")),
+            r#"<div data-observe="">comments</div>"#
+        );
+        assert!(viewport.visibility(&event(false)));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::self_virtual_dom::{virtual_dom_to_html, ElementType};
+
+/**
+ * 仮想DOMの木を読み取り専用で巡回するための trait
+ * enter は子要素の巡回前、leave は子要素の巡回後に呼ばれる
+ */
+pub trait Visitor {
+    fn enter(&mut self, _path: &[usize], _node: &ElementType) {}
+    fn leave(&mut self, _path: &[usize], _node: &ElementType) {}
+}
+
+/**
+ * 仮想DOMの木を書き換えながら巡回するための trait
+ * pre で子要素を差し替えた場合、差し替え後の子要素が巡回される
+ */
+pub trait Transformer {
+    fn pre(&mut self, _path: &[usize], _node: &mut ElementType) {}
+    fn post(&mut self, _path: &[usize], _node: &mut ElementType) {}
+}
+
+/**
+ * Visitor で木を巡回する関数
+ */
+pub fn walk<V: Visitor + ?Sized>(node: &ElementType, visitor: &mut V) {
+    walk_recursive(node, visitor, &mut Vec::new());
+}
+
+fn walk_recursive<V: Visitor + ?Sized>(node: &ElementType, visitor: &mut V, path: &mut Vec<usize>) {
+    visitor.enter(path, node);
+    if let ElementType::Element(_, _, children) = node {
+        for (index, child) in children.iter().enumerate() {
+            path.push(index);
+            walk_recursive(child, visitor, path);
+            path.pop();
+        }
+    }
+    visitor.leave(path, node);
+}
+
+/**
+ * Transformer で木を書き換えながら巡回する関数
+ */
+pub fn walk_mut<T: Transformer + ?Sized>(node: &mut ElementType, transformer: &mut T) {
+    walk_mut_recursive(node, transformer, &mut Vec::new());
+}
+
+fn walk_mut_recursive<T: Transformer + ?Sized>(
+    node: &mut ElementType,
+    transformer: &mut T,
+    path: &mut Vec<usize>,
+) {
+    transformer.pre(path, node);
+    if let ElementType::Element(_, _, children) = node {
+        for (index, child) in children.iter_mut().enumerate() {
+            path.push(index);
+            walk_mut_recursive(child, transformer, path);
+            path.pop();
+        }
+    }
+    transformer.post(path, node);
+}
+
+/**
+ * 複数の Transformer を登録順に適用するパイプライン
+ */
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Transformer + Send>>,
+}
+
+impl std::fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("passes", &self.passes.len())
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * パスを末尾に追加する関数
+     */
+    pub fn with<T: Transformer + Send + 'static>(mut self, pass: T) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /**
+     * 組み立て済みのパイプラインの末尾にパスを追加する関数
+     */
+    pub fn push<T: Transformer + Send + 'static>(&mut self, pass: T) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /**
+     * 登録されたパスを順に木へ適用する関数
+     */
+    pub fn run(&mut self, node: &mut ElementType) {
+        for pass in self.passes.iter_mut() {
+            walk_mut(node, pass.as_mut());
+        }
+    }
+
+    /**
+     * パスを適用した木をHTMLに変換する関数
+     */
+    pub fn render(&mut self, mut node: ElementType) -> String {
+        self.run(&mut node);
+        virtual_dom_to_html(&node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_virtual_dom::Attributes;
+
+    struct IdGenerator {
+        next: usize,
+    }
+
+    impl Transformer for IdGenerator {
+        fn pre(&mut self, _path: &[usize], node: &mut ElementType) {
+            if let ElementType::Element(_, attrs, _) = node {
+                attrs
+                    .entry("id".into())
+                    .or_insert_with(|| format!("node-{}", self.next).into());
+                self.next += 1;
+            }
+        }
+    }
+
+    struct Upcase;
+
+    impl Transformer for Upcase {
+        fn post(&mut self, _path: &[usize], node: &mut ElementType) {
+            if let ElementType::Text(text) = node {
+                *text = text.to_uppercase().into();
+            }
+        }
+    }
+
+    struct DepthCounter {
+        max_depth: usize,
+        leaves: usize,
+    }
+
+    impl Visitor for DepthCounter {
+        fn enter(&mut self, path: &[usize], _node: &ElementType) {
+            self.max_depth = self.max_depth.max(path.len());
+        }
+
+        fn leave(&mut self, _path: &[usize], node: &ElementType) {
+            if let ElementType::Text(_) = node {
+                self.leaves += 1;
+            }
+        }
+    }
+
+    fn sample() -> ElementType {
+        ElementType::Element(
+            "div".to_string(),
+            Attributes::new(),
+            vec![ElementType::Element(
+                "span".to_string(),
+                Attributes::new(),
+                vec![ElementType::Text("hi".into())],
+            )],
+        )
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut counter = DepthCounter {
+            max_depth: 0,
+            leaves: 0,
+        };
+        walk(&sample(), &mut counter);
+
+        assert_eq!(counter.max_depth, 2);
+        assert_eq!(counter.leaves, 1);
+    }
+
+    #[test]
+    fn test_pipeline_render() {
+        let mut pipeline = Pipeline::new().with(IdGenerator { next: 0 }).with(Upcase);
+
+        let html = pipeline.render(sample());
+
+        assert_eq!(
+            html,
+            r#"<div id="node-0"><span id="node-1">HI</span></div>"#
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use warp::http::StatusCode;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+use crate::auth::{authenticate, AuthProvider, Identity};
+use crate::broadcaster::{Broadcaster, Subscription, Topic};
+use crate::head::HeadPatch;
+use crate::query::parse_path;
+use crate::selector::{Selector, SelectorError};
+use crate::self_virtual_dom::Diff;
+
+/**
+ * WebSocket でサーバーからクライアントへ送るメッセージを表す列挙型
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /** ページの再読み込みを指示する */
+    Reload,
+    /** 購読範囲に関係する差分を届ける */
+    Patch {
+        version: u64,
+        diff: Vec<Diff>,
+        /** 差分を発生させたリクエストのID */
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+    /** 差分を届けきれなかったため、現在のHTML全体で置き換えさせる */
+    Resync { version: u64, html: String },
+    /** document.head を更新させる */
+    Head { patches: Vec<HeadPatch> },
+}
+
+/**
+ * 購読範囲を指定するクエリ
+ * root（`/0/1` 形式のパス式）か selector のどちらかを指定し、省略時はすべての差分を購読する
+ */
+#[derive(Debug, Default, Deserialize)]
+struct SubscribeQuery {
+    root: Option<String>,
+    selector: Option<String>,
+}
+
+impl SubscribeQuery {
+    fn topic(&self) -> Result<Topic, SelectorError> {
+        match (&self.selector, &self.root) {
+            (Some(selector), _) => Ok(Topic::Selector(Selector::parse(selector)?)),
+            (None, Some(root)) => Ok(Topic::Root(parse_path(root)?)),
+            (None, None) => Ok(Topic::All),
+        }
+    }
+}
+
+/**
+ * 接続したクライアントへ ServerMessage を配信する WebSocket ルートを返す関数
+ * 接続の確立前に送信者を認証する
+ */
+pub fn ws_route(
+    sender: broadcast::Sender<ServerMessage>,
+    broadcaster: Arc<Broadcaster>,
+    auth: Arc<dyn AuthProvider>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("ws")
+        .and(authenticate(auth))
+        .and(warp::ws())
+        .and(warp::query::<SubscribeQuery>())
+        .map(
+            move |_identity: Identity, ws: Ws, query: SubscribeQuery| match query.topic() {
+                Ok(topic) => {
+                    let receiver = sender.subscribe();
+                    let subscription = broadcaster.subscribe(topic);
+                    let broadcaster = broadcaster.clone();
+                    ws.on_upgrade(move |socket| async move {
+                        let id = subscription.id;
+                        forward_messages(socket, receiver, subscription).await;
+                        broadcaster.unsubscribe(id);
+                    })
+                    .into_response()
+                }
+                Err(err) => warp::reply::with_status(err.to_string(), StatusCode::BAD_REQUEST)
+                    .into_response(),
+            },
+        )
+}
+
+/**
+ * チャネルに届いたメッセージを JSON にしてクライアントへ送り続ける関数
+ */
+async fn forward_messages(
+    socket: WebSocket,
+    mut receiver: broadcast::Receiver<ServerMessage>,
+    subscription: Subscription,
+) {
+    let (mut outgoing, mut incoming) = socket.split();
+
+    loop {
+        let message = tokio::select! {
+            message = receiver.recv() => match message {
+                Ok(message) => message,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = subscription.queue.recv() => message,
+            incoming_message = incoming.next() => match incoming_message {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            },
+        };
+
+        let Ok(text) = serde_json::to_string(&message) else {
+            continue;
+        };
+        if outgoing.send(Message::text(text)).await.is_err() {
+            break;
+        }
+    }
+}
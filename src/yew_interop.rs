@@ -0,0 +1,177 @@
+use std::fmt;
+use yew::virtual_dom::{ApplyAttributeAs, VList, VNode, VTag, VText};
+use yew::AttrValue;
+
+use crate::self_virtual_dom::{virtual_dom_to_html, Attributes, ElementType};
+
+/**
+ * Yew の仮想DOMをこのクレートの仮想DOMに変換できなかったことを表すエラー
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YewConversionError {
+    pub message: String,
+}
+
+impl fmt::Display for YewConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot convert Yew node: {}", self.message)
+    }
+}
+
+impl std::error::Error for YewConversionError {}
+
+/**
+ * 仮想DOMを Yew の VNode に変換する関数
+ * key 属性は VTag の key に、input と textarea の value は Yew の value に移す
+ * ElementType は Display を実装しており Yew の `From<T: ToString>` と衝突するため、From ではなく関数で提供する
+ * 以前の `VNode::from(&tree)` や `tree.into()` は Yew の From によってHTMLの文字列を持つテキストノードになるため、
+ * to_yew か IntoYew::into_yew に置き換える
+ */
+pub fn to_yew(node: &ElementType) -> VNode {
+    match node {
+        ElementType::Text(text) => VText::new(text.to_string()).into(),
+        ElementType::I18n(..) => VText::new(virtual_dom_to_html(node)).into(),
+        ElementType::Comment(_) => VList::new().into(),
+        ElementType::Element(tag, attrs, children) => {
+            let mut vtag = VTag::new(tag.clone());
+            let has_value = matches!(tag.as_str(), "input" | "textarea");
+            for (name, value) in attrs {
+                match name.as_ref() {
+                    "key" => vtag.key = Some(value.as_ref().into()),
+                    "value" if has_value => vtag.set_value(value.to_string()),
+                    _ => {
+                        vtag.attributes.get_mut_index_map().insert(
+                            AttrValue::from(name.clone()),
+                            (AttrValue::from(value.clone()), ApplyAttributeAs::Attribute),
+                        );
+                    }
+                }
+            }
+            vtag.add_children(children.iter().map(to_yew));
+            vtag.into()
+        }
+    }
+}
+
+/**
+ * 削除した `From<ElementType> for VNode` の代わりに、メソッドの形で Yew の VNode に変換する trait
+ */
+pub trait IntoYew {
+    fn into_yew(self) -> VNode;
+}
+
+impl IntoYew for &ElementType {
+    fn into_yew(self) -> VNode {
+        to_yew(self)
+    }
+}
+
+impl IntoYew for ElementType {
+    fn into_yew(self) -> VNode {
+        to_yew(&self)
+    }
+}
+
+/**
+ * Yew の VNode を仮想DOMに変換する
+ * VList は親要素の子として展開するため、最上位の VList は1つの子を持つ場合のみ変換できる
+ * コンポーネントやポータルなどHTMLの要素に対応しないノードはエラーにする
+ */
+impl TryFrom<&VNode> for ElementType {
+    type Error = YewConversionError;
+
+    fn try_from(node: &VNode) -> Result<Self, Self::Error> {
+        let mut nodes = Vec::new();
+        collect_nodes(node, &mut nodes)?;
+        match <[ElementType; 1]>::try_from(nodes) {
+            Ok([node]) => Ok(node),
+            Err(nodes) => Err(YewConversionError {
+                message: format!("expected a single root node, found {}", nodes.len()),
+            }),
+        }
+    }
+}
+
+fn collect_nodes(node: &VNode, nodes: &mut Vec<ElementType>) -> Result<(), YewConversionError> {
+    match node {
+        VNode::VText(vtext) => nodes.push(ElementType::Text(vtext.text.to_string().into())),
+        VNode::VList(vlist) => collect_list(vlist, nodes)?,
+        VNode::VTag(vtag) => {
+            let mut attrs: Attributes = vtag
+                .attributes
+                .iter()
+                .map(|(name, value)| (name.to_string().into(), value.to_string().into()))
+                .collect();
+            if let Some(key) = &vtag.key {
+                attrs.insert("key".into(), key.to_string().into());
+            }
+            if let Some(value) = vtag.value() {
+                attrs.insert("value".into(), value.to_string().into());
+            }
+            if vtag.checked() == Some(true) {
+                attrs.insert("checked".into(), "".into());
+            }
+
+            let mut children = Vec::new();
+            if let Some(child) = vtag.children() {
+                collect_nodes(child, &mut children)?;
+            }
+            nodes.push(ElementType::Element(
+                vtag.tag().to_string(),
+                attrs,
+                children,
+            ));
+        }
+        VNode::VComp(_) => return Err(unsupported("component")),
+        VNode::VPortal(_) => return Err(unsupported("portal")),
+        VNode::VRef(_) => return Err(unsupported("node reference")),
+        VNode::VSuspense(_) => return Err(unsupported("suspense")),
+        VNode::VRaw(_) => return Err(unsupported("raw HTML")),
+    }
+    Ok(())
+}
+
+fn collect_list(vlist: &VList, nodes: &mut Vec<ElementType>) -> Result<(), YewConversionError> {
+    vlist
+        .iter()
+        .try_for_each(|child| collect_nodes(child, nodes))
+}
+
+fn unsupported(kind: &str) -> YewConversionError {
+    YewConversionError {
+        message: format!("{} nodes have no HTML element equivalent", kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yew_round_trip() {
+        let tree = ElementType::Element(
+            "form".to_string(),
+            [("key".into(), "login".into())].into_iter().collect(),
+            vec![
+                ElementType::Element(
+                    "input".to_string(),
+                    [
+                        ("name".into(), "user".into()),
+                        ("value".into(), "alice".into()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    vec![],
+                ),
+                ElementType::Text("Sign in".into()),
+            ],
+        );
+
+        let vnode = to_yew(&tree);
+        assert_eq!(ElementType::try_from(&vnode).unwrap(), tree);
+        assert!(matches!(tree.clone().into_yew(), VNode::VTag(_)));
+        // Yew の From はHTMLの文字列のテキストノードにしてしまう
+        assert!(matches!(VNode::from(&tree), VNode::VText(_)));
+        assert!(ElementType::try_from(&VNode::VList(VList::new())).is_err());
+    }
+}